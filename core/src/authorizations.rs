@@ -0,0 +1,174 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Authorizations for processes to use interfaces.
+//!
+//! By default, processes are banned from using any interface that checks authorizations, and
+//! must instead be explicitly whitelisted.
+//!
+//! An [`Authorizations`] object is shared between the [`System`](crate::System) and the
+//! interface handlers. The [`System`](crate::System) keeps it up to date as processes start and
+//! stop, and interface handlers call [`Authorizations::is_authorized`] whenever they receive a
+//! message.
+//!
+//! Authorizations can be granted in two ways:
+//!
+//! - By module, with [`Authorizations::grant_module`]. Every process started from a module with
+//! the given hash is automatically granted the interface.
+//! - By [`Pid`], with [`Authorizations::grant`]. This is meant to be used by whoever launches a
+//! process, immediately after it has been started. Processes can do so through the `interface`
+//! interface, for the interfaces that they are themselves allowed to use.
+//!
+//! > **Note**: It is the responsibility of each interface handler to check authorizations. The
+//! >           [`System`](crate::System) itself doesn't prevent any message from being emitted.
+
+use crate::module::ModuleHash;
+
+use fnv::FnvBuildHasher;
+use hashbrown::{HashMap, HashSet};
+use nohash_hasher::BuildNoHashHasher;
+use redshirt_syscalls::{InterfaceHash, Pid};
+use spinning_top::Spinlock;
+
+/// Table of which process is allowed to use which interface.
+pub struct Authorizations {
+    /// For each running process, the list of interfaces it is allowed to use.
+    by_pid: Spinlock<HashMap<Pid, HashSet<InterfaceHash, FnvBuildHasher>, BuildNoHashHasher<u64>>>,
+
+    /// For each module hash, the list of interfaces granted to processes started from that
+    /// module.
+    by_module:
+        Spinlock<HashMap<ModuleHash, HashSet<InterfaceHash, FnvBuildHasher>, FnvBuildHasher>>,
+}
+
+impl Authorizations {
+    /// Builds a new empty table. No process is authorized to use anything.
+    pub fn new() -> Self {
+        Authorizations {
+            by_pid: Spinlock::new(HashMap::default()),
+            by_module: Spinlock::new(HashMap::default()),
+        }
+    }
+
+    /// Grants to all the processes that are later started from the given module the right to use
+    /// the given interface.
+    ///
+    /// > **Note**: Processes that are already running aren't affected.
+    pub fn grant_module(&self, module: ModuleHash, interface: InterfaceHash) {
+        self.by_module
+            .lock()
+            .entry(module)
+            .or_insert_with(Default::default)
+            .insert(interface);
+    }
+
+    /// Grants to the given process the right to use the given interface.
+    ///
+    /// Has no effect if the process has already been destroyed.
+    pub fn grant(&self, pid: Pid, interface: InterfaceHash) {
+        if let Some(list) = self.by_pid.lock().get_mut(&pid) {
+            list.insert(interface);
+        }
+    }
+
+    /// Removes from the given process the right to use the given interface.
+    pub fn revoke(&self, pid: Pid, interface: &InterfaceHash) {
+        if let Some(list) = self.by_pid.lock().get_mut(&pid) {
+            list.remove(interface);
+        }
+    }
+
+    /// Returns true if the given process is allowed to use the given interface.
+    pub fn is_authorized(&self, pid: Pid, interface: &InterfaceHash) -> bool {
+        self.by_pid
+            .lock()
+            .get(&pid)
+            .map_or(false, |list| list.contains(interface))
+    }
+
+    /// Notifies the table that a process is being started from the given module, and applies
+    /// the grants of this module to it.
+    ///
+    /// Must be called before the process can run, so that it can't be observed without its
+    /// authorizations.
+    pub(crate) fn process_started(&self, module: &ModuleHash, pid: Pid) {
+        let granted = self
+            .by_module
+            .lock()
+            .get(module)
+            .cloned()
+            .unwrap_or_default();
+        self.by_pid.lock().insert(pid, granted);
+    }
+
+    /// Notifies the table that a process has been destroyed.
+    pub(crate) fn process_destroyed(&self, pid: Pid) {
+        self.by_pid.lock().remove(&pid);
+    }
+}
+
+impl Default for Authorizations {
+    fn default() -> Self {
+        Authorizations::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Authorizations;
+    use crate::module::ModuleHash;
+    use redshirt_syscalls::{InterfaceHash, Pid};
+
+    #[test]
+    fn denied_by_default() {
+        let authorizations = Authorizations::new();
+        let pid = Pid::from(1);
+        authorizations.process_started(&ModuleHash::from([0; 32]), pid);
+        assert!(!authorizations.is_authorized(pid, &InterfaceHash::from_raw_hash([1; 32])));
+    }
+
+    #[test]
+    fn module_grants_applied_at_start() {
+        let authorizations = Authorizations::new();
+        let interface = InterfaceHash::from_raw_hash([1; 32]);
+        authorizations.grant_module(ModuleHash::from([5; 32]), interface.clone());
+
+        let granted = Pid::from(1);
+        authorizations.process_started(&ModuleHash::from([5; 32]), granted);
+        let other = Pid::from(2);
+        authorizations.process_started(&ModuleHash::from([6; 32]), other);
+        assert!(authorizations.is_authorized(granted, &interface));
+        assert!(!authorizations.is_authorized(other, &interface));
+    }
+
+    #[test]
+    fn grant_revoke_destroy() {
+        let authorizations = Authorizations::new();
+        let interface = InterfaceHash::from_raw_hash([1; 32]);
+        let pid = Pid::from(1);
+        authorizations.process_started(&ModuleHash::from([0; 32]), pid);
+
+        authorizations.grant(pid, interface.clone());
+        assert!(authorizations.is_authorized(pid, &interface));
+        authorizations.revoke(pid, &interface);
+        assert!(!authorizations.is_authorized(pid, &interface));
+
+        authorizations.grant(pid, interface.clone());
+        authorizations.process_destroyed(pid);
+        assert!(!authorizations.is_authorized(pid, &interface));
+        authorizations.grant(pid, interface.clone());
+        assert!(!authorizations.is_authorized(pid, &interface));
+    }
+}
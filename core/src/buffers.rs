@@ -0,0 +1,447 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Buffers of data shared between programs.
+//!
+//! Messages are copied every time they are emitted or delivered, which is wasteful for bulk data
+//! such as the pixels of a framebuffer. Programs can instead put this data in a *buffer* owned by
+//! the kernel, and embed the [`BufferHandle`] of that buffer in their messages.
+//!
+//! Each buffer is owned by a process, and only the owner can access it. Buffers are destroyed
+//! when the process that owns them terminates.
+//!
+//! The total size of the buffers that a process owns is limited by [`MAX_PROCESS_BUFFERS_LEN`].
+//! Processes can't create or claim buffers past this limit. Buffers created by native programs
+//! count towards it as well, but are never refused.
+//!
+//! Passing a buffer to another program is done in two steps:
+//!
+//! - The owner *transfers* the buffer, after which it no longer has access to it, then embeds its
+//! handle in a message.
+//! - The first process that accesses the buffer through its handle becomes its new owner. Native
+//! programs, instead, call [`Buffers::take`] in order to extract the content of the buffer.
+//!
+//! Buffers that have been transferred but not claimed yet still count towards the limit of the
+//! process that has transferred them, and are destroyed when that process terminates. Nobody can
+//! destroy them in the meanwhile.
+//!
+//! A [`Buffers`] object is shared between the [`System`](crate::System), which keeps it up to
+//! date as processes start and stop, and the native programs that receive or send buffers.
+//!
+//! > **Note**: Handles are chosen randomly by the kernel, and knowing the handle of a transferred
+//! >           buffer is enough to claim it. A buffer should therefore only be transferred right
+//! >           before its handle is sent out.
+
+use crate::id_pool::IdPool;
+
+use alloc::{vec, vec::Vec};
+use core::fmt;
+use hashbrown::HashMap;
+use nohash_hasher::BuildNoHashHasher;
+use redshirt_syscalls::{BufferHandle, Payload, Pid};
+use spinning_top::Spinlock;
+
+/// Maximum total length, in bytes, of the buffers owned by a process for it to be allowed to
+/// create a new buffer.
+pub const MAX_PROCESS_BUFFERS_LEN: usize = 64 * 1024 * 1024;
+
+/// Collection of buffers.
+pub struct Buffers {
+    /// Pool of identifiers used for the handles.
+    id_pool: IdPool,
+    /// List of buffers and processes.
+    inner: Spinlock<Inner>,
+}
+
+struct Inner {
+    /// List of buffers that currently exist.
+    buffers: HashMap<BufferHandle, Buffer, BuildNoHashHasher<u64>>,
+    /// List of processes that are alive, with the total length of the buffers they own,
+    /// including the ones they have transferred and that haven't been claimed yet.
+    processes: HashMap<Pid, usize, BuildNoHashHasher<u64>>,
+}
+
+/// A single buffer.
+struct Buffer {
+    /// Process that owns the buffer, or that has transferred it if `transferred` is true.
+    owner: Pid,
+    /// If true, the next process that accesses the buffer becomes its owner.
+    transferred: bool,
+    /// Content of the buffer.
+    data: Vec<u8>,
+}
+
+impl Buffers {
+    /// Builds a new empty collection.
+    pub fn new() -> Self {
+        Buffers {
+            id_pool: IdPool::new(),
+            inner: Spinlock::new(Inner {
+                buffers: HashMap::default(),
+                processes: HashMap::default(),
+            }),
+        }
+    }
+
+    /// Creates a new buffer owned by the given process and containing `data`.
+    ///
+    /// This is meant to be used by native programs that send bulk data to a process. The buffer
+    /// is destroyed when `owner` terminates. If `owner` has already terminated, the data is
+    /// immediately discarded and the returned handle is invalid.
+    pub fn create(&self, owner: Pid, data: Vec<u8>) -> BufferHandle {
+        let handle = BufferHandle::from(self.id_pool.assign::<u64>());
+        let mut inner = self.inner.lock();
+        if let Some(used) = inner.processes.get_mut(&owner) {
+            *used += data.len();
+            inner.insert(handle, owner, data);
+        }
+        handle
+    }
+
+    /// Extracts the content of a buffer that has been transferred, and destroys that buffer.
+    ///
+    /// Returns an error if the handle is invalid or if the buffer hasn't been transferred.
+    pub fn take(&self, handle: BufferHandle) -> Result<Vec<u8>, InvalidBufferErr> {
+        let mut inner = self.inner.lock();
+        match inner.buffers.get(&handle) {
+            Some(buffer) if buffer.transferred => Ok(inner.remove(handle)),
+            _ => Err(InvalidBufferErr),
+        }
+    }
+
+    /// Extracts the data of a [`Payload`] received by a native program.
+    ///
+    /// If the data is in a buffer, the buffer is destroyed. See [`Buffers::take`].
+    pub fn take_payload(&self, payload: Payload) -> Result<Vec<u8>, InvalidBufferErr> {
+        match payload {
+            Payload::Inline(data) => Ok(data),
+            Payload::Buffer(handle) => self.take(handle),
+        }
+    }
+
+    /// Builds a [`Payload`] containing `data`, meant to be sent by a native program to the given
+    /// process.
+    ///
+    /// If the length of `data` is superior to
+    /// [`PAYLOAD_INLINE_MAX`](redshirt_syscalls::PAYLOAD_INLINE_MAX), the data is put in a
+    /// buffer owned by `owner`. See [`Buffers::create`].
+    pub fn make_payload(&self, owner: Pid, data: Vec<u8>) -> Payload {
+        if data.len() <= redshirt_syscalls::PAYLOAD_INLINE_MAX {
+            Payload::Inline(data)
+        } else {
+            Payload::Buffer(self.create(owner, data))
+        }
+    }
+
+    /// Notifies the collection that a process has been started. Must be called before any other
+    /// method is called with this process.
+    pub(crate) fn process_started(&self, pid: Pid) {
+        self.inner.lock().processes.entry(pid).or_insert(0);
+    }
+
+    /// Creates a new buffer of `len` bytes, filled with zeroes, on behalf of `pid`.
+    ///
+    /// Returns an error if the total length of the buffers owned by `pid` would exceed
+    /// [`MAX_PROCESS_BUFFERS_LEN`], or if `pid` isn't alive.
+    pub(crate) fn alloc(&self, pid: Pid, len: usize) -> Result<BufferHandle, QuotaExceededErr> {
+        let mut inner = self.inner.lock();
+        let used = inner.processes.get_mut(&pid).ok_or(QuotaExceededErr)?;
+        match used.checked_add(len) {
+            Some(total) if total <= MAX_PROCESS_BUFFERS_LEN => *used = total,
+            _ => return Err(QuotaExceededErr),
+        }
+
+        let handle = BufferHandle::from(self.id_pool.assign::<u64>());
+        inner.insert(handle, pid, vec![0; len]);
+        Ok(handle)
+    }
+
+    /// Grants `pid` access to the content of the given buffer.
+    ///
+    /// If the buffer has been transferred, `pid` becomes its owner.
+    ///
+    /// Returns an error if the handle is invalid, if the buffer belongs to another process, or if
+    /// claiming it would bring the total length of the buffers owned by `pid` above
+    /// [`MAX_PROCESS_BUFFERS_LEN`].
+    pub(crate) fn access<T>(
+        &self,
+        pid: Pid,
+        handle: BufferHandle,
+        access: impl FnOnce(&mut [u8]) -> T,
+    ) -> Result<T, InvalidBufferErr> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let buffer = inner.buffers.get_mut(&handle).ok_or(InvalidBufferErr)?;
+        if buffer.transferred {
+            let claimer_used = inner.processes.get_mut(&pid).ok_or(InvalidBufferErr)?;
+            match claimer_used.checked_add(buffer.data.len()) {
+                Some(total) if total <= MAX_PROCESS_BUFFERS_LEN => *claimer_used = total,
+                _ => return Err(InvalidBufferErr),
+            }
+            if let Some(used) = inner.processes.get_mut(&buffer.owner) {
+                *used -= buffer.data.len();
+            }
+            buffer.owner = pid;
+            buffer.transferred = false;
+        } else if buffer.owner != pid {
+            return Err(InvalidBufferErr);
+        }
+        Ok(access(&mut buffer.data))
+    }
+
+    /// Relinquishes the ownership of the given buffer by `pid`.
+    ///
+    /// Returns an error if the handle is invalid or if the buffer belongs to another process.
+    pub(crate) fn transfer(&self, pid: Pid, handle: BufferHandle) -> Result<(), InvalidBufferErr> {
+        let mut inner = self.inner.lock();
+        match inner.buffers.get_mut(&handle) {
+            Some(buffer) if buffer.owner == pid && !buffer.transferred => {
+                buffer.transferred = true;
+                Ok(())
+            }
+            _ => Err(InvalidBufferErr),
+        }
+    }
+
+    /// Destroys the given buffer on behalf of `pid`.
+    ///
+    /// Returns an error if the handle is invalid or if the buffer doesn't belong to `pid`.
+    /// Buffers that have been transferred must be claimed before they can be destroyed.
+    pub(crate) fn destroy(&self, pid: Pid, handle: BufferHandle) -> Result<(), InvalidBufferErr> {
+        let mut inner = self.inner.lock();
+        match inner.buffers.get(&handle) {
+            Some(buffer) if buffer.owner == pid && !buffer.transferred => {
+                inner.remove(handle);
+                Ok(())
+            }
+            _ => Err(InvalidBufferErr),
+        }
+    }
+
+    /// Notifies the collection that a process has been destroyed.
+    ///
+    /// Destroys all the buffers owned by this process, and the ones it has transferred but that
+    /// haven't been claimed.
+    pub(crate) fn process_destroyed(&self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        inner.buffers.retain(|_, buffer| buffer.owner != pid);
+        inner.processes.remove(&pid);
+    }
+}
+
+impl Inner {
+    /// Inserts a new buffer in the list. Doesn't update [`Inner::processes`].
+    fn insert(&mut self, handle: BufferHandle, owner: Pid, data: Vec<u8>) {
+        let _prev = self.buffers.insert(
+            handle,
+            Buffer {
+                owner,
+                transferred: false,
+                data,
+            },
+        );
+        debug_assert!(_prev.is_none());
+    }
+
+    /// Removes a buffer from the list and returns its content.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the handle is invalid.
+    fn remove(&mut self, handle: BufferHandle) -> Vec<u8> {
+        let buffer = self.buffers.remove(&handle).unwrap();
+        if let Some(used) = self.processes.get_mut(&buffer.owner) {
+            *used -= buffer.data.len();
+        }
+        buffer.data
+    }
+}
+
+impl Default for Buffers {
+    fn default() -> Self {
+        Buffers::new()
+    }
+}
+
+/// Error when accessing a buffer.
+///
+/// Either the handle is invalid, the buffer belongs to someone else, or claiming it would exceed
+/// the quota of the process.
+#[derive(Debug)]
+pub struct InvalidBufferErr;
+
+impl fmt::Display for InvalidBufferErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid buffer handle")
+    }
+}
+
+/// Error when creating a buffer on behalf of a process.
+///
+/// The process owns too many buffers already.
+#[derive(Debug)]
+pub struct QuotaExceededErr;
+
+impl fmt::Display for QuotaExceededErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Buffers quota exceeded")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Buffers, MAX_PROCESS_BUFFERS_LEN};
+    use redshirt_syscalls::{Payload, Pid};
+
+    /// Builds a collection where the processes with a PID between 1 and 3 are alive.
+    fn buffers() -> Buffers {
+        let buffers = Buffers::new();
+        for pid in 1..=3u64 {
+            buffers.process_started(Pid::from(pid));
+        }
+        buffers
+    }
+
+    #[test]
+    fn owner_only() {
+        let buffers = buffers();
+        let handle = buffers.create(Pid::from(1), vec![1, 2, 3]);
+        assert_eq!(buffers.access(Pid::from(1), handle, |d| d.to_vec()).unwrap(), [1, 2, 3]);
+        assert!(buffers.access(Pid::from(2), handle, |_| ()).is_err());
+        assert!(buffers.transfer(Pid::from(2), handle).is_err());
+        assert!(buffers.destroy(Pid::from(2), handle).is_err());
+        assert!(buffers.take(handle).is_err());
+    }
+
+    #[test]
+    fn transfer_and_claim() {
+        let buffers = buffers();
+        let handle = buffers.create(Pid::from(1), vec![5; 4]);
+        buffers.transfer(Pid::from(1), handle).unwrap();
+        assert!(buffers.transfer(Pid::from(1), handle).is_err());
+
+        buffers.access(Pid::from(2), handle, |d| d[0] = 6).unwrap();
+        assert!(buffers.access(Pid::from(1), handle, |_| ()).is_err());
+        assert!(buffers.access(Pid::from(3), handle, |_| ()).is_err());
+
+        buffers.transfer(Pid::from(2), handle).unwrap();
+        assert_eq!(buffers.take(handle).unwrap(), [6, 5, 5, 5]);
+        assert!(buffers.take(handle).is_err());
+    }
+
+    #[test]
+    fn process_destroyed() {
+        let buffers = buffers();
+        let owned = buffers.create(Pid::from(1), vec![0; 4]);
+        let transferred = buffers.create(Pid::from(1), vec![0; 4]);
+        buffers.transfer(Pid::from(1), transferred).unwrap();
+        let other = buffers.create(Pid::from(2), vec![0; 4]);
+
+        buffers.process_destroyed(Pid::from(1));
+        assert!(buffers.access(Pid::from(1), owned, |_| ()).is_err());
+        assert!(buffers.take(transferred).is_err());
+        assert!(buffers.access(Pid::from(2), other, |_| ()).is_ok());
+    }
+
+    #[test]
+    fn create_for_dead_process() {
+        let buffers = buffers();
+        buffers.process_destroyed(Pid::from(1));
+        let handle = buffers.create(Pid::from(1), vec![0; 4]);
+        assert!(buffers.inner.lock().buffers.is_empty());
+        assert!(buffers.access(Pid::from(1), handle, |_| ()).is_err());
+
+        let handle = buffers.create(Pid::from(4), vec![0; 4]);
+        assert!(buffers.inner.lock().buffers.is_empty());
+        assert!(buffers.access(Pid::from(4), handle, |_| ()).is_err());
+    }
+
+    #[test]
+    fn quota() {
+        let buffers = buffers();
+        let handle = buffers.alloc(Pid::from(1), MAX_PROCESS_BUFFERS_LEN - 4).unwrap();
+        assert!(buffers.alloc(Pid::from(1), 5).is_err());
+        assert!(buffers.alloc(Pid::from(1), usize::max_value()).is_err());
+        let small = buffers.alloc(Pid::from(1), 4).unwrap();
+        assert!(buffers.alloc(Pid::from(1), 1).is_err());
+
+        // Transferred buffers count towards the quota until they are claimed.
+        buffers.transfer(Pid::from(1), handle).unwrap();
+        assert!(buffers.alloc(Pid::from(1), 1).is_err());
+        buffers.access(Pid::from(2), handle, |_| ()).unwrap();
+        assert!(buffers.alloc(Pid::from(2), 5).is_err());
+        buffers.alloc(Pid::from(1), 5).unwrap();
+
+        // Destroying a buffer frees its space.
+        buffers.destroy(Pid::from(1), small).unwrap();
+        buffers.alloc(Pid::from(1), 4).unwrap();
+        buffers.destroy(Pid::from(2), handle).unwrap();
+        buffers.alloc(Pid::from(2), MAX_PROCESS_BUFFERS_LEN).unwrap();
+    }
+
+    #[test]
+    fn claim_over_quota() {
+        let buffers = buffers();
+        let handle = buffers.alloc(Pid::from(1), 8).unwrap();
+        buffers.transfer(Pid::from(1), handle).unwrap();
+        buffers.alloc(Pid::from(2), MAX_PROCESS_BUFFERS_LEN - 4).unwrap();
+
+        // The buffer stays transferred, and charged to the process that has transferred it.
+        assert!(buffers.access(Pid::from(2), handle, |_| ()).is_err());
+        assert!(buffers.alloc(Pid::from(1), MAX_PROCESS_BUFFERS_LEN - 4).is_err());
+        buffers.access(Pid::from(3), handle, |_| ()).unwrap();
+        buffers.alloc(Pid::from(1), MAX_PROCESS_BUFFERS_LEN).unwrap();
+    }
+
+    #[test]
+    fn transferred_not_destroyable() {
+        let buffers = buffers();
+        let handle = buffers.create(Pid::from(1), vec![0; 4]);
+        buffers.transfer(Pid::from(1), handle).unwrap();
+        assert!(buffers.destroy(Pid::from(1), handle).is_err());
+        assert!(buffers.destroy(Pid::from(2), handle).is_err());
+
+        buffers.access(Pid::from(2), handle, |_| ()).unwrap();
+        buffers.destroy(Pid::from(2), handle).unwrap();
+    }
+
+    #[test]
+    fn unknown_process() {
+        let buffers = buffers();
+        assert!(buffers.alloc(Pid::from(4), 4).is_err());
+
+        let handle = buffers.create(Pid::from(1), vec![0; 4]);
+        buffers.transfer(Pid::from(1), handle).unwrap();
+        assert!(buffers.access(Pid::from(4), handle, |_| ()).is_err());
+        assert!(!buffers.inner.lock().processes.contains_key(&Pid::from(4)));
+        assert!(buffers.take(handle).is_ok());
+    }
+
+    #[test]
+    fn payloads() {
+        let buffers = buffers();
+        let small = buffers.make_payload(Pid::from(1), vec![1; 16]);
+        assert_eq!(small, Payload::Inline(vec![1; 16]));
+
+        let large_data = vec![2; redshirt_syscalls::PAYLOAD_INLINE_MAX + 1];
+        let large = buffers.make_payload(Pid::from(1), large_data.clone());
+        let handle = match large {
+            Payload::Buffer(handle) => handle,
+            _ => panic!(),
+        };
+        buffers.transfer(Pid::from(1), handle).unwrap();
+        assert_eq!(buffers.take_payload(Payload::Buffer(handle)).unwrap(), large_data);
+    }
+}
@@ -34,7 +34,8 @@ pub mod wasi;
 /// Trait implemented on types that can handle extrinsics.
 ///
 /// The `Default` trait is used to instantiate structs that implement this trait. One instance is
-/// created for each WASM process.
+/// created for each WASM process, unless one is passed explicitly to
+/// [`Core::execute_with_extrinsics`](crate::scheduler::Core::execute_with_extrinsics).
 // TODO: in this API one can only emit one message at the time; this is fine in terms of logic, but
 // is sub-optimal
 pub trait Extrinsics: Default {
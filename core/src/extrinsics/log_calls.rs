@@ -19,31 +19,84 @@
 use crate::extrinsics::{Extrinsics, ExtrinsicsAction, ExtrinsicsMemoryAccess, SupportedExtrinsic};
 use crate::{EncodedMessage, ThreadId, WasmValue};
 
-use alloc::{borrow::Cow, format, string::String, vec, vec::Vec};
+use alloc::{borrow::Cow, format, string::String, sync::Arc, vec, vec::Vec};
 use core::mem;
 
-/// Implementation of the [`Extrinsics`] trait that logs all calls to the underlying handler.
+mod wasi_decode;
+
+/// Implementation of the [`Extrinsics`] trait that logs calls to the underlying handler.
 ///
 /// Every time a call has finished, a message is sent to the `log` interface containing the
 /// parameters and return value.
 ///
-/// This struct only has access to the low-level signatures of the functions it wraps around, and
-/// therefore cannot, for example, print the content of memory that the inner function reads from
-/// or writes to.
+/// This struct only has access to the low-level signatures of the functions it wraps around.
+/// The only exception is the WASI functions, whose parameters and return values are decoded. For
+/// example, the paths passed to `path_open` or the size of the buffers passed to `fd_write` are
+/// printed, and error codes are printed by name.
 #[derive(Debug)]
 pub struct LogExtrinsics<TInner> {
     /// Actual implementation.
     inner: TInner,
+    /// Which calls to log. If `None`, nothing is logged.
+    config: Option<Arc<LogConfig>>,
+}
+
+/// Configuration of a [`LogExtrinsics`].
+#[derive(Debug, Clone)]
+pub struct LogConfig {
     /// Log level for the messages.
-    log_level: redshirt_log_interface::Level,
+    pub level: redshirt_log_interface::Level,
+
+    /// If `Some`, only the functions in this list are logged.
+    ///
+    /// Each entry is either the name of a function (for example `fd_write`), or the name of a
+    /// function prefixed with the name of its WASM interface (for example
+    /// `wasi_snapshot_preview1::fd_write`).
+    pub functions: Option<Vec<String>>,
+}
+
+impl LogConfig {
+    /// Returns true if calls to the given function must be logged.
+    fn is_logged(&self, id: &ExtrinsicId<impl Sized>) -> bool {
+        match &self.functions {
+            Some(functions) => functions
+                .iter()
+                .any(|f| *f == id.f_name || *f == id.function_name),
+            None => true,
+        }
+    }
+}
+
+impl Default for LogConfig {
+    /// Logs all the calls at the `Trace` level.
+    fn default() -> Self {
+        LogConfig {
+            level: redshirt_log_interface::Level::Trace,
+            functions: None,
+        }
+    }
 }
 
 impl<TInner> LogExtrinsics<TInner> {
-    /// Builds a new [`LogExtrinsics`].
+    /// Builds a new [`LogExtrinsics`] that logs all the calls at the `Trace` level.
     pub fn new(inner: TInner) -> Self {
+        Self::with_config(inner, Arc::new(LogConfig::default()))
+    }
+
+    /// Builds a new [`LogExtrinsics`] that logs the calls according to the given configuration.
+    pub fn with_config(inner: TInner, config: Arc<LogConfig>) -> Self {
+        LogExtrinsics {
+            inner,
+            config: Some(config),
+        }
+    }
+
+    /// Builds a new [`LogExtrinsics`] that doesn't log anything and directly forwards all the
+    /// calls to `inner`.
+    pub fn disabled(inner: TInner) -> Self {
         LogExtrinsics {
             inner,
-            log_level: redshirt_log_interface::Level::Trace,
+            config: None,
         }
     }
 }
@@ -62,6 +115,10 @@ where
 pub struct ExtrinsicId<TInner> {
     /// The function name prefixed with its module name.
     f_name: String,
+    /// The function name alone.
+    function_name: Cow<'static, str>,
+    /// If true, the function is a WASI function whose parameters can be decoded.
+    wasi: bool,
     /// Actual identifier.
     inner: TInner,
 }
@@ -71,6 +128,17 @@ pub struct Context<TInner> {
     /// The inner context.
     inner: TInner,
 
+    /// If false, the call isn't logged and everything is forwarded to the inner context.
+    logged: bool,
+
+    /// Name of the function if it is a WASI function, in which case its return value is
+    /// decoded.
+    wasi_function: Option<Cow<'static, str>>,
+
+    /// Parameters of the call. Used to decode the output of WASI functions. Empty if the call
+    /// isn't logged.
+    params: Vec<WasmValue>,
+
     /// Start of the encoded log message. Only the return value is missing.
     message_start: Vec<u8>,
 
@@ -102,17 +170,44 @@ where
         params: impl ExactSizeIterator<Item = WasmValue>,
         mem_access: &mut impl ExtrinsicsMemoryAccess,
     ) -> (Self::Context, ExtrinsicsAction) {
+        let config = match &self.config {
+            Some(config) if config.is_logged(id) => config,
+            _ => {
+                let (inner, action) =
+                    self.inner
+                        .new_context(thread_id, &id.inner, params, mem_access);
+                let ctxt = Context {
+                    inner,
+                    logged: false,
+                    wasi_function: None,
+                    params: Vec::new(),
+                    message_start: Vec::new(),
+                    waiting_for_log_message: None,
+                };
+                return (ctxt, action);
+            }
+        };
+
         let params = params.collect::<Vec<_>>();
 
         let message_start = {
-            let mut msg = vec![u8::from(self.log_level)];
+            let mut msg = vec![u8::from(config.level)];
             msg.extend(id.f_name.as_bytes());
             msg.extend(b"(");
-            for (n, param) in params.iter().enumerate() {
-                if n != 0 {
-                    msg.extend(b", ");
+            let decoded = if id.wasi {
+                wasi_decode::params(&id.function_name, &params, mem_access)
+            } else {
+                None
+            };
+            if let Some(decoded) = decoded {
+                msg.extend(decoded.as_bytes());
+            } else {
+                for (n, param) in params.iter().enumerate() {
+                    if n != 0 {
+                        msg.extend(b", ");
+                    }
+                    msg.extend(format!("{:?}", param).as_bytes());
                 }
-                msg.extend(format!("{:?}", param).as_bytes());
             }
             msg.extend(b") -> ");
             msg
@@ -120,9 +215,16 @@ where
 
         let (inner_ctxt, action) =
             self.inner
-                .new_context(thread_id, &id.inner, params.into_iter(), mem_access);
+                .new_context(thread_id, &id.inner, params.iter().cloned(), mem_access);
         let mut ctxt = Context {
             inner: inner_ctxt,
+            logged: true,
+            wasi_function: if id.wasi {
+                Some(id.function_name.clone())
+            } else {
+                None
+            },
+            params,
             message_start,
             waiting_for_log_message: None,
         };
@@ -130,7 +232,9 @@ where
         let ret_value_str = match action {
             ExtrinsicsAction::Resume(val) => {
                 ctxt.waiting_for_log_message = Some(ExtrinsicsAction::Resume(val));
-                Cow::Owned(format!("{:?}", val).into_bytes())
+                let wasi_function = ctxt.wasi_function.as_deref();
+                let val_str = return_value_str(wasi_function, &ctxt.params, val, mem_access);
+                Cow::Owned(val_str.into_bytes())
             }
             a @ ExtrinsicsAction::ProgramCrash => {
                 ctxt.waiting_for_log_message = Some(a);
@@ -158,6 +262,12 @@ where
         response: Option<EncodedMessage>,
         mem_access: &mut impl ExtrinsicsMemoryAccess,
     ) -> ExtrinsicsAction {
+        if !ctxt.logged {
+            return self
+                .inner
+                .inject_message_response(&mut ctxt.inner, response, mem_access);
+        }
+
         if let Some(waiting_for_log_message) = ctxt.waiting_for_log_message.take() {
             debug_assert!(response.is_none());
             debug_assert!(ctxt.message_start.is_empty());
@@ -171,7 +281,9 @@ where
             {
                 ExtrinsicsAction::Resume(val) => {
                     ctxt.waiting_for_log_message = Some(ExtrinsicsAction::Resume(val));
-                    Cow::Owned(format!("{:?}", val).into_bytes())
+                    let wasi_function = ctxt.wasi_function.as_deref();
+                    let val_str = return_value_str(wasi_function, &ctxt.params, val, mem_access);
+                    Cow::Owned(val_str.into_bytes())
                 }
                 a @ ExtrinsicsAction::ProgramCrash => {
                     ctxt.waiting_for_log_message = Some(a);
@@ -192,6 +304,18 @@ where
     }
 }
 
+/// Returns the string representation of the return value of a call.
+fn return_value_str(
+    wasi_function: Option<&str>,
+    params: &[WasmValue],
+    value: Option<WasmValue>,
+    mem_access: &impl ExtrinsicsMemoryAccess,
+) -> String {
+    wasi_function
+        .and_then(|function| wasi_decode::result(function, params, value, mem_access))
+        .unwrap_or_else(|| format!("{:?}", value))
+}
+
 impl<TInner, TExtId> Iterator for LogIterator<TInner>
 where
     TInner: Iterator<Item = SupportedExtrinsic<TExtId>>,
@@ -203,6 +327,8 @@ where
 
         let id = ExtrinsicId {
             f_name: format!("{}::{}", item.wasm_interface, item.function_name),
+            function_name: item.function_name.clone(),
+            wasi: item.wasm_interface == wasi_decode::WASM_INTERFACE,
             inner: item.id,
         };
 
@@ -223,3 +349,51 @@ impl<TInner, TExtId> ExactSizeIterator for LogIterator<TInner> where
     TInner: ExactSizeIterator<Item = SupportedExtrinsic<TExtId>>
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ExtrinsicId, LogConfig};
+    use alloc::{borrow::Cow, format, string::String, vec};
+
+    fn id(wasm_interface: &str, function_name: &'static str) -> ExtrinsicId<()> {
+        ExtrinsicId {
+            f_name: format!("{}::{}", wasm_interface, function_name),
+            function_name: Cow::Borrowed(function_name),
+            wasi: false,
+            inner: (),
+        }
+    }
+
+    #[test]
+    fn all_functions_logged_by_default() {
+        let config = LogConfig::default();
+        assert!(config.is_logged(&id("wasi_snapshot_preview1", "fd_write")));
+        assert!(config.is_logged(&id("redshirt", "emit_message")));
+    }
+
+    #[test]
+    fn functions_filter() {
+        let config = LogConfig {
+            level: redshirt_log_interface::Level::Info,
+            functions: Some(vec![
+                String::from("fd_write"),
+                String::from("redshirt::emit_message"),
+            ]),
+        };
+
+        assert!(config.is_logged(&id("wasi_snapshot_preview1", "fd_write")));
+        assert!(config.is_logged(&id("other", "fd_write")));
+        assert!(config.is_logged(&id("redshirt", "emit_message")));
+        assert!(!config.is_logged(&id("other", "emit_message")));
+        assert!(!config.is_logged(&id("wasi_snapshot_preview1", "fd_read")));
+    }
+
+    #[test]
+    fn empty_functions_filter() {
+        let config = LogConfig {
+            level: redshirt_log_interface::Level::Info,
+            functions: Some(vec![]),
+        };
+        assert!(!config.is_logged(&id("wasi_snapshot_preview1", "fd_write")));
+    }
+}
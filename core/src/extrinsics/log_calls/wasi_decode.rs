@@ -0,0 +1,372 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Decoding of the parameters and return values of WASI functions, for the purpose of printing
+//! them.
+//!
+//! Reference for function signatures:
+//! https://github.com/WebAssembly/wasi-libc/blob/e1149ab0677317c6c981bcbb5e4c159e4d2b9669/libc-bottom-half/headers/public/wasi/api.h
+
+use crate::extrinsics::ExtrinsicsMemoryAccess;
+use crate::WasmValue;
+
+use alloc::{format, string::String, vec::Vec};
+use core::{cmp, convert::TryFrom as _};
+
+/// Name of the WASM interface the WASI functions belong to.
+pub const WASM_INTERFACE: &str = "wasi_snapshot_preview1";
+
+/// Maximum number of iovecs whose size is printed.
+const MAX_IOVECS: u32 = 16;
+
+/// Maximum length of a path that is printed.
+const MAX_PATH_LEN: u32 = 1024;
+
+/// Returns a human-readable representation of the parameters of a call to the given WASI
+/// function, or `None` if the function isn't known.
+///
+/// Must be called before the call is performed, as the memory of the process might be modified
+/// by the call.
+pub fn params(
+    function: &str,
+    params: &[WasmValue],
+    mem_access: &impl ExtrinsicsMemoryAccess,
+) -> Option<String> {
+    Some(match (function, params) {
+        ("fd_read", [fd, iovs, iovs_len, _]) | ("fd_write", [fd, iovs, iovs_len, _]) => format!(
+            "fd={}, iovs={}",
+            fd.into_i32()?,
+            iovecs(mem_access, as_u32(iovs)?, as_u32(iovs_len)?)
+        ),
+        ("fd_close", [fd]) | ("fd_fdstat_get", [fd, _]) | ("fd_filestat_get", [fd, _]) => {
+            format!("fd={}", fd.into_i32()?)
+        }
+        ("fd_prestat_get", [fd, _]) | ("fd_tell", [fd, _]) => format!("fd={}", fd.into_i32()?),
+        ("fd_seek", [fd, offset, whence, _]) => format!(
+            "fd={}, offset={}, whence={}",
+            fd.into_i32()?,
+            offset.into_i64()?,
+            whence_name(as_u32(whence)?)
+        ),
+        ("path_open", [fd, _, path, path_len, oflags, _, _, fdflags, _]) => format!(
+            "dirfd={}, path={}, oflags={:#x}, fdflags={:#x}",
+            fd.into_i32()?,
+            string(mem_access, as_u32(path)?, as_u32(path_len)?),
+            as_u32(oflags)?,
+            as_u32(fdflags)?
+        ),
+        ("path_create_directory", [fd, path, path_len])
+        | ("path_filestat_get", [fd, _, path, path_len, _]) => format!(
+            "dirfd={}, path={}",
+            fd.into_i32()?,
+            string(mem_access, as_u32(path)?, as_u32(path_len)?)
+        ),
+        ("clock_time_get", [clock_id, precision, _]) => format!(
+            "clock_id={}, precision={}",
+            clock_name(as_u32(clock_id)?),
+            precision.into_i64()?
+        ),
+        ("random_get", [_, buf_len]) => format!("buf_len={}", as_u32(buf_len)?),
+        ("proc_exit", [code]) => format!("code={}", code.into_i32()?),
+        _ => return None,
+    })
+}
+
+/// Returns a human-readable representation of the return value of a call to the given WASI
+/// function, or `None` if the function isn't known.
+///
+/// Must be called after the call has been performed, with the same parameters as the ones passed
+/// to [`params`].
+pub fn result(
+    function: &str,
+    params: &[WasmValue],
+    value: Option<WasmValue>,
+    mem_access: &impl ExtrinsicsMemoryAccess,
+) -> Option<String> {
+    let errno = u16::try_from(value?.into_i32()?).ok()?;
+    let errno_str = errno_name(errno);
+
+    // Values written back by the function are only meaningful in case of success.
+    if errno != 0 {
+        return Some(String::from(errno_str));
+    }
+
+    let output = match (function, params) {
+        ("fd_read", [_, _, _, out]) => format!("nread={}", read_u32(mem_access, as_u32(out)?)?),
+        ("fd_write", [_, _, _, out]) => {
+            format!("nwritten={}", read_u32(mem_access, as_u32(out)?)?)
+        }
+        ("fd_seek", [_, _, _, out]) | ("fd_tell", [_, out]) => {
+            format!("offset={}", read_u64(mem_access, as_u32(out)?)?)
+        }
+        ("path_open", [_, _, _, _, _, _, _, _, out]) => {
+            format!("fd={}", read_u32(mem_access, as_u32(out)?)?)
+        }
+        ("clock_time_get", [_, _, out]) => format!("time={}", read_u64(mem_access, as_u32(out)?)?),
+        ("fd_prestat_dir_name", [_, path, path_len]) => format!(
+            "path={}",
+            string(mem_access, as_u32(path)?, as_u32(path_len)?)
+        ),
+        ("args_sizes_get", [argc, _]) | ("environ_sizes_get", [argc, _]) => {
+            format!("count={}", read_u32(mem_access, as_u32(argc)?)?)
+        }
+        _ => return Some(String::from(errno_str)),
+    };
+
+    Some(format!("{} ({})", errno_str, output))
+}
+
+/// Returns the value of a pointer or a size passed as parameter.
+fn as_u32(value: &WasmValue) -> Option<u32> {
+    Some(value.into_i32()? as u32)
+}
+
+/// Reads a little-endian `u32` from the memory of the process.
+fn read_u32(mem_access: &impl ExtrinsicsMemoryAccess, ptr: u32) -> Option<u32> {
+    let bytes = mem_access.read_memory(ptr..ptr.checked_add(4)?).ok()?;
+    Some(u32::from_le_bytes(<[u8; 4]>::try_from(&bytes[..]).ok()?))
+}
+
+/// Reads a little-endian `u64` from the memory of the process.
+fn read_u64(mem_access: &impl ExtrinsicsMemoryAccess, ptr: u32) -> Option<u64> {
+    let bytes = mem_access.read_memory(ptr..ptr.checked_add(8)?).ok()?;
+    Some(u64::from_le_bytes(<[u8; 8]>::try_from(&bytes[..]).ok()?))
+}
+
+/// Reads a string from the memory of the process and returns it quoted, or `<invalid>` if the
+/// memory can't be read.
+fn string(mem_access: &impl ExtrinsicsMemoryAccess, ptr: u32, len: u32) -> String {
+    let end = match ptr.checked_add(cmp::min(len, MAX_PATH_LEN)) {
+        Some(end) => end,
+        None => return String::from("<invalid>"),
+    };
+
+    match mem_access.read_memory(ptr..end) {
+        Ok(bytes) if len > MAX_PATH_LEN => format!("{:?}...", String::from_utf8_lossy(&bytes)),
+        Ok(bytes) => format!("{:?}", String::from_utf8_lossy(&bytes)),
+        Err(_) => String::from("<invalid>"),
+    }
+}
+
+/// Reads a list of iovecs from the memory of the process and returns the list of their sizes,
+/// or `<invalid>` if the memory can't be read.
+fn iovecs(mem_access: &impl ExtrinsicsMemoryAccess, ptr: u32, num: u32) -> String {
+    let sizes = (0..cmp::min(num, MAX_IOVECS))
+        .map(|n| {
+            // Each iovec is a pointer followed with a length.
+            let len_ptr = ptr.checked_add(n.checked_mul(8)?)?.checked_add(4)?;
+            read_u32(mem_access, len_ptr)
+        })
+        .collect::<Option<Vec<_>>>();
+
+    match sizes {
+        Some(sizes) if num > MAX_IOVECS => format!("{:?} and {} more", sizes, num - MAX_IOVECS),
+        Some(sizes) => format!("{:?}", sizes),
+        None => String::from("<invalid>"),
+    }
+}
+
+/// Returns the name of a `whence` parameter of `fd_seek`.
+fn whence_name(whence: u32) -> &'static str {
+    match whence {
+        0 => "SET",
+        1 => "CUR",
+        2 => "END",
+        _ => "<invalid>",
+    }
+}
+
+/// Returns the name of a clock identifier.
+fn clock_name(clock_id: u32) -> &'static str {
+    match clock_id {
+        0 => "REALTIME",
+        1 => "MONOTONIC",
+        2 => "PROCESS_CPUTIME_ID",
+        3 => "THREAD_CPUTIME_ID",
+        _ => "<invalid>",
+    }
+}
+
+/// Returns the name of a WASI error code.
+fn errno_name(errno: u16) -> &'static str {
+    const NAMES: [&str; 77] = [
+        "ESUCCESS",
+        "E2BIG",
+        "EACCES",
+        "EADDRINUSE",
+        "EADDRNOTAVAIL",
+        "EAFNOSUPPORT",
+        "EAGAIN",
+        "EALREADY",
+        "EBADF",
+        "EBADMSG",
+        "EBUSY",
+        "ECANCELED",
+        "ECHILD",
+        "ECONNABORTED",
+        "ECONNREFUSED",
+        "ECONNRESET",
+        "EDEADLK",
+        "EDESTADDRREQ",
+        "EDOM",
+        "EDQUOT",
+        "EEXIST",
+        "EFAULT",
+        "EFBIG",
+        "EHOSTUNREACH",
+        "EIDRM",
+        "EILSEQ",
+        "EINPROGRESS",
+        "EINTR",
+        "EINVAL",
+        "EIO",
+        "EISCONN",
+        "EISDIR",
+        "ELOOP",
+        "EMFILE",
+        "EMLINK",
+        "EMSGSIZE",
+        "EMULTIHOP",
+        "ENAMETOOLONG",
+        "ENETDOWN",
+        "ENETRESET",
+        "ENETUNREACH",
+        "ENFILE",
+        "ENOBUFS",
+        "ENODEV",
+        "ENOENT",
+        "ENOEXEC",
+        "ENOLCK",
+        "ENOLINK",
+        "ENOMEM",
+        "ENOMSG",
+        "ENOPROTOOPT",
+        "ENOSPC",
+        "ENOSYS",
+        "ENOTCONN",
+        "ENOTDIR",
+        "ENOTEMPTY",
+        "ENOTRECOVERABLE",
+        "ENOTSOCK",
+        "ENOTSUP",
+        "ENOTTY",
+        "ENXIO",
+        "EOVERFLOW",
+        "EOWNERDEAD",
+        "EPERM",
+        "EPIPE",
+        "EPROTO",
+        "EPROTONOSUPPORT",
+        "EPROTOTYPE",
+        "ERANGE",
+        "EROFS",
+        "ESPIPE",
+        "ESRCH",
+        "ESTALE",
+        "ETIMEDOUT",
+        "ETXTBSY",
+        "EXDEV",
+        "ENOTCAPABLE",
+    ];
+
+    NAMES
+        .get(usize::from(errno))
+        .cloned()
+        .unwrap_or("<unknown errno>")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::extrinsics::{ExtrinsicsMemoryAccess, ExtrinsicsMemoryAccessErr};
+    use crate::WasmValue;
+    use alloc::vec::Vec;
+    use core::{convert::TryFrom as _, ops::Range};
+
+    struct Memory(Vec<u8>);
+
+    impl ExtrinsicsMemoryAccess for Memory {
+        fn read_memory(&self, range: Range<u32>) -> Result<Vec<u8>, ExtrinsicsMemoryAccessErr> {
+            let range = usize::try_from(range.start).unwrap()..usize::try_from(range.end).unwrap();
+            self.0
+                .get(range)
+                .map(|s| s.to_vec())
+                .ok_or(ExtrinsicsMemoryAccessErr::OutOfRange)
+        }
+
+        fn write_memory(&mut self, _: u32, _: &[u8]) -> Result<(), ExtrinsicsMemoryAccessErr> {
+            // Decoding never modifies the memory.
+            Err(ExtrinsicsMemoryAccessErr::OutOfRange)
+        }
+    }
+
+    #[test]
+    fn fd_write() {
+        // Two iovecs of sizes 5 and 3 at offset 0, and the number of bytes written at offset 16.
+        let mut memory = Vec::new();
+        for value in &[100u32, 5, 200, 3, 8] {
+            memory.extend_from_slice(&value.to_le_bytes());
+        }
+        let memory = Memory(memory);
+
+        let params = [
+            WasmValue::I32(1),
+            WasmValue::I32(0),
+            WasmValue::I32(2),
+            WasmValue::I32(16),
+        ];
+        assert_eq!(
+            super::params("fd_write", &params, &memory).unwrap(),
+            "fd=1, iovs=[5, 3]"
+        );
+        assert_eq!(
+            super::result("fd_write", &params, Some(WasmValue::I32(0)), &memory).unwrap(),
+            "ESUCCESS (nwritten=8)"
+        );
+        assert_eq!(
+            super::result("fd_write", &params, Some(WasmValue::I32(8)), &memory).unwrap(),
+            "EBADF"
+        );
+    }
+
+    #[test]
+    fn path_open() {
+        let memory = Memory(b"foo/bar".to_vec());
+        let params = [
+            WasmValue::I32(3),
+            WasmValue::I32(0),
+            WasmValue::I32(0),
+            WasmValue::I32(7),
+            WasmValue::I32(1),
+            WasmValue::I64(0),
+            WasmValue::I64(0),
+            WasmValue::I32(0),
+            WasmValue::I32(1000),
+        ];
+        assert_eq!(
+            super::params("path_open", &params, &memory).unwrap(),
+            "dirfd=3, path=\"foo/bar\", oflags=0x1, fdflags=0x0"
+        );
+        assert_eq!(
+            super::result("path_open", &params, Some(WasmValue::I32(44)), &memory).unwrap(),
+            "ENOENT"
+        );
+    }
+
+    #[test]
+    fn unknown_function() {
+        let memory = Memory(Vec::new());
+        assert!(super::params("foo", &[], &memory).is_none());
+    }
+}
@@ -53,8 +53,8 @@ pub struct WasiExtrinsics {
 
 #[derive(Debug)]
 enum FileDescriptor {
-    /// Valid file descriptor but that points to nothing.
-    Empty,
+    /// Reads from and writes to the console interface.
+    Console,
     LogOut {
         /// We buffer data and emit a log message only on line splits.
         buffer: Vec<u8>,
@@ -98,12 +98,9 @@ impl Default for WasiExtrinsics {
             env_vars: vec![b"HOME=/home".to_vec()], // TODO: dummy
             file_descriptors: Spinlock::new(vec![
                 // stdin
-                Some(FileDescriptor::Empty),
+                Some(FileDescriptor::Console),
                 // stdout
-                Some(FileDescriptor::LogOut {
-                    level: redshirt_log_interface::Level::Info,
-                    buffer: Vec::new(),
-                }),
+                Some(FileDescriptor::Console),
                 // stderr
                 Some(FileDescriptor::LogOut {
                     level: redshirt_log_interface::Level::Error,
@@ -155,6 +152,13 @@ pub struct Context(ContextInner);
 
 enum ContextInner {
     WaitClockVal { out_ptr: u32 },
+    /// Waiting for the answer to a [`redshirt_console_interface::ffi::ConsoleMessage::Read`].
+    WaitConsoleRead {
+        /// Pointers and lengths of the buffers to write to, alternating.
+        out_buffers_list: Vec<u32>,
+        /// Where to write the number of bytes that have been read.
+        out_ptr: u32,
+    },
     WaitRandom { out_ptr: u32, remaining_len: u32 },
     TryFlushLogOut(usize),
     Resume(Option<WasmValue>),
@@ -414,6 +418,48 @@ impl Extrinsics for WasiExtrinsics {
                     }
                 }
             }
+            ContextInner::WaitConsoleRead {
+                ref out_buffers_list,
+                out_ptr,
+            } => {
+                let response = response.unwrap();
+                let data: Vec<u8> = match response.decode() {
+                    Ok(v) => v,
+                    Err(_) => return ExtrinsicsAction::ProgramCrash,
+                };
+
+                let mut remaining = &data[..];
+                for buffer in out_buffers_list.chunks(2) {
+                    let buffer_len = usize::try_from(buffer[1]).unwrap_or(usize::max_value());
+                    let to_copy = cmp::min(remaining.len(), buffer_len);
+                    if to_copy == 0 {
+                        break;
+                    }
+                    if mem_access
+                        .write_memory(buffer[0], &remaining[..to_copy])
+                        .is_err()
+                    {
+                        return ExtrinsicsAction::ProgramCrash;
+                    }
+                    remaining = &remaining[to_copy..];
+                }
+
+                // The handler isn't supposed to return more than what we asked for.
+                if !remaining.is_empty() {
+                    return ExtrinsicsAction::ProgramCrash;
+                }
+
+                let total_read = u32::try_from(data.len()).unwrap();
+                if mem_access
+                    .write_memory(out_ptr, &total_read.to_le_bytes())
+                    .is_err()
+                {
+                    return ExtrinsicsAction::ProgramCrash;
+                }
+
+                ctxt.0 = ContextInner::Finished;
+                ExtrinsicsAction::Resume(Some(WasmValue::I32(0)))
+            }
             ContextInner::TryFlushLogOut(fd) => {
                 let mut file_descriptors_lock = self.file_descriptors.lock();
                 let file_descriptor = {
@@ -643,10 +689,12 @@ fn fd_fdstat_get(
         | wasi::RIGHTS_POLL_FD_READWRITE;
 
     let stat = match file_descriptor {
-        FileDescriptor::Empty => wasi::Fdstat {
+        FileDescriptor::Console => wasi::Fdstat {
             fs_filetype: wasi::FILETYPE_CHARACTER_DEVICE,
-            fs_flags: 0,
-            fs_rights_base: 0,
+            fs_flags: wasi::FDFLAGS_APPEND,
+            fs_rights_base: wasi::RIGHTS_FD_READ
+                | wasi::RIGHTS_FD_WRITE
+                | wasi::RIGHTS_POLL_FD_READWRITE,
             fs_rights_inheriting: 0,
         },
         FileDescriptor::LogOut { .. } => wasi::Fdstat {
@@ -749,7 +797,7 @@ fn fd_prestat_dir_name(
     };
 
     let name = match file_descriptor {
-        FileDescriptor::Empty | FileDescriptor::LogOut { .. } => {
+        FileDescriptor::Console | FileDescriptor::LogOut { .. } => {
             // TODO: is that the correct return type?
             let ret = Some(WasmValue::I32(From::from(wasi::ERRNO_BADF)));
             let action = ExtrinsicsAction::Resume(ret);
@@ -795,7 +843,7 @@ fn fd_prestat_get(
     };
 
     let pr_name_len: u32 = match file_descriptor {
-        FileDescriptor::Empty | FileDescriptor::LogOut { .. } => {
+        FileDescriptor::Console | FileDescriptor::LogOut { .. } => {
             let ret = Some(WasmValue::I32(From::from(wasi::ERRNO_NOTSUP)));
             let action = ExtrinsicsAction::Resume(ret);
             return Ok((ContextInner::Finished, action));
@@ -861,7 +909,11 @@ fn fd_read(
     let out_buffers_list = {
         let addr = u32::try_from(params.next().unwrap().into_i32().unwrap())?;
         let num = u32::try_from(params.next().unwrap().into_i32().unwrap())?;
-        let list_buf = mem_access.read_memory(addr..addr + 4 * num * 2)?;
+        let list_end = num
+            .checked_mul(4 * 2)
+            .and_then(|len| addr.checked_add(len))
+            .ok_or(WasiCallErr)?;
+        let list_buf = mem_access.read_memory(addr..list_end)?;
         // TODO: don't panic if allocation size is too large
         let mut list_out = Vec::with_capacity(usize::try_from(num)?);
         for elem in list_buf.chunks(4) {
@@ -871,7 +923,31 @@ fn fd_read(
     };
 
     let total_read: u32 = match &mut file_descriptor {
-        FileDescriptor::Empty | FileDescriptor::LogOut { .. } => 0,
+        FileDescriptor::Console => {
+            let out_ptr = u32::try_from(params.next().unwrap().into_i32().unwrap())?;
+            assert!(params.next().is_none());
+
+            let max_len = out_buffers_list
+                .chunks(2)
+                .fold(0u32, |total, buffer| total.saturating_add(buffer[1]));
+            if max_len == 0 {
+                mem_access.write_memory(out_ptr, &0u32.to_le_bytes())?;
+                let action = ExtrinsicsAction::Resume(Some(WasmValue::I32(0)));
+                return Ok((ContextInner::Finished, action));
+            }
+
+            let action = ExtrinsicsAction::EmitMessage {
+                interface: redshirt_console_interface::ffi::INTERFACE,
+                message: redshirt_console_interface::ffi::ConsoleMessage::Read { max_len }.encode(),
+                response_expected: true,
+            };
+            let context = ContextInner::WaitConsoleRead {
+                out_buffers_list,
+                out_ptr,
+            };
+            return Ok((context, action));
+        }
+        FileDescriptor::LogOut { .. } => 0,
         FileDescriptor::FilesystemEntry {
             inode,
             file_cursor_pos,
@@ -946,7 +1022,7 @@ fn fd_seek(
     let whence = u8::try_from(params.next().unwrap().into_i32().unwrap())?;
 
     let new_offset: u64 = match &mut file_descriptor {
-        FileDescriptor::Empty | FileDescriptor::LogOut { .. } => {
+        FileDescriptor::Console | FileDescriptor::LogOut { .. } => {
             // TODO: is that the correct error?
             let ret = Some(WasmValue::I32(From::from(wasi::ERRNO_BADF)));
             let action = ExtrinsicsAction::Resume(ret);
@@ -1021,7 +1097,11 @@ fn fd_write(
     let list_to_write = {
         let addr = u32::try_from(params.next().unwrap().into_i32().unwrap())?;
         let num = u32::try_from(params.next().unwrap().into_i32().unwrap())?;
-        let list_buf = mem_access.read_memory(addr..addr + 4 * num * 2)?;
+        let list_end = num
+            .checked_mul(4 * 2)
+            .and_then(|len| addr.checked_add(len))
+            .ok_or(WasiCallErr)?;
+        let list_buf = mem_access.read_memory(addr..list_end)?;
         // TODO: don't panic if allocation size is too large
         let mut list_out = Vec::with_capacity(usize::try_from(num)?);
         for elem in list_buf.chunks(4) {
@@ -1031,19 +1111,44 @@ fn fd_write(
     };
 
     match file_descriptor {
-        FileDescriptor::Empty => {
-            // TODO: is that the right error code?
-            let ret = Some(WasmValue::I32(From::from(wasi::ERRNO_NOSYS)));
-            let action = ExtrinsicsAction::Resume(ret);
-            Ok((ContextInner::Finished, action))
+        FileDescriptor::Console => {
+            let mut data = Vec::new();
+            for ptr_and_len in list_to_write.chunks(2) {
+                let ptr = ptr_and_len[0];
+                let len = ptr_and_len[1];
+                let end = ptr.checked_add(len).ok_or(WasiCallErr)?;
+                data.extend(mem_access.read_memory(ptr..end)?);
+            }
+
+            // Write to the fourth parameter the number of bytes written to the file descriptor.
+            {
+                let out_ptr = u32::try_from(params.next().unwrap().into_i32().unwrap())?;
+                let total_written = u32::try_from(data.len())?;
+                mem_access.write_memory(out_ptr, &total_written.to_le_bytes())?;
+            }
+
+            assert!(params.next().is_none());
+
+            if data.is_empty() {
+                let action = ExtrinsicsAction::Resume(Some(WasmValue::I32(0)));
+                return Ok((ContextInner::Finished, action));
+            }
+
+            let action = ExtrinsicsAction::EmitMessage {
+                interface: redshirt_console_interface::ffi::INTERFACE,
+                message: redshirt_console_interface::ffi::ConsoleMessage::Write(data).encode(),
+                response_expected: false,
+            };
+            Ok((ContextInner::Resume(Some(WasmValue::I32(0))), action))
         }
         FileDescriptor::LogOut { level, buffer } => {
             let mut total_written = 0usize;
             for ptr_and_len in list_to_write.chunks(2) {
                 let ptr = ptr_and_len[0];
                 let len = ptr_and_len[1];
+                let end = ptr.checked_add(len).ok_or(WasiCallErr)?;
 
-                buffer.extend(mem_access.read_memory(ptr..ptr + len)?);
+                buffer.extend(mem_access.read_memory(ptr..end)?);
                 total_written = total_written.checked_add(usize::try_from(len)?)?;
             }
 
@@ -1100,7 +1205,7 @@ fn path_filestat_get(
     };
 
     let fd_inode = match file_descriptor {
-        FileDescriptor::Empty | FileDescriptor::LogOut { .. } => {
+        FileDescriptor::Console | FileDescriptor::LogOut { .. } => {
             // TODO: is that the correct return type?
             let ret = Some(WasmValue::I32(From::from(wasi::ERRNO_BADF)));
             let action = ExtrinsicsAction::Resume(ret);
@@ -1199,7 +1304,7 @@ fn path_open(
     };
 
     let fd_inode = match file_descriptor {
-        FileDescriptor::Empty | FileDescriptor::LogOut { .. } => {
+        FileDescriptor::Console | FileDescriptor::LogOut { .. } => {
             // TODO: is that the correct return type?
             let ret = Some(WasmValue::I32(From::from(wasi::ERRNO_BADF)));
             let action = ExtrinsicsAction::Resume(ret);
@@ -1426,3 +1531,155 @@ fn resolve_path(root: &Arc<Inode>, path: &str) -> Option<Arc<Inode>> {
 
     Some(current)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ExtrinsicId, ExtrinsicIdInner, WasiExtrinsics};
+    use crate::extrinsics::{
+        Extrinsics, ExtrinsicsAction, ExtrinsicsMemoryAccess, ExtrinsicsMemoryAccessErr,
+    };
+    use crate::{Encode as _, ThreadId, WasmValue};
+    use alloc::{vec, vec::Vec};
+    use core::{convert::TryFrom as _, ops::Range};
+
+    struct Memory(Vec<u8>);
+
+    impl ExtrinsicsMemoryAccess for Memory {
+        fn read_memory(&self, range: Range<u32>) -> Result<Vec<u8>, ExtrinsicsMemoryAccessErr> {
+            let range = usize::try_from(range.start).unwrap()..usize::try_from(range.end).unwrap();
+            self.0
+                .get(range)
+                .map(|s| s.to_vec())
+                .ok_or(ExtrinsicsMemoryAccessErr::OutOfRange)
+        }
+
+        fn write_memory(
+            &mut self,
+            offset: u32,
+            data: &[u8],
+        ) -> Result<(), ExtrinsicsMemoryAccessErr> {
+            let start = usize::try_from(offset).unwrap();
+            self.0
+                .get_mut(start..start + data.len())
+                .ok_or(ExtrinsicsMemoryAccessErr::OutOfRange)?
+                .copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    /// Builds a memory containing a single iovec, at offset 0, pointing to `buffer_ptr` and
+    /// of length `buffer_len`.
+    fn memory(buffer_ptr: u32, buffer_len: u32) -> Memory {
+        let mut memory = vec![0; 64];
+        memory[0..4].copy_from_slice(&buffer_ptr.to_le_bytes());
+        memory[4..8].copy_from_slice(&buffer_len.to_le_bytes());
+        Memory(memory)
+    }
+
+    /// Calls `fd_read` on the standard input with the iovec at offset 0, then injects
+    /// `console_data` as the response of the console.
+    fn fd_read_console(
+        memory: &mut Memory,
+        out_ptr: u32,
+        console_data: Vec<u8>,
+    ) -> ExtrinsicsAction {
+        let extrinsics = WasiExtrinsics::default();
+        let params = vec![
+            WasmValue::I32(0),
+            WasmValue::I32(0),
+            WasmValue::I32(1),
+            WasmValue::I32(i32::try_from(out_ptr).unwrap()),
+        ];
+        let (mut context, action) = extrinsics.new_context(
+            ThreadId::from(1u64),
+            &ExtrinsicId(ExtrinsicIdInner::FdRead),
+            params.into_iter(),
+            memory,
+        );
+        match action {
+            ExtrinsicsAction::EmitMessage {
+                response_expected: true,
+                ..
+            } => {}
+            _ => panic!(),
+        }
+
+        extrinsics.inject_message_response(&mut context, Some(console_data.encode()), memory)
+    }
+
+    #[test]
+    fn console_read() {
+        let mut memory = memory(32, 8);
+        match fd_read_console(&mut memory, 16, vec![1, 2, 3]) {
+            ExtrinsicsAction::Resume(Some(WasmValue::I32(0))) => {}
+            _ => panic!(),
+        }
+        assert_eq!(&memory.0[32..35], &[1, 2, 3]);
+        assert_eq!(&memory.0[16..20], &3u32.to_le_bytes());
+    }
+
+    #[test]
+    fn console_read_bad_iovec() {
+        let mut memory = memory(1000, 8);
+        match fd_read_console(&mut memory, 16, vec![1, 2, 3]) {
+            ExtrinsicsAction::ProgramCrash => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn console_read_bad_out_ptr() {
+        let mut memory = memory(32, 8);
+        match fd_read_console(&mut memory, 1000, vec![1, 2, 3]) {
+            ExtrinsicsAction::ProgramCrash => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn bad_iovec_list() {
+        let extrinsics = WasiExtrinsics::default();
+        let mut memory = Memory(vec![0; 64]);
+        let params = vec![
+            WasmValue::I32(0),
+            WasmValue::I32(60),
+            WasmValue::I32(i32::max_value()),
+            WasmValue::I32(16),
+        ];
+        let (_, action) = extrinsics.new_context(
+            ThreadId::from(1u64),
+            &ExtrinsicId(ExtrinsicIdInner::FdRead),
+            params.into_iter(),
+            &mut memory,
+        );
+        match action {
+            ExtrinsicsAction::ProgramCrash => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn write_overflowing_iovec() {
+        // Standard output and standard error.
+        for fd in &[1, 2] {
+            let extrinsics = WasiExtrinsics::default();
+            let mut memory = memory(u32::max_value() - 4, 8);
+            let params = vec![
+                WasmValue::I32(*fd),
+                WasmValue::I32(0),
+                WasmValue::I32(1),
+                WasmValue::I32(16),
+            ];
+            let (_, action) = extrinsics.new_context(
+                ThreadId::from(1u64),
+                &ExtrinsicId(ExtrinsicIdInner::FdWrite),
+                params.into_iter(),
+                &mut memory,
+            );
+            match action {
+                ExtrinsicsAction::ProgramCrash => {}
+                _ => panic!(),
+            }
+        }
+    }
+}
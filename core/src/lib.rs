@@ -109,6 +109,18 @@
 //! to somehow report to the user the list of programs being stuck waiting for an interface
 //! handler.
 //!
+//! # Authorizations
+//!
+//! Programs are by default banned from using interfaces that check authorizations, and must be
+//! whitelisted. The [`System`] holds an [`Authorizations`] table that the interface handlers
+//! can query. See [the `authorizations` module](authorizations) for more information.
+//!
+//! # Buffers
+//!
+//! Bulk data can be passed between programs through buffers owned by the kernel, rather than by
+//! copying it within messages. The [`System`] holds a [`Buffers`] collection that is shared with
+//! the native programs. See [the `buffers` module](buffers) for more information.
+//!
 
 #![warn(missing_docs)]
 //#![deny(unsafe_code)] // TODO: 🤷
@@ -119,7 +131,10 @@
 
 extern crate alloc;
 
+pub use self::authorizations::Authorizations;
+pub use self::buffers::Buffers;
 pub use self::module::Module;
+pub use self::process_info::ProcessesInfo;
 pub use self::system::{System, SystemBuilder, SystemRunOutcome};
 pub use primitives::{ValueType, WasmValue};
 pub use redshirt_syscalls::{
@@ -157,9 +172,12 @@ macro_rules! from_wat {
 
 mod id_pool;
 
+pub mod authorizations;
+pub mod buffers;
 pub mod extrinsics;
 pub mod module;
 pub mod native;
 pub mod primitives;
+pub mod process_info;
 pub mod scheduler;
 pub mod system;
@@ -13,7 +13,8 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use core::fmt;
+use alloc::string::String;
+use core::{convert::TryFrom as _, fmt, str};
 
 /// Represents a successfully-parsed binary.
 ///
@@ -22,12 +23,23 @@ use core::fmt;
 pub struct Module {
     inner: wasmi::Module,
     hash: ModuleHash,
+    name: Option<String>,
 }
 
 /// Hash of a module.
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ModuleHash([u8; 32]);
 
+/// Signature of a [`ModuleHash`] by the publisher of the module.
+///
+/// Contains an Ed25519 public key and an Ed25519 signature of the hash. A valid signature proves
+/// that the owner of the private key corresponding to the public key has published the module.
+#[derive(Clone)]
+pub struct ModuleSignature {
+    public_key: [u8; 32],
+    signature: [u8; 64],
+}
+
 /// Error that can happen when calling [`ModuleHash::from_bytes`].
 #[derive(Debug)]
 pub struct FromBytesError {}
@@ -40,9 +52,10 @@ impl Module {
     /// Parses a module from WASM bytes.
     pub fn from_bytes(buffer: impl AsRef<[u8]>) -> Result<Self, FromBytesError> {
         let inner = wasmi::Module::from_buffer(buffer.as_ref()).map_err(|_| FromBytesError {})?;
+        let name = parse_module_name(buffer.as_ref());
         let hash = ModuleHash::from_bytes(buffer);
 
-        Ok(Module { inner, hash })
+        Ok(Module { inner, hash, name })
     }
 
     /// Returns a reference to the internal module.
@@ -56,6 +69,11 @@ impl Module {
     pub fn hash(&self) -> &ModuleHash {
         &self.hash
     }
+
+    /// Returns the name of the module found in its `name` custom section, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|n| &n[..])
+    }
 }
 
 impl From<[u8; 32]> for ModuleHash {
@@ -97,12 +115,60 @@ impl ModuleHash {
     }
 }
 
+impl ModuleSignature {
+    /// Builds a signature from its components.
+    ///
+    /// Returns `None` if `signature` isn't 64 bytes long.
+    pub fn from_parts(public_key: [u8; 32], signature: &[u8]) -> Option<Self> {
+        if signature.len() != 64 {
+            return None;
+        }
+
+        let mut sig = [0; 64];
+        sig.copy_from_slice(signature);
+        Some(ModuleSignature {
+            public_key,
+            signature: sig,
+        })
+    }
+
+    /// Returns the public key of the publisher.
+    pub fn public_key(&self) -> &[u8; 32] {
+        &self.public_key
+    }
+
+    /// Returns true if this is a valid signature of the given hash.
+    pub fn verify(&self, hash: &ModuleHash) -> bool {
+        let public_key = match ed25519_dalek::PublicKey::from_bytes(&self.public_key) {
+            Ok(k) => k,
+            Err(_) => return false,
+        };
+        let signature = match ed25519_dalek::Signature::from_bytes(&self.signature) {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        public_key.verify(&hash.0, &signature).is_ok()
+    }
+}
+
+impl fmt::Debug for ModuleSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ModuleSignature({})", bs58::encode(&self.public_key).into_string())
+    }
+}
+
 impl fmt::Debug for ModuleHash {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "ModuleHash({})", bs58::encode(&self.0).into_string())
     }
 }
 
+impl fmt::Display for ModuleHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", bs58::encode(&self.0).into_string())
+    }
+}
+
 impl fmt::Display for FromBase58Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "FromBase58Error")
@@ -115,8 +181,70 @@ impl fmt::Display for FromBytesError {
     }
 }
 
+/// Extracts the module name from the `name` custom section of the given WASM binary.
+///
+/// Returns `None` if there is no such section or if it is malformed. See
+/// https://webassembly.github.io/spec/core/appendix/custom.html#name-section.
+fn parse_module_name(bytes: &[u8]) -> Option<String> {
+    // Skip the magic number and the version.
+    let mut sections = bytes.get(8..)?;
+
+    while !sections.is_empty() {
+        let section_id = sections[0];
+        sections = &sections[1..];
+        let section_size = read_leb128(&mut sections)?;
+        let mut payload = sections.get(..section_size)?;
+        sections = &sections[section_size..];
+
+        // Only custom sections (whose ID is 0) are interesting.
+        if section_id != 0 {
+            continue;
+        }
+
+        let section_name_len = read_leb128(&mut payload)?;
+        if payload.get(..section_name_len)? != b"name" {
+            continue;
+        }
+        payload = &payload[section_name_len..];
+
+        // The `name` section is made of subsections. The module name is subsection 0.
+        while !payload.is_empty() {
+            let subsection_id = payload[0];
+            payload = &payload[1..];
+            let subsection_size = read_leb128(&mut payload)?;
+            let mut subsection = payload.get(..subsection_size)?;
+            payload = &payload[subsection_size..];
+
+            if subsection_id == 0 {
+                let name_len = read_leb128(&mut subsection)?;
+                let name = str::from_utf8(subsection.get(..name_len)?).ok()?;
+                return Some(String::from(name));
+            }
+        }
+    }
+
+    None
+}
+
+/// Reads an unsigned LEB128-encoded 32 bits number at the start of `data`, and updates `data`
+/// to point after it.
+fn read_leb128(data: &mut &[u8]) -> Option<usize> {
+    let mut out: u64 = 0;
+    for n in 0..5 {
+        let byte = *data.get(n)?;
+        out |= u64::from(byte & 0x7f) << (7 * n);
+        if byte & 0x80 == 0 {
+            *data = &data[n + 1..];
+            return usize::try_from(out).ok();
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{Module, ModuleHash, ModuleSignature};
+
     #[test]
     fn empty_wat_works() {
         let _ = from_wat!(local, "(module)");
@@ -136,4 +264,31 @@ mod tests {
             "#
         );
     }
+
+    #[test]
+    fn module_name() {
+        let module = Module::from_bytes(wat_to_bin!("(module $foo)")).unwrap();
+        assert_eq!(module.name(), Some("foo"));
+
+        let module = Module::from_bytes(wat_to_bin!("(module)")).unwrap();
+        assert_eq!(module.name(), None);
+    }
+
+    #[test]
+    fn signature_verify() {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[7; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        let hash = ModuleHash::from([1; 32]);
+        let signature = ed25519_dalek::ExpandedSecretKey::from(&secret).sign(&[1; 32], &public);
+
+        let signature =
+            ModuleSignature::from_parts(public.to_bytes(), &signature.to_bytes()[..]).unwrap();
+        assert!(signature.verify(&hash));
+        assert!(!signature.verify(&ModuleHash::from([2; 32])));
+    }
+
+    #[test]
+    fn signature_wrong_length() {
+        assert!(ModuleSignature::from_parts([0; 32], &[0; 63]).is_none());
+    }
 }
@@ -250,8 +250,9 @@ where
             }) => {
                 if interface == redshirt_interface_interface::ffi::INTERFACE {
                     // TODO: check whether registration succeeds, but hard if `message_id_write` is `None
-                    if let Ok(msg) = InterfaceMessage::decode(message.clone()) {
-                        let InterfaceMessage::Register(to_reg) = msg;
+                    if let Ok(InterfaceMessage::Register(to_reg)) =
+                        InterfaceMessage::decode(message.clone())
+                    {
                         let mut registered_interfaces = self.registered_interfaces.lock();
                         registered_interfaces.insert(to_reg);
                     }
@@ -0,0 +1,83 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Information about the processes running in a [`System`](crate::System).
+//!
+//! A [`ProcessesInfo`] table is shared between the [`System`](crate::System) and whoever wants
+//! to show information about processes, such as the handler of the `log` interface. The
+//! [`System`](crate::System) keeps it up to date as processes start and stop.
+
+use crate::module::ModuleHash;
+
+use alloc::string::String;
+use hashbrown::HashMap;
+use nohash_hasher::BuildNoHashHasher;
+use redshirt_syscalls::Pid;
+use spinning_top::Spinlock;
+
+/// Table of information about the running processes.
+pub struct ProcessesInfo {
+    /// Information about each running process.
+    by_pid: Spinlock<HashMap<Pid, ProcessInfo, BuildNoHashHasher<u64>>>,
+}
+
+/// Information about a process.
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    /// Hash of the module the process has been started from.
+    pub module_hash: ModuleHash,
+    /// Human-readable name of the process, if known.
+    ///
+    /// Either passed when starting the process, or taken from the `name` custom section of the
+    /// module.
+    pub name: Option<String>,
+    /// Process whose actions have led to this process being started, if any.
+    pub parent: Option<Pid>,
+    /// Value of the clock passed to [`SystemBuilder::with_clock`](crate::SystemBuilder::with_clock)
+    /// when the process has been started, or `None` if no clock has been passed.
+    pub start_time: Option<u64>,
+}
+
+impl ProcessesInfo {
+    /// Builds a new empty table.
+    pub fn new() -> Self {
+        ProcessesInfo {
+            by_pid: Spinlock::new(HashMap::default()),
+        }
+    }
+
+    /// Returns information about the given process, or `None` if it isn't running.
+    pub fn get(&self, pid: Pid) -> Option<ProcessInfo> {
+        self.by_pid.lock().get(&pid).cloned()
+    }
+
+    /// Stores the information about a process that is being started. Must be called before the
+    /// process can run.
+    pub(crate) fn insert(&self, pid: Pid, info: ProcessInfo) {
+        self.by_pid.lock().insert(pid, info);
+    }
+
+    /// Notifies the table that a process has been destroyed. Returns the information about it,
+    /// or `None` if the process is unknown.
+    pub(crate) fn process_destroyed(&self, pid: Pid) -> Option<ProcessInfo> {
+        self.by_pid.lock().remove(&pid)
+    }
+}
+
+impl Default for ProcessesInfo {
+    fn default() -> Self {
+        ProcessesInfo::new()
+    }
+}
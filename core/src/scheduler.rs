@@ -23,5 +23,8 @@ mod processes;
 mod tests;
 mod vm;
 
-pub use self::ipc::{Core, CoreBuilder, CoreProcess, CoreRunOutcome};
+pub use self::extrinsics::ThreadState;
+pub use self::ipc::{
+    Core, CoreBuilder, CoreProcess, CoreRunOutcome, InterfaceState, TraceEvent, TraceEventKind,
+};
 pub use self::vm::NewErr;
@@ -31,7 +31,10 @@
 //! - Interrupted threads are more strongly typed and are split into two categories: threads that
 //! are interrupted because they want to emit a message, and threads that are interrupted because
 //! they are waiting for a notification.
+//!
+//! - Calls to the functions that manipulate [`Buffers`] are entirely handled by this module.
 
+use crate::buffers::Buffers;
 use crate::extrinsics::{
     Extrinsics, ExtrinsicsAction, ExtrinsicsMemoryAccess, ExtrinsicsMemoryAccessErr,
 };
@@ -40,7 +43,7 @@ use crate::scheduler::{processes, vm};
 use crate::sig;
 use crate::{InterfaceHash, MessageId};
 
-use alloc::vec::Vec;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use core::{convert::TryFrom as _, fmt, iter, mem, ops::Range};
 use crossbeam_queue::SegQueue;
 use redshirt_syscalls::{EncodedMessage, Pid, ThreadId};
@@ -69,11 +72,16 @@ pub struct ProcessesCollectionExtrinsics<TPud, TTud, TExt: Extrinsics> {
     /// or the [`LocalThreadState::OtherExtrinsicReportWait`] state.
     // TODO: we have to notify wakers when we push an element
     local_run_queue: SegQueue<ThreadId>,
+
+    /// Buffers accessed through the `buffer_*` functions.
+    buffers: Arc<Buffers>,
 }
 
 /// Prototype for a `ProcessesCollectionExtrinsics` under construction.
 pub struct Builder<TExt: Extrinsics> {
     inner: processes::ProcessesCollectionBuilder<Extrinsic<TExt::ExtrinsicId>>,
+    /// See [`ProcessesCollectionExtrinsics::buffers`].
+    buffers: Arc<Buffers>,
 }
 
 /// Access to a process within the collection.
@@ -146,6 +154,23 @@ pub trait ThreadAccessAccess<'a> {
     fn user_data(&mut self) -> &mut Self::ThreadUserData;
 }
 
+/// State of a thread, as reported by [`ProcAccess::threads`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThreadState {
+    /// Thread is ready to run and waiting for its turn.
+    Ready,
+    /// Thread is currently running, or a call that it has made is being processed.
+    Running,
+    /// Thread is sleeping and waiting for a notification to come.
+    WaitNotification,
+    /// Thread wants to emit a message on the given interface, and is waiting for it to be
+    /// registered.
+    EmitMessage(InterfaceHash),
+    /// Thread is in the middle of a call to an extrinsic and is waiting for the answer to a
+    /// message.
+    WaitExtrinsic,
+}
+
 /// Error that can happen when calling `interrupted_thread_by_id`.
 #[derive(Debug)]
 pub enum ThreadByIdErr {
@@ -163,6 +188,7 @@ enum Extrinsic<TExtId> {
     EmitMessageError,
     EmitAnswer,
     CancelMessage,
+    Buffer(calls::BufferFunction),
     Other(TExtId),
 }
 
@@ -341,14 +367,21 @@ where
     ///
     /// A single main thread (whose user data is passed by parameter) is automatically created and
     /// is paused at the start of the "_start" function of the module.
+    ///
+    /// The calls to extrinsics made by the process are handled by `extrinsics`.
+    ///
+    /// `before_start` is called with the [`Pid`] of the new process before any of its threads can
+    /// run.
     pub fn execute(
         &self,
         module: &Module,
+        extrinsics: TExt,
         proc_user_data: TPud,
         main_thread_user_data: TTud,
+        before_start: impl FnOnce(Pid),
     ) -> Result<(ProcAccess<TPud, TTud, TExt>, ThreadId), vm::NewErr> {
         let proc_user_data = LocalProcessUserData {
-            extrinsics: Default::default(),
+            extrinsics,
             external_user_data: proc_user_data,
         };
         let main_thread_user_data = LocalThreadUserData {
@@ -357,7 +390,10 @@ where
         };
         let (inner, main_tid) =
             self.inner
-                .execute(module, proc_user_data, main_thread_user_data)?;
+                .execute(module, proc_user_data, main_thread_user_data, |pid| {
+                    self.buffers.process_started(pid);
+                    before_start(pid)
+                })?;
         Ok((
             ProcAccess {
                 parent: self,
@@ -599,6 +635,25 @@ where
                 }
             }
 
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::Buffer(function),
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let outcome = calls::parse_extrinsic_buffer(&mut thread, function, params)
+                    .map_err(|_| ())
+                    .and_then(|call| apply_buffer_call(&self.buffers, &mut thread, call));
+                match outcome {
+                    Ok(success) => {
+                        let ret = if success { 0 } else { 1 };
+                        thread.resume(Some(crate::WasmValue::I32(ret)));
+                    }
+                    Err(()) => thread.process().abort(),
+                }
+                None
+            }
+
             processes::RunOneOutcome::Interrupted {
                 ref mut thread,
                 id: Extrinsic::Other(ext_id),
@@ -635,6 +690,19 @@ where
         })
     }
 
+    /// Returns an iterator to all the processes that exist in the collection.
+    ///
+    /// This is equivalent to calling [`ProcessesCollectionExtrinsics::process_by_id`] for each
+    /// possible ID.
+    pub fn processes<'a>(
+        &'a self,
+    ) -> impl ExactSizeIterator<Item = ProcAccess<'a, TPud, TTud, TExt>> + 'a {
+        self.inner.processes().map(move |inner| ProcAccess {
+            parent: self,
+            inner,
+        })
+    }
+
     /// Returns a thread by its [`ThreadId`], if it exists and is not running.
     ///
     /// It is only possible to access threads that aren't currently running.
@@ -717,6 +785,42 @@ where
                 "cancel_message",
                 sig!((I32)),
                 Extrinsic::CancelMessage,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "buffer_create",
+                sig!((I32, I32) -> I32),
+                Extrinsic::Buffer(calls::BufferFunction::Create),
+            )
+            .with_extrinsic(
+                "redshirt",
+                "buffer_len",
+                sig!((I32, I32) -> I32),
+                Extrinsic::Buffer(calls::BufferFunction::Len),
+            )
+            .with_extrinsic(
+                "redshirt",
+                "buffer_read",
+                sig!((I32, I32, I32, I32) -> I32),
+                Extrinsic::Buffer(calls::BufferFunction::Read),
+            )
+            .with_extrinsic(
+                "redshirt",
+                "buffer_write",
+                sig!((I32, I32, I32, I32) -> I32),
+                Extrinsic::Buffer(calls::BufferFunction::Write),
+            )
+            .with_extrinsic(
+                "redshirt",
+                "buffer_transfer",
+                sig!((I32) -> I32),
+                Extrinsic::Buffer(calls::BufferFunction::Transfer),
+            )
+            .with_extrinsic(
+                "redshirt",
+                "buffer_destroy",
+                sig!((I32) -> I32),
+                Extrinsic::Buffer(calls::BufferFunction::Destroy),
             );
 
         for supported in TExt::supported_extrinsics() {
@@ -728,7 +832,10 @@ where
             );
         }
 
-        Builder { inner }
+        Builder {
+            inner,
+            buffers: Arc::new(Buffers::new()),
+        }
     }
 }
 
@@ -743,11 +850,28 @@ impl<TExt: Extrinsics> Builder<TExt> {
         self.inner.reserve_pid()
     }
 
+    /// Sets a monotonic clock, returning a number of nanoseconds, used to measure the time
+    /// each process spends executing. See [`ProcAccess::cpu_time`].
+    pub fn with_clock(mut self, clock: Box<processes::Clock>) -> Self {
+        self.inner = self.inner.with_clock(clock);
+        self
+    }
+
+    /// Sets the collection of buffers that processes can access through the `buffer_*`
+    /// functions.
+    ///
+    /// By default, a new empty collection is used.
+    pub fn with_buffers(mut self, buffers: Arc<Buffers>) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
     /// Turns the builder into a [`ProcessesCollectionExtrinsics`].
     pub fn build<TPud, TTud>(self) -> ProcessesCollectionExtrinsics<TPud, TTud, TExt> {
         ProcessesCollectionExtrinsics {
             inner: self.inner.build(),
             local_run_queue: SegQueue::new(),
+            buffers: self.buffers,
         }
     }
 }
@@ -767,6 +891,46 @@ where
         &self.inner.user_data().external_user_data
     }
 
+    /// Returns the size in bytes of the memory of the process.
+    pub fn memory_size(&self) -> u64 {
+        self.inner.memory_size()
+    }
+
+    /// Returns the total number of nanoseconds spent executing the threads of this process, or
+    /// `None` if no clock has been passed to [`Builder::with_clock`].
+    pub fn cpu_time(&self) -> Option<u64> {
+        self.inner.cpu_time()
+    }
+
+    /// Returns the list of threads of the process and their state.
+    pub fn threads(&self) -> Vec<(ThreadId, ThreadState)> {
+        self.inner
+            .threads(|thread| match &thread.state {
+                LocalThreadState::ReadyToRun => ThreadState::Ready,
+                LocalThreadState::OtherExtrinsicApplyAction { .. }
+                | LocalThreadState::OtherExtrinsicReportWait { .. }
+                | LocalThreadState::Poisoned => ThreadState::Running,
+                LocalThreadState::OtherExtrinsicEmit { interface, .. } => {
+                    ThreadState::EmitMessage(interface.clone())
+                }
+                LocalThreadState::EmitMessage(emit) => {
+                    ThreadState::EmitMessage(emit.interface.clone())
+                }
+                LocalThreadState::OtherExtrinsicWait { .. } => ThreadState::WaitExtrinsic,
+                LocalThreadState::NotificationWait(_) => ThreadState::WaitNotification,
+            })
+            .into_iter()
+            .map(|(tid, state)| {
+                let state = match state {
+                    processes::ThreadState::Ready => ThreadState::Ready,
+                    processes::ThreadState::Running => ThreadState::Running,
+                    processes::ThreadState::Interrupted(state) => state,
+                };
+                (tid, state)
+            })
+            .collect()
+    }
+
     /// Adds a new thread to the process, starting the function with the given index and passing
     /// the given parameters.
     ///
@@ -1208,6 +1372,84 @@ impl<TExtCtxt> LocalThreadState<TExtCtxt> {
     }
 }
 
+/// Applies a call to one of the `buffer_*` functions made by the given thread.
+///
+/// Returns whether the call has succeeded, which determines the value to resume the thread with.
+/// Returns an error if the process has passed an invalid output pointer, in which case it must
+/// be aborted.
+fn apply_buffer_call<TExtr, TPud, TTud>(
+    buffers: &Buffers,
+    thread: &mut processes::ThreadAccess<TExtr, TPud, TTud>,
+    call: calls::BufferCall,
+) -> Result<bool, ()> {
+    /// Returns the range of a buffer of length `buffer_len` covered by `offset` and `len`, or
+    /// `None` if it is out of bounds.
+    fn checked_range(offset: u32, len: u32, buffer_len: usize) -> Option<Range<usize>> {
+        let start = usize::try_from(offset).ok()?;
+        let end = start.checked_add(usize::try_from(len).ok()?)?;
+        if end <= buffer_len {
+            Some(start..end)
+        } else {
+            None
+        }
+    }
+
+    let pid = thread.pid();
+    match call {
+        calls::BufferCall::Create { len, handle_out } => {
+            let handle = match usize::try_from(len).map(|len| buffers.alloc(pid, len)) {
+                Ok(Ok(handle)) => handle,
+                Ok(Err(_)) | Err(_) => return Ok(false),
+            };
+            let handle_bytes = u64::from(handle).to_le_bytes();
+            if thread.write_memory(handle_out, &handle_bytes).is_err() {
+                let _ = buffers.destroy(pid, handle);
+                return Err(());
+            }
+            Ok(true)
+        }
+        calls::BufferCall::Len { handle, len_out } => {
+            let len = match buffers.access(pid, handle, |data| u32::try_from(data.len())) {
+                Ok(Ok(len)) => len,
+                Ok(Err(_)) | Err(_) => return Ok(false),
+            };
+            thread.write_memory(len_out, &len.to_le_bytes())?;
+            Ok(true)
+        }
+        calls::BufferCall::Read {
+            handle,
+            offset,
+            out_pointer,
+            len,
+        } => {
+            let outcome = buffers.access(pid, handle, |data| {
+                let range = checked_range(offset, len, data.len())?;
+                Some(thread.write_memory(out_pointer, &data[range]))
+            });
+            match outcome {
+                Ok(Some(Ok(()))) => Ok(true),
+                Ok(Some(Err(()))) => Err(()),
+                Ok(None) | Err(_) => Ok(false),
+            }
+        }
+        calls::BufferCall::Write {
+            handle,
+            offset,
+            data,
+        } => {
+            let outcome = buffers.access(pid, handle, |buffer| {
+                let len = u32::try_from(data.len()).ok()?;
+                let range = checked_range(offset, len, buffer.len())?;
+                buffer[range].copy_from_slice(&data);
+                Some(())
+            });
+            Ok(matches!(outcome, Ok(Some(()))))
+        }
+        calls::BufferCall::Transfer { handle } => Ok(buffers.transfer(pid, handle).is_ok()),
+        calls::BufferCall::Destroy { handle } => Ok(buffers.destroy(pid, handle).is_ok()),
+    }
+}
+
 /// Implementation of the [`ExtrinsicsMemoryAccess`] trait for a process.
 struct MemoryAccessImpl<'a, 'b, TExtr, TPud, TTud>(
     &'a mut processes::ThreadAccess<'b, TExtr, TPud, TTud>,
@@ -20,7 +20,7 @@ use crate::{InterfaceHash, InvalidMessageIdErr, MessageId};
 
 use alloc::vec::Vec;
 use core::{convert::TryFrom as _, num::NonZeroU64};
-use redshirt_syscalls::EncodedMessage;
+use redshirt_syscalls::{BufferHandle, EncodedMessage};
 
 /// Analyzes a call to `next_notification` made by the given thread.
 ///
@@ -413,3 +413,156 @@ pub enum ExtrinsicCancelMessageErr {
     /// The message id is invalid.
     InvalidMessageId(InvalidMessageIdErr),
 }
+
+/// Which `buffer_*` function a thread is calling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BufferFunction {
+    /// `buffer_create`.
+    Create,
+    /// `buffer_len`.
+    Len,
+    /// `buffer_read`.
+    Read,
+    /// `buffer_write`.
+    Write,
+    /// `buffer_transfer`.
+    Transfer,
+    /// `buffer_destroy`.
+    Destroy,
+}
+
+/// Analyzes a call to one of the `buffer_*` functions made by the given thread.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+pub fn parse_extrinsic_buffer<TExtr, TPud, TTud>(
+    thread: &mut processes::ThreadAccess<TExtr, TPud, TTud>,
+    function: BufferFunction,
+    params: Vec<crate::WasmValue>,
+) -> Result<BufferCall, ExtrinsicBufferErr> {
+    let param = |n: usize| -> Result<u32, ExtrinsicBufferErr> {
+        u32::try_from(
+            params[n]
+                .into_i32()
+                .ok_or(ExtrinsicBufferErr::BadParameter)?,
+        )
+        .map_err(|_| ExtrinsicBufferErr::BadParameter)
+    };
+
+    let read_handle = |addr: u32| -> Result<BufferHandle, ExtrinsicBufferErr> {
+        let buf = thread
+            .read_memory(addr, 8)
+            .map_err(|_| ExtrinsicBufferErr::BadParameter)?;
+        let id = u64::from_le_bytes(<[u8; 8]>::try_from(&buf[..]).unwrap());
+        Ok(BufferHandle::from(id))
+    };
+
+    // We use asserts here rather than runtime checks because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    match function {
+        BufferFunction::Create => {
+            assert_eq!(params.len(), 2);
+            Ok(BufferCall::Create {
+                len: param(0)?,
+                handle_out: param(1)?,
+            })
+        }
+        BufferFunction::Len => {
+            assert_eq!(params.len(), 2);
+            Ok(BufferCall::Len {
+                handle: read_handle(param(0)?)?,
+                len_out: param(1)?,
+            })
+        }
+        BufferFunction::Read => {
+            assert_eq!(params.len(), 4);
+            Ok(BufferCall::Read {
+                handle: read_handle(param(0)?)?,
+                offset: param(1)?,
+                out_pointer: param(2)?,
+                len: param(3)?,
+            })
+        }
+        BufferFunction::Write => {
+            assert_eq!(params.len(), 4);
+            let data = thread
+                .read_memory(param(2)?, param(3)?)
+                .map_err(|_| ExtrinsicBufferErr::BadParameter)?;
+            Ok(BufferCall::Write {
+                handle: read_handle(param(0)?)?,
+                offset: param(1)?,
+                data,
+            })
+        }
+        BufferFunction::Transfer => {
+            assert_eq!(params.len(), 1);
+            Ok(BufferCall::Transfer {
+                handle: read_handle(param(0)?)?,
+            })
+        }
+        BufferFunction::Destroy => {
+            assert_eq!(params.len(), 1);
+            Ok(BufferCall::Destroy {
+                handle: read_handle(param(0)?)?,
+            })
+        }
+    }
+}
+
+/// Call to one of the `buffer_*` functions.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BufferCall {
+    /// Create a buffer.
+    Create {
+        /// Length of the buffer to create.
+        len: u32,
+        /// Location in the process' memory where to write the handle of the new buffer.
+        handle_out: u32,
+    },
+    /// Query the length of a buffer.
+    Len {
+        /// Buffer to query.
+        handle: BufferHandle,
+        /// Location in the process' memory where to write the length.
+        len_out: u32,
+    },
+    /// Copy data from a buffer to the memory of the process.
+    Read {
+        /// Buffer to read from.
+        handle: BufferHandle,
+        /// Offset within the buffer of the data to read.
+        offset: u32,
+        /// Location in the process' memory where to write the data.
+        out_pointer: u32,
+        /// Number of bytes to read.
+        len: u32,
+    },
+    /// Copy data from the memory of the process to a buffer.
+    Write {
+        /// Buffer to write to.
+        handle: BufferHandle,
+        /// Offset within the buffer where to write the data.
+        offset: u32,
+        /// Data to write. Copy of what is in the process's memory.
+        data: Vec<u8>,
+    },
+    /// Relinquish the ownership of a buffer.
+    Transfer {
+        /// Buffer to transfer.
+        handle: BufferHandle,
+    },
+    /// Destroy a buffer.
+    Destroy {
+        /// Buffer to destroy.
+        handle: BufferHandle,
+    },
+}
+
+/// Error that [`parse_extrinsic_buffer`] can return.
+#[derive(Debug)]
+pub enum ExtrinsicBufferErr {
+    /// Bad type or invalid value for a parameter.
+    BadParameter,
+}
@@ -13,15 +13,16 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::buffers::Buffers;
 use crate::extrinsics::Extrinsics;
-use crate::module::Module;
+use crate::module::{Module, ModuleHash};
 use crate::scheduler::{
-    extrinsics::{self, ThreadAccessAccess as _},
+    extrinsics::{self, ThreadAccessAccess as _, ThreadState},
     vm,
 };
 use crate::InterfaceHash;
 
-use alloc::vec::Vec;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use crossbeam_queue::SegQueue;
 use fnv::FnvBuildHasher;
 use hashbrown::HashSet;
@@ -75,6 +76,12 @@ pub struct Core<TExt: Extrinsics> {
 
     /// List of messages that are waiting for an answer. Associates messages to their senders.
     active_messages: active_messages::ActiveMessages,
+
+    /// Clock passed to [`CoreBuilder::with_clock`], if any. Used to timestamp trace events.
+    clock: Option<Arc<Clock>>,
+
+    /// Function passed to [`CoreBuilder::with_tracer`], if any.
+    tracer: Option<Box<Tracer>>,
 }
 
 /// Prototype for a `Core` under construction.
@@ -83,8 +90,18 @@ pub struct CoreBuilder<TExt: Extrinsics> {
     reserved_pids: HashSet<Pid, BuildNoHashHasher<u64>>,
     /// Builder for the [`processes`][Core::processes] field in `Core`.
     inner_builder: extrinsics::Builder<TExt>,
+    /// See the corresponding field in `Core`.
+    clock: Option<Arc<Clock>>,
+    /// See the corresponding field in `Core`.
+    tracer: Option<Box<Tracer>>,
 }
 
+/// Monotonic clock returning a number of nanoseconds. See [`CoreBuilder::with_clock`].
+type Clock = dyn Fn() -> u64 + Send + Sync;
+
+/// Function called for every [`TraceEvent`]. See [`CoreBuilder::with_tracer`].
+type Tracer = dyn Fn(TraceEvent) + Send + Sync;
+
 /// Outcome of calling [`run`](Core::run).
 #[derive(Debug)]
 pub enum CoreRunOutcome {
@@ -94,6 +111,9 @@ pub enum CoreRunOutcome {
         /// Id of the program that has stopped.
         pid: Pid,
 
+        /// Hash of the module the program has been started from.
+        module_hash: ModuleHash,
+
         /// List of interfaces that were registered by the process and no longer are.
         unregistered_interfaces: Vec<InterfaceHash>,
 
@@ -109,6 +129,9 @@ pub enum CoreRunOutcome {
         /// Thread that emitted the message.
         thread_id: ThreadId,
 
+        /// Process the thread belongs to.
+        pid: Pid,
+
         /// Interface that the thread is trying to access.
         interface: InterfaceHash,
     },
@@ -128,9 +151,87 @@ pub enum CoreRunOutcome {
     },
 }
 
+/// State of an interface, as reported by [`Core::interfaces`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceState {
+    /// Interface has been registered by the given process.
+    Registered(Pid),
+    /// Interface hasn't been registered yet, but threads or messages are waiting for it.
+    Requested {
+        /// Number of threads and messages waiting for the interface to be registered.
+        waiting: usize,
+    },
+}
+
+/// Event related to inter-process communications, reported to the function passed to
+/// [`CoreBuilder::with_tracer`].
+#[derive(Debug, Clone)]
+pub struct TraceEvent {
+    /// Value of the clock passed to [`CoreBuilder::with_clock`] when the event happened, or
+    /// `None` if no clock has been passed.
+    pub timestamp: Option<u64>,
+    /// What happened.
+    pub kind: TraceEventKind,
+}
+
+/// Kind of [`TraceEvent`].
+#[derive(Debug, Clone)]
+pub enum TraceEventKind {
+    /// A message has been emitted on an interface.
+    ///
+    /// If the interface isn't registered yet, this event is only generated once the message
+    /// has been accepted, which might happen after the interface gets registered.
+    Emit {
+        /// Process that has emitted the message.
+        emitter: Pid,
+        /// Interface the message has been emitted on.
+        interface: InterfaceHash,
+        /// Identifier of the message, or `None` if no answer is expected.
+        message_id: Option<MessageId>,
+        /// Size in bytes of the message.
+        size: usize,
+    },
+
+    /// A message has been delivered to the handler of an interface.
+    Deliver {
+        /// Process that handles the interface. Can be a reserved `Pid`.
+        handler: Pid,
+        /// Process that has emitted the message.
+        emitter: Pid,
+        /// Interface the message has been emitted on.
+        interface: InterfaceHash,
+        /// Identifier of the message, or `None` if no answer is expected.
+        message_id: Option<MessageId>,
+        /// Size in bytes of the message.
+        size: usize,
+    },
+
+    /// A message has been answered.
+    Answer {
+        /// Identifier of the message.
+        message_id: MessageId,
+        /// Process that has emitted the message, and that receives the answer.
+        emitter: Pid,
+        /// Size in bytes of the answer, or `None` if the handler has reported an error or has
+        /// stopped before answering.
+        size: Option<usize>,
+    },
+
+    /// The emitter of a message has cancelled it before it was answered.
+    Cancel {
+        /// Identifier of the message.
+        message_id: MessageId,
+        /// Process that has emitted and cancelled the message.
+        emitter: Pid,
+    },
+}
+
 /// Additional information about a process.
 #[derive(Debug)]
 struct Process {
+    /// Hash of the module the process has been started from.
+    module_hash: ModuleHash,
+
     /// Notifications available for retrieval by the process by calling `next_notification`.
     notifications_queue: notifications_queue::NotificationsQueue,
 
@@ -230,6 +331,7 @@ impl<TExt: Extrinsics> Core<TExt> {
 
                 Some(CoreRunOutcome::ProgramFinished {
                     pid,
+                    module_hash: user_data.module_hash,
                     unregistered_interfaces,
                     outcome,
                 })
@@ -272,7 +374,15 @@ impl<TExt: Extrinsics> Core<TExt> {
                         };
 
                         let message = thread.accept_emit(message_id);
+                        self.trace_emit(emitter_pid, &interface, message_id, &message);
                         if let Some(process) = self.processes.process_by_id(handler_pid) {
+                            self.trace_deliver(
+                                handler_pid,
+                                emitter_pid,
+                                &interface,
+                                message_id,
+                                &message,
+                            );
                             process
                                 .user_data()
                                 .notifications_queue
@@ -285,6 +395,13 @@ impl<TExt: Extrinsics> Core<TExt> {
                             self.try_resume_notification_wait(process);
                             None
                         } else if self.reserved_pids.contains(&handler_pid) {
+                            self.trace_deliver(
+                                handler_pid,
+                                emitter_pid,
+                                &interface,
+                                message_id,
+                                &message,
+                            );
                             Some(CoreRunOutcome::ReservedPidInterfaceMessage {
                                 pid: emitter_pid,
                                 message_id,
@@ -306,6 +423,7 @@ impl<TExt: Extrinsics> Core<TExt> {
                         reg.insert_waiting_thread(thread.tid());
                         Some(CoreRunOutcome::ThreadWaitUnavailableInterface {
                             thread_id: thread.tid(),
+                            pid: emitter_pid,
                             interface: interface.clone(),
                         })
                     }
@@ -339,8 +457,16 @@ impl<TExt: Extrinsics> Core<TExt> {
                 // Cancelling a message is implemented by simply removing it from the list of
                 // active messages. For the sake of simplicity, no effort is for example being
                 // made to maybe remove the notification destined to the interface handler.
-                self.active_messages
-                    .remove_if_emitted_by(message_id, process.pid());
+                let emitter = process.pid();
+                if self
+                    .active_messages
+                    .remove_if_emitted_by(message_id, emitter)
+                {
+                    self.trace(|| TraceEventKind::Cancel {
+                        message_id,
+                        emitter,
+                    });
+                }
                 None
             }
         }
@@ -352,6 +478,29 @@ impl<TExt: Extrinsics> Core<TExt> {
         Some(CoreProcess { process: p })
     }
 
+    /// Returns an iterator to all the processes that are running.
+    pub fn processes<'a>(&'a self) -> impl ExactSizeIterator<Item = CoreProcess<'a, TExt>> + 'a {
+        self.processes
+            .processes()
+            .map(|process| CoreProcess { process })
+    }
+
+    /// Returns the list of interfaces that have either been registered, or that threads or
+    /// messages are waiting for.
+    pub fn interfaces(&self) -> Vec<(InterfaceHash, InterfaceState)> {
+        self.interfaces
+            .list()
+            .into_iter()
+            .map(|(interface, state)| {
+                let state = match state {
+                    Ok(pid) => InterfaceState::Registered(pid),
+                    Err(waiting) => InterfaceState::Requested { waiting },
+                };
+                (interface, state)
+            })
+            .collect()
+    }
+
     /// Sets which process is the handler of which interface.
     // TODO: better API
     pub fn set_interface_handler(
@@ -368,10 +517,21 @@ impl<TExt: Extrinsics> Core<TExt> {
 
         // Registering the interface. We have stored a list of things to deliver to that interface
         // as soon as it is registered.
-        for requested in self
+        let requested_list = self
             .interfaces
-            .set_interface_handler(interface.clone(), new_handler_pid)?
-        {
+            .set_interface_handler(interface.clone(), new_handler_pid)?;
+
+        // Keep track of the registration so that the interface is unregistered when the process
+        // terminates.
+        if let Some(new_handler) = &new_handler {
+            new_handler
+                .user_data()
+                .registered_interfaces
+                .lock()
+                .push(interface.clone());
+        }
+
+        for requested in requested_list {
             match requested {
                 // A thread is blocked waiting to deliver a message on this interface.
                 interface_handlers::WaitingForInterface::Thread(thread_id) => {
@@ -399,6 +559,14 @@ impl<TExt: Extrinsics> Core<TExt> {
                     };
 
                     let message = thread.accept_emit(message_id);
+                    self.trace_emit(emitter_pid, &interface, message_id, &message);
+                    self.trace_deliver(
+                        new_handler_pid,
+                        emitter_pid,
+                        &interface,
+                        message_id,
+                        &message,
+                    );
 
                     if let Some(new_handler) = &new_handler {
                         new_handler
@@ -426,20 +594,35 @@ impl<TExt: Extrinsics> Core<TExt> {
                     emitter_pid,
                     message_id,
                     message,
-                } => match &new_handler {
-                    Some(p) => p
-                        .user_data()
-                        .notifications_queue
-                        .push_interface_notification(&interface, message_id, emitter_pid, message),
-                    None => self
-                        .pending_events
-                        .push(CoreRunOutcome::ReservedPidInterfaceMessage {
-                            pid: new_handler_pid,
-                            message_id,
-                            interface: interface.clone(),
-                            message,
-                        }),
-                },
+                } => {
+                    self.trace_deliver(
+                        new_handler_pid,
+                        emitter_pid,
+                        &interface,
+                        message_id,
+                        &message,
+                    );
+
+                    match &new_handler {
+                        Some(p) => p
+                            .user_data()
+                            .notifications_queue
+                            .push_interface_notification(
+                                &interface,
+                                message_id,
+                                emitter_pid,
+                                message,
+                            ),
+                        None => self
+                            .pending_events
+                            .push(CoreRunOutcome::ReservedPidInterfaceMessage {
+                                pid: new_handler_pid,
+                                message_id,
+                                interface: interface.clone(),
+                                message,
+                            }),
+                    }
+                }
             }
         }
 
@@ -508,15 +691,19 @@ impl<TExt: Extrinsics> Core<TExt> {
             None
         };
 
+        self.trace_emit(emitter_pid, &interface, message_id, &message);
+
         match self.interfaces.get(&interface) {
             interface_handlers::Interface::Registered(handler_pid) => {
                 if let Some(handler_process) = self.processes.process_by_id(handler_pid) {
+                    self.trace_deliver(handler_pid, emitter_pid, &interface, message_id, &message);
                     handler_process
                         .user_data()
                         .notifications_queue
                         .push_interface_notification(&interface, message_id, emitter_pid, message);
                     self.try_resume_notification_wait(handler_process);
                 } else if self.reserved_pids.contains(&emitter_pid) {
+                    self.trace_deliver(handler_pid, emitter_pid, &interface, message_id, &message);
                     self.pending_events
                         .push(CoreRunOutcome::ReservedPidInterfaceMessage {
                             pid: emitter_pid,
@@ -562,6 +749,12 @@ impl<TExt: Extrinsics> Core<TExt> {
             None => return,
         };
 
+        self.trace(|| TraceEventKind::Answer {
+            message_id,
+            emitter: emitter_pid,
+            size: response.as_ref().ok().map(|response| response.0.len()),
+        });
+
         if let Some(process) = self.processes.process_by_id(emitter_pid) {
             process
                 .user_data()
@@ -579,11 +772,72 @@ impl<TExt: Extrinsics> Core<TExt> {
         }
     }
 
+    /// Reports an event to the tracer, if any. The closure is only called if a tracer has been
+    /// passed to [`CoreBuilder::with_tracer`].
+    fn trace(&self, kind: impl FnOnce() -> TraceEventKind) {
+        if let Some(tracer) = &self.tracer {
+            tracer(TraceEvent {
+                timestamp: self.clock.as_ref().map(|clock| clock()),
+                kind: kind(),
+            });
+        }
+    }
+
+    /// Reports a [`TraceEventKind::Emit`] event to the tracer, if any.
+    fn trace_emit(
+        &self,
+        emitter: Pid,
+        interface: &InterfaceHash,
+        message_id: Option<MessageId>,
+        message: &EncodedMessage,
+    ) {
+        self.trace(|| TraceEventKind::Emit {
+            emitter,
+            interface: interface.clone(),
+            message_id,
+            size: message.0.len(),
+        });
+    }
+
+    /// Reports a [`TraceEventKind::Deliver`] event to the tracer, if any.
+    fn trace_deliver(
+        &self,
+        handler: Pid,
+        emitter: Pid,
+        interface: &InterfaceHash,
+        message_id: Option<MessageId>,
+        message: &EncodedMessage,
+    ) {
+        self.trace(|| TraceEventKind::Deliver {
+            handler,
+            emitter,
+            interface: interface.clone(),
+            message_id,
+            size: message.0.len(),
+        });
+    }
+
     /// Start executing the module passed as parameter.
     ///
     /// Each import of the [`Module`](crate::module::Module) is resolved.
     pub fn execute(&self, module: &Module) -> Result<(CoreProcess<TExt>, ThreadId), vm::NewErr> {
+        self.execute_with_extrinsics(module, Default::default(), |_| {})
+    }
+
+    /// Same as [`Core::execute`], but uses the given extrinsics handler for this process instead
+    /// of building one with `Default`.
+    ///
+    /// `before_start` is called with the [`Pid`] of the new process before any of its threads can
+    /// run. This can be used to set up the state associated with this process before it can
+    /// emit any message.
+    pub fn execute_with_extrinsics(
+        &self,
+        module: &Module,
+        extrinsics: TExt,
+        before_start: impl FnOnce(Pid),
+    ) -> Result<(CoreProcess<TExt>, ThreadId), vm::NewErr> {
         let proc_metadata = Process {
+            module_hash: module.hash().clone(),
             notifications_queue: notifications_queue::NotificationsQueue::new(),
             registered_interfaces: Spinlock::new(SmallVec::new()),
             used_interfaces: HashSet::with_hasher(Default::default()),
@@ -591,7 +845,9 @@ impl<TExt: Extrinsics> Core<TExt> {
             wait_notifications_threads: waiting_threads::WaitingThreads::new(),
         };
 
-        let (process, main_tid) = self.processes.execute(module, proc_metadata, ())?;
+        let (process, main_tid) =
+            self.processes
+                .execute(module, extrinsics, proc_metadata, (), before_start)?;
 
         Ok((CoreProcess { process }, main_tid))
     }
@@ -622,6 +878,43 @@ impl<'a, TExt: Extrinsics> CoreProcess<'a, TExt> {
         self.process.pid()
     }
 
+    /// Returns the hash of the module the process has been started from.
+    pub fn module_hash(&self) -> &ModuleHash {
+        &self.process.user_data().module_hash
+    }
+
+    /// Returns the size in bytes of the memory of the process.
+    pub fn memory_size(&self) -> u64 {
+        self.process.memory_size()
+    }
+
+    /// Returns the total number of nanoseconds spent executing the threads of this process, or
+    /// `None` if no clock has been passed to [`CoreBuilder::with_clock`].
+    pub fn cpu_time(&self) -> Option<u64> {
+        self.process.cpu_time()
+    }
+
+    /// Returns the list of threads of the process and their state.
+    pub fn threads(&self) -> Vec<(ThreadId, ThreadState)> {
+        self.process.threads()
+    }
+
+    /// Returns the list of interfaces that the process has registered.
+    pub fn registered_interfaces(&self) -> Vec<InterfaceHash> {
+        self.process
+            .user_data()
+            .registered_interfaces
+            .lock()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the number of notifications waiting to be retrieved by the process.
+    pub fn notifications_queue_len(&self) -> usize {
+        self.process.user_data().notifications_queue.len()
+    }
+
     /// Adds a new thread to the process, starting the function with the given index and passing
     /// the given parameters.
     pub fn start_thread(
@@ -646,6 +939,8 @@ impl<TExt: Extrinsics> CoreBuilder<TExt> {
         CoreBuilder {
             reserved_pids: HashSet::with_hasher(Default::default()),
             inner_builder: extrinsics::Builder::default(),
+            clock: None,
+            tracer: None,
         }
     }
 
@@ -662,6 +957,41 @@ impl<TExt: Extrinsics> CoreBuilder<TExt> {
         pid
     }
 
+    /// Sets a monotonic clock, returning a number of nanoseconds, used to measure the time
+    /// each process spends executing. See [`CoreProcess::cpu_time`].
+    ///
+    /// The clock is also used to timestamp the events passed to the function set with
+    /// [`CoreBuilder::with_tracer`].
+    ///
+    /// By default, the time spent executing isn't measured.
+    pub fn with_clock(mut self, clock: Box<dyn Fn() -> u64 + Send + Sync>) -> Self {
+        let clock: Arc<Clock> = Arc::from(clock);
+        let inner_clock = clock.clone();
+        self.inner_builder = self
+            .inner_builder
+            .with_clock(Box::new(move || inner_clock()));
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Sets the collection of buffers that processes can access. See [the `buffers`
+    /// module](crate::buffers).
+    ///
+    /// By default, a new empty collection is used.
+    pub fn with_buffers(mut self, buffers: Arc<Buffers>) -> Self {
+        self.inner_builder = self.inner_builder.with_buffers(buffers);
+        self
+    }
+
+    /// Sets a function called whenever a message is emitted, delivered, answered or cancelled.
+    ///
+    /// The function is called synchronously while the [`Core`] is running, and should therefore
+    /// return quickly.
+    pub fn with_tracer(mut self, tracer: Box<dyn Fn(TraceEvent) + Send + Sync>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
     /// Turns the builder into a [`Core`].
     pub fn build(mut self) -> Core<TExt> {
         self.reserved_pids.shrink_to_fit();
@@ -672,6 +1002,8 @@ impl<TExt: Extrinsics> CoreBuilder<TExt> {
             interfaces: interface_handlers::InterfaceHandlers::new(),
             reserved_pids: self.reserved_pids,
             active_messages: active_messages::ActiveMessages::new(),
+            clock: self.clock,
+            tracer: self.tracer,
         }
     }
 }
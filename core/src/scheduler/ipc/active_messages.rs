@@ -59,13 +59,17 @@ impl ActiveMessages {
     }
 
     /// Removes the given message, but only if it has been emitted by the given `Pid`.
-    pub fn remove_if_emitted_by(&self, message_id: MessageId, pid: Pid) {
+    ///
+    /// Returns `true` if the message has been removed.
+    pub fn remove_if_emitted_by(&self, message_id: MessageId, pid: Pid) -> bool {
         let mut active_messages = self.active_messages.lock();
         if let Entry::Occupied(entry) = active_messages.entry(message_id) {
             if *entry.get() == pid {
                 entry.remove();
+                return true;
             }
         }
+        false
     }
 }
 
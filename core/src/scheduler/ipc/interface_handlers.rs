@@ -102,6 +102,22 @@ impl InterfaceHandlers {
         Ok(requested.into_iter())
     }
 
+    /// Returns the list of all the interfaces that have been registered or requested. For each
+    /// interface, returns either the handler, or the number of things waiting for a handler.
+    pub fn list(&self) -> Vec<(InterfaceHash, Result<Pid, usize>)> {
+        let interfaces = self.interfaces.lock();
+        interfaces
+            .iter()
+            .map(|(interface, state)| {
+                let state = match state {
+                    InterfaceState::Process(pid) => Ok(*pid),
+                    InterfaceState::Requested(list) => Err(list.len()),
+                };
+                (interface.clone(), state)
+            })
+            .collect()
+    }
+
     /// Sets the given interface as not having a handler. Returns the `Pid` that was registered,
     /// if any.
     pub fn unregister(&self, interface: InterfaceHash) -> Option<Pid> {
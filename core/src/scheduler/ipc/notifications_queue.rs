@@ -65,6 +65,11 @@ impl NotificationsQueue {
         })
     }
 
+    /// Returns the number of notifications waiting to be delivered.
+    pub fn len(&self) -> usize {
+        self.notifications_queue.lock().len()
+    }
+
     /// Adds an interface notification at the end of the queue.
     pub fn push_interface_notification(
         &self,
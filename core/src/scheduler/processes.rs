@@ -129,6 +129,10 @@ pub struct ProcessesCollection<TExtr, TPud, TTud> {
     extrinsics_id_assign:
         HashMap<(Cow<'static, str>, Cow<'static, str>), (usize, Signature), FnvBuildHasher>,
 
+    /// Monotonic clock used to measure the time each process spends executing. Returns a number
+    /// of nanoseconds.
+    clock: Option<Box<Clock>>,
+
     /// Queue of process deaths to report to the external API.
     death_reports: SegQueue<(
         Pid,
@@ -147,8 +151,13 @@ pub struct ProcessesCollectionBuilder<TExtr> {
     /// See the corresponding field in `ProcessesCollection`.
     extrinsics_id_assign:
         HashMap<(Cow<'static, str>, Cow<'static, str>), (usize, Signature), FnvBuildHasher>,
+    /// See the corresponding field in `ProcessesCollection`.
+    clock: Option<Box<Clock>>,
 }
 
+/// Monotonic clock passed to [`ProcessesCollectionBuilder::with_clock`].
+pub type Clock = dyn Fn() -> u64 + Send + Sync;
+
 /// Description of a process. Always addressed through an `Arc`.
 ///
 /// Note that the process might be dead.
@@ -161,6 +170,13 @@ struct Process<TPud, TTud> {
     // `vm` module supports multithreading
     lock: Spinlock<ProcessLock<TTud>>,
 
+    /// Total number of nanoseconds spent executing the threads of this process. Always 0 if no
+    /// clock has been passed to the builder.
+    ///
+    /// Kept outside of [`Process::lock`] so that it can be updated while the outcome of an
+    /// execution borrows the virtual machine.
+    cpu_time: Spinlock<u64>,
+
     /// User-chosen data (opaque to us) that describes the process.
     user_data: TPud,
 }
@@ -194,6 +210,17 @@ struct Thread {
     thread_id: ThreadId,
 }
 
+/// State of a thread, as reported by [`ProcAccess::threads`].
+#[derive(Debug)]
+pub enum ThreadState<T> {
+    /// Thread is ready to run and waiting for its turn.
+    Ready,
+    /// Thread is either currently running, or has been locked through a [`ThreadAccess`].
+    Running,
+    /// Thread is interrupted. Contains the value extracted from its user data.
+    Interrupted(T),
+}
+
 /// Access to a process within the collection.
 pub struct ProcAccess<'a, TExtr, TPud, TTud> {
     collection: &'a ProcessesCollection<TExtr, TPud, TTud>,
@@ -290,11 +317,15 @@ impl<TExtr, TPud, TTud> ProcessesCollection<TExtr, TPud, TTud> {
     ///
     /// A single main thread (whose user data is passed by parameter) is automatically created and
     /// is paused at the start of the "_start" function of the module.
+    ///
+    /// `before_start` is called with the [`Pid`] of the new process before any of its threads can
+    /// run, and before it can be found in the collection.
     pub fn execute(
         &self,
         module: &Module,
         proc_user_data: TPud,
         main_thread_user_data: TTud,
+        before_start: impl FnOnce(Pid),
     ) -> Result<(ProcAccess<TExtr, TPud, TTud>, ThreadId), vm::NewErr> {
         let main_thread_id = self.pid_tid_pool.assign(); // TODO: check for duplicates?
 
@@ -323,6 +354,7 @@ impl<TExtr, TPud, TTud> ProcessesCollection<TExtr, TPud, TTud> {
 
         // We only modify `self` at the very end.
         let new_pid = self.pid_tid_pool.assign();
+        before_start(new_pid);
         let process = Arc::new(Process {
             pid: new_pid,
             lock: Spinlock::new(ProcessLock {
@@ -334,6 +366,7 @@ impl<TExtr, TPud, TTud> ProcessesCollection<TExtr, TPud, TTud> {
                 },
                 dead: None,
             }),
+            cpu_time: Spinlock::new(0),
             user_data: proc_user_data,
         });
 
@@ -477,6 +510,7 @@ impl<TExtr> Default for ProcessesCollectionBuilder<TExtr> {
             pid_tid_pool: IdPool::new(),
             extrinsics: Default::default(),
             extrinsics_id_assign: Default::default(),
+            clock: None,
         }
     }
 }
@@ -553,6 +587,7 @@ impl<'a, TExtr, TPud, TTud> Future for RunFuture<'a, TExtr, TPud, TTud> {
 
             // Now run a thread until something happens.
             // This takes most of the CPU time of this function.
+            let start_time = this.clock.as_ref().map(|clock| clock());
             let run_outcome = {
                 debug_assert!(!proc_state.vm.is_poisoned());
                 let thread_index = (0..proc_state.vm.num_threads())
@@ -564,6 +599,9 @@ impl<'a, TExtr, TPud, TTud> Future for RunFuture<'a, TExtr, TPud, TTud> {
                     .unwrap()
                     .run(resume_value)
             };
+            if let (Some(clock), Some(start_time)) = (this.clock.as_ref(), start_time) {
+                *process.cpu_time.lock() += clock().saturating_sub(start_time);
+            }
 
             match run_outcome {
                 Err(vm::RunErr::BadValueTy { .. }) => panic!(), // TODO:
@@ -728,6 +766,13 @@ impl<TExtr> ProcessesCollectionBuilder<TExtr> {
         self
     }
 
+    /// Sets a monotonic clock, returning a number of nanoseconds, used to measure the time
+    /// each process spends executing. See [`ProcAccess::cpu_time`].
+    pub fn with_clock(mut self, clock: Box<Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
     /// Turns the builder into a [`ProcessesCollection`].
     pub fn build<TPud, TTud>(mut self) -> ProcessesCollection<TExtr, TPud, TTud> {
         // We're not going to modify these fields ever again, so let's free some memory.
@@ -749,6 +794,7 @@ impl<TExtr> ProcessesCollectionBuilder<TExtr> {
             )),
             extrinsics: self.extrinsics,
             extrinsics_id_assign: self.extrinsics_id_assign,
+            clock: self.clock,
             death_reports: SegQueue::new(),
         }
     }
@@ -766,6 +812,74 @@ impl<'a, TExtr, TPud, TTud> ProcAccess<'a, TExtr, TPud, TTud> {
         &self.process.as_ref().unwrap().user_data
     }
 
+    /// Returns the size in bytes of the memory of the process.
+    ///
+    /// Returns 0 if the process is dying.
+    pub fn memory_size(&self) -> u64 {
+        let process_state = self.process.as_ref().unwrap().lock.lock();
+        if process_state.dead.is_some() {
+            return 0;
+        }
+        process_state.vm.memory_size()
+    }
+
+    /// Returns the total number of nanoseconds spent executing the threads of this process.
+    ///
+    /// Returns `None` if no clock has been passed to
+    /// [`ProcessesCollectionBuilder::with_clock`].
+    pub fn cpu_time(&self) -> Option<u64> {
+        if self.collection.clock.is_some() {
+            Some(*self.process.as_ref().unwrap().cpu_time.lock())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the list of threads of the process and their state.
+    ///
+    /// The `interrupted` closure is called with the user data of each interrupted thread in
+    /// order to extract information from it.
+    ///
+    /// Returns an empty list if the process is dying.
+    pub fn threads<T>(
+        &self,
+        mut interrupted: impl FnMut(&TTud) -> T,
+    ) -> Vec<(ThreadId, ThreadState<T>)> {
+        let mut process_state = self.process.as_ref().unwrap().lock.lock();
+        if process_state.dead.is_some() {
+            return Vec::new();
+        }
+
+        // Note that the locking order (process, then interrupted threads) is the same as in the
+        // rest of this module.
+        let interrupted_threads = self.collection.interrupted_threads.lock();
+
+        let mut out = Vec::with_capacity(process_state.vm.num_threads());
+        for index in 0..process_state.vm.num_threads() {
+            let tid = process_state
+                .vm
+                .thread(index)
+                .unwrap()
+                .user_data()
+                .thread_id;
+
+            let state = if process_state
+                .threads_to_resume
+                .iter()
+                .any(|(t, _, _)| *t == tid)
+            {
+                ThreadState::Ready
+            } else if let Some((user_data, _)) = interrupted_threads.get(&tid) {
+                ThreadState::Interrupted(interrupted(user_data))
+            } else {
+                ThreadState::Running
+            };
+
+            out.push((tid, state));
+        }
+        out
+    }
+
     /// Adds a new thread to the process, starting the function with the given index and passing
     /// the given parameters.
     ///
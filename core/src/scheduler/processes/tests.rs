@@ -42,7 +42,7 @@ fn basic() {
     "#
     );
     let processes = ProcessesCollectionBuilder::<()>::default().build();
-    processes.execute(&module, (), ()).unwrap();
+    processes.execute(&module, (), (), |_| {}).unwrap();
     match futures::executor::block_on(processes.run()) {
         RunOneOutcome::ProcessFinished { outcome, .. } => {
             assert!(matches!(outcome.unwrap(), Some(crate::WasmValue::I32(5))));
@@ -62,7 +62,7 @@ fn aborting_works() {
     "#
     );
     let processes = ProcessesCollectionBuilder::<()>::default().build();
-    processes.execute(&module, (), ()).unwrap().0.abort();
+    processes.execute(&module, (), (), |_| {}).unwrap().0.abort();
     match futures::executor::block_on(processes.run()) {
         RunOneOutcome::ProcessFinished {
             outcome: Err(_), ..
@@ -92,7 +92,7 @@ fn many_processes() {
     );
     let mut spawned_pids = HashSet::<_, fnv::FnvBuildHasher>::default();
     for _ in 0..num_processes {
-        let pid = processes.execute(&module, (), ()).unwrap().0.pid();
+        let pid = processes.execute(&module, (), (), |_| {}).unwrap().0.pid();
         assert!(spawned_pids.insert(pid));
     }
 
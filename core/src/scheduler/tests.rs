@@ -20,6 +20,7 @@ use crate::extrinsics::NoExtrinsics;
 mod basic_module;
 mod emit_not_available;
 mod emit_reserved_pid;
+mod trace_events;
 mod trapping_module;
 mod wasm_recv_interface_msg;
 
@@ -0,0 +1,249 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::extrinsics::NoExtrinsics;
+use crate::scheduler::{CoreBuilder, CoreRunOutcome, TraceEvent, TraceEventKind};
+use crate::{EncodedMessage, InterfaceHash};
+
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+use futures::prelude::*;
+use spinning_top::Spinlock;
+
+#[test]
+fn emit_and_deliver_traced() {
+    let module = from_wat!(
+        local,
+        r#"
+(module
+    (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (memory $memory 1)
+    (data (i32.const 0) "\00\01\02\03\04\05\06\07\10\11\12\13\14\15\16\17\20\21\22\23\24\25\26\27\30\31\32\33\34\35\36\37")
+    (data (i32.const 32) "\01\02\03\04")
+    (data (i32.const 40) "\20\00\00\00\04\00\00\00")
+    (func $_start (result i32)
+        (drop (call $emit_message
+            (i32.const 0) (i32.const 40) (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 48)))
+        (i32.const 0))
+    (export "memory" (memory 0))
+    (export "_start" (func $_start)))"#
+    );
+
+    let interface = InterfaceHash::from_raw_hash([
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16,
+        0x17, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35,
+        0x36, 0x37,
+    ]);
+
+    let events = Arc::new(Spinlock::new(Vec::<TraceEvent>::new()));
+
+    let mut builder = CoreBuilder::<NoExtrinsics>::new();
+    let reserved_pid = builder.reserve_pid();
+    let core = builder
+        .with_clock(Box::new(|| 12))
+        .with_tracer({
+            let events = events.clone();
+            Box::new(move |event| events.lock().push(event))
+        })
+        .build();
+    core.set_interface_handler(interface.clone(), reserved_pid)
+        .unwrap();
+
+    let pid = core.execute(&module).unwrap().0.pid();
+
+    match core.run().now_or_never() {
+        Some(CoreRunOutcome::ReservedPidInterfaceMessage { .. }) => {}
+        _ => panic!(),
+    }
+
+    let events = events.lock();
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().all(|ev| ev.timestamp == Some(12)));
+
+    match &events[0].kind {
+        TraceEventKind::Emit {
+            emitter,
+            interface: emit_interface,
+            message_id,
+            size,
+        } => {
+            assert_eq!(*emitter, pid);
+            assert_eq!(*emit_interface, interface);
+            assert!(message_id.is_none());
+            assert_eq!(*size, 4);
+        }
+        _ => panic!(),
+    }
+
+    match &events[1].kind {
+        TraceEventKind::Deliver {
+            handler,
+            emitter,
+            size,
+            ..
+        } => {
+            assert_eq!(*handler, reserved_pid);
+            assert_eq!(*emitter, pid);
+            assert_eq!(*size, 4);
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn answer_traced() {
+    let module = from_wat!(
+        local,
+        r#"
+(module
+    (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (memory $memory 1)
+    (data (i32.const 0) "\00\01\02\03\04\05\06\07\10\11\12\13\14\15\16\17\20\21\22\23\24\25\26\27\30\31\32\33\34\35\36\37")
+    (data (i32.const 32) "\01\02\03\04")
+    (data (i32.const 40) "\20\00\00\00\04\00\00\00")
+    (func $_start (result i32)
+        (drop (call $emit_message
+            (i32.const 0) (i32.const 40) (i32.const 1) (i32.const 1) (i32.const 1) (i32.const 48)))
+        (i32.const 0))
+    (export "memory" (memory 0))
+    (export "_start" (func $_start)))"#
+    );
+
+    let interface = InterfaceHash::from_raw_hash([
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16,
+        0x17, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35,
+        0x36, 0x37,
+    ]);
+
+    let events = Arc::new(Spinlock::new(Vec::<TraceEvent>::new()));
+
+    let mut builder = CoreBuilder::<NoExtrinsics>::new();
+    let reserved_pid = builder.reserve_pid();
+    let core = builder
+        .with_tracer({
+            let events = events.clone();
+            Box::new(move |event| events.lock().push(event))
+        })
+        .build();
+    core.set_interface_handler(interface, reserved_pid).unwrap();
+
+    let pid = core.execute(&module).unwrap().0.pid();
+
+    let message_id = match core.run().now_or_never() {
+        Some(CoreRunOutcome::ReservedPidInterfaceMessage {
+            message_id: Some(message_id),
+            ..
+        }) => message_id,
+        _ => panic!(),
+    };
+
+    core.answer_message(message_id, Ok(EncodedMessage(vec![5, 6, 7])));
+
+    let events = events.lock();
+    assert_eq!(events.len(), 3);
+    assert!(events.iter().all(|ev| ev.timestamp.is_none()));
+
+    match &events[0].kind {
+        TraceEventKind::Emit {
+            message_id: emit_message_id,
+            ..
+        } => assert_eq!(*emit_message_id, Some(message_id)),
+        _ => panic!(),
+    }
+
+    match &events[2].kind {
+        TraceEventKind::Answer {
+            message_id: answer_message_id,
+            emitter,
+            size,
+        } => {
+            assert_eq!(*answer_message_id, message_id);
+            assert_eq!(*emitter, pid);
+            assert_eq!(*size, Some(3));
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn cancel_traced() {
+    let module = from_wat!(
+        local,
+        r#"
+(module
+    (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "redshirt" "cancel_message" (func $cancel_message (param i32)))
+    (memory $memory 1)
+    (data (i32.const 0) "\00\01\02\03\04\05\06\07\10\11\12\13\14\15\16\17\20\21\22\23\24\25\26\27\30\31\32\33\34\35\36\37")
+    (data (i32.const 32) "\01\02\03\04")
+    (data (i32.const 40) "\20\00\00\00\04\00\00\00")
+    (func $_start (result i32)
+        (drop (call $emit_message
+            (i32.const 0) (i32.const 40) (i32.const 1) (i32.const 1) (i32.const 1) (i32.const 48)))
+        (call $cancel_message (i32.const 48))
+        (i32.const 0))
+    (export "memory" (memory 0))
+    (export "_start" (func $_start)))"#
+    );
+
+    let interface = InterfaceHash::from_raw_hash([
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16,
+        0x17, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35,
+        0x36, 0x37,
+    ]);
+
+    let events = Arc::new(Spinlock::new(Vec::<TraceEvent>::new()));
+
+    let mut builder = CoreBuilder::<NoExtrinsics>::new();
+    let reserved_pid = builder.reserve_pid();
+    let core = builder
+        .with_tracer({
+            let events = events.clone();
+            Box::new(move |event| events.lock().push(event))
+        })
+        .build();
+    core.set_interface_handler(interface, reserved_pid).unwrap();
+
+    let pid = core.execute(&module).unwrap().0.pid();
+
+    let message_id = match core.run().now_or_never() {
+        Some(CoreRunOutcome::ReservedPidInterfaceMessage {
+            message_id: Some(message_id),
+            ..
+        }) => message_id,
+        _ => panic!(),
+    };
+
+    match core.run().now_or_never() {
+        Some(CoreRunOutcome::ProgramFinished { outcome, .. }) => assert!(outcome.is_ok()),
+        _ => panic!(),
+    }
+
+    // Answering a cancelled message is a no-op.
+    core.answer_message(message_id, Ok(EncodedMessage(vec![5, 6, 7])));
+
+    let events = events.lock();
+    assert_eq!(events.len(), 3);
+
+    match &events[2].kind {
+        TraceEventKind::Cancel {
+            message_id: cancel_message_id,
+            emitter,
+        } => {
+            assert_eq!(*cancel_message_id, message_id);
+            assert_eq!(*emitter, pid);
+        }
+        _ => panic!(),
+    }
+}
@@ -460,6 +460,20 @@ impl<T> ProcessStateMachine<T> {
         self.threads.into_iter().map(|thread| thread.user_data)
     }
 
+    /// Returns the size in bytes of the memory of the process.
+    ///
+    /// Returns 0 if the process doesn't export any memory.
+    pub fn memory_size(&self) -> u64 {
+        let mem = match self.memory.as_ref() {
+            Some(m) => m,
+            None => return 0,
+        };
+
+        let num_pages: u64 = mem.current_size().0.try_into().unwrap();
+        let page_size: u64 = wasmi::LINEAR_MEMORY_PAGE_SIZE.0.try_into().unwrap();
+        num_pages * page_size
+    }
+
     /// Copies the given memory range into a `Vec<u8>`.
     ///
     /// Returns an error if the range is invalid or out of range.
@@ -22,19 +22,32 @@
 //!
 //! - `interface`.
 //!
+//! # Lazy loading
+//!
+//! A module can be registered as the default handler of an interface with
+//! [`SystemBuilder::with_interface_handler`]. When a process tries to emit a message on an
+//! interface that nobody has registered yet, the module is fetched through the `loader`
+//! interface and started. It is then expected to register the interface.
+//!
 
-use crate::extrinsics::wasi;
-use crate::module::{Module, ModuleHash};
+use crate::authorizations::Authorizations;
+use crate::buffers::Buffers;
+use crate::extrinsics::{log_calls, wasi};
+use crate::module::{FromBytesError, Module, ModuleHash, ModuleSignature};
 use crate::native::{self, NativeProgramMessageIdWrite as _};
-use crate::scheduler::{Core, CoreBuilder, CoreRunOutcome, NewErr};
+use crate::process_info::{ProcessInfo, ProcessesInfo};
+use crate::scheduler::{
+    Core, CoreBuilder, CoreRunOutcome, InterfaceState, NewErr, ThreadState, TraceEvent,
+};
 
-use alloc::vec::Vec;
-use core::{iter, num::NonZeroU64, task::Poll};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use core::{convert::TryFrom as _, fmt, iter, num::NonZeroU64, task::Poll};
 use crossbeam_queue::SegQueue;
+use fnv::FnvBuildHasher;
 use futures::prelude::*;
-use hashbrown::HashSet;
+use hashbrown::HashMap;
 use nohash_hasher::BuildNoHashHasher;
-use redshirt_syscalls::{Decode, Encode, MessageId, Pid};
+use redshirt_syscalls::{Decode, Encode, EncodedMessage, InterfaceHash, MessageId, Pid};
 use spinning_top::Spinlock;
 
 /// Main struct that handles a system, including the scheduler, program loader,
@@ -43,50 +56,118 @@ use spinning_top::Spinlock;
 /// See [the module-level documentation](super) for more information.
 pub struct System<'a> {
     /// Inner system with inter-process communications.
-    core: Core<wasi::WasiExtrinsics>,
+    core: Core<Extrinsics>,
 
     /// Collection of programs. Each is assigned a `Pid` that is reserved within `core`.
     /// Can communicate with the WASM programs that are within `core`.
     native_programs: native::NativeProgramsCollection<'a>,
 
+    /// Which process is allowed to use which interface. Shared with the interface handlers.
+    authorizations: Arc<Authorizations>,
+
+    /// Information about the running processes. Shared with whoever wants to show it.
+    processes_info: Arc<ProcessesInfo>,
+
+    /// Buffers of data owned by the processes. Shared with `core` and with the native programs
+    /// that exchange bulk data.
+    buffers: Arc<Buffers>,
+
+    /// Clock passed to [`SystemBuilder::with_clock`], if any.
+    clock: Option<Arc<Clock>>,
+
     /// PID of the program that handles the `loader` interface, or `None` is no such program
     /// exists yet.
     // TODO: add timeout for loader interface availability?
     loader_pid: Spinlock<Option<NonZeroU64>>,
 
-    /// List of programs to load if the loader interface handler is available.
-    programs_to_load: SegQueue<ModuleHash>,
+    /// List of programs to load if the loader interface handler is available, with the process
+    /// that has caused the loading, if any.
+    programs_to_load: SegQueue<(ModuleHash, Option<Pid>)>,
 
     /// "Virtual" pid for the process that sends messages towards the loader.
     load_source_virtual_pid: Pid,
 
-    /// Set of messages that we emitted of requests to load a program from the loader interface.
+    /// Set of messages that we emitted of requests to load a program from the loader interface,
+    /// with the hash of the requested program and the process that has caused the loading.
     /// All these messages expect a `redshirt_loader_interface::ffi::LoadResponse` as answer.
     // TODO: call shink_to_fit from time to time
-    loading_programs: Spinlock<HashSet<MessageId, BuildNoHashHasher<u64>>>,
+    loading_programs:
+        Spinlock<HashMap<MessageId, (ModuleHash, Option<Pid>), BuildNoHashHasher<u64>>>,
+
+    /// For each interface, the module to load if a process tries to use the interface while it
+    /// isn't registered.
+    interface_handlers: HashMap<InterfaceHash, ModuleHash, FnvBuildHasher>,
+
+    /// Interfaces whose default handler has been queued for loading, but that haven't been
+    /// registered yet. Prevents loading the same handler multiple times.
+    ///
+    /// Contains the process that has been started from the handler, or `None` if it is still
+    /// being loaded. The entry is removed if this process terminates without registering the
+    /// interface, so that the handler can be loaded again.
+    lazy_loading: Spinlock<HashMap<InterfaceHash, Option<Pid>, FnvBuildHasher>>,
+
+    /// Decides whether a module obtained through the loader is allowed to run.
+    module_policy: Option<Box<ModulePolicy<'a>>>,
+
+    /// For each module whose calls to extrinsics must be logged, the logging configuration.
+    extrinsics_logging: HashMap<ModuleHash, Arc<log_calls::LogConfig>, FnvBuildHasher>,
 }
 
 /// Prototype for a [`System`].
 pub struct SystemBuilder<'a> {
     /// Builder for the inner core.
-    core: CoreBuilder<wasi::WasiExtrinsics>,
+    core: CoreBuilder<Extrinsics>,
 
     /// Native programs.
     native_programs: native::NativeProgramsCollection<'a>,
 
+    /// Same field as [`System::authorizations`].
+    authorizations: Arc<Authorizations>,
+
+    /// Same field as [`System::processes_info`].
+    processes_info: Arc<ProcessesInfo>,
+
+    /// Same field as [`System::buffers`].
+    buffers: Arc<Buffers>,
+
+    /// Same field as [`System::clock`].
+    clock: Option<Arc<Clock>>,
+
     /// "Virtual" pid for handling messages on the `interface` interface.
     interface_interface_pid: Pid,
 
     /// "Virtual" pid for the process that sends messages towards the loader.
     load_source_virtual_pid: Pid,
 
+    /// "Virtual" pid for handling messages on the `kernel-debug` interface.
+    kernel_debug_pid: Pid,
+
     /// List of programs to start executing immediately after construction.
     startup_processes: Vec<Module>,
 
     /// Same field as [`System::programs_to_load`].
-    programs_to_load: SegQueue<ModuleHash>,
+    programs_to_load: SegQueue<(ModuleHash, Option<Pid>)>,
+
+    /// Same field as [`System::interface_handlers`].
+    interface_handlers: HashMap<InterfaceHash, ModuleHash, FnvBuildHasher>,
+
+    /// Same field as [`System::module_policy`].
+    module_policy: Option<Box<ModulePolicy<'a>>>,
+
+    /// Same field as [`System::extrinsics_logging`].
+    extrinsics_logging: HashMap<ModuleHash, Arc<log_calls::LogConfig>, FnvBuildHasher>,
 }
 
+/// Extrinsics available to the processes.
+type Extrinsics = log_calls::LogExtrinsics<wasi::WasiExtrinsics>;
+
+/// Monotonic clock returning a number of nanoseconds. See [`SystemBuilder::with_clock`].
+type Clock = dyn Fn() -> u64 + Send + Sync;
+
+/// Function that decides whether a module obtained through the loader is allowed to run.
+/// See [`SystemBuilder::with_module_policy`].
+type ModulePolicy<'a> = dyn Fn(&ModuleHash, Option<&ModuleSignature>) -> bool + Send + Sync + 'a;
+
 /// Outcome of running the [`System`] once.
 #[derive(Debug)]
 pub enum SystemRunOutcome {
@@ -94,11 +175,46 @@ pub enum SystemRunOutcome {
     ProgramFinished {
         /// Identifier of the process that has stopped.
         pid: Pid,
+        /// Information about the process that has stopped.
+        info: ProcessInfo,
         /// Either `Ok(())` if the main thread has ended, or the error that happened in the
         /// process.
         // TODO: change error type
         outcome: Result<(), wasmi::Error>,
     },
+
+    /// A program passed to [`SystemBuilder::with_main_program`], or the default handler of an
+    /// interface, couldn't be loaded or started.
+    ProgramLoadFailed {
+        /// Hash of the module that was requested to the loader.
+        hash: ModuleHash,
+        /// Reason why the program couldn't be started.
+        error: LoadError,
+    },
+}
+
+/// Error that can happen when loading a program through the `loader` interface.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The handler of the `loader` interface has answered with an error or an invalid message.
+    BadLoaderResponse,
+    /// The loader couldn't find the requested module.
+    NotFound,
+    /// The hash of the data returned by the loader doesn't match the requested hash.
+    ///
+    /// Since any process can register the `loader` interface, this data must not be trusted.
+    HashMismatch {
+        /// Hash of the data that the loader has returned.
+        obtained: ModuleHash,
+    },
+    /// The loader has provided a signature that isn't valid for the module.
+    InvalidSignature,
+    /// The policy passed to [`SystemBuilder::with_module_policy`] has refused the module.
+    Rejected,
+    /// The data returned by the loader isn't a valid WASM module.
+    InvalidModule(FromBytesError),
+    /// Error while starting the process.
+    StartFailed(NewErr),
 }
 
 #[derive(Debug)]
@@ -110,8 +226,46 @@ enum RunOnceOutcome {
 
 impl<'a> System<'a> {
     /// Start executing a program.
+    ///
+    /// The new process is granted the interfaces that have been granted to its module through
+    /// [`Authorizations::grant_module`].
     pub fn execute(&self, program: &Module) -> Result<Pid, NewErr> {
-        Ok(self.core.execute(program)?.0.pid())
+        self.start_process(program, None, None)
+    }
+
+    /// Same as [`System::execute`], but gives a name to the process. The name is reported by
+    /// [`System::process_info`] instead of the one found in the module, if any.
+    pub fn execute_with_name(
+        &self,
+        program: &Module,
+        name: impl Into<String>,
+    ) -> Result<Pid, NewErr> {
+        self.start_process(program, Some(name.into()), None)
+    }
+
+    /// Returns information about the given process, or `None` if it isn't running.
+    pub fn process_info(&self, pid: Pid) -> Option<ProcessInfo> {
+        self.processes_info.get(pid)
+    }
+
+    /// Returns the table of information about the processes of this [`System`].
+    ///
+    /// This can be passed to the interface handlers that want to show information about
+    /// processes.
+    pub fn processes_info(&self) -> &Arc<ProcessesInfo> {
+        &self.processes_info
+    }
+
+    /// Returns the table of authorizations of the processes of this [`System`].
+    ///
+    /// This can be used to grant interfaces to a newly-created process.
+    pub fn authorizations(&self) -> &Arc<Authorizations> {
+        &self.authorizations
+    }
+
+    /// Returns the collection of buffers of the processes of this [`System`].
+    pub fn buffers(&self) -> &Arc<Buffers> {
+        &self.buffers
     }
 
     /// Runs the [`System`] once and returns the outcome.
@@ -126,15 +280,19 @@ impl<'a> System<'a> {
             loop {
                 // If we have a handler for the loader interface, start loading pending programs.
                 if self.loader_pid.lock().is_some() {
-                    while let Ok(hash) = self.programs_to_load.pop() {
+                    while let Ok((hash, parent)) = self.programs_to_load.pop() {
                         // TODO: can this not fail if the handler crashed in parallel in a
                         // multithreaded situation?
                         let message_id = self.core.emit_interface_message_answer(
                             self.load_source_virtual_pid,
                             redshirt_loader_interface::ffi::INTERFACE,
-                            redshirt_loader_interface::ffi::LoaderMessage::Load(From::from(hash)),
+                            redshirt_loader_interface::ffi::LoaderMessage::Load(From::from(
+                                hash.clone(),
+                            )),
                         );
-                        self.loading_programs.lock().insert(message_id);
+                        self.loading_programs
+                            .lock()
+                            .insert(message_id, (hash, parent));
                     }
                 }
 
@@ -202,34 +360,70 @@ impl<'a> System<'a> {
 
     async fn run_once(&self) -> RunOnceOutcome {
         match self.core.run().await {
-            CoreRunOutcome::ProgramFinished { pid, outcome, .. } => {
+            CoreRunOutcome::ProgramFinished {
+                pid,
+                module_hash,
+                outcome,
+                ..
+            } => {
                 let mut loader_pid = self.loader_pid.lock();
                 if *loader_pid == NonZeroU64::new(u64::from(pid)) {
                     *loader_pid = None;
                 }
+                self.authorizations.process_destroyed(pid);
+                self.buffers.process_destroyed(pid);
+                self.lazy_loading
+                    .lock()
+                    .retain(|_, handler| *handler != Some(pid));
                 self.native_programs.process_destroyed(pid);
+                // The information is stored before the process starts and should always be
+                // known, but we don't want to panic if the two tables ever get out of sync.
+                let info = self
+                    .processes_info
+                    .process_destroyed(pid)
+                    .unwrap_or_else(|| ProcessInfo {
+                        module_hash,
+                        name: None,
+                        parent: None,
+                        start_time: None,
+                    });
                 return RunOnceOutcome::Report(SystemRunOutcome::ProgramFinished {
                     pid,
+                    info,
                     outcome: outcome.map(|_| ()).map_err(|err| err.into()),
                 });
             }
 
-            CoreRunOutcome::ThreadWaitUnavailableInterface { .. } => {} // TODO: lazy-loading
+            CoreRunOutcome::ThreadWaitUnavailableInterface { interface, pid, .. } => {
+                // If we know of a module that handles this interface, load it. The loading is
+                // performed in `run` as soon as the loader is available.
+                if let Some(module) = self.interface_handlers.get(&interface) {
+                    let mut lazy_loading = self.lazy_loading.lock();
+                    if !lazy_loading.contains_key(&interface) {
+                        lazy_loading.insert(interface, None);
+                        self.programs_to_load.push((module.clone(), Some(pid)));
+                        return RunOnceOutcome::LoopAgainNow;
+                    }
+                }
+            }
 
             CoreRunOutcome::MessageResponse {
                 message_id,
                 response,
                 ..
             } => {
-                if self.loading_programs.lock().remove(&message_id) {
-                    let redshirt_loader_interface::ffi::LoadResponse { result } =
-                        Decode::decode(response.unwrap()).unwrap();
-                    // TODO: don't unwrap
-                    let module = Module::from_bytes(&result.expect("loader returned error"))
-                        .expect("module isn't proper wasm");
-                    match self.core.execute(&module) {
-                        Ok(_) => {}
-                        Err(_) => panic!(),
+                let loading = self.loading_programs.lock().remove(&message_id);
+                if let Some((hash, parent)) = loading {
+                    if let Err(error) = self.execute_loaded(&hash, parent, response) {
+                        // Allow the handlers of the interfaces that this module was supposed to
+                        // register to be loaded again next time they're needed.
+                        self.lazy_loading.lock().retain(|i, handler| {
+                            handler.is_some() || self.interface_handlers.get(i) != Some(&hash)
+                        });
+                        return RunOnceOutcome::Report(SystemRunOutcome::ProgramLoadFailed {
+                            hash,
+                            error,
+                        });
                     }
                 } else {
                     self.native_programs.message_response(message_id, response);
@@ -257,6 +451,10 @@ impl<'a> System<'a> {
                             self.core.answer_message(message_id, Ok(response.encode()));
                         }
 
+                        if result.is_ok() {
+                            self.lazy_loading.lock().remove(&interface_hash);
+                        }
+
                         // Special handling if the registered interface is the loader.
                         if result.is_ok()
                             && interface_hash == redshirt_loader_interface::ffi::INTERFACE
@@ -266,6 +464,17 @@ impl<'a> System<'a> {
                             return RunOnceOutcome::LoopAgainNow;
                         }
                     }
+                    Ok(redshirt_interface_interface::ffi::InterfaceMessage::Grant {
+                        pid: target,
+                        interface,
+                    }) => {
+                        let response = redshirt_interface_interface::ffi::InterfaceGrantResponse {
+                            result: self.grant_interface(pid, target, interface),
+                        };
+                        if let Some(message_id) = message_id {
+                            self.core.answer_message(message_id, Ok(response.encode()));
+                        }
+                    }
                     Err(_) => {
                         if let Some(message_id) = message_id {
                             self.core.answer_message(message_id, Err(()));
@@ -274,6 +483,27 @@ impl<'a> System<'a> {
                 }
             }
 
+            CoreRunOutcome::ReservedPidInterfaceMessage {
+                pid,
+                message_id,
+                interface,
+                message,
+            } if interface == redshirt_kernel_debug_interface::ffi::INTERFACE => {
+                // Handling messages on the `kernel-debug` interface. Only processes that have
+                // been granted the interface can query information about the other processes.
+                let response = if !self.authorizations.is_authorized(pid, &interface) {
+                    Err(())
+                } else {
+                    match Decode::decode(message) {
+                        Ok(message) => Ok(self.kernel_debug_response(message)),
+                        Err(_) => Err(()),
+                    }
+                };
+                if let Some(message_id) = message_id {
+                    self.core.answer_message(message_id, response);
+                }
+            }
+
             CoreRunOutcome::ReservedPidInterfaceMessage {
                 pid,
                 message_id,
@@ -287,6 +517,169 @@ impl<'a> System<'a> {
 
         RunOnceOutcome::LoopAgain
     }
+
+    /// Grants `target` the right to use `interface` on behalf of `emitter`, which must itself be
+    /// allowed to use this interface.
+    fn grant_interface(
+        &self,
+        emitter: Pid,
+        target: Pid,
+        interface: InterfaceHash,
+    ) -> Result<(), redshirt_interface_interface::ffi::InterfaceGrantError> {
+        if !self.authorizations.is_authorized(emitter, &interface) {
+            return Err(redshirt_interface_interface::ffi::InterfaceGrantError::NotAuthorized);
+        }
+
+        self.authorizations.grant(target, interface);
+        Ok(())
+    }
+
+    /// Builds the answer to a message on the `kernel-debug` interface.
+    fn kernel_debug_response(
+        &self,
+        message: redshirt_kernel_debug_interface::ffi::KernelDebugMessage,
+    ) -> EncodedMessage {
+        use redshirt_kernel_debug_interface::ffi;
+
+        match message {
+            ffi::KernelDebugMessage::GetProcesses => {
+                let processes = self
+                    .core
+                    .processes()
+                    .map(|process| {
+                        let info = self.processes_info.get(process.pid());
+                        ffi::ProcessInfo {
+                            pid: process.pid(),
+                            module_hash: process.module_hash().clone().into(),
+                            name: info.as_ref().and_then(|i| i.name.clone()),
+                            parent: info.as_ref().and_then(|i| i.parent),
+                            start_time: info.as_ref().and_then(|i| i.start_time),
+                            threads: process
+                                .threads()
+                                .into_iter()
+                                .map(|(thread_id, state)| ffi::ThreadInfo {
+                                    thread_id,
+                                    state: thread_state_to_ffi(state),
+                                })
+                                .collect(),
+                            memory_size: process.memory_size(),
+                            cpu_time: process.cpu_time(),
+                            registered_interfaces: process.registered_interfaces(),
+                            notifications_queue_len: u32::try_from(
+                                process.notifications_queue_len(),
+                            )
+                            .unwrap_or(u32::max_value()),
+                        }
+                    })
+                    .collect();
+                ffi::ProcessesList { processes }.encode()
+            }
+            ffi::KernelDebugMessage::GetInterfaces => {
+                let interfaces = self
+                    .core
+                    .interfaces()
+                    .into_iter()
+                    .map(|(interface, state)| ffi::InterfaceInfo {
+                        interface,
+                        handler: match state {
+                            InterfaceState::Registered(pid) => ffi::InterfaceHandler::Process(pid),
+                            InterfaceState::Requested { waiting } => {
+                                ffi::InterfaceHandler::Requested {
+                                    waiting: u32::try_from(waiting).unwrap_or(u32::max_value()),
+                                }
+                            }
+                        },
+                    })
+                    .collect();
+                ffi::InterfacesList { interfaces }.encode()
+            }
+        }
+    }
+
+    /// Starts executing the program contained in a response from the `loader` interface.
+    ///
+    /// `hash` is the hash that was requested from the loader. The response is rejected if its
+    /// content doesn't match it.
+    fn execute_loaded(
+        &self,
+        hash: &ModuleHash,
+        parent: Option<Pid>,
+        response: Result<EncodedMessage, ()>,
+    ) -> Result<Pid, LoadError> {
+        let response = response.map_err(|()| LoadError::BadLoaderResponse)?;
+        let redshirt_loader_interface::ffi::LoadResponse { result, signature } =
+            Decode::decode(response).map_err(|_| LoadError::BadLoaderResponse)?;
+        let bytes = result.map_err(|()| LoadError::NotFound)?;
+
+        let obtained = ModuleHash::from_bytes(&bytes);
+        if obtained != *hash {
+            return Err(LoadError::HashMismatch { obtained });
+        }
+
+        let signature = match signature {
+            Some(signature) => {
+                let signature =
+                    ModuleSignature::from_parts(signature.public_key, &signature.signature)
+                        .ok_or(LoadError::InvalidSignature)?;
+                if !signature.verify(hash) {
+                    return Err(LoadError::InvalidSignature);
+                }
+                Some(signature)
+            }
+            None => None,
+        };
+
+        if let Some(policy) = &self.module_policy {
+            if !policy(hash, signature.as_ref()) {
+                return Err(LoadError::Rejected);
+            }
+        }
+
+        let module = Module::from_bytes(&bytes).map_err(LoadError::InvalidModule)?;
+        self.start_process(&module, None, parent)
+            .map_err(LoadError::StartFailed)
+    }
+
+    /// Starts executing a program, applies the authorizations of its module, and stores the
+    /// information about the process.
+    ///
+    /// If `name` is `None`, the name found in the module, if any, is used.
+    fn start_process(
+        &self,
+        program: &Module,
+        name: Option<String>,
+        parent: Option<Pid>,
+    ) -> Result<Pid, NewErr> {
+        let info = ProcessInfo {
+            module_hash: program.hash().clone(),
+            name: name.or_else(|| program.name().map(String::from)),
+            parent,
+            start_time: self.clock.as_ref().map(|clock| clock()),
+        };
+
+        let extrinsics = match self.extrinsics_logging.get(program.hash()) {
+            Some(config) => Extrinsics::with_config(Default::default(), config.clone()),
+            None => Extrinsics::disabled(Default::default()),
+        };
+
+        let (process, _) = self
+            .core
+            .execute_with_extrinsics(program, extrinsics, |pid| {
+                self.authorizations.process_started(program.hash(), pid);
+                self.processes_info.insert(pid, info);
+
+                // Remember which process is supposed to register the interfaces being lazily
+                // loaded, in case it terminates before doing so.
+                for (interface, handler) in self.lazy_loading.lock().iter_mut() {
+                    if handler.is_none()
+                        && self.interface_handlers.get(interface) == Some(program.hash())
+                    {
+                        *handler = Some(pid);
+                    }
+                }
+            })?;
+        Ok(process.pid())
+    }
 }
 
 impl<'a> SystemBuilder<'a> {
@@ -296,17 +689,52 @@ impl<'a> SystemBuilder<'a> {
         let mut core = CoreBuilder::new();
         let interface_interface_pid = core.reserve_pid();
         let load_source_virtual_pid = core.reserve_pid();
+        let kernel_debug_pid = core.reserve_pid();
 
         SystemBuilder {
             core,
             interface_interface_pid,
             load_source_virtual_pid,
+            kernel_debug_pid,
             startup_processes: Vec::new(),
             programs_to_load: SegQueue::new(),
+            interface_handlers: HashMap::default(),
+            module_policy: None,
+            extrinsics_logging: HashMap::default(),
             native_programs: native::NativeProgramsCollection::new(),
+            authorizations: Arc::new(Authorizations::new()),
+            processes_info: Arc::new(ProcessesInfo::new()),
+            buffers: Arc::new(Buffers::new()),
+            clock: None,
         }
     }
 
+    /// Sets the table of authorizations that the [`System`] must keep up to date.
+    ///
+    /// The same table should be passed to the interface handlers that check authorizations.
+    /// By default, a new empty table is used.
+    pub fn with_authorizations(mut self, authorizations: Arc<Authorizations>) -> Self {
+        self.authorizations = authorizations;
+        self
+    }
+
+    /// Sets the table of information about processes that the [`System`] must keep up to date.
+    ///
+    /// By default, a new empty table is used.
+    pub fn with_processes_info(mut self, processes_info: Arc<ProcessesInfo>) -> Self {
+        self.processes_info = processes_info;
+        self
+    }
+
+    /// Sets the collection of buffers that processes can create and exchange.
+    ///
+    /// The same collection should be passed to the native programs that receive or send
+    /// buffers. By default, a new empty collection is used.
+    pub fn with_buffers(mut self, buffers: Arc<Buffers>) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
     /// Registers native code that can communicate with the WASM programs.
     pub fn with_native_program<T>(mut self, program: T) -> Self
     where
@@ -335,7 +763,7 @@ impl<'a> SystemBuilder<'a> {
     /// times.
     pub fn with_main_programs(self, hashes: impl IntoIterator<Item = ModuleHash>) -> Self {
         for hash in hashes {
-            self.programs_to_load.push(hash);
+            self.programs_to_load.push((hash, None));
         }
         self
     }
@@ -354,12 +782,79 @@ impl<'a> SystemBuilder<'a> {
         self.with_main_programs(iter::once(hash))
     }
 
+    /// Registers a module as the default handler of an interface.
+    ///
+    /// If a process tries to emit a message on this interface while no process has registered
+    /// it, the module will be loaded through the `loader` interface and started. The module is
+    /// expected to register the interface, at which point the message is delivered.
+    ///
+    /// The module is loaded at most once per interface, even if multiple processes are waiting.
+    /// Calling this method multiple times for the same interface overwrites the previous value.
+    pub fn with_interface_handler(mut self, interface: InterfaceHash, module: ModuleHash) -> Self {
+        self.interface_handlers.insert(interface, module);
+        self
+    }
+
+    /// Sets a function that decides whether a module obtained through the `loader` interface is
+    /// allowed to run.
+    ///
+    /// The function is passed the hash of the module and its signature, if the loader has
+    /// provided one. Signatures are verified before the function is called, and modules with an
+    /// invalid signature are always refused.
+    ///
+    /// Modules passed to [`with_startup_process`](SystemBuilder::with_startup_process) or to
+    /// [`System::execute`] aren't subject to this policy. By default, all modules are accepted.
+    pub fn with_module_policy(
+        mut self,
+        policy: impl Fn(&ModuleHash, Option<&ModuleSignature>) -> bool + Send + Sync + 'a,
+    ) -> Self {
+        self.module_policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Logs the calls to extrinsics (for example WASI functions) made by the processes started
+    /// from the given module, similar to what `strace` does.
+    ///
+    /// The calls are sent as messages on the `log` interface.
+    pub fn with_extrinsics_logging(
+        mut self,
+        module: ModuleHash,
+        config: log_calls::LogConfig,
+    ) -> Self {
+        self.extrinsics_logging.insert(module, Arc::new(config));
+        self
+    }
+
+    /// Sets a monotonic clock, returning a number of nanoseconds, used to measure the CPU time
+    /// of each process and the time when each process has started. The CPU time is reported
+    /// through the `kernel-debug` interface.
+    ///
+    /// By default, the CPU time and start time of processes aren't measured.
+    pub fn with_clock(mut self, clock: impl Fn() -> u64 + Send + Sync + 'static) -> Self {
+        let clock: Arc<Clock> = Arc::new(clock);
+        let core_clock = clock.clone();
+        self.core = self.core.with_clock(Box::new(move || core_clock()));
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Sets a function called whenever a message is emitted, delivered, answered or cancelled
+    /// between processes.
+    ///
+    /// Events are timestamped using the clock passed to [`SystemBuilder::with_clock`], if any.
+    /// The function is called synchronously while the [`System`] is running, and should
+    /// therefore return quickly.
+    pub fn with_ipc_tracer(mut self, tracer: impl Fn(TraceEvent) + Send + Sync + 'static) -> Self {
+        self.core = self.core.with_tracer(Box::new(tracer));
+        self
+    }
+
     /// Builds the [`System`].
     ///
     /// Returns an error if any of the programs passed through
     /// [`SystemBuilder::with_startup_process`] fails to start.
     pub fn build(self) -> Result<System<'a>, NewErr> {
-        let core = self.core.build();
+        let core = self.core.with_buffers(self.buffers.clone()).build();
 
         // We ask the core to redirect messages for the `interface` interface towards our
         // "virtual" `Pid`.
@@ -371,18 +866,66 @@ impl<'a> SystemBuilder<'a> {
             Err(_) => unreachable!(),
         };
 
-        for program in self.startup_processes {
-            core.execute(&program)?;
-        }
+        // Same for the `kernel-debug` interface.
+        match core.set_interface_handler(
+            redshirt_kernel_debug_interface::ffi::INTERFACE,
+            self.kernel_debug_pid,
+        ) {
+            Ok(()) => {}
+            Err(_) => unreachable!(),
+        };
 
-        Ok(System {
+        let system = System {
             core,
             native_programs: self.native_programs,
+            authorizations: self.authorizations,
+            processes_info: self.processes_info,
+            buffers: self.buffers,
+            clock: self.clock,
             loader_pid: Spinlock::new(None),
             load_source_virtual_pid: self.load_source_virtual_pid,
             loading_programs: Spinlock::new(Default::default()),
             programs_to_load: self.programs_to_load,
-        })
+            interface_handlers: self.interface_handlers,
+            lazy_loading: Spinlock::new(Default::default()),
+            module_policy: self.module_policy,
+            extrinsics_logging: self.extrinsics_logging,
+        };
+
+        for program in self.startup_processes {
+            system.execute(&program)?;
+        }
+
+        Ok(system)
+    }
+}
+
+/// Converts the state of a thread to its equivalent on the `kernel-debug` interface.
+fn thread_state_to_ffi(state: ThreadState) -> redshirt_kernel_debug_interface::ffi::ThreadState {
+    use redshirt_kernel_debug_interface::ffi;
+
+    match state {
+        ThreadState::Ready => ffi::ThreadState::Ready,
+        ThreadState::Running => ffi::ThreadState::Running,
+        ThreadState::WaitNotification => ffi::ThreadState::WaitNotification,
+        ThreadState::EmitMessage(interface) => ffi::ThreadState::EmitMessage(interface),
+        ThreadState::WaitExtrinsic => ffi::ThreadState::WaitExtrinsic,
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::BadLoaderResponse => write!(f, "Invalid response from the loader"),
+            LoadError::NotFound => write!(f, "Module not found by the loader"),
+            LoadError::HashMismatch { obtained } => {
+                write!(f, "Loader returned a module with the wrong hash: {}", obtained)
+            }
+            LoadError::InvalidSignature => write!(f, "Invalid module signature"),
+            LoadError::Rejected => write!(f, "Module refused by the policy"),
+            LoadError::InvalidModule(err) => write!(f, "Invalid module: {}", err),
+            LoadError::StartFailed(err) => write!(f, "Failed to start the process: {}", err),
+        }
     }
 }
 
@@ -392,11 +935,4 @@ impl<'a> Default for SystemBuilder<'a> {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn send_sync() {
-        fn is_send_sync<T: Send + Sync>() {}
-        is_send_sync::<super::System>()
-    }
-}
+mod tests;
@@ -0,0 +1,155 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use crate::module::ModuleHash;
+use crate::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
+use crate::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{pin::Pin, sync::atomic, task::Poll};
+use crossbeam_queue::SegQueue;
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_loader_interface::ffi::{LoadResponse, LoaderMessage, PublisherSignature};
+use spinning_top::Spinlock;
+
+mod buffers;
+mod grant;
+mod kernel_debug;
+mod lazy_loading;
+mod load_failures;
+mod module_hash;
+mod module_policy;
+mod process_info;
+
+#[test]
+fn send_sync() {
+    fn is_send_sync<T: Send + Sync>() {}
+    is_send_sync::<super::System>()
+}
+
+/// Module that immediately returns.
+fn returning_module() -> Vec<u8> {
+    wat_to_bin!(
+        r#"(module
+        (func $_start (result i32)
+            i32.const 0)
+        (export "_start" (func $_start)))
+    "#
+    )
+    .to_vec()
+}
+
+/// Native program that handles the `loader` interface and serves a fixed list of modules.
+struct TestLoader {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Data and signature to return for each hash. The data doesn't necessarily match the hash,
+    /// in order to simulate a malicious loader.
+    modules: Vec<(ModuleHash, Vec<u8>, Option<PublisherSignature>)>,
+    /// Hashes that have been requested so far, in order.
+    requests: Arc<Spinlock<Vec<ModuleHash>>>,
+    /// Answers waiting to be sent back.
+    answers: SegQueue<(MessageId, LoadResponse)>,
+    /// Woken up when an answer is pushed to `answers`.
+    waker: AtomicWaker,
+}
+
+impl TestLoader {
+    /// Builds a loader that serves the given modules.
+    fn new(modules: Vec<Vec<u8>>) -> (Self, Arc<Spinlock<Vec<ModuleHash>>>) {
+        TestLoader::with_entries(
+            modules
+                .into_iter()
+                .map(|m| (ModuleHash::from_bytes(&m), m, None))
+                .collect(),
+        )
+    }
+
+    /// Builds a loader that returns the given data and signature when the given hash is
+    /// requested.
+    fn with_entries(
+        modules: Vec<(ModuleHash, Vec<u8>, Option<PublisherSignature>)>,
+    ) -> (Self, Arc<Spinlock<Vec<ModuleHash>>>) {
+        let requests = Arc::new(Spinlock::new(Vec::new()));
+        let loader = TestLoader {
+            registered: atomic::AtomicBool::new(false),
+            modules,
+            requests: requests.clone(),
+            answers: SegQueue::new(),
+            waker: AtomicWaker::new(),
+        };
+        (loader, requests)
+    }
+}
+
+impl<'a> NativeProgramRef<'a> for &'a TestLoader {
+    type Future =
+        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event(self) -> Self::Future {
+        Box::pin(future::poll_fn(move |cx| {
+            if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+                return Poll::Ready(NativeProgramEvent::Emit {
+                    interface: redshirt_interface_interface::ffi::INTERFACE,
+                    message_id_write: None,
+                    message: redshirt_interface_interface::ffi::InterfaceMessage::Register(
+                        redshirt_loader_interface::ffi::INTERFACE,
+                    )
+                    .encode(),
+                });
+            }
+
+            self.waker.register(cx.waker());
+            if let Ok((message_id, response)) = self.answers.pop() {
+                return Poll::Ready(NativeProgramEvent::Answer {
+                    message_id,
+                    answer: Ok(response.encode()),
+                });
+            }
+
+            Poll::Pending
+        }))
+    }
+
+    fn interface_message(
+        self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        _: Pid,
+        message: EncodedMessage,
+    ) {
+        assert_eq!(interface, redshirt_loader_interface::ffi::INTERFACE);
+        let LoaderMessage::Load(hash) = LoaderMessage::decode(message).unwrap();
+        let hash = ModuleHash::from(hash);
+
+        let (result, signature) = match self.modules.iter().find(|(h, _, _)| *h == hash) {
+            Some((_, data, signature)) => (Ok(data.clone()), signature.clone()),
+            None => (Err(()), None),
+        };
+        self.requests.lock().push(hash);
+        self.answers
+            .push((message_id.unwrap(), LoadResponse { result, signature }));
+        self.waker.wake();
+    }
+
+    fn process_destroyed(self, _: Pid) {}
+
+    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::module::Module;
+use crate::system::{SystemBuilder, SystemRunOutcome};
+
+use alloc::vec::Vec;
+use futures::prelude::*;
+
+/// Module that creates a buffer, then writes to it and reads from it. Traps if one of the calls
+/// doesn't return what is expected.
+fn buffers_module() -> Vec<u8> {
+    wat_to_bin!(
+        r#"
+(module
+    (import "redshirt" "buffer_create" (func $create (param i32 i32) (result i32)))
+    (import "redshirt" "buffer_len" (func $len (param i32 i32) (result i32)))
+    (import "redshirt" "buffer_read" (func $read (param i32 i32 i32 i32) (result i32)))
+    (import "redshirt" "buffer_write" (func $write (param i32 i32 i32 i32) (result i32)))
+    (import "redshirt" "buffer_destroy" (func $destroy (param i32) (result i32)))
+    (memory $memory 1)
+    (data (i32.const 16) "\01\02\03\04")
+    (func $_start (result i32)
+        (if (call $create (i32.const 4) (i32.const 0)) (then unreachable))
+        (if (call $write (i32.const 0) (i32.const 0) (i32.const 16) (i32.const 4))
+            (then unreachable))
+        (if (i32.eqz (call $write (i32.const 0) (i32.const 1) (i32.const 16) (i32.const 4)))
+            (then unreachable))
+        (if (call $len (i32.const 0) (i32.const 8)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 8)) (i32.const 4)) (then unreachable))
+        (if (call $read (i32.const 0) (i32.const 0) (i32.const 32) (i32.const 4))
+            (then unreachable))
+        (if (i32.ne (i32.load (i32.const 32)) (i32.load (i32.const 16))) (then unreachable))
+        (if (call $destroy (i32.const 0)) (then unreachable))
+        (if (i32.eqz (call $len (i32.const 0) (i32.const 8))) (then unreachable))
+        (i32.const 0))
+    (export "memory" (memory 0))
+    (export "_start" (func $_start)))"#
+    )
+    .to_vec()
+}
+
+/// Module that creates a buffer and asks for its handle to be written out of its memory.
+fn bad_pointer_module() -> Vec<u8> {
+    wat_to_bin!(
+        r#"
+(module
+    (import "redshirt" "buffer_create" (func $create (param i32 i32) (result i32)))
+    (memory $memory 1)
+    (func $_start (result i32)
+        (drop (call $create (i32.const 4) (i32.const 70000)))
+        (i32.const 0))
+    (export "memory" (memory 0))
+    (export "_start" (func $_start)))"#
+    )
+    .to_vec()
+}
+
+#[test]
+fn create_write_read() {
+    let system = SystemBuilder::new()
+        .with_startup_process(Module::from_bytes(buffers_module()).unwrap())
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramFinished { outcome, .. } => assert!(outcome.is_ok()),
+        _ => panic!(),
+    }
+    assert!(system.run().now_or_never().is_none());
+}
+
+#[test]
+fn bad_pointer_aborts() {
+    let system = SystemBuilder::new()
+        .with_startup_process(Module::from_bytes(bad_pointer_module()).unwrap())
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramFinished { outcome, .. } => assert!(outcome.is_err()),
+        _ => panic!(),
+    }
+}
@@ -0,0 +1,41 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::returning_module;
+use crate::module::Module;
+use crate::system::SystemBuilder;
+use crate::InterfaceHash;
+
+use redshirt_interface_interface::ffi::InterfaceGrantError;
+
+const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([7; 32]);
+
+#[test]
+fn grant_requires_authorization() {
+    let system = SystemBuilder::new().build().unwrap();
+    let module = Module::from_bytes(returning_module()).unwrap();
+    let granter = system.execute(&module).unwrap();
+    let target = system.execute(&module).unwrap();
+
+    assert!(matches!(
+        system.grant_interface(granter, target, INTERFACE),
+        Err(InterfaceGrantError::NotAuthorized)
+    ));
+    assert!(!system.authorizations().is_authorized(target, &INTERFACE));
+
+    system.authorizations().grant(granter, INTERFACE);
+    system.grant_interface(granter, target, INTERFACE).unwrap();
+    assert!(system.authorizations().is_authorized(target, &INTERFACE));
+}
@@ -0,0 +1,116 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::module::{Module, ModuleHash};
+use crate::system::SystemBuilder;
+use crate::{Decode as _, InterfaceHash};
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use futures::prelude::*;
+use redshirt_kernel_debug_interface::ffi;
+
+/// Interface that nobody registers.
+const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+    0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37,
+]);
+
+/// Module with one page of memory that emits a message on [`INTERFACE`] and waits for the
+/// interface to be available.
+fn blocked_module() -> Vec<u8> {
+    wat_to_bin!(
+        r#"
+(module
+    (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (memory $memory 1)
+    (data (i32.const 0) "\00\01\02\03\04\05\06\07\10\11\12\13\14\15\16\17\20\21\22\23\24\25\26\27\30\31\32\33\34\35\36\37")
+    (data (i32.const 32) "\01\02\03\04")
+    (data (i32.const 40) "\20\00\00\00\04\00\00\00")
+    (func $_start (result i32)
+        (drop (call $emit_message
+            (i32.const 0) (i32.const 40) (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 48)))
+        (i32.const 0))
+    (export "memory" (memory 0))
+    (export "_start" (func $_start)))"#
+    )
+    .to_vec()
+}
+
+#[test]
+fn processes_list() {
+    let system = SystemBuilder::new()
+        .with_startup_process(Module::from_bytes(blocked_module()).unwrap())
+        .build()
+        .unwrap();
+    assert!(system.run().now_or_never().is_none());
+
+    let response = system.kernel_debug_response(ffi::KernelDebugMessage::GetProcesses);
+    let list = ffi::ProcessesList::decode(response).unwrap();
+
+    assert_eq!(list.processes.len(), 1);
+    let process = &list.processes[0];
+    let expected_hash = ModuleHash::from_bytes(blocked_module());
+    assert_eq!(process.module_hash, <[u8; 32]>::from(expected_hash));
+    assert_eq!(process.memory_size, 65536);
+    assert_eq!(process.cpu_time, None);
+    assert!(process.registered_interfaces.is_empty());
+    assert_eq!(process.threads.len(), 1);
+    assert_eq!(process.threads[0].state, ffi::ThreadState::EmitMessage(INTERFACE));
+}
+
+#[test]
+fn interfaces_list() {
+    let system = SystemBuilder::new()
+        .with_startup_process(Module::from_bytes(blocked_module()).unwrap())
+        .build()
+        .unwrap();
+    assert!(system.run().now_or_never().is_none());
+
+    let response = system.kernel_debug_response(ffi::KernelDebugMessage::GetInterfaces);
+    let list = ffi::InterfacesList::decode(response).unwrap();
+
+    let waiting = list
+        .interfaces
+        .iter()
+        .find(|i| i.interface == INTERFACE)
+        .unwrap();
+    assert_eq!(waiting.handler, ffi::InterfaceHandler::Requested { waiting: 1 });
+
+    // The kernel handles the `kernel-debug` interface itself.
+    assert!(list
+        .interfaces
+        .iter()
+        .any(|i| i.interface == ffi::INTERFACE));
+}
+
+#[test]
+fn cpu_time_measured() {
+    // Clock that advances by one microsecond every time it is read.
+    static CLOCK: AtomicU64 = AtomicU64::new(0);
+
+    let system = SystemBuilder::new()
+        .with_clock(|| CLOCK.fetch_add(1000, Ordering::Relaxed))
+        .with_startup_process(Module::from_bytes(blocked_module()).unwrap())
+        .build()
+        .unwrap();
+    assert!(system.run().now_or_never().is_none());
+
+    let response = system.kernel_debug_response(ffi::KernelDebugMessage::GetProcesses);
+    let list = ffi::ProcessesList::decode(response).unwrap();
+    let cpu_time = list.processes[0].cpu_time.unwrap();
+    assert!(cpu_time > 0);
+    assert!(cpu_time < CLOCK.load(Ordering::Relaxed));
+}
@@ -0,0 +1,175 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::TestLoader;
+use crate::module::{Module, ModuleHash};
+use crate::system::{SystemBuilder, SystemRunOutcome};
+use crate::{Decode as _, InterfaceHash};
+
+use alloc::{vec, vec::Vec};
+use futures::prelude::*;
+use redshirt_kernel_debug_interface::ffi as kernel_debug;
+
+/// Interface that the client module emits a message on.
+const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+    0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37,
+]);
+
+/// Module that emits a message without answer on [`INTERFACE`], waiting for the interface to be
+/// available, then returns.
+fn client() -> Vec<u8> {
+    wat_to_bin!(
+        r#"
+(module
+    (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (memory $memory 1)
+    (data (i32.const 0) "\00\01\02\03\04\05\06\07\10\11\12\13\14\15\16\17\20\21\22\23\24\25\26\27\30\31\32\33\34\35\36\37")
+    (data (i32.const 32) "\01\02\03\04")
+    (data (i32.const 40) "\20\00\00\00\04\00\00\00")
+    (func $_start (result i32)
+        (drop (call $emit_message
+            (i32.const 0) (i32.const 40) (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 48)))
+        (i32.const 0))
+    (export "memory" (memory 0))
+    (export "_start" (func $_start)))"#
+    )
+    .to_vec()
+}
+
+/// Module that registers itself as the handler of [`INTERFACE`], then returns.
+fn handler() -> Vec<u8> {
+    wat_to_bin!(
+        r#"
+(module
+    (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (memory $memory 1)
+    (data (i32.const 0) "\49\6e\56\14\8c\d4\2b\c3\9b\4e\bf\5e\b6\2c\60\4d\7d\d5\70\92\4d\4f\70\df\b3\da\f6\fe\dc\65\93\8a")
+    (data (i32.const 32) "\00\00\01\02\03\04\05\06\07\10\11\12\13\14\15\16\17\20\21\22\23\24\25\26\27\30\31\32\33\34\35\36\37")
+    (data (i32.const 72) "\20\00\00\00\21\00\00\00")
+    (func $_start (result i32)
+        (drop (call $emit_message
+            (i32.const 0) (i32.const 72) (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 80)))
+        (i32.const 0))
+    (export "memory" (memory 0))
+    (export "_start" (func $_start)))"#
+    )
+    .to_vec()
+}
+
+/// Module that returns immediately, without registering any interface.
+fn quitter() -> Vec<u8> {
+    wat_to_bin!(
+        r#"
+(module
+    (memory $memory 1)
+    (func $_start (result i32)
+        (i32.const 0))
+    (export "memory" (memory 0))
+    (export "_start" (func $_start)))"#
+    )
+    .to_vec()
+}
+
+#[test]
+fn handler_loaded_on_demand() {
+    let (loader, requests) = TestLoader::new(vec![handler()]);
+
+    // Two processes wait for the same interface. The handler must only be loaded once.
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_interface_handler(INTERFACE, ModuleHash::from_bytes(handler()))
+        .with_startup_process(Module::from_bytes(client()).unwrap())
+        .with_startup_process(Module::from_bytes(client()).unwrap())
+        .build()
+        .unwrap();
+
+    // The two clients and the handler must all finish successfully.
+    for _ in 0..3 {
+        match futures::executor::block_on(system.run()) {
+            SystemRunOutcome::ProgramFinished { outcome, .. } => assert!(outcome.is_ok()),
+            _ => panic!(),
+        }
+    }
+
+    assert_eq!(*requests.lock(), vec![ModuleHash::from_bytes(handler())]);
+    assert!(system.run().now_or_never().is_none());
+}
+
+#[test]
+fn handler_loaded_again_if_exits_without_registering() {
+    let (loader, requests) = TestLoader::new(vec![quitter()]);
+
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_interface_handler(INTERFACE, ModuleHash::from_bytes(quitter()))
+        .with_startup_process(Module::from_bytes(client()).unwrap())
+        .build()
+        .unwrap();
+
+    // The handler terminates without registering the interface.
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramFinished { outcome, .. } => assert!(outcome.is_ok()),
+        _ => panic!(),
+    }
+    assert_eq!(requests.lock().len(), 1);
+
+    // The next process that waits on the interface must cause the handler to be loaded again.
+    system.execute(&Module::from_bytes(client()).unwrap()).unwrap();
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramFinished { outcome, .. } => assert!(outcome.is_ok()),
+        _ => panic!(),
+    }
+    assert_eq!(*requests.lock(), vec![ModuleHash::from_bytes(quitter()); 2]);
+}
+
+#[test]
+fn nothing_loaded_without_registered_handler() {
+    let (loader, requests) = TestLoader::new(vec![handler()]);
+
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_startup_process(Module::from_bytes(client()).unwrap())
+        .build()
+        .unwrap();
+
+    // The client stays blocked, as nobody handles its interface.
+    assert!(system.run().now_or_never().is_none());
+    assert!(requests.lock().is_empty());
+}
+
+#[test]
+fn handler_unregistered_on_exit() {
+    let system = SystemBuilder::new()
+        .with_startup_process(Module::from_bytes(handler()).unwrap())
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramFinished { outcome, .. } => assert!(outcome.is_ok()),
+        _ => panic!(),
+    }
+
+    // The interfaces registered by a process must be unregistered when it terminates, so that
+    // its default handler can be loaded again.
+    let response = system.kernel_debug_response(kernel_debug::KernelDebugMessage::GetInterfaces);
+    let list = kernel_debug::InterfacesList::decode(response).unwrap();
+    let handler = list
+        .interfaces
+        .iter()
+        .find(|i| i.interface == INTERFACE)
+        .map(|i| &i.handler);
+    assert!(!matches!(handler, Some(kernel_debug::InterfaceHandler::Process(_))));
+}
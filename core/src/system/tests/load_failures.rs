@@ -0,0 +1,65 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::TestLoader;
+use crate::module::ModuleHash;
+use crate::system::{LoadError, SystemBuilder, SystemRunOutcome};
+
+use alloc::vec;
+use futures::prelude::*;
+
+#[test]
+fn module_not_found() {
+    let (loader, requests) = TestLoader::new(vec![]);
+    let hash = ModuleHash::from([0xaa; 32]);
+
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_main_program(hash.clone())
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramLoadFailed {
+            hash: failed,
+            error: LoadError::NotFound,
+        } => assert_eq!(failed, hash),
+        _ => panic!(),
+    }
+
+    assert_eq!(*requests.lock(), vec![hash]);
+    assert!(system.run().now_or_never().is_none());
+}
+
+#[test]
+fn invalid_module() {
+    let data = b"not a wasm module".to_vec();
+    let hash = ModuleHash::from_bytes(&data);
+    let (loader, _) = TestLoader::new(vec![data]);
+
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_main_program(hash.clone())
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramLoadFailed {
+            hash: failed,
+            error: LoadError::InvalidModule(_),
+        } => assert_eq!(failed, hash),
+        _ => panic!(),
+    }
+}
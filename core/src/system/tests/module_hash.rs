@@ -0,0 +1,65 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{returning_module, TestLoader};
+use crate::module::ModuleHash;
+use crate::system::{LoadError, SystemBuilder, SystemRunOutcome};
+
+use alloc::vec;
+use futures::prelude::*;
+
+#[test]
+fn matching_hash_executed() {
+    let (loader, _) = TestLoader::new(vec![returning_module()]);
+
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_main_program(ModuleHash::from_bytes(returning_module()))
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramFinished { outcome, .. } => assert!(outcome.is_ok()),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn mismatching_hash_rejected() {
+    // The loader returns a valid module, but not the one that has been requested.
+    let requested = ModuleHash::from([0xaa; 32]);
+    let (loader, _) =
+        TestLoader::with_entries(vec![(requested.clone(), returning_module(), None)]);
+
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_main_program(requested.clone())
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramLoadFailed {
+            hash,
+            error: LoadError::HashMismatch { obtained },
+        } => {
+            assert_eq!(hash, requested);
+            assert_eq!(obtained, ModuleHash::from_bytes(returning_module()));
+        }
+        _ => panic!(),
+    }
+
+    // The module must not have been started.
+    assert!(system.run().now_or_never().is_none());
+}
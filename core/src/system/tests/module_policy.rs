@@ -0,0 +1,130 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{returning_module, TestLoader};
+use crate::module::ModuleHash;
+use crate::system::{LoadError, SystemBuilder, SystemRunOutcome};
+
+use alloc::vec;
+use futures::prelude::*;
+use redshirt_loader_interface::ffi::PublisherSignature;
+
+/// Signs `hash` with a private key derived from `seed`.
+fn sign(seed: u8, hash: &ModuleHash) -> PublisherSignature {
+    let secret = ed25519_dalek::SecretKey::from_bytes(&[seed; 32]).unwrap();
+    let public = ed25519_dalek::PublicKey::from(&secret);
+    let hash: [u8; 32] = hash.clone().into();
+    let signature = ed25519_dalek::ExpandedSecretKey::from(&secret).sign(&hash, &public);
+    PublisherSignature {
+        public_key: public.to_bytes(),
+        signature: signature.to_bytes().to_vec(),
+    }
+}
+
+/// Returns the public key derived from `seed`.
+fn public_key(seed: u8) -> [u8; 32] {
+    let secret = ed25519_dalek::SecretKey::from_bytes(&[seed; 32]).unwrap();
+    ed25519_dalek::PublicKey::from(&secret).to_bytes()
+}
+
+#[test]
+fn trusted_publisher_accepted() {
+    let hash = ModuleHash::from_bytes(returning_module());
+    let (loader, _) =
+        TestLoader::with_entries(vec![(hash.clone(), returning_module(), Some(sign(1, &hash)))]);
+
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_module_policy(|_, signature| {
+            signature.map_or(false, |s| *s.public_key() == public_key(1))
+        })
+        .with_main_program(hash)
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramFinished { outcome, .. } => assert!(outcome.is_ok()),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn untrusted_publisher_rejected() {
+    let hash = ModuleHash::from_bytes(returning_module());
+    let (loader, _) =
+        TestLoader::with_entries(vec![(hash.clone(), returning_module(), Some(sign(2, &hash)))]);
+
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_module_policy(|_, signature| {
+            signature.map_or(false, |s| *s.public_key() == public_key(1))
+        })
+        .with_main_program(hash)
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramLoadFailed {
+            error: LoadError::Rejected,
+            ..
+        } => {}
+        _ => panic!(),
+    }
+    assert!(system.run().now_or_never().is_none());
+}
+
+#[test]
+fn unsigned_rejected() {
+    let (loader, _) = TestLoader::new(vec![returning_module()]);
+
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_module_policy(|_, signature| signature.is_some())
+        .with_main_program(ModuleHash::from_bytes(returning_module()))
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramLoadFailed {
+            error: LoadError::Rejected,
+            ..
+        } => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn invalid_signature_rejected() {
+    // The signature is valid, but for a different module.
+    let hash = ModuleHash::from_bytes(returning_module());
+    let signature = sign(1, &ModuleHash::from([0xaa; 32]));
+    let (loader, _) =
+        TestLoader::with_entries(vec![(hash.clone(), returning_module(), Some(signature))]);
+
+    // No policy is set. Invalid signatures must be refused anyway.
+    let system = SystemBuilder::new()
+        .with_native_program(loader)
+        .with_main_program(hash)
+        .build()
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramLoadFailed {
+            error: LoadError::InvalidSignature,
+            ..
+        } => {}
+        _ => panic!(),
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::module::{Module, ModuleHash};
+use crate::system::{SystemBuilder, SystemRunOutcome};
+
+use alloc::vec::Vec;
+use futures::prelude::*;
+
+/// Module named `foo` that immediately returns.
+fn named_module() -> Vec<u8> {
+    wat_to_bin!(
+        r#"(module $foo
+        (func $_start (result i32)
+            i32.const 0)
+        (export "_start" (func $_start)))
+    "#
+    )
+    .to_vec()
+}
+
+#[test]
+fn name_from_module() {
+    let system = SystemBuilder::new().with_clock(|| 12).build().unwrap();
+    let pid = system
+        .execute(&Module::from_bytes(named_module()).unwrap())
+        .unwrap();
+
+    let info = system.process_info(pid).unwrap();
+    assert_eq!(info.module_hash, ModuleHash::from_bytes(named_module()));
+    assert_eq!(info.name.as_ref().map(|n| &n[..]), Some("foo"));
+    assert_eq!(info.parent, None);
+    assert_eq!(info.start_time, Some(12));
+}
+
+#[test]
+fn info_reported_on_exit() {
+    let system = SystemBuilder::new().build().unwrap();
+    let pid = system
+        .execute_with_name(&Module::from_bytes(named_module()).unwrap(), "bar")
+        .unwrap();
+
+    match futures::executor::block_on(system.run()) {
+        SystemRunOutcome::ProgramFinished {
+            pid: finished,
+            info,
+            outcome,
+        } => {
+            assert_eq!(finished, pid);
+            assert_eq!(info.name.as_ref().map(|n| &n[..]), Some("bar"));
+            assert!(outcome.is_ok());
+        }
+        _ => panic!(),
+    }
+
+    // The information is no longer available once the process has finished.
+    assert!(system.process_info(pid).is_none());
+    assert!(system.run().now_or_never().is_none());
+}
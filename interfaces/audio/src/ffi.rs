@@ -0,0 +1,159 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Audio streams are sequences of PCM samples that the interface handler plays, for example
+//! through the speakers of the machine.
+//!
+//! Messages are SCALE-encoded [`AudioMessage`]s. Messages that the handler fails to decode are
+//! answered with an error, if they expect an answer.
+//!
+//! Each process allocates the identifiers of its streams. Before any other message can be sent
+//! about a stream, it must be opened with [`AudioMessage::Open`], which contains the version of
+//! this protocol that the process implements and the format of the samples. Handlers refuse to
+//! open streams whose version or format they don't support.
+//!
+//! # Samples
+//!
+//! Samples are sent in frames. A frame contains one sample for each channel, in order. Samples
+//! are little endian. Silence corresponds to samples whose bytes are all zero.
+//!
+//! # Backpressure and underruns
+//!
+//! Streams start playing when samples are first pushed on them. The handler answers each
+//! [`AudioMessage::Push`] once the samples queued on the stream, including the pushed ones, fall
+//! under a handler-defined duration, typically a few tens of milliseconds. Processes are
+//! expected to wait for this answer before pushing more samples.
+//!
+//! If a stream that is playing runs out of samples, the handler plays silence instead and counts
+//! the number of frames of silence played, which it reports in the next [`PushStatus`]. This is
+//! called an underrun. Streams stop playing, and therefore stop underrunning, once they have
+//! been drained with [`AudioMessage::Drain`].
+
+use alloc::vec::Vec;
+use core::fmt;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x50, 0x77, 0x7c, 0x78, 0x64, 0xc9, 0x40, 0x74, 0x84, 0x28, 0x3c, 0x39, 0x2e, 0x85, 0xa3, 0x98,
+    0xfe, 0x3f, 0x5b, 0x72, 0xbe, 0x97, 0xad, 0x96, 0x15, 0x93, 0x3b, 0x19, 0xf0, 0x2e, 0x09, 0x53,
+]);
+
+/// Version of the protocol described in this module.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Message sent on the audio interface.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum AudioMessage {
+    /// Opens a new stream. If an answer is expected, it is a `Result<(), AudioError>`.
+    Open {
+        /// Version of the protocol. Should be [`PROTOCOL_VERSION`].
+        version: u32,
+        /// Identifier of the stream, as chosen by the emitter.
+        id: u32,
+        /// Number of frames per second.
+        sample_rate: u32,
+        /// Number of samples in each frame.
+        channels: u16,
+        /// Format of each sample.
+        format: SampleFormat,
+    },
+
+    /// Closes a stream. The samples that haven't been played yet are discarded. If an answer is
+    /// expected, it is a `Result<(), AudioError>`.
+    Close {
+        /// Identifier of the stream.
+        id: u32,
+    },
+
+    /// Queues samples at the end of a stream. If an answer is expected, it is a
+    /// `Result<PushStatus, AudioError>`, and is sent when the handler is ready to accept more
+    /// samples.
+    Push {
+        /// Identifier of the stream.
+        id: u32,
+        /// Frames to play. Must be a multiple of [`frame_len`] bytes.
+        data: Vec<u8>,
+    },
+
+    /// Waits for all the samples queued on a stream to have been played, then stops the stream
+    /// until more samples are pushed. If an answer is expected, it is a
+    /// `Result<(), AudioError>`, and is sent once the samples have been played.
+    Drain {
+        /// Identifier of the stream.
+        id: u32,
+    },
+}
+
+/// Format of a sample.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum SampleFormat {
+    /// Signed 16 bits integer.
+    I16,
+    /// 32 bits floating point number between -1.0 and 1.0.
+    F32,
+}
+
+impl SampleFormat {
+    /// Returns the number of bytes of a sample.
+    pub fn bytes_per_sample(&self) -> u32 {
+        match self {
+            SampleFormat::I16 => 2,
+            SampleFormat::F32 => 4,
+        }
+    }
+}
+
+/// Successful answer to a [`AudioMessage::Push`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct PushStatus {
+    /// Number of frames of silence that have been played because the stream had run out of
+    /// samples, since the previous answer to a push on this stream.
+    pub underrun_frames: u64,
+}
+
+/// Error that can happen on a stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum AudioError {
+    /// The version passed when opening the stream isn't supported by the handler.
+    UnsupportedVersion,
+    /// The sample rate, the number of channels or the sample format isn't supported by the
+    /// handler.
+    UnsupportedFormat,
+    /// A stream with this identifier already exists.
+    AlreadyExists,
+    /// No stream with this identifier exists.
+    UnknownStream,
+    /// The length of the data isn't a multiple of the length of a frame.
+    InvalidDataLength,
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioError::UnsupportedVersion => write!(f, "Unsupported protocol version"),
+            AudioError::UnsupportedFormat => write!(f, "Unsupported stream format"),
+            AudioError::AlreadyExists => write!(f, "Stream already exists"),
+            AudioError::UnknownStream => write!(f, "Unknown stream"),
+            AudioError::InvalidDataLength => write!(f, "Invalid data length"),
+        }
+    }
+}
+
+/// Returns the number of bytes of a frame of the given number of channels and format.
+pub fn frame_len(channels: u16, format: SampleFormat) -> u32 {
+    u32::from(channels) * format.bytes_per_sample()
+}
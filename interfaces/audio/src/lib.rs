@@ -0,0 +1,128 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Audio interface.
+//!
+//! Allows playing sounds.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod ffi;
+
+/// Stream played by the interface handler. Closed when dropped.
+pub struct Stream {
+    id: u32,
+    sample_rate: u32,
+    channels: u16,
+    format: ffi::SampleFormat,
+}
+
+impl Stream {
+    /// Opens a new stream with the given number of frames per second, samples per frame, and
+    /// sample format.
+    pub async fn open(
+        sample_rate: u32,
+        channels: u16,
+        format: ffi::SampleFormat,
+    ) -> Result<Self, ffi::AudioError> {
+        let id = unsafe {
+            let mut out = [0; 4];
+            redshirt_random_interface::generate_in(&mut out).await;
+            u32::from_le_bytes(out)
+        };
+
+        let message = ffi::AudioMessage::Open {
+            version: ffi::PROTOCOL_VERSION,
+            id,
+            sample_rate,
+            channels,
+            format,
+        };
+
+        unsafe {
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message)
+                .unwrap()
+                .await?;
+        }
+
+        Ok(Stream {
+            id,
+            sample_rate,
+            channels,
+            format,
+        })
+    }
+
+    /// Returns the number of frames per second of the stream.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the number of samples in each frame.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Returns the format of the samples.
+    pub fn format(&self) -> ffi::SampleFormat {
+        self.format
+    }
+
+    /// Queues frames at the end of the stream, and waits until the handler is ready to accept
+    /// more.
+    ///
+    /// See the documentation of the [`ffi`] module for the layout of `data`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the length of `data` isn't a multiple of the length of a frame.
+    ///
+    pub async fn push(&self, data: &[u8]) -> Result<ffi::PushStatus, ffi::AudioError> {
+        let frame_len = ffi::frame_len(self.channels, self.format) as usize;
+        assert_eq!(data.len() % frame_len, 0);
+
+        let message = ffi::AudioMessage::Push {
+            id: self.id,
+            data: data.to_vec(),
+        };
+
+        unsafe {
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message)
+                .unwrap()
+                .await
+        }
+    }
+
+    /// Waits for all the frames pushed on the stream to have been played.
+    pub async fn drain(&self) -> Result<(), ffi::AudioError> {
+        let message = ffi::AudioMessage::Drain { id: self.id };
+        unsafe {
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message)
+                .unwrap()
+                .await
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe {
+            let message = ffi::AudioMessage::Close { id: self.id };
+            redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, message).unwrap();
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The console is a text terminal shared by all processes.
+//!
+//! Messages are SCALE-encoded [`ConsoleMessage`]s. Messages that the handler fails to decode are
+//! answered with an error, if they expect an answer.
+//!
+//! # Output
+//!
+//! Text written to the console is UTF-8, and can contain the following control characters and
+//! ANSI escape sequences. Handlers ignore the characters and sequences they don't support.
+//!
+//! - `\n` moves the cursor to the beginning of the next line, scrolling if necessary.
+//! - `\r` moves the cursor to the beginning of the current line.
+//! - `\x08` (backspace) moves the cursor one column to the left.
+//! - `\t` moves the cursor to the next column that is a multiple of 8.
+//! - `ESC [ <n> ; ... m` changes the color of the text that follows. Supported values are `0`
+//! (reset), `30` to `37` and `90` to `97` (foreground colors), and `39` (default foreground).
+//! - `ESC [ <row> ; <column> H` moves the cursor. Rows and columns start at 1 and default to 1.
+//! - `ESC [ 2 J` clears the screen.
+//! - `ESC [ K` clears the line from the cursor to its end.
+//!
+//! # Input
+//!
+//! The handler reads the keyboard line by line. It echoes what the user types and lets them
+//! erase characters before pressing Enter. [`ConsoleMessage::Read`] only returns characters
+//! of lines that have been completed with Enter, each line ending with `\n`.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xb8, 0x0b, 0x62, 0xb9, 0x25, 0x7c, 0x13, 0x86, 0x69, 0xcf, 0xf5, 0xf0, 0xf0, 0x11, 0xe6, 0x79,
+    0xfa, 0x74, 0xf5, 0x2e, 0x4d, 0x5d, 0xc1, 0xe6, 0x2c, 0xf2, 0x5d, 0x6c, 0x1f, 0xb0, 0x5c, 0xba,
+]);
+
+/// Message sent on the console interface.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum ConsoleMessage {
+    /// Writes UTF-8 text to the console. Never answered.
+    ///
+    /// A UTF-8 character can be split between two consecutive messages of the same process.
+    Write(Vec<u8>),
+
+    /// Reads input from the console. Must be answered with a `Vec<u8>` containing between 1 and
+    /// `max_len` bytes of UTF-8 text, as soon as input is available.
+    ///
+    /// If multiple processes are reading at the same time, input is delivered in the order in
+    /// which the messages have been received.
+    Read {
+        /// Maximum number of bytes to return.
+        max_len: u32,
+    },
+
+    /// Asks for the dimensions of the console. Must be answered with a [`ConsoleSize`].
+    Size,
+}
+
+/// Dimensions of the console.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ConsoleSize {
+    /// Number of characters on each line.
+    pub columns: u32,
+    /// Number of lines.
+    pub rows: u32,
+}
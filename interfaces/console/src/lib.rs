@@ -0,0 +1,81 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Console interface.
+//!
+//! Allows writing text to the console shared by all processes, and reading what the user types.
+//!
+//! > **Note**: Programs compiled for WASI can also use their standard input and output, which
+//! >           the kernel maps to the console.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::future::Future;
+
+pub mod ffi;
+
+pub use ffi::ConsoleSize;
+
+/// Writes UTF-8 text, possibly containing ANSI escape sequences, to the console.
+///
+/// See the [`ffi`] module for the list of supported escape sequences.
+pub fn write(text: &str) {
+    write_bytes(text.as_bytes());
+}
+
+/// Writes bytes to the console. They must form UTF-8 text, but a character can be split between
+/// two consecutive calls.
+pub fn write_bytes(data: &[u8]) {
+    unsafe {
+        let message = ffi::ConsoleMessage::Write(data.to_vec());
+        redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, message).unwrap();
+    }
+}
+
+/// Reads at most `max_len` bytes of the lines that the user has typed.
+///
+/// Waits until at least one byte is available.
+pub fn read(max_len: u32) -> impl Future<Output = Vec<u8>> {
+    unsafe {
+        let message = ffi::ConsoleMessage::Read { max_len };
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message).unwrap()
+    }
+}
+
+/// Reads the next line that the user types, without the final `\n`.
+pub async fn read_line() -> String {
+    let mut line = Vec::new();
+    loop {
+        // We read byte by byte in order to not consume the lines that follow.
+        let data = read(1).await;
+        if data == b"\n" {
+            break;
+        }
+        line.extend(data);
+    }
+
+    String::from_utf8_lossy(&line).into_owned()
+}
+
+/// Returns the dimensions of the console.
+pub fn size() -> impl Future<Output = ConsoleSize> {
+    unsafe {
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, ffi::ConsoleMessage::Size)
+            .unwrap()
+    }
+}
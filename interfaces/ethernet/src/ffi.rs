@@ -24,27 +24,130 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
 
 #[derive(Debug, Encode, Decode)]
 pub enum NetworkMessage {
-    /// Notify of the existence of a new Ethernet interface.
-    // TODO: what if this id was already registered?
+    /// Notify of the existence of a new Ethernet interface. Must answer with a
+    /// [`RegisterInterfaceResponse`].
     RegisterInterface {
         /// Unique per-process identifier.
         id: u64,
         /// MAC address of the interface.
         mac_address: [u8; 6],
+        /// Maximum size, in bytes, of the payload of the Ethernet frames that the interface can
+        /// send and receive. Typically 1500.
+        mtu: u32,
+        /// Number of queues of the interface. Must be at least 1.
+        ///
+        /// Each queue is independent from the others. Frames are only guaranteed to be delivered
+        /// in order within the same queue.
+        num_queues: u16,
+        /// Checksums that the hardware can verify on received frames.
+        rx_checksum_offload: Checksums,
+        /// Checksums that the hardware can compute on frames to send.
+        tx_checksum_offload: Checksums,
     },
 
     /// Removes a previously-registered interface.
     UnregisterInterface(u64),
 
-    /// Notify when an interface has received data (e.g. from the outside world). Must answer with
-    /// a `()` when the send is finished and we're ready to accept a new packet.
+    /// Notify that the link of an interface has gone up or down.
     ///
-    /// The packet must be an Ethernet frame without the CRC.
-    InterfaceOnData(u64, Vec<u8>),
+    /// Interfaces are considered down right after being registered.
+    InterfaceLinkStatus(u64, LinkStatus),
+
+    /// Notify of the latest statistics about an interface.
+    InterfaceStatistics(u64, Statistics),
+
+    /// Notify when an interface has received data (e.g. from the outside world). Must answer with
+    /// a `()` when the send is finished and we're ready to accept new frames on this queue.
+    InterfaceOnData {
+        /// Identifier of the interface.
+        id: u64,
+        /// Queue the frames have been received on. Must be inferior to `num_queues`.
+        queue: u16,
+        /// List of received frames, in the order in which they have been received.
+        frames: Vec<Frame>,
+    },
 
-    /// Asks for the next packet of data to send out through this interface (e.g. going towards
-    /// the outside world). Must answer with a `Vec<u8>`.
+    /// Asks for the next frames of data to send out through this interface (e.g. going towards
+    /// the outside world). Must answer with a `Vec<Frame>` containing between 1 and `max_frames`
+    /// frames.
+    InterfaceWaitData {
+        /// Identifier of the interface.
+        id: u64,
+        /// Queue the frames will be sent out on. Must be inferior to `num_queues`.
+        queue: u16,
+        /// Maximum number of frames to put in the response. Must be at least 1.
+        max_frames: u32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct RegisterInterfaceResponse {
+    pub result: Result<(), RegisterInterfaceError>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum RegisterInterfaceError {
+    /// The same process has already registered an interface with the same identifier.
+    DuplicateId,
+    /// The interface configuration is invalid, for example it has zero queues.
+    InvalidConfig,
+}
+
+/// Ethernet frame sent or received on an interface.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Frame {
+    /// Ethernet frame, without the CRC.
+    pub data: Vec<u8>,
+    /// For a received frame, the checksums that have been successfully verified by the hardware.
+    /// For a frame to send, the checksums that the hardware must fill.
     ///
-    /// The packet must be an Ethernet frame without the CRC.
-    InterfaceWaitData(u64),
+    /// Can only contain checksums that have been indicated as supported when registering the
+    /// interface.
+    pub checksums: Checksums,
+}
+
+/// Set of checksums that can be offloaded to the hardware.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Checksums {
+    /// Checksum of the header of IPv4 packets.
+    pub ipv4: bool,
+    /// Checksum of TCP segments, over IPv4 or IPv6.
+    pub tcp: bool,
+    /// Checksum of UDP datagrams, over IPv4 or IPv6.
+    pub udp: bool,
+}
+
+/// Status of the link of an interface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum LinkStatus {
+    /// Not connected to anything.
+    Down,
+    /// Connected.
+    Up {
+        /// Speed of the link in Mbit/s, if known.
+        speed_mbps: Option<u32>,
+        /// True if the link is full-duplex.
+        full_duplex: bool,
+    },
+    /// The driver has no way to detect whether the interface is connected.
+    Unknown,
+}
+
+/// Statistics about an interface since it has been registered.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Statistics {
+    /// Number of frames received from the network.
+    pub rx_frames: u64,
+    /// Number of bytes received from the network.
+    pub rx_bytes: u64,
+    /// Number of incoming frames that have been discarded because of an error.
+    pub rx_errors: u64,
+    /// Number of incoming frames that have been discarded because of a lack of space.
+    pub rx_dropped: u64,
+    /// Number of frames sent out to the network.
+    pub tx_frames: u64,
+    /// Number of bytes sent out to the network.
+    pub tx_bytes: u64,
+    /// Number of frames that failed to be sent out.
+    pub tx_errors: u64,
 }
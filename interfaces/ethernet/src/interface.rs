@@ -23,16 +23,27 @@
 //! # Usage
 //!
 //! - Call [`register_interface`] in order to notify of the existence of an interface.
-//! - You obtain a [`NetInterfaceRegistration`] that you can use to report packets that came from
-//! the wire, and from which you can obtain packets to send to the wire.
+//! - You obtain a [`NetInterfaceRegistration`] that you can use to report frames that came from
+//! the wire, and from which you can obtain frames to send to the wire.
+//! - Use [`NetInterfaceRegistration::set_link_status`] to report when the link goes up or down,
+//! and [`NetInterfaceRegistration::report_statistics`] to report statistics.
 //! - Dropping the [`NetInterfaceRegistration`] unregisters the interface.
 //!
+//! # Queues
+//!
+//! An interface can have multiple queues, for example if the hardware has multiple reception and
+//! transmission rings. Each queue can be used independently from the others, and frames are only
+//! guaranteed to be delivered in order within the same queue. The methods that don't take a queue
+//! index as parameter use the queue 0.
+//!
 
 use crate::ffi;
 use core::fmt;
 use futures::lock::{Mutex, MutexGuard};
 use redshirt_syscalls::Encode as _;
 
+pub use ffi::{Checksums, Frame, LinkStatus, RegisterInterfaceError, Statistics};
+
 /// Configuration of an interface to register.
 #[derive(Debug)]
 pub struct InterfaceConfig {
@@ -40,27 +51,64 @@ pub struct InterfaceConfig {
     ///
     /// If this is a virtual device, feel free to randomly generate a MAC address.
     pub mac_address: [u8; 6],
+
+    /// Maximum size, in bytes, of the payload of the Ethernet frames. Typically 1500.
+    pub mtu: u32,
+
+    /// Number of queues of the interface. Must be at least 1.
+    pub num_queues: u16,
+
+    /// Maximum number of frames that can be obtained at once when asking for frames to send.
+    /// Must be at least 1.
+    pub tx_batch_size: u32,
+
+    /// Checksums that the hardware verifies on received frames.
+    pub rx_checksum_offload: Checksums,
+
+    /// Checksums that the hardware can compute on frames to send.
+    pub tx_checksum_offload: Checksums,
 }
 
 /// Registers a new network interface.
-pub async fn register_interface(config: InterfaceConfig) -> NetInterfaceRegistration {
-    unsafe {
-        let id = redshirt_random_interface::generate_u64().await;
+///
+/// Returns an error if the network manager refuses the registration.
+pub async fn register_interface(
+    config: InterfaceConfig,
+) -> Result<NetInterfaceRegistration, RegisterInterfaceError> {
+    if config.num_queues == 0 || config.tx_batch_size == 0 {
+        return Err(RegisterInterfaceError::InvalidConfig);
+    }
+
+    let id = redshirt_random_interface::generate_u64().await;
 
-        redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, &{
+    let response: ffi::RegisterInterfaceResponse = unsafe {
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, {
             ffi::NetworkMessage::RegisterInterface {
                 id,
                 mac_address: config.mac_address,
+                mtu: config.mtu,
+                num_queues: config.num_queues,
+                rx_checksum_offload: config.rx_checksum_offload,
+                tx_checksum_offload: config.tx_checksum_offload,
             }
         })
-        .unwrap();
+        .unwrap()
+        .await
+    };
+    response.result?;
 
-        NetInterfaceRegistration {
-            id,
+    let queues = (0..config.num_queues)
+        .map(|queue| Queue {
             packet_from_net: Mutex::new(None),
-            packet_to_net: Mutex::new(build_packet_to_net(id)),
-        }
-    }
+            packet_to_net: Mutex::new(build_packet_to_net(id, queue, config.tx_batch_size)),
+        })
+        .collect();
+
+    Ok(NetInterfaceRegistration {
+        id,
+        tx_batch_size: config.tx_batch_size,
+        queues,
+    })
 }
 
 /// Registered network interface.
@@ -69,20 +117,37 @@ pub async fn register_interface(config: InterfaceConfig) -> NetInterfaceRegistra
 pub struct NetInterfaceRegistration {
     /// Identifier of the interface in the network manager.
     id: u64,
-    /// Future that will resolve once we receive a packet from the network manager to send to the
+    /// Value of [`InterfaceConfig::tx_batch_size`].
+    tx_batch_size: u32,
+    /// State of each queue. Never empty.
+    queues: Vec<Queue>,
+}
+
+/// State of a queue of a [`NetInterfaceRegistration`].
+struct Queue {
+    /// Future that will resolve once we receive frames from the network manager to send to the
     /// network. Must always be `Some`.
-    packet_to_net: Mutex<redshirt_syscalls::MessageResponseFuture<Vec<u8>>>,
-    /// Future that will resolve once we have successfully delivered a packet from the network,
-    /// and are ready to deliver a next one.
+    packet_to_net: Mutex<redshirt_syscalls::MessageResponseFuture<Vec<Frame>>>,
+    /// Future that will resolve once we have successfully delivered frames from the network,
+    /// and are ready to deliver next ones.
     packet_from_net: Mutex<Option<redshirt_syscalls::MessageResponseFuture<()>>>,
 }
 
-/// Build a `Future` resolving to the next packet to send to the network.
+/// Build a `Future` resolving to the next frames to send to the network.
 ///
-/// Only one such `Future` must be alive at any given point in time.
-fn build_packet_to_net(interface_id: u64) -> redshirt_syscalls::MessageResponseFuture<Vec<u8>> {
+/// Only one such `Future` per queue must be alive at any given point in time.
+fn build_packet_to_net(
+    interface_id: u64,
+    queue: u16,
+    max_frames: u32,
+) -> redshirt_syscalls::MessageResponseFuture<Vec<Frame>> {
     unsafe {
-        let message = ffi::NetworkMessage::InterfaceWaitData(interface_id).encode();
+        let message = ffi::NetworkMessage::InterfaceWaitData {
+            id: interface_id,
+            queue,
+            max_frames,
+        }
+        .encode();
         let msg_id = redshirt_syscalls::MessageBuilder::new()
             .add_data(&message)
             .emit_with_response_raw(&ffi::INTERFACE)
@@ -92,16 +157,34 @@ fn build_packet_to_net(interface_id: u64) -> redshirt_syscalls::MessageResponseF
 }
 
 impl NetInterfaceRegistration {
-    /// Wait until the network manager is ready to accept a packet coming from the network.
+    /// Returns the number of queues of this interface, as passed in the configuration.
+    pub fn num_queues(&self) -> u16 {
+        // The number of queues comes from a `u16`.
+        self.queues.len() as u16
+    }
+
+    /// Wait until the network manager is ready to accept frames coming from the network on the
+    /// queue 0.
     ///
-    /// Returns a [`PacketFromNetwork`] object that allows you to transmit the packet.
+    /// Returns a [`PacketFromNetwork`] object that allows you to transmit the frames.
     ///
     /// > **Note**: It is possible to call this method multiple times on the same
     /// >           [`NetInterfaceRegistration`]. If that is done, no guarantee exists as to which
     /// >           `Future` finishes first.
     pub async fn packet_from_network<'a>(&'a self) -> PacketFromNetwork<'a> {
+        self.packet_from_network_queue(0).await
+    }
+
+    /// Same as [`packet_from_network`](NetInterfaceRegistration::packet_from_network), but for
+    /// the given queue.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `queue` is superior or equal to the number of queues.
+    ///
+    pub async fn packet_from_network_queue<'a>(&'a self, queue: u16) -> PacketFromNetwork<'a> {
         // Wait for the previous send to be finished.
-        let mut packet_from_net = self.packet_from_net.lock().await;
+        let mut packet_from_net = self.queues[usize::from(queue)].packet_from_net.lock().await;
         if let Some(fut) = packet_from_net.as_mut() {
             fut.await;
         }
@@ -109,21 +192,56 @@ impl NetInterfaceRegistration {
 
         PacketFromNetwork {
             parent: self,
+            queue,
             send_future: packet_from_net,
         }
     }
 
-    /// Returns the next packet to send to the network.
+    /// Returns the next frames to send to the network on the queue 0.
+    ///
+    /// The returned list is never empty, and never contains more than
+    /// [`InterfaceConfig::tx_batch_size`] frames.
     ///
     /// > **Note**: It is possible to call this method multiple times on the same
     /// >           [`NetInterfaceRegistration`]. If that is done, no guarantee exists as to which
     /// >           `Future` finishes first.
-    pub async fn packet_to_send(&self) -> Vec<u8> {
-        let mut packet_to_net = self.packet_to_net.lock().await;
+    pub async fn packets_to_send(&self) -> Vec<Frame> {
+        self.packets_to_send_queue(0).await
+    }
+
+    /// Same as [`packets_to_send`](NetInterfaceRegistration::packets_to_send), but for the given
+    /// queue.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `queue` is superior or equal to the number of queues.
+    ///
+    pub async fn packets_to_send_queue(&self, queue: u16) -> Vec<Frame> {
+        let mut packet_to_net = self.queues[usize::from(queue)].packet_to_net.lock().await;
         let data = (&mut *packet_to_net).await;
-        *packet_to_net = build_packet_to_net(self.id);
+        *packet_to_net = build_packet_to_net(self.id, queue, self.tx_batch_size);
         data
     }
+
+    /// Notifies the network manager that the link has gone up or down.
+    ///
+    /// Interfaces are considered down right after they have been registered.
+    pub fn set_link_status(&self, status: LinkStatus) {
+        unsafe {
+            let message = ffi::NetworkMessage::InterfaceLinkStatus(self.id, status);
+            redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, &message).unwrap();
+        }
+    }
+
+    /// Reports to the network manager the latest statistics about the interface.
+    ///
+    /// The statistics must be cumulative since the registration.
+    pub fn report_statistics(&self, statistics: &Statistics) {
+        unsafe {
+            let message = ffi::NetworkMessage::InterfaceStatistics(self.id, statistics.clone());
+            redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, &message).unwrap();
+        }
+    }
 }
 
 impl fmt::Debug for NetInterfaceRegistration {
@@ -143,20 +261,36 @@ impl Drop for NetInterfaceRegistration {
     }
 }
 
-/// Allows you to transmit a packet received from the network to the manager.
+/// Allows you to transmit frames received from the network to the manager.
 #[must_use]
 pub struct PacketFromNetwork<'a> {
     parent: &'a NetInterfaceRegistration,
+    queue: u16,
     send_future: MutexGuard<'a, Option<redshirt_syscalls::MessageResponseFuture<()>>>,
 }
 
 impl<'a> PacketFromNetwork<'a> {
-    /// Send the packet to the manager.
-    pub fn send(mut self, data: impl Into<Vec<u8>>) {
+    /// Send a single frame to the manager, without any checksum verified.
+    ///
+    /// The frame must be an Ethernet frame without the CRC.
+    pub fn send(self, data: impl Into<Vec<u8>>) {
+        self.send_frames(vec![Frame {
+            data: data.into(),
+            checksums: Checksums::default(),
+        }])
+    }
+
+    /// Send multiple frames at once to the manager, in the order in which they have been
+    /// received.
+    pub fn send_frames(mut self, frames: impl Into<Vec<Frame>>) {
         unsafe {
             debug_assert!(self.send_future.is_none());
-            let message =
-                ffi::NetworkMessage::InterfaceOnData(self.parent.id, data.into()).encode();
+            let message = ffi::NetworkMessage::InterfaceOnData {
+                id: self.parent.id,
+                queue: self.queue,
+                frames: frames.into(),
+            }
+            .encode();
             let msg_id = redshirt_syscalls::MessageBuilder::new()
                 .add_data(&message)
                 .emit_with_response_raw(&ffi::INTERFACE)
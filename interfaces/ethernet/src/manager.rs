@@ -0,0 +1,574 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! State machine of a handler of the Ethernet interface.
+//!
+//! This module is meant to be used by network managers, in other words programs that register
+//! themselves as the handler of the Ethernet interface. It keeps track of the interfaces that
+//! drivers have registered and of the frames that flow through them, and indicates how to answer
+//! the messages sent by the drivers.
+//!
+//! # Usage
+//!
+//! - Pass each decoded [`NetworkMessage`](crate::ffi::NetworkMessage) to
+//! [`NetworkManager::handle_message`], and each process destruction to
+//! [`NetworkManager::process_destroyed`].
+//! - These methods, as well as [`NetworkManager::send`], return a list of [`Answer`]s to send
+//! back to the drivers.
+//! - Frames received from the network can be obtained with [`NetworkManager::pop_received`].
+//!
+
+use crate::ffi::{
+    Checksums, Frame, LinkStatus, NetworkMessage, RegisterInterfaceError,
+    RegisterInterfaceResponse, Statistics,
+};
+use redshirt_syscalls::{MessageId, Pid};
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
+
+/// Identifier of an interface within the [`NetworkManager`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct InterfaceId {
+    /// Process that has registered the interface.
+    pub pid: Pid,
+    /// Identifier chosen by this process.
+    pub id: u64,
+}
+
+/// Answer to send back to a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    /// Answer to a [`NetworkMessage::RegisterInterface`] message.
+    Register(RegisterInterfaceResponse),
+    /// Answer to a [`NetworkMessage::InterfaceOnData`] message. The frames have been processed
+    /// and the driver can send the next ones. Must be encoded as `()`.
+    DataProcessed,
+    /// Answer to a [`NetworkMessage::InterfaceWaitData`] message, containing the frames to send.
+    DataToSend(Vec<Frame>),
+    /// The message is invalid, for example because it refers to an unknown interface or queue,
+    /// or the interface has been unregistered while the message was waiting for an answer.
+    /// Must be sent back as an error.
+    Error,
+}
+
+/// Frame that has been received from the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedFrame {
+    /// Interface the frame has been received on.
+    pub interface: InterfaceId,
+    /// Queue the frame has been received on.
+    pub queue: u16,
+    /// The frame itself.
+    pub frame: Frame,
+}
+
+/// Error when an interface or queue doesn't exist.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UnknownInterfaceErr;
+
+/// State machine of a handler of the Ethernet interface. See the module-level documentation.
+#[derive(Debug, Default)]
+pub struct NetworkManager {
+    /// List of registered interfaces.
+    interfaces: HashMap<InterfaceId, Interface>,
+    /// Frames received from the network, in the order in which they have been received.
+    received: VecDeque<ReceivedFrame>,
+}
+
+/// State of a registered interface.
+#[derive(Debug)]
+pub struct Interface {
+    /// MAC address of the interface.
+    pub mac_address: [u8; 6],
+    /// Maximum size of the payload of the frames.
+    pub mtu: u32,
+    /// Checksums that the hardware verifies on received frames.
+    pub rx_checksum_offload: Checksums,
+    /// Checksums that the hardware can compute on frames to send.
+    pub tx_checksum_offload: Checksums,
+    /// Latest reported status of the link.
+    pub link_status: LinkStatus,
+    /// Latest reported statistics.
+    pub statistics: Statistics,
+    /// State of each queue. Never empty.
+    queues: Vec<Queue>,
+}
+
+/// State of a queue of an [`Interface`].
+#[derive(Debug, Default)]
+struct Queue {
+    /// `InterfaceWaitData` messages waiting for frames to send, with their `max_frames`.
+    waiting: VecDeque<(MessageId, u32)>,
+    /// Frames to send that no message has asked for yet.
+    to_send: VecDeque<Frame>,
+}
+
+impl NetworkManager {
+    /// Initializes a new manager without any interface.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the state of the given interface, if it is registered.
+    pub fn interface(&self, interface: &InterfaceId) -> Option<&Interface> {
+        self.interfaces.get(interface)
+    }
+
+    /// Returns the list of registered interfaces.
+    pub fn interfaces(&self) -> impl Iterator<Item = (&InterfaceId, &Interface)> {
+        self.interfaces.iter()
+    }
+
+    /// Processes a message sent by a driver, and returns the answers to send back.
+    ///
+    /// The returned list can contain answers to previous messages, and can be empty if the
+    /// message doesn't have to be answered yet.
+    pub fn handle_message(
+        &mut self,
+        emitter_pid: Pid,
+        message_id: Option<MessageId>,
+        message: NetworkMessage,
+    ) -> Vec<(MessageId, Answer)> {
+        match message {
+            NetworkMessage::RegisterInterface {
+                id,
+                mac_address,
+                mtu,
+                num_queues,
+                rx_checksum_offload,
+                tx_checksum_offload,
+            } => {
+                let result = match self.interfaces.entry(InterfaceId {
+                    pid: emitter_pid,
+                    id,
+                }) {
+                    Entry::Occupied(_) => Err(RegisterInterfaceError::DuplicateId),
+                    Entry::Vacant(_) if num_queues == 0 => {
+                        Err(RegisterInterfaceError::InvalidConfig)
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(Interface {
+                            mac_address,
+                            mtu,
+                            rx_checksum_offload,
+                            tx_checksum_offload,
+                            link_status: LinkStatus::Down,
+                            statistics: Statistics::default(),
+                            queues: (0..num_queues).map(|_| Queue::default()).collect(),
+                        });
+                        Ok(())
+                    }
+                };
+
+                message_id
+                    .map(|m| (m, Answer::Register(RegisterInterfaceResponse { result })))
+                    .into_iter()
+                    .collect()
+            }
+
+            NetworkMessage::UnregisterInterface(id) => {
+                let interface = InterfaceId {
+                    pid: emitter_pid,
+                    id,
+                };
+                match self.interfaces.remove(&interface) {
+                    Some(interface) => interface.cancel_waiting(),
+                    None => Vec::new(),
+                }
+            }
+
+            NetworkMessage::InterfaceLinkStatus(id, status) => {
+                let interface = InterfaceId {
+                    pid: emitter_pid,
+                    id,
+                };
+                if let Some(interface) = self.interfaces.get_mut(&interface) {
+                    interface.link_status = status;
+                }
+                Vec::new()
+            }
+
+            NetworkMessage::InterfaceStatistics(id, statistics) => {
+                let interface = InterfaceId {
+                    pid: emitter_pid,
+                    id,
+                };
+                if let Some(interface) = self.interfaces.get_mut(&interface) {
+                    interface.statistics = statistics;
+                }
+                Vec::new()
+            }
+
+            NetworkMessage::InterfaceOnData { id, queue, frames } => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return Vec::new(),
+                };
+
+                let interface = InterfaceId {
+                    pid: emitter_pid,
+                    id,
+                };
+                let answer = match self.interfaces.get(&interface) {
+                    Some(state) if usize::from(queue) < state.queues.len() => {
+                        self.received
+                            .extend(frames.into_iter().map(|frame| ReceivedFrame {
+                                interface,
+                                queue,
+                                frame,
+                            }));
+                        Answer::DataProcessed
+                    }
+                    _ => Answer::Error,
+                };
+
+                vec![(message_id, answer)]
+            }
+
+            NetworkMessage::InterfaceWaitData {
+                id,
+                queue,
+                max_frames,
+            } => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return Vec::new(),
+                };
+
+                let interface = InterfaceId {
+                    pid: emitter_pid,
+                    id,
+                };
+                let queue = match self.interfaces.get_mut(&interface) {
+                    Some(state) if max_frames != 0 => state.queues.get_mut(usize::from(queue)),
+                    _ => None,
+                };
+
+                match queue {
+                    Some(queue) => {
+                        queue.waiting.push_back((message_id, max_frames));
+                        queue.flush()
+                    }
+                    None => vec![(message_id, Answer::Error)],
+                }
+            }
+        }
+    }
+
+    /// Queues a frame to be sent out on the given interface and queue, and returns the answers
+    /// to send back.
+    pub fn send(
+        &mut self,
+        interface: &InterfaceId,
+        queue: u16,
+        frame: Frame,
+    ) -> Result<Vec<(MessageId, Answer)>, UnknownInterfaceErr> {
+        let queue = self
+            .interfaces
+            .get_mut(interface)
+            .and_then(|state| state.queues.get_mut(usize::from(queue)))
+            .ok_or(UnknownInterfaceErr)?;
+        queue.to_send.push_back(frame);
+        Ok(queue.flush())
+    }
+
+    /// Returns the oldest frame received from the network that hasn't been popped yet.
+    pub fn pop_received(&mut self) -> Option<ReceivedFrame> {
+        self.received.pop_front()
+    }
+
+    /// Removes all the interfaces of the given process, and returns the answers to send back.
+    ///
+    /// Since the process is dead, the answers are only returned for consistency and can be
+    /// ignored.
+    pub fn process_destroyed(&mut self, pid: Pid) -> Vec<(MessageId, Answer)> {
+        let removed = self
+            .interfaces
+            .keys()
+            .filter(|interface| interface.pid == pid)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        removed
+            .into_iter()
+            .flat_map(|interface| self.interfaces.remove(&interface).unwrap().cancel_waiting())
+            .collect()
+    }
+}
+
+impl Interface {
+    /// Returns the number of queues of this interface.
+    pub fn num_queues(&self) -> u16 {
+        // The number of queues comes from a `u16`.
+        self.queues.len() as u16
+    }
+
+    /// Answers all the pending `InterfaceWaitData` messages with an error.
+    fn cancel_waiting(self) -> Vec<(MessageId, Answer)> {
+        self.queues
+            .into_iter()
+            .flat_map(|queue| queue.waiting.into_iter())
+            .map(|(message_id, _)| (message_id, Answer::Error))
+            .collect()
+    }
+}
+
+impl Queue {
+    /// Answers the pending `InterfaceWaitData` messages for as long as there are frames to send.
+    fn flush(&mut self) -> Vec<(MessageId, Answer)> {
+        let mut answers = Vec::new();
+
+        while !self.to_send.is_empty() {
+            let (message_id, max_frames) = match self.waiting.pop_front() {
+                Some(w) => w,
+                None => break,
+            };
+
+            let num_frames = self.to_send.len().min(max_frames as usize);
+            let frames = self.to_send.drain(..num_frames).collect();
+            answers.push((message_id, Answer::DataToSend(frames)));
+        }
+
+        answers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Answer, InterfaceId, NetworkManager, ReceivedFrame};
+    use crate::ffi::{
+        Checksums, Frame, LinkStatus, NetworkMessage, RegisterInterfaceError,
+        RegisterInterfaceResponse, Statistics,
+    };
+    use redshirt_syscalls::{MessageId, Pid};
+    use std::convert::TryFrom as _;
+
+    fn message_id(n: u64) -> MessageId {
+        MessageId::try_from(n).unwrap()
+    }
+
+    fn register(id: u64, num_queues: u16) -> NetworkMessage {
+        NetworkMessage::RegisterInterface {
+            id,
+            mac_address: [1, 2, 3, 4, 5, 6],
+            mtu: 1500,
+            num_queues,
+            rx_checksum_offload: Checksums::default(),
+            tx_checksum_offload: Checksums::default(),
+        }
+    }
+
+    fn frame(byte: u8) -> Frame {
+        Frame {
+            data: vec![byte; 64],
+            checksums: Checksums::default(),
+        }
+    }
+
+    #[test]
+    fn duplicate_id_rejected() {
+        let mut manager = NetworkManager::new();
+        let pid = Pid::from(1);
+
+        let answers = manager.handle_message(pid, Some(message_id(10)), register(5, 1));
+        assert_eq!(
+            answers,
+            vec![(
+                message_id(10),
+                Answer::Register(RegisterInterfaceResponse { result: Ok(()) })
+            )]
+        );
+
+        let answers = manager.handle_message(pid, Some(message_id(11)), register(5, 1));
+        assert_eq!(
+            answers,
+            vec![(
+                message_id(11),
+                Answer::Register(RegisterInterfaceResponse {
+                    result: Err(RegisterInterfaceError::DuplicateId)
+                })
+            )]
+        );
+
+        // Identifiers are per process.
+        let answers = manager.handle_message(Pid::from(2), Some(message_id(12)), register(5, 1));
+        assert_eq!(
+            answers,
+            vec![(
+                message_id(12),
+                Answer::Register(RegisterInterfaceResponse { result: Ok(()) })
+            )]
+        );
+        assert_eq!(manager.interfaces().count(), 2);
+    }
+
+    #[test]
+    fn zero_queues_rejected() {
+        let mut manager = NetworkManager::new();
+        let answers = manager.handle_message(Pid::from(1), Some(message_id(10)), register(5, 0));
+        assert_eq!(
+            answers,
+            vec![(
+                message_id(10),
+                Answer::Register(RegisterInterfaceResponse {
+                    result: Err(RegisterInterfaceError::InvalidConfig)
+                })
+            )]
+        );
+        assert_eq!(manager.interfaces().count(), 0);
+    }
+
+    #[test]
+    fn link_status_and_statistics() {
+        let mut manager = NetworkManager::new();
+        let interface = InterfaceId {
+            pid: Pid::from(1),
+            id: 5,
+        };
+        manager.handle_message(interface.pid, Some(message_id(10)), register(5, 1));
+        assert_eq!(
+            manager.interface(&interface).unwrap().link_status,
+            LinkStatus::Down
+        );
+
+        let status = LinkStatus::Up {
+            speed_mbps: Some(100),
+            full_duplex: true,
+        };
+        let statistics = Statistics {
+            rx_frames: 3,
+            ..Default::default()
+        };
+        let message = NetworkMessage::InterfaceLinkStatus(5, status);
+        assert!(manager.handle_message(interface.pid, None, message).is_empty());
+        let message = NetworkMessage::InterfaceStatistics(5, statistics.clone());
+        assert!(manager.handle_message(interface.pid, None, message).is_empty());
+
+        let state = manager.interface(&interface).unwrap();
+        assert_eq!(state.link_status, status);
+        assert_eq!(state.statistics, statistics);
+        assert_eq!(state.mtu, 1500);
+    }
+
+    #[test]
+    fn batched_received_frames() {
+        let mut manager = NetworkManager::new();
+        let interface = InterfaceId {
+            pid: Pid::from(1),
+            id: 5,
+        };
+        manager.handle_message(interface.pid, Some(message_id(10)), register(5, 2));
+
+        let message = NetworkMessage::InterfaceOnData {
+            id: 5,
+            queue: 1,
+            frames: vec![frame(1), frame(2), frame(3)],
+        };
+        let answers = manager.handle_message(interface.pid, Some(message_id(11)), message);
+        assert_eq!(answers, vec![(message_id(11), Answer::DataProcessed)]);
+
+        for byte in 1..=3 {
+            let expected = ReceivedFrame {
+                interface,
+                queue: 1,
+                frame: frame(byte),
+            };
+            assert_eq!(manager.pop_received(), Some(expected));
+        }
+        assert_eq!(manager.pop_received(), None);
+
+        // Queue out of range.
+        let message = NetworkMessage::InterfaceOnData {
+            id: 5,
+            queue: 2,
+            frames: vec![frame(4)],
+        };
+        let answers = manager.handle_message(interface.pid, Some(message_id(12)), message);
+        assert_eq!(answers, vec![(message_id(12), Answer::Error)]);
+        assert_eq!(manager.pop_received(), None);
+    }
+
+    #[test]
+    fn batched_frames_to_send() {
+        let mut manager = NetworkManager::new();
+        let interface = InterfaceId {
+            pid: Pid::from(1),
+            id: 5,
+        };
+        manager.handle_message(interface.pid, Some(message_id(10)), register(5, 1));
+
+        for byte in 1..=5 {
+            assert!(manager.send(&interface, 0, frame(byte)).unwrap().is_empty());
+        }
+
+        let wait = || NetworkMessage::InterfaceWaitData {
+            id: 5,
+            queue: 0,
+            max_frames: 2,
+        };
+        let answers = manager.handle_message(interface.pid, Some(message_id(11)), wait());
+        assert_eq!(
+            answers,
+            vec![(message_id(11), Answer::DataToSend(vec![frame(1), frame(2)]))]
+        );
+        let answers = manager.handle_message(interface.pid, Some(message_id(12)), wait());
+        assert_eq!(
+            answers,
+            vec![(message_id(12), Answer::DataToSend(vec![frame(3), frame(4)]))]
+        );
+        let answers = manager.handle_message(interface.pid, Some(message_id(13)), wait());
+        assert_eq!(
+            answers,
+            vec![(message_id(13), Answer::DataToSend(vec![frame(5)]))]
+        );
+
+        // Nothing left to send: the message stays pending.
+        assert!(manager
+            .handle_message(interface.pid, Some(message_id(14)), wait())
+            .is_empty());
+        assert_eq!(
+            manager.send(&interface, 0, frame(6)).unwrap(),
+            vec![(message_id(14), Answer::DataToSend(vec![frame(6)]))]
+        );
+        assert!(manager.send(&interface, 1, frame(7)).is_err());
+    }
+
+    #[test]
+    fn unregister_cancels_pending() {
+        let mut manager = NetworkManager::new();
+        let pid = Pid::from(1);
+        manager.handle_message(pid, Some(message_id(10)), register(5, 1));
+        manager.handle_message(pid, Some(message_id(11)), register(6, 1));
+
+        let wait = |id| NetworkMessage::InterfaceWaitData {
+            id,
+            queue: 0,
+            max_frames: 1,
+        };
+        assert!(manager
+            .handle_message(pid, Some(message_id(12)), wait(5))
+            .is_empty());
+        assert!(manager
+            .handle_message(pid, Some(message_id(13)), wait(6))
+            .is_empty());
+
+        let message = NetworkMessage::UnregisterInterface(5);
+        let answers = manager.handle_message(pid, None, message);
+        assert_eq!(answers, vec![(message_id(12), Answer::Error)]);
+
+        let answers = manager.process_destroyed(pid);
+        assert_eq!(answers, vec![(message_id(13), Answer::Error)]);
+        assert_eq!(manager.interfaces().count(), 0);
+    }
+}
@@ -0,0 +1,575 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Helpers for the handlers that show all the framebuffers on a single screen.
+//!
+//! [`Framebuffers`] keeps track of the framebuffers of all processes, validates the messages
+//! sent on the interface, and composites the framebuffers one row of the screen at a time.
+//! Writing these rows to the actual screen is left to the handler.
+//!
+//! Framebuffers are drawn in the order in which they have been created, each one on top of the
+//! previous ones. Each new framebuffer is placed a fixed offset to the right of and below the
+//! previous one, wrapping around at the edges of the screen.
+//!
+//! Input events aren't supported. `NextEvent` messages stay pending until their framebuffer is
+//! destroyed, at which point they are answered with an error.
+
+use crate::ffi::{self, FramebufferError, FramebufferMessage};
+
+use alloc::{vec, vec::Vec};
+use redshirt_syscalls::{Encode as _, EncodedMessage, MessageId, Payload, Pid};
+
+/// Framebuffers of all processes, and their position on the screen.
+pub struct Framebuffers {
+    /// Width of the screen in pixels.
+    screen_width: u32,
+    /// Height of the screen in pixels.
+    screen_height: u32,
+    /// Offset in pixels between a framebuffer and the one created before it.
+    cascade_offset: u32,
+    /// List of framebuffers, from bottom to top.
+    list: Vec<Framebuffer>,
+    /// Position on the screen of the next framebuffer to create.
+    next_position: (u32, u32),
+}
+
+/// Framebuffer of a process.
+struct Framebuffer {
+    /// Process that has created the framebuffer.
+    owner: Pid,
+    /// Identifier chosen by the owner.
+    id: u32,
+    /// Horizontal position of the left of the framebuffer on the screen.
+    x: u32,
+    /// Vertical position of the top of the framebuffer on the screen.
+    y: u32,
+    /// Width in pixels.
+    width: u32,
+    /// Height in pixels.
+    height: u32,
+    /// Format of [`Framebuffer::pixels`].
+    format: ffi::PixelFormat,
+    /// Pixels, row by row from top to bottom, as sent by the owner.
+    pixels: Vec<u8>,
+    /// `NextEvent` messages that are waiting for an answer.
+    pending_events: Vec<MessageId>,
+}
+
+/// What the handler must do after a message has been processed.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    /// Messages to answer. Contains the answer to the processed message, unless it doesn't
+    /// expect any or is answered later, and the answers to the pending `NextEvent` messages of
+    /// a destroyed framebuffer.
+    pub answers: Vec<(MessageId, Result<EncodedMessage, ()>)>,
+    /// Area of the screen to draw again, if any. Always within the screen.
+    pub damage: Option<ffi::Rect>,
+}
+
+impl Framebuffers {
+    /// Initializes an empty list of framebuffers, for a screen of the given dimensions.
+    pub fn new(screen_width: u32, screen_height: u32, cascade_offset: u32) -> Self {
+        Framebuffers {
+            screen_width,
+            screen_height,
+            cascade_offset,
+            list: Vec::new(),
+            next_position: (0, 0),
+        }
+    }
+
+    /// Returns true if no framebuffer exists.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Processes a message sent by `emitter_pid`.
+    ///
+    /// `take_payload` extracts the pixels sent along with the message. It is called, and the
+    /// buffer it refers to claimed, even if the message is refused.
+    pub fn handle_message(
+        &mut self,
+        emitter_pid: Pid,
+        message_id: Option<MessageId>,
+        message: FramebufferMessage,
+        take_payload: impl FnOnce(Payload) -> Option<Vec<u8>>,
+    ) -> Outcome {
+        let mut outcome = Outcome::default();
+
+        let result = match message {
+            FramebufferMessage::Create {
+                version,
+                id,
+                width,
+                height,
+                format,
+            } => {
+                if version != ffi::PROTOCOL_VERSION {
+                    Err(FramebufferError::UnsupportedVersion)
+                } else if !self.valid_dimensions(width, height) {
+                    Err(FramebufferError::InvalidDimensions)
+                } else if self.position(emitter_pid, id).is_some() {
+                    Err(FramebufferError::AlreadyExists)
+                } else {
+                    let rect = self.create(emitter_pid, id, width, height, format);
+                    outcome.add_damage(rect, self.screen_width, self.screen_height);
+                    Ok(())
+                }
+            }
+            FramebufferMessage::Destroy { id } => match self.position(emitter_pid, id) {
+                Some(pos) => {
+                    let framebuffer = self.list.remove(pos);
+                    let rect = framebuffer.screen_rect();
+                    outcome.add_damage(rect, self.screen_width, self.screen_height);
+                    for message_id in framebuffer.pending_events {
+                        outcome.answers.push((message_id, Err(())));
+                    }
+                    Ok(())
+                }
+                None => Err(FramebufferError::UnknownFramebuffer),
+            },
+            FramebufferMessage::SetData { id, data } => {
+                let data = take_payload(data);
+                match (self.position(emitter_pid, id), data) {
+                    (Some(pos), Some(data)) => {
+                        let rect = ffi::Rect {
+                            x: 0,
+                            y: 0,
+                            width: self.list[pos].width,
+                            height: self.list[pos].height,
+                        };
+                        self.update(pos, rect, &data, &mut outcome)
+                    }
+                    (Some(_), None) => Err(FramebufferError::InvalidBuffer),
+                    (None, _) => Err(FramebufferError::UnknownFramebuffer),
+                }
+            }
+            FramebufferMessage::Update { id, rect, data } => {
+                let data = take_payload(data);
+                match (self.position(emitter_pid, id), data) {
+                    (Some(pos), Some(data)) => self.update(pos, rect, &data, &mut outcome),
+                    (Some(_), None) => Err(FramebufferError::InvalidBuffer),
+                    (None, _) => Err(FramebufferError::UnknownFramebuffer),
+                }
+            }
+            FramebufferMessage::Resize { id, width, height } => {
+                match self.position(emitter_pid, id) {
+                    Some(_) if !self.valid_dimensions(width, height) => {
+                        Err(FramebufferError::InvalidDimensions)
+                    }
+                    Some(pos) => {
+                        let framebuffer = &mut self.list[pos];
+                        let old_rect = framebuffer.screen_rect();
+                        framebuffer.width = width;
+                        framebuffer.height = height;
+                        framebuffer.pixels = vec![0; framebuffer.data_len()];
+                        let new_rect = framebuffer.screen_rect();
+                        outcome.add_damage(old_rect, self.screen_width, self.screen_height);
+                        outcome.add_damage(new_rect, self.screen_width, self.screen_height);
+                        Ok(())
+                    }
+                    None => Err(FramebufferError::UnknownFramebuffer),
+                }
+            }
+            FramebufferMessage::NextEvent { id } => {
+                match (self.position(emitter_pid, id), message_id) {
+                    (Some(pos), Some(message_id)) => self.list[pos].pending_events.push(message_id),
+                    (None, Some(message_id)) => outcome.answers.push((message_id, Err(()))),
+                    (_, None) => {}
+                }
+                return outcome;
+            }
+        };
+
+        if let Some(message_id) = message_id {
+            outcome.answers.push((message_id, Ok(result.encode())));
+        }
+        outcome
+    }
+
+    /// Removes all the framebuffers of the given process. Returns the area of the screen to
+    /// draw again, if any.
+    ///
+    /// The pending `NextEvent` messages of the process are discarded, as nobody can receive
+    /// their answer anymore.
+    pub fn process_destroyed(&mut self, pid: Pid) -> Option<ffi::Rect> {
+        let mut outcome = Outcome::default();
+        let (screen_width, screen_height) = (self.screen_width, self.screen_height);
+        self.list.retain(|framebuffer| {
+            if framebuffer.owner != pid {
+                return true;
+            }
+            outcome.add_damage(framebuffer.screen_rect(), screen_width, screen_height);
+            false
+        });
+        outcome.damage
+    }
+
+    /// Composites the framebuffers on a row of the screen. `row` is the part of the row at the
+    /// vertical position `screen_y` that starts at `screen_x`.
+    ///
+    /// Pixels that aren't covered by any framebuffer are black.
+    pub fn draw_row(&self, screen_x: u32, screen_y: u32, row: &mut [[u8; 3]]) {
+        for pixel in row.iter_mut() {
+            *pixel = [0, 0, 0];
+        }
+        for framebuffer in &self.list {
+            framebuffer.draw_row(screen_x, screen_y, row);
+        }
+    }
+
+    /// Returns the index within `list` of the given framebuffer.
+    fn position(&self, owner: Pid, id: u32) -> Option<usize> {
+        self.list
+            .iter()
+            .position(|fb| fb.owner == owner && fb.id == id)
+    }
+
+    /// Returns true if a framebuffer can have the given dimensions.
+    ///
+    /// Framebuffers can't be larger than the screen.
+    fn valid_dimensions(&self, width: u32, height: u32) -> bool {
+        width != 0 && height != 0 && width <= self.screen_width && height <= self.screen_height
+    }
+
+    /// Adds a new framebuffer on top of the other ones, filled with black. Returns the area it
+    /// covers.
+    ///
+    /// The dimensions must have been checked with [`Framebuffers::valid_dimensions`].
+    fn create(
+        &mut self,
+        owner: Pid,
+        id: u32,
+        width: u32,
+        height: u32,
+        format: ffi::PixelFormat,
+    ) -> ffi::Rect {
+        let (mut x, mut y) = self.next_position;
+        if x.saturating_add(width) > self.screen_width {
+            x = 0;
+        }
+        if y.saturating_add(height) > self.screen_height {
+            y = 0;
+        }
+
+        self.next_position = (
+            x.saturating_add(self.cascade_offset) % self.screen_width,
+            y.saturating_add(self.cascade_offset) % self.screen_height,
+        );
+
+        let mut framebuffer = Framebuffer {
+            owner,
+            id,
+            x,
+            y,
+            width,
+            height,
+            format,
+            pixels: Vec::new(),
+            pending_events: Vec::new(),
+        };
+        framebuffer.pixels = vec![0; framebuffer.data_len()];
+
+        let rect = framebuffer.screen_rect();
+        self.list.push(framebuffer);
+        rect
+    }
+
+    /// Checks the rectangle and the data against a framebuffer, then updates its content.
+    fn update(
+        &mut self,
+        pos: usize,
+        rect: ffi::Rect,
+        data: &[u8],
+        outcome: &mut Outcome,
+    ) -> Result<(), FramebufferError> {
+        let framebuffer = &mut self.list[pos];
+
+        let right = rect.x.checked_add(rect.width);
+        let bottom = rect.y.checked_add(rect.height);
+        if right.map_or(true, |r| r > framebuffer.width)
+            || bottom.map_or(true, |b| b > framebuffer.height)
+        {
+            return Err(FramebufferError::OutOfBounds);
+        }
+
+        if Some(data.len()) != ffi::data_len(rect.width, rect.height, framebuffer.format) {
+            return Err(FramebufferError::InvalidDataLength);
+        }
+
+        if rect.width == 0 || rect.height == 0 {
+            return Ok(());
+        }
+
+        let bytes_per_pixel = framebuffer.format.bytes_per_pixel() as usize;
+        let row_len = rect.width as usize * bytes_per_pixel;
+        for (row, src) in data.chunks_exact(row_len).enumerate() {
+            let dst_start = ((rect.y as usize + row) * framebuffer.width as usize
+                + rect.x as usize)
+                * bytes_per_pixel;
+            framebuffer.pixels[dst_start..dst_start + row_len].copy_from_slice(src);
+        }
+
+        let screen_rect = ffi::Rect {
+            x: framebuffer.x + rect.x,
+            y: framebuffer.y + rect.y,
+            width: rect.width,
+            height: rect.height,
+        };
+        outcome.add_damage(screen_rect, self.screen_width, self.screen_height);
+        Ok(())
+    }
+}
+
+impl Framebuffer {
+    /// Returns the number of bytes that [`Framebuffer::pixels`] must contain.
+    fn data_len(&self) -> usize {
+        // The dimensions are bounded by the size of the screen, which fits in memory.
+        ffi::data_len(self.width, self.height, self.format).unwrap()
+    }
+
+    /// Returns the area covered by this framebuffer, in screen coordinates. Might extend past
+    /// the screen if the framebuffer has been enlarged.
+    fn screen_rect(&self) -> ffi::Rect {
+        ffi::Rect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Overwrites the parts of `row` that this framebuffer covers with its pixels. `row` is a
+    /// row of the screen at the vertical position `screen_y`, starting at `screen_x`.
+    fn draw_row(&self, screen_x: u32, screen_y: u32, row: &mut [[u8; 3]]) {
+        let y = match screen_y.checked_sub(self.y) {
+            Some(y) if y < self.height => y,
+            _ => return,
+        };
+
+        // Both sides are bounded by twice the width of the screen and can't overflow.
+        let start = self.x.max(screen_x);
+        let end = (self.x + self.width).min(screen_x + row.len() as u32);
+        if start >= end {
+            return;
+        }
+
+        let bytes_per_pixel = self.format.bytes_per_pixel() as usize;
+        let src_start =
+            (y as usize * self.width as usize + (start - self.x) as usize) * bytes_per_pixel;
+        let src = &self.pixels[src_start..src_start + (end - start) as usize * bytes_per_pixel];
+        let dst = &mut row[(start - screen_x) as usize..(end - screen_x) as usize];
+
+        for (dst, src) in dst.iter_mut().zip(src.chunks_exact(bytes_per_pixel)) {
+            *dst = match self.format {
+                ffi::PixelFormat::Rgb24 | ffi::PixelFormat::Rgba32 => [src[0], src[1], src[2]],
+                ffi::PixelFormat::Bgra32 => [src[2], src[1], src[0]],
+            };
+        }
+    }
+}
+
+impl Outcome {
+    /// Adds the part of `rect` that is within the screen to [`Outcome::damage`].
+    fn add_damage(&mut self, rect: ffi::Rect, screen_width: u32, screen_height: u32) {
+        let right = rect.x.saturating_add(rect.width).min(screen_width);
+        let bottom = rect.y.saturating_add(rect.height).min(screen_height);
+        if rect.x >= right || rect.y >= bottom {
+            return;
+        }
+
+        let (left, top) = match self.damage {
+            Some(damage) => (rect.x.min(damage.x), rect.y.min(damage.y)),
+            None => (rect.x, rect.y),
+        };
+        let (right, bottom) = match self.damage {
+            Some(damage) => (
+                right.max(damage.x + damage.width),
+                bottom.max(damage.y + damage.height),
+            ),
+            None => (right, bottom),
+        };
+
+        self.damage = Some(ffi::Rect {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Framebuffers;
+    use crate::ffi::{self, FramebufferError, FramebufferMessage};
+    use alloc::{vec, vec::Vec};
+    use core::convert::TryFrom as _;
+    use redshirt_syscalls::{Decode as _, MessageId, Payload, Pid};
+
+    /// Processes a message that expects an answer, and returns that answer.
+    fn request(
+        framebuffers: &mut Framebuffers,
+        pid: u64,
+        message: FramebufferMessage,
+    ) -> Result<(), FramebufferError> {
+        let message_id = MessageId::try_from(1u64).unwrap();
+        let outcome =
+            framebuffers.handle_message(Pid::from(pid), Some(message_id), message, |payload| {
+                match payload {
+                    Payload::Inline(data) => Some(data),
+                    Payload::Buffer(_) => None,
+                }
+            });
+        assert_eq!(outcome.answers.len(), 1);
+        assert_eq!(outcome.answers[0].0, message_id);
+        let answer = outcome.answers.into_iter().next().unwrap().1.unwrap();
+        Result::<(), FramebufferError>::decode(answer).unwrap()
+    }
+
+    /// Builds a message that creates an RGB framebuffer.
+    fn create(id: u32, width: u32, height: u32) -> FramebufferMessage {
+        FramebufferMessage::Create {
+            version: ffi::PROTOCOL_VERSION,
+            id,
+            width,
+            height,
+            format: ffi::PixelFormat::Rgb24,
+        }
+    }
+
+    /// Returns the composited row of the screen at the given position.
+    fn row(framebuffers: &Framebuffers, x: u32, y: u32, len: usize) -> Vec<[u8; 3]> {
+        let mut row = vec![[0xff; 3]; len];
+        framebuffers.draw_row(x, y, &mut row);
+        row
+    }
+
+    #[test]
+    fn cascade_and_composite() {
+        let mut framebuffers = Framebuffers::new(8, 8, 2);
+        assert_eq!(request(&mut framebuffers, 1, create(0, 4, 4)), Ok(()));
+        assert_eq!(request(&mut framebuffers, 2, create(0, 4, 4)), Ok(()));
+
+        let data = Payload::Inline(vec![1; 4 * 4 * 3]);
+        let set_data = FramebufferMessage::SetData { id: 0, data };
+        assert_eq!(request(&mut framebuffers, 2, set_data), Ok(()));
+
+        assert_eq!(row(&framebuffers, 0, 0, 8), vec![[0; 3]; 8]);
+        let mut expected = vec![[0; 3]; 8];
+        for pixel in &mut expected[2..6] {
+            *pixel = [1; 3];
+        }
+        assert_eq!(row(&framebuffers, 0, 3, 8), expected);
+        assert_eq!(row(&framebuffers, 3, 3, 2), vec![[1; 3]; 2]);
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let mut framebuffers = Framebuffers::new(8, 8, 2);
+        assert_eq!(
+            request(&mut framebuffers, 1, create(0, 9, 4)),
+            Err(FramebufferError::InvalidDimensions)
+        );
+        assert_eq!(request(&mut framebuffers, 1, create(0, 4, 4)), Ok(()));
+        assert_eq!(
+            request(&mut framebuffers, 1, create(0, 4, 4)),
+            Err(FramebufferError::AlreadyExists)
+        );
+
+        let update = FramebufferMessage::Update {
+            id: 0,
+            rect: ffi::Rect {
+                x: 2,
+                y: 2,
+                width: 3,
+                height: 1,
+            },
+            data: Payload::Inline(vec![0; 9]),
+        };
+        assert_eq!(
+            request(&mut framebuffers, 1, update.clone()),
+            Err(FramebufferError::OutOfBounds)
+        );
+        assert_eq!(
+            request(&mut framebuffers, 2, update),
+            Err(FramebufferError::UnknownFramebuffer)
+        );
+
+        let data = Payload::Buffer(From::from(5u64));
+        let set_data = FramebufferMessage::SetData { id: 0, data };
+        assert_eq!(
+            request(&mut framebuffers, 1, set_data),
+            Err(FramebufferError::InvalidBuffer)
+        );
+    }
+
+    #[test]
+    fn next_event_answered_on_destroy() {
+        let mut framebuffers = Framebuffers::new(8, 8, 2);
+        assert_eq!(request(&mut framebuffers, 1, create(0, 4, 4)), Ok(()));
+
+        let next_event = FramebufferMessage::NextEvent { id: 0 };
+        let outcome = framebuffers.handle_message(
+            Pid::from(1),
+            Some(MessageId::try_from(7u64).unwrap()),
+            next_event,
+            |_| unreachable!(),
+        );
+        assert!(outcome.answers.is_empty());
+
+        let destroy = FramebufferMessage::Destroy { id: 0 };
+        let outcome = framebuffers.handle_message(Pid::from(1), None, destroy, |_| unreachable!());
+        assert_eq!(
+            outcome.answers,
+            vec![(MessageId::try_from(7u64).unwrap(), Err(()))]
+        );
+        assert!(framebuffers.is_empty());
+    }
+
+    #[test]
+    fn damage_within_screen() {
+        let mut framebuffers = Framebuffers::new(8, 8, 6);
+        assert_eq!(request(&mut framebuffers, 1, create(0, 2, 2)), Ok(()));
+        assert_eq!(request(&mut framebuffers, 1, create(1, 2, 2)), Ok(()));
+
+        let resize = FramebufferMessage::Resize {
+            id: 1,
+            width: 8,
+            height: 8,
+        };
+        let outcome = framebuffers.handle_message(Pid::from(1), None, resize, |_| unreachable!());
+        assert_eq!(
+            outcome.damage,
+            Some(ffi::Rect {
+                x: 6,
+                y: 6,
+                width: 2,
+                height: 2,
+            })
+        );
+
+        assert_eq!(
+            framebuffers.process_destroyed(Pid::from(1)),
+            Some(ffi::Rect {
+                x: 0,
+                y: 0,
+                width: 8,
+                height: 8,
+            })
+        );
+        assert!(framebuffers.process_destroyed(Pid::from(1)).is_none());
+    }
+}
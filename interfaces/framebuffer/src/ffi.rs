@@ -13,24 +13,30 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+//! Framebuffers are rectangles of pixels that the interface handler shows to the user, for
+//! example in a window.
 //!
+//! Messages are SCALE-encoded [`FramebufferMessage`]s. Messages that the handler fails to decode
+//! are answered with an error, if they expect an answer.
 //!
-//! Message format:
+//! Each process allocates the identifiers of its framebuffers. Before any other message can be
+//! sent about a framebuffer, it must be created with [`FramebufferMessage::Create`], which
+//! contains the version of this protocol that the process implements. Handlers refuse to create
+//! framebuffers whose version they don't support.
 //!
-//! The type of message depends on the first byte:
+//! All the messages except [`FramebufferMessage::NextEvent`] can optionally expect an answer, in
+//! which case the handler answers with a `Result<(), FramebufferError>`. Errors are silently
+//! ignored if no answer is expected.
 //!
-//! - 0: Creates a framebuffer. Next 4 bytes are a "framebuffer ID" as decided by the message
-//! emitter. Next 4 bytes are the width in little endian. Next 4 bytes are the height in little
-//! endian.
-//! - 1: Destroys a framebuffer. Next 4 bytes are the framebuffer ID.
-//! - 2: Set framebuffer content. Next 4 bytes are the framebuffer ID. The rest is 3 * width *
-//! height values. The rest is RGB triplets.
-//! - 3: Send back the next input event. Next 4 bytes are the framebuffer ID. The answer consists
-//! in an input event whose format isn't properly defined yet. Sorry, it's kind of useless,
-//! but well 🤷
-// TODO: define input event
-
-use redshirt_syscalls::InterfaceHash;
+//! Pixel data is always sent from the top-left corner, row by row from top to bottom, without
+//! any padding between rows. It is sent as a [`Payload`], in order for large frames to be passed
+//! through a buffer rather than copied within the message. Handlers always claim the buffer,
+//! even if the message is refused.
+
+use alloc::string::String;
+use core::{convert::TryFrom as _, fmt};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls::{InterfaceHash, Payload};
 
 // TODO: split interface in two? one with inputs and one without?
 // TODO: this has been randomly generated; instead should be a hash or something
@@ -38,3 +44,441 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
     0xfc, 0x60, 0x2e, 0x6e, 0xf2, 0x43, 0x9c, 0xa0, 0x40, 0x88, 0x81, 0x7d, 0xe5, 0xaf, 0xb6, 0x90,
     0x9e, 0x57, 0xc6, 0xc2, 0x5e, 0xbf, 0x02, 0x5b, 0x87, 0x7f, 0xaa, 0xae, 0xbe, 0xd5, 0x19, 0x9c,
 ]);
+
+/// Version of the protocol described in this module.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Message sent on the framebuffer interface.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum FramebufferMessage {
+    /// Creates a new framebuffer, initially filled with black.
+    Create {
+        /// Version of the protocol. Should be [`PROTOCOL_VERSION`].
+        version: u32,
+        /// Identifier of the framebuffer, as chosen by the emitter.
+        id: u32,
+        /// Width in pixels.
+        width: u32,
+        /// Height in pixels.
+        height: u32,
+        /// Format of the pixels sent for this framebuffer.
+        format: PixelFormat,
+    },
+
+    /// Destroys a framebuffer.
+    Destroy {
+        /// Identifier of the framebuffer.
+        id: u32,
+    },
+
+    /// Replaces the whole content of a framebuffer.
+    SetData {
+        /// Identifier of the framebuffer.
+        id: u32,
+        /// Pixels of the framebuffer. Must be `width * height * format.bytes_per_pixel()` bytes.
+        data: Payload,
+    },
+
+    /// Replaces the content of a rectangle within a framebuffer.
+    Update {
+        /// Identifier of the framebuffer.
+        id: u32,
+        /// Rectangle to update. Must be within the framebuffer.
+        rect: Rect,
+        /// Pixels of the rectangle. Must be
+        /// `rect.width * rect.height * format.bytes_per_pixel()` bytes.
+        data: Payload,
+    },
+
+    /// Changes the dimensions of a framebuffer. Its content is reset to black.
+    Resize {
+        /// Identifier of the framebuffer.
+        id: u32,
+        /// New width in pixels.
+        width: u32,
+        /// New height in pixels.
+        height: u32,
+    },
+
+    /// Asks for the next input event that happens on the framebuffer. Must be answered with an
+    /// [`InputEvent`].
+    ///
+    /// Input events that happen while no such message is pending are buffered by the handler,
+    /// up to an implementation-defined limit. Pending messages are answered with an error when
+    /// the framebuffer is destroyed. Handlers that don't support input keep these messages
+    /// pending until then.
+    NextEvent {
+        /// Identifier of the framebuffer.
+        id: u32,
+    },
+}
+
+/// Format of the pixels of a framebuffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum PixelFormat {
+    /// Three bytes per pixel: red, green, blue.
+    Rgb24,
+    /// Four bytes per pixel: red, green, blue, alpha.
+    Rgba32,
+    /// Four bytes per pixel: blue, green, red, alpha.
+    Bgra32,
+}
+
+impl PixelFormat {
+    /// Returns the number of bytes that each pixel occupies.
+    pub fn bytes_per_pixel(&self) -> u32 {
+        match self {
+            PixelFormat::Rgb24 => 3,
+            PixelFormat::Rgba32 | PixelFormat::Bgra32 => 4,
+        }
+    }
+}
+
+/// Rectangle within a framebuffer, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Rect {
+    /// Distance between the left of the framebuffer and the left of the rectangle.
+    pub x: u32,
+    /// Distance between the top of the framebuffer and the top of the rectangle.
+    pub y: u32,
+    /// Width of the rectangle.
+    pub width: u32,
+    /// Height of the rectangle.
+    pub height: u32,
+}
+
+/// Error answered to a message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum FramebufferError {
+    /// The version passed when creating the framebuffer isn't supported by the handler.
+    UnsupportedVersion,
+    /// A framebuffer with this identifier already exists.
+    AlreadyExists,
+    /// No framebuffer with this identifier exists.
+    UnknownFramebuffer,
+    /// The width or height is zero or too large.
+    InvalidDimensions,
+    /// The rectangle isn't within the framebuffer.
+    OutOfBounds,
+    /// The length of the data doesn't match the dimensions and the pixel format.
+    InvalidDataLength,
+    /// The data refers to a buffer that doesn't exist or that has already been claimed.
+    InvalidBuffer,
+}
+
+impl fmt::Display for FramebufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FramebufferError::UnsupportedVersion => write!(f, "Unsupported protocol version"),
+            FramebufferError::AlreadyExists => write!(f, "Framebuffer already exists"),
+            FramebufferError::UnknownFramebuffer => write!(f, "Unknown framebuffer"),
+            FramebufferError::InvalidDimensions => write!(f, "Invalid dimensions"),
+            FramebufferError::OutOfBounds => write!(f, "Rectangle out of bounds"),
+            FramebufferError::InvalidDataLength => write!(f, "Invalid data length"),
+            FramebufferError::InvalidBuffer => write!(f, "Invalid buffer"),
+        }
+    }
+}
+
+/// Returns the expected length of the pixel data of a rectangle of the given dimensions, or
+/// `None` if it overflows.
+pub fn data_len(width: u32, height: u32, format: PixelFormat) -> Option<usize> {
+    let len = width
+        .checked_mul(height)?
+        .checked_mul(format.bytes_per_pixel())?;
+    usize::try_from(len).ok()
+}
+
+/// Input event that happened on a framebuffer.
+///
+/// Positions are in pixels of the framebuffer, with `(0, 0)` being the top-left corner.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum InputEvent {
+    /// A key of the keyboard has been pressed or released.
+    Key {
+        /// Hardware-dependant code of the key, independent of the keyboard layout.
+        scancode: u32,
+        /// Meaning of the key according to the keyboard layout, if known.
+        key: Option<Key>,
+        /// Whether the key has been pressed or released.
+        state: ElementState,
+    },
+
+    /// Text has been entered. This is reported in addition to the [`InputEvent::Key`] events,
+    /// and takes into account the keyboard layout and modifiers.
+    Text(String),
+
+    /// The pointer has moved to the given position.
+    PointerMoved {
+        /// Horizontal position, from the left.
+        x: i32,
+        /// Vertical position, from the top.
+        y: i32,
+    },
+
+    /// The pointer has left the framebuffer.
+    PointerLeft,
+
+    /// A button of the pointer has been pressed or released.
+    PointerButton {
+        /// Which button.
+        button: PointerButton,
+        /// Whether the button has been pressed or released.
+        state: ElementState,
+    },
+
+    /// The pointer wheel has been used.
+    PointerWheel(WheelDelta),
+
+    /// The area available to the framebuffer has been resized.
+    Resized {
+        /// New width, in pixels.
+        width: u32,
+        /// New height, in pixels.
+        height: u32,
+    },
+
+    /// The framebuffer has gained (`true`) or lost (`false`) the input focus.
+    Focus(bool),
+
+    /// The user has requested the framebuffer to be closed. The framebuffer isn't closed
+    /// automatically.
+    CloseRequested,
+}
+
+/// State of a key or button.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum ElementState {
+    Pressed,
+    Released,
+}
+
+/// Button of a pointing device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum PointerButton {
+    Left,
+    Right,
+    Middle,
+    Other(u8),
+}
+
+/// Movement of the pointer wheel.
+///
+/// Positive values correspond to scrolling right or up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum WheelDelta {
+    /// Movement in hundredths of lines or rows.
+    Lines {
+        /// Horizontal movement.
+        x: i32,
+        /// Vertical movement.
+        y: i32,
+    },
+    /// Movement in pixels, for devices that support precise scrolling.
+    Pixels {
+        /// Horizontal movement.
+        x: i32,
+        /// Vertical movement.
+        y: i32,
+    },
+}
+
+/// Meaning of a key according to the keyboard layout.
+///
+/// Keys that produce text are reported both through [`InputEvent::Key`] and
+/// [`InputEvent::Text`]. Only the latter should be used for entering text.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub enum Key {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    Digit0,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Escape,
+    Enter,
+    Tab,
+    Backspace,
+    Space,
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    ArrowLeft,
+    ArrowRight,
+    ArrowUp,
+    ArrowDown,
+    ShiftLeft,
+    ShiftRight,
+    ControlLeft,
+    ControlRight,
+    AltLeft,
+    AltRight,
+    MetaLeft,
+    MetaRight,
+    CapsLock,
+    NumLock,
+    ScrollLock,
+    PrintScreen,
+    Pause,
+    Minus,
+    Equal,
+    BracketLeft,
+    BracketRight,
+    Backslash,
+    Semicolon,
+    Quote,
+    Backquote,
+    Comma,
+    Period,
+    Slash,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn message_encoding() {
+        let message = FramebufferMessage::Create {
+            version: PROTOCOL_VERSION,
+            id: 5,
+            width: 640,
+            height: 480,
+            format: PixelFormat::Bgra32,
+        };
+        assert_eq!(
+            message.encode(),
+            vec![0, 2, 0, 0, 0, 5, 0, 0, 0, 0x80, 2, 0, 0, 0xe0, 1, 0, 0, 2]
+        );
+
+        let message = FramebufferMessage::Destroy { id: 5 };
+        assert_eq!(message.encode(), vec![1, 5, 0, 0, 0]);
+
+        let message = FramebufferMessage::NextEvent { id: 5 };
+        assert_eq!(message.encode(), vec![5, 5, 0, 0, 0]);
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let messages = vec![
+            FramebufferMessage::SetData {
+                id: 1,
+                data: Payload::Inline(vec![1, 2, 3]),
+            },
+            FramebufferMessage::Update {
+                id: 2,
+                rect: Rect {
+                    x: 1,
+                    y: 2,
+                    width: 3,
+                    height: 4,
+                },
+                data: Payload::Inline(vec![0; 36]),
+            },
+            FramebufferMessage::Resize {
+                id: 3,
+                width: 10,
+                height: 20,
+            },
+        ];
+
+        for message in messages {
+            let encoded = message.encode();
+            assert_eq!(
+                FramebufferMessage::decode(&mut &encoded[..]).unwrap(),
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn input_event_roundtrip() {
+        let events = vec![
+            InputEvent::Key {
+                scancode: 30,
+                key: Some(Key::A),
+                state: ElementState::Pressed,
+            },
+            InputEvent::Text(String::from("é")),
+            InputEvent::PointerMoved { x: -3, y: 7 },
+            InputEvent::PointerButton {
+                button: PointerButton::Other(8),
+                state: ElementState::Released,
+            },
+            InputEvent::PointerWheel(WheelDelta::Lines { x: 0, y: -100 }),
+            InputEvent::Resized {
+                width: 800,
+                height: 600,
+            },
+            InputEvent::CloseRequested,
+        ];
+
+        for event in events {
+            let encoded = event.encode();
+            assert_eq!(InputEvent::decode(&mut &encoded[..]).unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn error_answer_roundtrip() {
+        let answer: Result<(), FramebufferError> = Err(FramebufferError::OutOfBounds);
+        let encoded = answer.encode();
+        assert_eq!(
+            Result::<(), FramebufferError>::decode(&mut &encoded[..]).unwrap(),
+            answer
+        );
+    }
+
+    #[test]
+    fn data_len_overflow() {
+        assert_eq!(data_len(4, 2, PixelFormat::Rgb24), Some(24));
+        assert_eq!(data_len(4, 2, PixelFormat::Rgba32), Some(32));
+        assert_eq!(data_len(u32::max_value(), 2, PixelFormat::Rgb24), None);
+    }
+}
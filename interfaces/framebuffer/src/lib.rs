@@ -15,88 +15,167 @@
 
 //! Framebuffer interface.
 //!
-//! Allows drawing an image.
+//! Allows drawing an image and receiving the keyboard and pointer input that happens on it.
 //!
-//! > **Note**: The fate of this interface is kind of vague. It is also unclear whether
-//! >           keyboard/mouse input should be handled here as well. Use at your own risks.
+//! The [`composite`] module contains helpers for the handlers of this interface that draw all
+//! the framebuffers on a single screen.
+//!
+//! > **Note**: The fate of this interface is kind of vague. Use at your own risks.
 
 #![no_std]
 
-use core::convert::TryFrom as _;
+extern crate alloc;
+
+use core::{
+    future::Future,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
+pub mod composite;
 pub mod ffi;
 
-/// Framebuffer containing pixel data.
+/// Framebuffer shown by the interface handler. Destroyed when dropped.
 pub struct Framebuffer {
     id: u32,
     width: u32,
     height: u32,
+    format: ffi::PixelFormat,
 }
 
 impl Framebuffer {
-    /// Initializes a new framebuffer of the given width and height.
-    pub async fn new(width: u32, height: u32) -> Self {
-        let id = unsafe {
-            let mut out = [0; 4];
-            redshirt_random_interface::generate_in(&mut out).await;
-            u32::from_le_bytes(out)
+    /// Initializes a new framebuffer of the given width and height, whose pixels are RGB
+    /// triplets.
+    pub async fn new(width: u32, height: u32) -> Result<Self, ffi::FramebufferError> {
+        Self::with_format(width, height, ffi::PixelFormat::Rgb24).await
+    }
+
+    /// Initializes a new framebuffer of the given width, height, and pixel format.
+    pub async fn with_format(
+        width: u32,
+        height: u32,
+        format: ffi::PixelFormat,
+    ) -> Result<Self, ffi::FramebufferError> {
+        // Identifiers only need to be unique within the current process.
+        static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        let message = ffi::FramebufferMessage::Create {
+            version: ffi::PROTOCOL_VERSION,
+            id,
+            width,
+            height,
+            format,
         };
+        send_with_answer(message).await?;
 
-        unsafe {
-            let id_le_bytes = id.to_le_bytes();
-            let width_le_bytes = width.to_le_bytes();
-            let height_le_bytes = height.to_le_bytes();
-            redshirt_syscalls::MessageBuilder::new()
-                .add_data_raw(&[0])
-                .add_data_raw(&id_le_bytes[..])
-                .add_data_raw(&width_le_bytes[..])
-                .add_data_raw(&height_le_bytes[..])
-                .emit_without_response(&ffi::INTERFACE)
-                .unwrap();
-        }
+        Ok(Framebuffer {
+            id,
+            width,
+            height,
+            format,
+        })
+    }
+
+    /// Returns the width of the framebuffer in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the framebuffer in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
 
-        Framebuffer { id, width, height }
+    /// Returns the format of the pixels of the framebuffer.
+    pub fn format(&self) -> ffi::PixelFormat {
+        self.format
     }
 
     /// Sets the data in the framebuffer.
     ///
-    /// The size of `data` must be `width * height * 3`.
+    /// # Panic
+    ///
+    /// Panics if the size of `data` isn't `width * height * bytes_per_pixel`.
+    ///
     pub fn set_data(&self, data: &[u8]) {
+        assert_eq!(
+            Some(data.len()),
+            ffi::data_len(self.width, self.height, self.format)
+        );
+
+        send(ffi::FramebufferMessage::SetData {
+            id: self.id,
+            data: redshirt_syscalls::Payload::new(data).unwrap(),
+        });
+    }
+
+    /// Sets the data of a rectangle within the framebuffer. Much cheaper than
+    /// [`set_data`](Framebuffer::set_data) if only a small part of the framebuffer has changed.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the rectangle isn't within the framebuffer, or if the size of `data` isn't
+    /// `rect.width * rect.height * bytes_per_pixel`.
+    ///
+    pub fn update(&self, rect: ffi::Rect, data: &[u8]) {
+        assert!(rect.x.checked_add(rect.width).map_or(false, |r| r <= self.width));
+        assert!(rect.y.checked_add(rect.height).map_or(false, |b| b <= self.height));
+        assert_eq!(
+            Some(data.len()),
+            ffi::data_len(rect.width, rect.height, self.format)
+        );
+
+        send(ffi::FramebufferMessage::Update {
+            id: self.id,
+            rect,
+            data: redshirt_syscalls::Payload::new(data).unwrap(),
+        });
+    }
+
+    /// Changes the dimensions of the framebuffer. Its content is reset to black.
+    pub async fn resize(&mut self, width: u32, height: u32) -> Result<(), ffi::FramebufferError> {
+        let message = ffi::FramebufferMessage::Resize {
+            id: self.id,
+            width,
+            height,
+        };
+        send_with_answer(message).await?;
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Returns the next input event that happens on this framebuffer.
+    ///
+    /// Events that happen while no call to this method is pending are buffered by the handler.
+    /// Handlers that don't support input never generate any event, in which case the returned
+    /// future never finishes.
+    pub fn next_event(&self) -> impl Future<Output = ffi::InputEvent> {
         unsafe {
-            assert_eq!(
-                data.len(),
-                usize::try_from(
-                    self.width
-                        .checked_mul(self.height)
-                        .unwrap()
-                        .checked_mul(3)
-                        .unwrap()
-                )
-                .unwrap()
-            );
-
-            let id_le_bytes = self.id.to_le_bytes();
-            redshirt_syscalls::MessageBuilder::new()
-                .add_data_raw(&[2])
-                .add_data_raw(&id_le_bytes[..])
-                .add_data_raw(data)
-                .emit_without_response(&ffi::INTERFACE)
-                .unwrap();
+            let message = ffi::FramebufferMessage::NextEvent { id: self.id };
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message).unwrap()
         }
     }
-
-    // TODO: next_event() method
 }
 
 impl Drop for Framebuffer {
     fn drop(&mut self) {
-        unsafe {
-            let id_le_bytes = self.id.to_le_bytes();
-            redshirt_syscalls::MessageBuilder::new()
-                .add_data_raw(&[1])
-                .add_data_raw(&id_le_bytes[..])
-                .emit_without_response(&ffi::INTERFACE)
-                .unwrap();
-        }
+        send(ffi::FramebufferMessage::Destroy { id: self.id });
+    }
+}
+
+/// Sends a message that doesn't expect any answer.
+fn send(message: ffi::FramebufferMessage) {
+    unsafe {
+        redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, message).unwrap();
+    }
+}
+
+/// Sends a message and waits for the handler to report whether it has succeeded.
+async fn send_with_answer(message: ffi::FramebufferMessage) -> Result<(), ffi::FramebufferError> {
+    unsafe {
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message)
+            .unwrap()
+            .await
     }
 }
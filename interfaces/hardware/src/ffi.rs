@@ -53,12 +53,97 @@ pub enum HardwareMessage {
     // TODO: should we enforce some limits in the amount of data that can be returned in a response?
     HardwareAccess(Vec<Operation>),
 
-    /// Ask the handler to send back a response when the interrupt with the given number is
-    /// triggered.
+    /// Start listening to the given source of interrupts. Must answer with a
+    /// `Result<u64, InterruptError>`, where the `u64` identifies the subscription.
     ///
-    /// > **Note**: If called with a non-hardware interrupt, no response will ever come back.
-    // TODO: how to not miss any interrupt? we instead need some registration system or something
-    InterruptWait(u32),
+    /// Starting from the moment the subscription is created, the handler counts the number of
+    /// times the interrupt has been triggered. Interrupts are never missed, even when no
+    /// [`HardwareMessage::InterruptWait`] is in progress.
+    InterruptSubscribe(InterruptSource),
+    /// Destroys a subscription previously created with [`HardwareMessage::InterruptSubscribe`].
+    /// No response expected.
+    ///
+    /// Subscriptions are automatically destroyed when the process that created them terminates.
+    InterruptUnsubscribe(u64),
+    /// Ask the handler to send back a response once the interrupt of the given subscription has
+    /// been triggered. Must answer with a `Result<u32, ()>`, where the `u32` is the number of
+    /// times the interrupt has been triggered since the previous wait or since the subscription.
+    ///
+    /// If at least one interrupt has been triggered since the previous wait, the response is
+    /// sent back immediately.
+    ///
+    /// An error is returned if the subscription is invalid or if another wait is already in
+    /// progress for the same subscription.
+    ///
+    /// Sending this message also indicates that the device has been serviced. Level-triggered
+    /// interrupts, such as the legacy interrupts of PCI devices, are masked after they have
+    /// been triggered, and are unmasked once all the subscribers to the same source are waiting.
+    InterruptWait(u64),
+    /// Ask the handler for the message that a device must write in order to trigger the
+    /// interrupt of a subscription to [`InterruptSource::Msi`]. Must answer with a
+    /// `Result<MsiMessage, ()>`.
+    ///
+    /// An error is returned if the subscription is invalid or isn't for message-signalled
+    /// interrupts.
+    InterruptMsiMessage(u64),
+
+    /// Ask the handler for the list of memory regions where the configuration space of PCI
+    /// devices is mapped, as reported by the firmware. Must answer with a
+    /// `Vec<PciConfigRegion>`.
+    ///
+    /// The list is empty if the platform doesn't support or doesn't report this information.
+    PciConfigRegions,
+}
+
+/// Source of interrupts that can be subscribed to.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum InterruptSource {
+    /// Legacy ISA IRQ. On x86 platforms, this is also the value found in the "interrupt line"
+    /// field of the configuration space of PCI devices.
+    IsaIrq(u8),
+    /// Global system interrupt, as numbered by the platform's interrupt controller.
+    ///
+    /// Unless the platform reports otherwise, interrupts that don't correspond to an ISA IRQ
+    /// are assumed to be level-triggered and active low, like the legacy interrupts of PCI
+    /// devices.
+    Irq(u32),
+    /// Message-signalled interrupt. A new interrupt is allocated for each subscription. Use
+    /// [`HardwareMessage::InterruptMsiMessage`] in order to know what the device must write to
+    /// trigger it.
+    Msi,
+}
+
+/// Write that a device must perform in order to trigger a message-signalled interrupt.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct MsiMessage {
+    /// Physical memory address to write to.
+    pub address: u64,
+    /// Value to write.
+    pub data: u32,
+}
+
+/// Memory-mapped configuration space of the PCI devices of a bus. This is known as the
+/// *Enhanced Configuration Access Mechanism*, or ECAM.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct PciConfigRegion {
+    /// PCI segment group the bus belongs to.
+    pub segment_group: u16,
+    /// Bus whose devices are covered by this region.
+    pub bus: u8,
+    /// Physical memory address of the configuration space of device 0, function 0. Each
+    /// function of each device occupies 4kiB.
+    pub physical_address: u64,
+}
+
+/// Error that can happen when subscribing to interrupts.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum InterruptError {
+    /// The platform doesn't support delivering interrupts from this source.
+    Unsupported,
+    /// The interrupt is already used by the kernel itself.
+    Reserved,
+    /// No interrupt vector is available on the platform.
+    Exhausted,
 }
 
 /// Request to perform accesses to physical memory or to ports.
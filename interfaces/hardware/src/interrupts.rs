@@ -0,0 +1,88 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Hardware interrupts.
+//!
+//! Call [`subscribe`] in order to start listening to an interrupt, then [`Interrupt::wait`] to
+//! wait for it to be triggered.
+//!
+//! Interrupts are buffered by the interface handler. In other words, an interrupt that happens
+//! while no [`Interrupt::wait`] is in progress isn't lost, and the next call to
+//! [`Interrupt::wait`] finishes immediately.
+//!
+//! Level-triggered interrupts stay masked after they have been triggered, until the next call
+//! to [`Interrupt::wait`]. Drivers must therefore service their device before waiting again.
+//!
+//! > **Note**: Multiple devices can share the same interrupt, and an interrupt might be
+//! >           triggered spuriously. Drivers should always check the state of the device when
+//! >           they are woken up.
+
+use crate::ffi;
+
+pub use ffi::{InterruptError, InterruptSource, MsiMessage};
+
+use futures::prelude::*;
+
+/// Active subscription to an interrupt. Unsubscribes when dropped.
+pub struct Interrupt {
+    /// Identifier of the subscription, as returned by the interface handler.
+    id: u64,
+}
+
+/// Starts listening to the given source of interrupts.
+pub fn subscribe(
+    source: InterruptSource,
+) -> impl Future<Output = Result<Interrupt, InterruptError>> {
+    unsafe {
+        let msg = ffi::HardwareMessage::InterruptSubscribe(source);
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .map(|response: Result<u64, InterruptError>| response.map(|id| Interrupt { id }))
+    }
+}
+
+impl Interrupt {
+    /// Waits for the interrupt to be triggered. Returns the number of times it has been
+    /// triggered since the previous call, or since the subscription.
+    ///
+    /// Only one call to this method should be in progress at any given time.
+    ///
+    /// Returns an error if the interface handler no longer knows about this subscription.
+    pub fn wait(&self) -> impl Future<Output = Result<u32, ()>> {
+        unsafe {
+            let msg = ffi::HardwareMessage::InterruptWait(self.id);
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        }
+    }
+
+    /// Returns the write that a device must perform in order to trigger this interrupt.
+    ///
+    /// Returns an error if the subscription isn't for [`InterruptSource::Msi`].
+    pub fn msi_message(&self) -> impl Future<Output = Result<MsiMessage, ()>> {
+        unsafe {
+            let msg = ffi::HardwareMessage::InterruptMsiMessage(self.id);
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        }
+    }
+}
+
+impl Drop for Interrupt {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::HardwareMessage::InterruptUnsubscribe(self.id);
+            redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, &msg).unwrap();
+        }
+    }
+}
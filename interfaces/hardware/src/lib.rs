@@ -25,6 +25,7 @@ use alloc::{vec, vec::Vec};
 use futures::prelude::*;
 
 pub mod ffi;
+pub mod interrupts;
 pub mod malloc;
 
 /// Builder for write-only hardware operations.
@@ -95,6 +96,16 @@ impl HardwareWriteOperationsBuilder {
     }
 }
 
+/// Returns the list of memory regions where the configuration space of PCI devices is mapped.
+///
+/// The list is empty if the platform doesn't support or doesn't report this information.
+pub fn pci_config_regions() -> impl Future<Output = Vec<ffi::PciConfigRegion>> {
+    unsafe {
+        let msg = ffi::HardwareMessage::PciConfigRegions;
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
 /// Writes the given data to the given physical memory address location.
 pub unsafe fn write(address: u64, data: impl Into<Vec<u8>>) {
     let mut builder = HardwareWriteOperationsBuilder::with_capacity(1);
@@ -0,0 +1,217 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Human-interface devices are keyboards, pointing devices and gamepads.
+//!
+//! Messages are SCALE-encoded [`HidMessage`]s. Messages that the handler fails to decode are
+//! answered with an error, if they expect an answer.
+//!
+//! The handler of this interface is a hub between two kinds of processes:
+//!
+//! - Drivers register the devices they are responsible for with
+//! [`HidMessage::RegisterDevice`], then report what happens on them with
+//! [`HidMessage::ReportEvents`]. Each driver allocates the identifiers of its devices.
+//! - Consumers ask for the list of devices with [`HidMessage::ListDevices`], and for the stream
+//! of [`HidEvent`]s of all the devices with [`HidMessage::NextEvent`].
+//!
+//! The handler assigns to each device a [`DeviceId`] that is unique across all drivers and is
+//! never reused. Devices of a driver are unregistered when the driver process terminates.
+//!
+//! Events are device-level: keys are reported by their position on a US keyboard rather than by
+//! their meaning according to the keyboard layout, and pointers report movements rather than
+//! positions. Processes that want to show something to the user should use the input events of
+//! the `window` or `framebuffer` interfaces instead.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x43, 0x84, 0xf1, 0x3f, 0xd4, 0xa5, 0xfd, 0xc8, 0x13, 0x44, 0x3c, 0xae, 0x3d, 0x13, 0x68, 0xbd,
+    0xcc, 0x96, 0x60, 0xe6, 0xdc, 0x3b, 0xa7, 0xee, 0xa6, 0x7e, 0xd8, 0x6b, 0x27, 0xa8, 0xc0, 0x1e,
+]);
+
+/// Version of the protocol described in this module.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Message sent on the HID interface.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum HidMessage {
+    /// Sent by drivers. Registers a new device.
+    ///
+    /// Can optionally expect an answer, in which case the handler answers with a
+    /// `Result<(), HidError>`.
+    RegisterDevice {
+        /// Version of the protocol. Should be [`PROTOCOL_VERSION`].
+        version: u32,
+        /// Identifier of the device, as chosen by the driver.
+        id: u32,
+        /// What kind of device this is.
+        kind: DeviceKind,
+        /// Human-readable name of the device.
+        name: String,
+    },
+
+    /// Sent by drivers. Unregisters a device. Never answered.
+    UnregisterDevice {
+        /// Identifier of the device, as chosen by the driver.
+        id: u32,
+    },
+
+    /// Sent by drivers. Reports events that have happened on a device, in chronological order.
+    /// Never answered.
+    ReportEvents {
+        /// Identifier of the device, as chosen by the driver.
+        id: u32,
+        /// What happened.
+        events: Vec<DeviceEvent>,
+    },
+
+    /// Sent by consumers. Must be answered with a `Vec<DeviceInfo>` containing the devices that
+    /// are currently registered.
+    ListDevices,
+
+    /// Sent by consumers. Asks for the next event that happens on any device. Must be answered
+    /// with a [`HidEvent`].
+    ///
+    /// The handler starts buffering events for a process when it receives this message for the
+    /// first time. Events that happen while no such message is pending are buffered, up to an
+    /// implementation-defined limit.
+    NextEvent,
+}
+
+/// Identifier of a device, assigned by the handler.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Encode, Decode)]
+pub struct DeviceId(pub u64);
+
+/// Kind of human-interface device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub enum DeviceKind {
+    /// Reports [`DeviceEvent::Key`].
+    Keyboard,
+    /// Mouse, touchpad, trackball, and so on. Reports [`DeviceEvent::PointerMotion`],
+    /// [`DeviceEvent::PointerButton`] and [`DeviceEvent::Wheel`].
+    Pointer,
+    /// Reports [`DeviceEvent::GamepadButton`] and [`DeviceEvent::GamepadAxis`].
+    Gamepad,
+}
+
+/// Description of a registered device.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct DeviceInfo {
+    /// Identifier of the device.
+    pub id: DeviceId,
+    /// What kind of device this is.
+    pub kind: DeviceKind,
+    /// Human-readable name of the device, as reported by its driver.
+    pub name: String,
+}
+
+/// Answer to a [`HidMessage::NextEvent`].
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum HidEvent {
+    /// A device has been registered.
+    DeviceAdded(DeviceInfo),
+    /// A device has been unregistered. Its identifier will not be reused.
+    DeviceRemoved(DeviceId),
+    /// Something happened on a device.
+    Input {
+        /// Device the event comes from.
+        device: DeviceId,
+        /// What happened.
+        event: DeviceEvent,
+    },
+}
+
+/// Event that happened on a device.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum DeviceEvent {
+    /// A key has been pressed or released.
+    Key {
+        /// Usage code of the key, as defined in the "Keyboard/Keypad Page" of the USB HID Usage
+        /// Tables. For example, `0x04` is the key labelled `A` on a US keyboard. `None` if the
+        /// driver doesn't know which key this is.
+        usage: Option<u16>,
+        /// Raw code sent by the device. Its meaning depends on the driver.
+        scancode: u32,
+        /// New state of the key.
+        state: ButtonState,
+    },
+    /// The pointer has moved by the given amount, in device-specific units.
+    PointerMotion {
+        /// Horizontal movement. Positive values correspond to the right.
+        dx: i32,
+        /// Vertical movement. Positive values correspond to the bottom.
+        dy: i32,
+    },
+    /// A button of a pointer has been pressed or released.
+    PointerButton {
+        /// Index of the button. `0` is the primary (usually left) button, `1` the secondary
+        /// (usually right) button, and `2` the middle button.
+        button: u8,
+        /// New state of the button.
+        state: ButtonState,
+    },
+    /// The wheel of a pointer has been moved.
+    Wheel {
+        /// Horizontal movement in hundredths of notches. Positive values correspond to scrolling
+        /// right.
+        dx: i32,
+        /// Vertical movement in hundredths of notches. Positive values correspond to scrolling
+        /// up.
+        dy: i32,
+    },
+    /// A button of a gamepad has been pressed or released.
+    GamepadButton {
+        /// Index of the button, as numbered by the driver.
+        button: u8,
+        /// New state of the button.
+        state: ButtonState,
+    },
+    /// An axis of a gamepad has changed position.
+    GamepadAxis {
+        /// Index of the axis, as numbered by the driver.
+        axis: u8,
+        /// New position of the axis, where `0` is the resting position.
+        value: i16,
+    },
+}
+
+/// State of a key or button.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum ButtonState {
+    Pressed,
+    Released,
+}
+
+/// Error answered to a [`HidMessage::RegisterDevice`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum HidError {
+    /// The version passed when registering the device isn't supported by the handler.
+    UnsupportedVersion,
+    /// The driver has already registered a device with this identifier.
+    AlreadyExists,
+}
+
+impl fmt::Display for HidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HidError::UnsupportedVersion => write!(f, "Unsupported protocol version"),
+            HidError::AlreadyExists => write!(f, "Device already exists"),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Human-interface devices interface.
+//!
+//! Drivers use [`Device`] in order to report what happens on keyboards, pointing devices and
+//! gamepads. Other processes use [`devices`] and [`next_event`] in order to receive these events.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::future::Future;
+
+pub mod ffi;
+
+/// Device registered towards the interface handler. Unregistered when dropped.
+pub struct Device {
+    id: u32,
+}
+
+impl Device {
+    /// Registers a new device with the given kind and human-readable name.
+    pub async fn register(
+        kind: ffi::DeviceKind,
+        name: impl Into<String>,
+    ) -> Result<Self, ffi::HidError> {
+        let id = unsafe {
+            let mut out = [0; 4];
+            redshirt_random_interface::generate_in(&mut out).await;
+            u32::from_le_bytes(out)
+        };
+
+        let message = ffi::HidMessage::RegisterDevice {
+            version: ffi::PROTOCOL_VERSION,
+            id,
+            kind,
+            name: name.into(),
+        };
+
+        unsafe {
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message)
+                .unwrap()
+                .await?;
+        }
+
+        Ok(Device { id })
+    }
+
+    /// Reports events that have happened on the device, in chronological order.
+    pub fn report(&self, events: Vec<ffi::DeviceEvent>) {
+        if events.is_empty() {
+            return;
+        }
+
+        send(ffi::HidMessage::ReportEvents {
+            id: self.id,
+            events,
+        });
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        send(ffi::HidMessage::UnregisterDevice { id: self.id });
+    }
+}
+
+/// Returns the list of devices that are currently registered.
+pub fn devices() -> impl Future<Output = Vec<ffi::DeviceInfo>> {
+    unsafe {
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, ffi::HidMessage::ListDevices)
+            .unwrap()
+    }
+}
+
+/// Returns the next event that happens on any device.
+///
+/// Events start being buffered by the handler after the first call to this function. Events that
+/// happen while no call is pending are buffered, up to a limit.
+pub fn next_event() -> impl Future<Output = ffi::HidEvent> {
+    unsafe {
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, ffi::HidMessage::NextEvent)
+            .unwrap()
+    }
+}
+
+/// Sends a message that doesn't expect any answer.
+fn send(message: ffi::HidMessage) {
+    unsafe {
+        redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, message).unwrap();
+    }
+}
@@ -14,7 +14,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use parity_scale_codec::{Decode, Encode};
-use redshirt_syscalls::InterfaceHash;
+use redshirt_syscalls::{InterfaceHash, Pid};
 
 // TODO: this has been randomly generated; instead should be a hash or something
 pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
@@ -25,6 +25,11 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
 #[derive(Debug, Encode, Decode)]
 pub enum InterfaceMessage {
     Register(InterfaceHash),
+    /// Grants to a process the right to use an interface. The emitter must itself be allowed to
+    /// use this interface.
+    ///
+    /// Has no effect if the process doesn't exist.
+    Grant { pid: Pid, interface: InterfaceHash },
 }
 
 #[derive(Debug, Encode, Decode)]
@@ -37,3 +42,14 @@ pub enum InterfaceRegisterError {
     /// There already exists a process registered for this interface.
     AlreadyRegistered,
 }
+
+#[derive(Debug, Encode, Decode)]
+pub struct InterfaceGrantResponse {
+    pub result: Result<(), InterfaceGrantError>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub enum InterfaceGrantError {
+    /// The emitter of the message isn't allowed to use the interface it tries to grant.
+    NotAuthorized,
+}
@@ -18,9 +18,9 @@
 #![no_std]
 
 use futures::prelude::*;
-use redshirt_syscalls::InterfaceHash;
+use redshirt_syscalls::{InterfaceHash, Pid};
 
-pub use ffi::InterfaceRegisterError;
+pub use ffi::{InterfaceGrantError, InterfaceRegisterError};
 
 pub mod ffi;
 
@@ -41,3 +41,20 @@ pub fn register_interface(
             .map(|response: ffi::InterfaceRegisterResponse| response.result)
     }
 }
+
+/// Grants to the given process the right to use the given interface, for example right after
+/// having started it.
+///
+/// Returns an error if the current program isn't itself allowed to use this interface.
+pub fn grant(
+    pid: Pid,
+    interface: InterfaceHash,
+) -> impl Future<Output = Result<(), InterfaceGrantError>> {
+    let msg = ffi::InterfaceMessage::Grant { pid, interface };
+    // We unwrap cause there's always something that handles interface registration.
+    unsafe {
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .map(|response: ffi::InterfaceGrantResponse| response.result)
+    }
+}
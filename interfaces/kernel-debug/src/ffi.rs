@@ -0,0 +1,118 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls::{InterfaceHash, Pid, ThreadId};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x33, 0xbd, 0x31, 0x47, 0x78, 0x3b, 0x28, 0x0a, 0x23, 0xf5, 0x4d, 0xb3, 0x4d, 0xfc, 0xab, 0xd5,
+    0x9f, 0x4e, 0xa1, 0xdb, 0x04, 0xf7, 0xaa, 0xb2, 0xf5, 0xae, 0x46, 0x38, 0x34, 0x9a, 0x56, 0x0d,
+]);
+
+/// Message to send on the kernel-debug interface.
+#[derive(Debug, Encode, Decode)]
+pub enum KernelDebugMessage {
+    /// Request the list of processes. Answered with a [`ProcessesList`].
+    GetProcesses,
+    /// Request the list of interfaces. Answered with an [`InterfacesList`].
+    GetInterfaces,
+}
+
+/// Response to [`KernelDebugMessage::GetProcesses`].
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct ProcessesList {
+    pub processes: Vec<ProcessInfo>,
+}
+
+/// Information about a running process.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct ProcessInfo {
+    /// Identifier of the process.
+    pub pid: Pid,
+    /// Blake3 hash of the module the process has been started from.
+    pub module_hash: [u8; 32],
+    /// Human-readable name of the process, if known.
+    pub name: Option<String>,
+    /// Process whose actions have led to this process being started, if any.
+    pub parent: Option<Pid>,
+    /// Value of the kernel's monotonic clock, in nanoseconds, when the process has been started,
+    /// or `None` if the kernel doesn't measure it.
+    pub start_time: Option<u64>,
+    /// List of threads of the process.
+    pub threads: Vec<ThreadInfo>,
+    /// Size in bytes of the memory of the process.
+    pub memory_size: u64,
+    /// Total number of nanoseconds spent executing the process, or `None` if the kernel doesn't
+    /// measure it.
+    pub cpu_time: Option<u64>,
+    /// Interfaces that the process has registered.
+    pub registered_interfaces: Vec<InterfaceHash>,
+    /// Number of notifications waiting to be retrieved by the process.
+    pub notifications_queue_len: u32,
+}
+
+/// Information about a thread of a process.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct ThreadInfo {
+    /// Identifier of the thread.
+    pub thread_id: ThreadId,
+    /// What the thread is doing.
+    pub state: ThreadState,
+}
+
+/// What a thread is doing.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum ThreadState {
+    /// Thread is ready to run and waiting for its turn.
+    Ready,
+    /// Thread is currently running, or a call that it has made is being processed.
+    Running,
+    /// Thread is sleeping and waiting for a notification.
+    WaitNotification,
+    /// Thread is waiting for an interface to be registered in order to emit a message on it.
+    EmitMessage(InterfaceHash),
+    /// Thread is waiting for the answer to a message emitted by the kernel on its behalf.
+    WaitExtrinsic,
+}
+
+/// Response to [`KernelDebugMessage::GetInterfaces`].
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct InterfacesList {
+    pub interfaces: Vec<InterfaceInfo>,
+}
+
+/// Information about an interface.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct InterfaceInfo {
+    /// Hash of the interface.
+    pub interface: InterfaceHash,
+    /// How the interface is handled.
+    pub handler: InterfaceHandler,
+}
+
+/// How an interface is handled.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum InterfaceHandler {
+    /// Interface is registered by the given process. The process might be a program built into
+    /// the kernel.
+    Process(Pid),
+    /// Interface hasn't been registered yet.
+    Requested {
+        /// Number of threads and messages waiting for the interface to be registered.
+        waiting: u32,
+    },
+}
@@ -0,0 +1,55 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Gathering information and statistics about the kernel.
+//!
+//! This interface is handled by the kernel itself. It lets programs, such as a task manager,
+//! query the list of running processes and the state of the inter-process communications. The
+//! information returned is supposed to be shown to users.
+//!
+//! Only processes that have been authorized to use this interface can use it. Messages from
+//! other processes are answered with an error.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+pub mod ffi;
+
+/// Returns the list of processes currently running.
+pub async fn processes() -> Vec<ffi::ProcessInfo> {
+    unsafe {
+        let msg = ffi::KernelDebugMessage::GetProcesses;
+        let list: ffi::ProcessesList =
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await;
+        list.processes
+    }
+}
+
+/// Returns the list of interfaces that have been registered or that processes are waiting for.
+pub async fn interfaces() -> Vec<ffi::InterfaceInfo> {
+    unsafe {
+        let msg = ffi::KernelDebugMessage::GetInterfaces;
+        let list: ffi::InterfacesList =
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await;
+        list.interfaces
+    }
+}
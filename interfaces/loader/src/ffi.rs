@@ -23,6 +23,14 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
     0xb3, 0xc6, 0x2d, 0x24, 0x31, 0x88, 0x96, 0x95, 0x6e, 0xfc, 0x7d, 0x4d, 0x86, 0x3f, 0xff, 0xa6,
 ]);
 
+/// Interface with the same protocol as [`INTERFACE`], registered by loaders that are only
+/// queried when the handler of [`INTERFACE`] doesn't know the requested module.
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const FALLBACK_INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x8e, 0x3a, 0x0f, 0x52, 0xd1, 0x6b, 0x94, 0x27, 0x5c, 0xe0, 0x43, 0xb8, 0x19, 0x7d, 0xa6, 0x2f,
+    0x61, 0xc4, 0x0b, 0x9e, 0x35, 0xf7, 0x82, 0x5a, 0xdd, 0x10, 0x6e, 0xb3, 0x48, 0x29, 0xcf, 0x74,
+]);
+
 #[derive(Debug, Encode, Decode)]
 pub enum LoaderMessage {
     /// Load the data corresponding to the blake3 hash passed as parameter.
@@ -32,4 +40,19 @@ pub enum LoaderMessage {
 #[derive(Debug, Encode, Decode)]
 pub struct LoadResponse {
     pub result: Result<Vec<u8>, ()>,
+    /// Signature of the module by its publisher, if known.
+    ///
+    /// The p2p loader doesn't fetch signatures from the network and always sets this field to
+    /// `None`. Modules it returns are therefore rejected if only trusted publishers are allowed.
+    pub signature: Option<PublisherSignature>,
+}
+
+/// Signature of a module by its publisher.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct PublisherSignature {
+    /// Ed25519 public key of the publisher.
+    pub public_key: [u8; 32],
+    /// Ed25519 signature of the blake3 hash of the module, made with the private key
+    /// corresponding to `public_key`. Must be 64 bytes long.
+    pub signature: Vec<u8>,
 }
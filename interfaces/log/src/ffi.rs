@@ -15,8 +15,10 @@
 
 //! Communication between a process and the interface handler.
 //!
-//! A log message consists of one byte indicating the log level, followed with the log message
-//! itself encoded in UTF-8.
+//! The first byte of a message indicates its kind.
+//!
+//! If the first byte is a log level, the message is a log message, and the rest of the message is
+//! the log message itself encoded in UTF-8. The message doesn't expect any answer.
 //!
 //! Log levels:
 //!
@@ -26,8 +28,17 @@
 //! - Debug: 1
 //! - Trace: 0
 //!
+//! If the first byte is [`STRUCTURED_RECORD`], the rest of the message is a SCALE-encoded
+//! [`LogRecord`]. The message doesn't expect any answer.
+//!
+//! If the first byte is [`FILTER_REQUEST`], the message must not contain anything else, and
+//! expects a [`LogFilter`] as answer. Processes can use this filter to avoid emitting log
+//! messages that the handler would discard anyway.
+//!
 
+use alloc::{string::String, vec::Vec};
 use core::{convert::TryFrom, fmt, str};
+use parity_scale_codec::{DecodeAll as _, Encode as _};
 use redshirt_syscalls::{Decode, EncodedMessage, InterfaceHash};
 
 // TODO: this has been randomly generated; instead should be a hash or something
@@ -36,8 +47,16 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
     0x25, 0x57, 0x23, 0x91, 0x79, 0xc8, 0x16, 0x07, 0x6f, 0xab, 0xa9, 0xd6, 0x38, 0xca, 0x01, 0x8b,
 ]);
 
+/// First byte of a message containing a [`LogRecord`].
+pub const STRUCTURED_RECORD: u8 = 0x80;
+
+/// First byte of a message requesting the [`LogFilter`].
+pub const FILTER_REQUEST: u8 = 0x81;
+
 /// Log level of a message.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+///
+/// Levels are ordered from the least verbose to the most verbose.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Level {
     Error,
     Warn,
@@ -73,6 +92,148 @@ impl TryFrom<u8> for Level {
     }
 }
 
+impl parity_scale_codec::Encode for Level {
+    fn encode_to<T: parity_scale_codec::Output>(&self, dest: &mut T) {
+        u8::from(*self).encode_to(dest)
+    }
+}
+
+impl parity_scale_codec::Decode for Level {
+    fn decode<I: parity_scale_codec::Input>(
+        input: &mut I,
+    ) -> Result<Self, parity_scale_codec::Error> {
+        let value = <u8 as parity_scale_codec::Decode>::decode(input)?;
+        Level::try_from(value).map_err(|_| "Invalid log level".into())
+    }
+}
+
+/// Structured log message.
+#[derive(Debug, Clone, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub struct LogRecord {
+    /// Log level of the message.
+    pub level: Level,
+    /// Name of the component that has emitted the message. Typically a Rust module path.
+    pub target: String,
+    /// Path of the Rust module that has emitted the message, if known.
+    pub module_path: Option<String>,
+    /// The message itself.
+    pub message: String,
+    /// List of additional key-value pairs attached to the message.
+    pub fields: Vec<(String, String)>,
+    /// Value of the monotonic clock, in nanoseconds, when the message has been emitted.
+    ///
+    /// If `None`, the handler uses the time when the message has been received.
+    pub timestamp: Option<u128>,
+}
+
+/// Which log messages the handler is interested in.
+///
+/// Each target is associated to a maximum level. Messages whose level is more verbose than the
+/// maximum level of their target are discarded. A `None` maximum means that all messages are
+/// discarded.
+#[derive(Debug, Clone, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub struct LogFilter {
+    /// Maximum level for targets that don't match any entry in `targets`.
+    pub default: Option<Level>,
+    /// List of targets prefixes and their maximum level. If multiple entries match a target,
+    /// the longest one is used.
+    pub targets: Vec<(String, Option<Level>)>,
+}
+
+impl LogFilter {
+    /// Returns a filter that accepts all messages.
+    pub fn all() -> Self {
+        LogFilter {
+            default: Some(Level::Trace),
+            targets: Vec::new(),
+        }
+    }
+
+    /// Returns the maximum level of messages accepted for the given target.
+    pub fn max_level(&self, target: &str) -> Option<Level> {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| target_matches(target, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// Returns the most verbose level accepted by the filter, for any target.
+    pub fn max_level_any(&self) -> Option<Level> {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .chain(core::iter::once(self.default))
+            .max()
+            .flatten()
+    }
+
+    /// Returns true if a message with the given target and level is accepted.
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        self.max_level(target).map_or(false, |max| level <= max)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter::all()
+    }
+}
+
+impl str::FromStr for LogFilter {
+    type Err = ParseFilterError;
+
+    /// Parses a filter in the format `level,target=level,...`, similar to the `env_logger`
+    /// crate. Levels are `off`, `error`, `warn`, `info`, `debug` or `trace`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = LogFilter {
+            default: Some(Level::Trace),
+            targets: Vec::new(),
+        };
+
+        for directive in s.split(',').map(|d| d.trim()).filter(|d| !d.is_empty()) {
+            let mut split = directive.splitn(2, '=');
+            match (split.next(), split.next()) {
+                (Some(level), None) => filter.default = parse_level(level)?,
+                (Some(target), Some(level)) => {
+                    filter.targets.push((target.into(), parse_level(level)?));
+                }
+                (None, _) => unreachable!(),
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+/// Returns true if `target` is equal to `prefix`, or is a sub-module of `prefix`.
+fn target_matches(target: &str, prefix: &str) -> bool {
+    target == prefix || (target.starts_with(prefix) && target[prefix.len()..].starts_with("::"))
+}
+
+/// Parses a level passed in a filter.
+fn parse_level(level: &str) -> Result<Option<Level>, ParseFilterError> {
+    Ok(Some(match level {
+        "off" => return Ok(None),
+        "error" => Level::Error,
+        "warn" => Level::Warn,
+        "info" => Level::Info,
+        "debug" => Level::Debug,
+        "trace" => Level::Trace,
+        _ => return Err(ParseFilterError),
+    }))
+}
+
+/// Error that can happen when parsing a [`LogFilter`].
+#[derive(Debug)]
+pub struct ParseFilterError;
+
+impl fmt::Display for ParseFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid log filter")
+    }
+}
+
 /// Error that can happen when decoding a [`Level`].
 #[derive(Debug)]
 pub struct InvalidLevelError(u8);
@@ -91,21 +252,70 @@ impl fmt::Display for InvalidLevelError {
 }
 
 /// Decoded version of a message on the log interface.
+pub enum DecodedLogInterfaceMessage {
+    /// Log message. Doesn't expect any answer.
+    Log(DecodedLogMessage),
+    /// Request for the [`LogFilter`]. Expects a [`LogFilter`] as answer.
+    FilterRequest,
+}
+
+impl Decode for DecodedLogInterfaceMessage {
+    type Error = DecodeError;
+
+    fn decode(buffer: EncodedMessage) -> Result<Self, DecodeError> {
+        if buffer.0.first() == Some(&FILTER_REQUEST) {
+            if buffer.0.len() != 1 {
+                return Err(DecodeError::BadFilterRequest);
+            }
+            return Ok(DecodedLogInterfaceMessage::FilterRequest);
+        }
+
+        Ok(DecodedLogInterfaceMessage::Log(DecodedLogMessage::decode(buffer)?))
+    }
+}
+
+/// Decoded version of a log message on the log interface.
+///
+/// Log messages that don't use the structured format have an empty target and no fields.
 pub struct DecodedLogMessage {
-    level: Level,
-    buffer: EncodedMessage,
+    record: LogRecord,
 }
 
 impl DecodedLogMessage {
     /// Returns the log level of the message.
     pub fn level(&self) -> Level {
-        self.level
+        self.record.level
+    }
+
+    /// Returns the name of the component that has emitted the message. Empty if unknown.
+    pub fn target(&self) -> &str {
+        &self.record.target
+    }
+
+    /// Returns the path of the Rust module that has emitted the message, if known.
+    pub fn module_path(&self) -> Option<&str> {
+        self.record.module_path.as_ref().map(|p| &p[..])
     }
 
     /// Returns the message itself.
     pub fn message(&self) -> &str {
-        // We checked the validity when decoding.
-        str::from_utf8(&self.buffer.0[1..]).unwrap()
+        &self.record.message
+    }
+
+    /// Returns the additional key-value pairs attached to the message.
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.record.fields
+    }
+
+    /// Returns the value of the monotonic clock when the message has been emitted, if known.
+    pub fn timestamp(&self) -> Option<u128> {
+        self.record.timestamp
+    }
+}
+
+impl From<DecodedLogMessage> for LogRecord {
+    fn from(msg: DecodedLogMessage) -> LogRecord {
+        msg.record
     }
 }
 
@@ -113,12 +323,28 @@ impl Decode for DecodedLogMessage {
     type Error = DecodeError;
 
     fn decode(buffer: EncodedMessage) -> Result<Self, DecodeError> {
-        if buffer.0.is_empty() {
-            return Err(DecodeError::LevelMissing);
+        let (first, rest) = match buffer.0.split_first() {
+            Some(v) => v,
+            None => return Err(DecodeError::LevelMissing),
+        };
+
+        if *first == STRUCTURED_RECORD {
+            let record = LogRecord::decode_all(rest).map_err(|_| DecodeError::BadRecord)?;
+            return Ok(DecodedLogMessage { record });
         }
-        let level = Level::try_from(buffer.0[0]).map_err(DecodeError::LevelDecodeError)?;
-        let _ = str::from_utf8(&buffer.0[1..]).map_err(DecodeError::NotUtf8)?;
-        Ok(DecodedLogMessage { level, buffer })
+
+        let level = Level::try_from(*first).map_err(DecodeError::LevelDecodeError)?;
+        let message = str::from_utf8(rest).map_err(DecodeError::NotUtf8)?;
+        Ok(DecodedLogMessage {
+            record: LogRecord {
+                level,
+                target: String::new(),
+                module_path: None,
+                message: message.into(),
+                fields: Vec::new(),
+                timestamp: None,
+            },
+        })
     }
 }
 
@@ -128,6 +354,8 @@ pub enum DecodeError {
     LevelMissing,
     LevelDecodeError(InvalidLevelError),
     NotUtf8(str::Utf8Error),
+    BadRecord,
+    BadFilterRequest,
 }
 
 impl fmt::Display for DecodeError {
@@ -136,6 +364,103 @@ impl fmt::Display for DecodeError {
             DecodeError::LevelMissing => write!(f, "Missing log level"),
             DecodeError::LevelDecodeError(_) => write!(f, "Invalid log level"),
             DecodeError::NotUtf8(_) => write!(f, "Not UTF-8 error"),
+            DecodeError::BadRecord => write!(f, "Invalid structured log record"),
+            DecodeError::BadFilterRequest => write!(f, "Invalid filter request"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use parity_scale_codec::Encode as _;
+
+    #[test]
+    fn filter_from_str() {
+        let filter: LogFilter = "warn,foo=trace, foo::bar=off ,baz=info".parse().unwrap();
+        assert_eq!(filter.default, Some(Level::Warn));
+        assert_eq!(
+            filter.targets,
+            vec![
+                (String::from("foo"), Some(Level::Trace)),
+                (String::from("foo::bar"), None),
+                (String::from("baz"), Some(Level::Info)),
+            ]
+        );
+
+        let filter: LogFilter = "".parse().unwrap();
+        assert_eq!(filter.default, Some(Level::Trace));
+        assert!(filter.targets.is_empty());
+
+        assert!("foo=loud".parse::<LogFilter>().is_err());
+        assert!("verbose".parse::<LogFilter>().is_err());
+    }
+
+    #[test]
+    fn max_level_longest_prefix() {
+        let filter: LogFilter = "error,foo=debug,foo::bar=off,foo::bar::baz=trace".parse().unwrap();
+        assert_eq!(filter.max_level("other"), Some(Level::Error));
+        assert_eq!(filter.max_level("foo"), Some(Level::Debug));
+        assert_eq!(filter.max_level("foo::qux"), Some(Level::Debug));
+        assert_eq!(filter.max_level("foo::bar"), None);
+        assert_eq!(filter.max_level("foo::bar::qux"), None);
+        assert_eq!(filter.max_level("foo::bar::baz"), Some(Level::Trace));
+        assert_eq!(filter.max_level_any(), Some(Level::Trace));
+
+        assert!(filter.enabled("foo", Level::Info));
+        assert!(!filter.enabled("foo", Level::Trace));
+        assert!(!filter.enabled("foo::bar", Level::Error));
+    }
+
+    #[test]
+    fn target_matching() {
+        assert!(target_matches("foo", "foo"));
+        assert!(target_matches("foo::bar", "foo"));
+        assert!(!target_matches("foobar", "foo"));
+        assert!(!target_matches("foo", "foo::bar"));
+        assert!(!target_matches("bar::foo", "foo"));
+    }
+
+    #[test]
+    fn structured_record_decode() {
+        let record = LogRecord {
+            level: Level::Debug,
+            target: "foo::bar".into(),
+            module_path: Some("foo::bar".into()),
+            message: "hello".into(),
+            fields: vec![("key".into(), "value".into())],
+            timestamp: Some(12345),
+        };
+
+        let mut encoded = vec![STRUCTURED_RECORD];
+        encoded.extend_from_slice(&record.encode());
+
+        let decoded = DecodedLogMessage::decode(EncodedMessage(encoded)).unwrap();
+        assert_eq!(decoded.level(), Level::Debug);
+        assert_eq!(decoded.target(), "foo::bar");
+        assert_eq!(decoded.module_path(), Some("foo::bar"));
+        assert_eq!(decoded.message(), "hello");
+        assert_eq!(decoded.fields(), &[(String::from("key"), String::from("value"))][..]);
+        assert_eq!(decoded.timestamp(), Some(12345));
+    }
+
+    #[test]
+    fn unstructured_message_decode() {
+        let mut encoded = vec![u8::from(Level::Warn)];
+        encoded.extend_from_slice(b"hello");
+
+        let decoded = DecodedLogMessage::decode(EncodedMessage(encoded)).unwrap();
+        assert_eq!(decoded.level(), Level::Warn);
+        assert_eq!(decoded.target(), "");
+        assert_eq!(decoded.message(), "hello");
+        assert!(decoded.fields().is_empty());
+        assert!(decoded.timestamp().is_none());
+    }
+
+    #[test]
+    fn bad_record_rejected() {
+        let encoded = vec![STRUCTURED_RECORD, 0xff, 0xff];
+        assert!(DecodedLogMessage::decode(EncodedMessage(encoded)).is_err());
+    }
+}
@@ -30,12 +30,20 @@
 //! redshirt_log_interface::init();
 //! log::debug!("debug log message here");
 //! ```
+//!
+//! Log messages are sent as structured records, containing the target of the message, its
+//! key-value pairs, and the value of the monotonic clock when the message has been emitted.
+//! Reading the clock requires a round-trip to the handler of the time interface.
+//!
+//! By default, all log messages are emitted. Call [`update_filter`] in order to ask the handler
+//! of the interface which messages it is interested in, and not emit the other ones.
 
 #![no_std]
 
 extern crate alloc;
 
-use alloc::format;
+use alloc::{format, string::String, vec::Vec};
+use spinning_top::Spinlock;
 
 pub mod ffi;
 
@@ -63,6 +71,47 @@ pub fn emit_log(level: Level, msg: &str) {
     }
 }
 
+/// Appends a structured record to the logs of the program.
+pub fn emit_record(record: &ffi::LogRecord) {
+    unsafe {
+        let marker: [u8; 1] = [ffi::STRUCTURED_RECORD];
+        let encoded = parity_scale_codec::Encode::encode(record);
+        redshirt_syscalls::MessageBuilder::new()
+            .add_data_raw(&marker[..])
+            .add_data_raw(&encoded)
+            .emit_without_response(&ffi::INTERFACE)
+            .unwrap();
+    }
+}
+
+/// Filter applied by the global logger. `None` if [`update_filter`] has never been called, in
+/// which case all messages are emitted.
+static FILTER: Spinlock<Option<ffi::LogFilter>> = Spinlock::new(None);
+
+/// Asks the handler of the interface which log messages it is interested in, and configures the
+/// global logger to only emit these messages.
+///
+/// Can be called multiple times in order to refresh the filter.
+pub async fn update_filter() {
+    let response = unsafe {
+        let request: [u8; 1] = [ffi::FILTER_REQUEST];
+        redshirt_syscalls::MessageBuilder::new()
+            .add_data_raw(&request[..])
+            .emit_with_response::<ffi::LogFilter>(&ffi::INTERFACE)
+    };
+
+    let filter = match response {
+        Ok(fut) => fut.await,
+        Err(_) => return,
+    };
+
+    log::set_max_level(match filter.max_level_any() {
+        Some(level) => to_log_level(level).to_level_filter(),
+        None => log::LevelFilter::Off,
+    });
+    *FILTER.lock() = Some(filter);
+}
+
 /// Attempts to initializes the global logger.
 ///
 /// # Panic
@@ -94,22 +143,67 @@ pub fn init() {
 pub struct GlobalLogger;
 
 impl log::Log for GlobalLogger {
-    fn enabled(&self, _: &log::Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        match &*FILTER.lock() {
+            Some(filter) => filter.enabled(metadata.target(), from_log_level(metadata.level())),
+            None => true,
+        }
     }
 
     fn log(&self, record: &log::Record) {
-        let level = match record.level() {
-            log::Level::Error => Level::Error,
-            log::Level::Warn => Level::Warn,
-            log::Level::Info => Level::Info,
-            log::Level::Debug => Level::Debug,
-            log::Level::Trace => Level::Trace,
-        };
-
-        let message = format!("{} -- {}", record.target(), record.args());
-        emit_log(level, &message)
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut fields = Vec::new();
+        let _ = record.key_values().visit(&mut FieldsVisitor(&mut fields));
+
+        emit_record(&ffi::LogRecord {
+            level: from_log_level(record.level()),
+            target: record.target().into(),
+            module_path: record.module_path().map(String::from),
+            message: format!("{}", record.args()),
+            fields,
+            // Querying the clock would require waiting for an answer from within the logger,
+            // which might be called while the program is already blocked. The handler stamps
+            // the record when it receives it instead.
+            timestamp: None,
+        })
     }
 
     fn flush(&self) {}
 }
+
+/// Collects the key-value pairs of a [`log::Record`].
+struct FieldsVisitor<'a>(&'a mut Vec<(String, String)>);
+
+impl<'a, 'kvs> log::kv::Visitor<'kvs> for FieldsVisitor<'a> {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.push((key.as_str().into(), format!("{}", value)));
+        Ok(())
+    }
+}
+
+fn from_log_level(level: log::Level) -> Level {
+    match level {
+        log::Level::Error => Level::Error,
+        log::Level::Warn => Level::Warn,
+        log::Level::Info => Level::Info,
+        log::Level::Debug => Level::Debug,
+        log::Level::Trace => Level::Trace,
+    }
+}
+
+fn to_log_level(level: Level) -> log::Level {
+    match level {
+        Level::Error => log::Level::Error,
+        Level::Warn => log::Level::Warn,
+        Level::Info => log::Level::Info,
+        Level::Debug => log::Level::Debug,
+        Level::Trace => log::Level::Trace,
+    }
+}
@@ -24,10 +24,66 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
 ]);
 
 /// Message in destination to the PCI interface handler.
+///
+/// The first process that sends a [`PciMessage::WriteConfig`], [`PciMessage::SetBusMaster`],
+/// [`PciMessage::EnableMsi`] or [`PciMessage::EnableMsiX`] for a device becomes the owner of
+/// this device until it terminates. These messages are denied for all the other processes.
 #[derive(Debug, Encode, Decode)]
 pub enum PciMessage {
     /// Request list of PCI devices. Answer with a [`GetDevicesListResponse`].
     GetDevicesList,
+    /// Reads 32 bits of the configuration space of a device. Answer with a
+    /// `Result<u32, PciError>`.
+    ///
+    /// `offset` must be a multiple of 4. Offsets above 256 are only available if the platform
+    /// supports the PCI Express extended configuration space.
+    ReadConfig {
+        location: PciDeviceLocation,
+        offset: u16,
+    },
+    /// Writes 32 bits of the configuration space of a device. If a response is expected,
+    /// answer with a `Result<(), PciError>`.
+    ///
+    /// Same restrictions on `offset` as [`PciMessage::ReadConfig`]. Additionally, the MSI and
+    /// MSI-X capabilities can only be modified through [`PciMessage::EnableMsi`] and
+    /// [`PciMessage::EnableMsiX`].
+    WriteConfig {
+        location: PciDeviceLocation,
+        offset: u16,
+        value: u32,
+    },
+    /// Enables or disables the ability of the device to perform memory accesses on its own,
+    /// also known as bus mastering. This is necessary for the device to perform DMA or to
+    /// deliver message-signalled interrupts. If a response is expected, answer with a
+    /// `Result<(), PciError>`.
+    SetBusMaster {
+        location: PciDeviceLocation,
+        enabled: bool,
+    },
+    /// Configures the device to deliver message-signalled interrupts (MSI) by writing `data` at
+    /// `address`, and disables its legacy interrupts. Answer with a `Result<(), PciError>`.
+    ///
+    /// Only one interrupt vector per device is supported.
+    ///
+    /// `address` and `data` must designate an interrupt of a processor, such as the ones that
+    /// the `hardware` interface reports for a subscription to message-signalled interrupts.
+    /// Otherwise, [`PciError::InvalidMessage`] is returned.
+    EnableMsi {
+        location: PciDeviceLocation,
+        address: u64,
+        data: u32,
+    },
+    /// Configures an entry of the MSI-X table of the device to deliver an interrupt by writing
+    /// `data` at `address`, then enables MSI-X and disables the legacy interrupts. Answer with
+    /// a `Result<(), PciError>`.
+    ///
+    /// Same restrictions on `address` and `data` as [`PciMessage::EnableMsi`].
+    EnableMsiX {
+        location: PciDeviceLocation,
+        entry: u16,
+        address: u64,
+        data: u32,
+    },
 }
 
 /// Response to [`PciMessage::GetDevicesList`].
@@ -37,13 +93,56 @@ pub struct GetDevicesListResponse {
     pub devices: Vec<PciDeviceInfo>,
 }
 
+/// Error that can happen when accessing a device.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum PciError {
+    /// No device exists at the given location.
+    UnknownDevice,
+    /// The offset in the configuration space is misaligned or out of range.
+    InvalidOffset,
+    /// The device doesn't have the capability required by the request.
+    CapabilityNotSupported,
+    /// The MSI-X table entry is out of range.
+    InvalidEntry,
+    /// The address and data of a message-signalled interrupt don't designate an interrupt of a
+    /// processor.
+    InvalidMessage,
+    /// The device is owned by a different process.
+    Denied,
+}
+
+/// Location of a device on the PCI buses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct PciDeviceLocation {
+    pub bus: u8,
+    /// Between 0 and 31.
+    pub device: u8,
+    /// Between 0 and 7.
+    pub function: u8,
+}
+
 /// Description of a single PCI device.
 #[derive(Debug, Clone, Encode, Decode)]
 pub struct PciDeviceInfo {
+    /// Where the device is located. Used to refer to this device in requests.
+    pub location: PciDeviceLocation,
     pub vendor_id: u16,
     pub device_id: u16,
-    pub base_address_registers: Vec<PciBaseAddressRegister>,
-    // TODO: add more fields
+    /// Broad category of the device.
+    pub class_code: u8,
+    /// Category of the device within its class.
+    pub subclass: u8,
+    /// Programming interface of the device, whose meaning depends on the class and subclass.
+    pub prog_if: u8,
+    pub revision_id: u8,
+    /// Contains 6 elements for regular devices, and 2 for PCI-to-PCI bridges. `None` if the BAR
+    /// is unused or if it is the upper half of a 64 bits memory BAR.
+    pub base_address_registers: Vec<Option<PciBaseAddressRegister>>,
+    /// Legacy IRQ that the device uses to signal interrupts, as configured by the firmware.
+    /// `None` if the device doesn't use interrupts or if no IRQ has been assigned to it.
+    pub interrupt_line: Option<u8>,
+    /// List of capabilities that the device supports.
+    pub capabilities: Vec<PciCapability>,
 }
 
 /// Description of a single PCI device.
@@ -51,10 +150,24 @@ pub struct PciDeviceInfo {
 #[derive(Debug, Clone, Encode, Decode)]
 pub enum PciBaseAddressRegister {
     Memory {
-        base_address: u32,
+        base_address: u64,
+        /// Number of bytes that the BAR covers.
+        size: u64,
         prefetchable: bool,
     },
     Io {
         base_address: u32,
+        /// Number of ports that the BAR covers.
+        size: u32,
     },
 }
+
+/// Capability of a PCI device.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct PciCapability {
+    /// Identifier of the capability, as assigned by the PCI-SIG. For example, `0x05` for MSI
+    /// or `0x11` for MSI-X.
+    pub id: u8,
+    /// Offset of the capability structure within the configuration space of the device.
+    pub offset: u8,
+}
@@ -21,7 +21,9 @@
 
 extern crate alloc;
 
-pub use self::ffi::{PciBaseAddressRegister, PciDeviceInfo};
+pub use self::ffi::{
+    PciBaseAddressRegister, PciCapability, PciDeviceInfo, PciDeviceLocation, PciError,
+};
 
 use alloc::vec::Vec;
 use futures::prelude::*;
@@ -38,3 +40,82 @@ pub fn get_pci_devices() -> impl Future<Output = Vec<PciDeviceInfo>> {
             .map(|response: ffi::GetDevicesListResponse| response.devices)
     }
 }
+
+/// Reads 32 bits of the configuration space of a device.
+///
+/// `offset` must be a multiple of 4.
+pub fn read_config(
+    location: PciDeviceLocation,
+    offset: u16,
+) -> impl Future<Output = Result<u32, PciError>> {
+    unsafe {
+        let msg = ffi::PciMessage::ReadConfig { location, offset };
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Writes 32 bits of the configuration space of a device.
+///
+/// `offset` must be a multiple of 4.
+pub fn write_config(
+    location: PciDeviceLocation,
+    offset: u16,
+    value: u32,
+) -> impl Future<Output = Result<(), PciError>> {
+    unsafe {
+        let msg = ffi::PciMessage::WriteConfig {
+            location,
+            offset,
+            value,
+        };
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Enables or disables bus mastering on the given device. This is necessary for the device to
+/// perform DMA or to deliver message-signalled interrupts.
+pub fn set_bus_master(
+    location: PciDeviceLocation,
+    enabled: bool,
+) -> impl Future<Output = Result<(), PciError>> {
+    unsafe {
+        let msg = ffi::PciMessage::SetBusMaster { location, enabled };
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Configures the given device to deliver message-signalled interrupts by writing `data` at
+/// `address`.
+pub fn enable_msi(
+    location: PciDeviceLocation,
+    address: u64,
+    data: u32,
+) -> impl Future<Output = Result<(), PciError>> {
+    unsafe {
+        let msg = ffi::PciMessage::EnableMsi {
+            location,
+            address,
+            data,
+        };
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Configures an entry of the MSI-X table of the given device to deliver an interrupt by
+/// writing `data` at `address`, then enables MSI-X.
+pub fn enable_msi_x(
+    location: PciDeviceLocation,
+    entry: u16,
+    address: u64,
+    data: u32,
+) -> impl Future<Output = Result<(), PciError>> {
+    unsafe {
+        let msg = ffi::PciMessage::EnableMsiX {
+            location,
+            entry,
+            address,
+            data,
+        };
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::BufferHandle;
+
+use alloc::vec::Vec;
+use core::{convert::TryFrom as _, fmt};
+
+/// Payloads whose length is superior to this value are put in a [`Buffer`] by [`Payload::new`].
+pub const PAYLOAD_INLINE_MAX: usize = 16 * 1024;
+
+/// Buffer of data owned by the kernel.
+///
+/// Only the process that owns a buffer can access it. Use [`Buffer::transfer`] in order to pass
+/// the buffer to another program, then embed the returned [`BufferHandle`] in a message.
+///
+/// The buffer is destroyed when the [`Buffer`] is dropped, unless it has been transferred.
+pub struct Buffer {
+    handle: BufferHandle,
+}
+
+impl Buffer {
+    /// Creates a new buffer of `len` bytes filled with zeroes.
+    ///
+    /// Returns an error if the buffers owned by the current process would be too large in total.
+    pub fn new(len: u32) -> Result<Self, BufferErr> {
+        #[cfg(target_arch = "wasm32")] // TODO: we should have a proper operating system name instead
+        fn imp(len: u32) -> Result<Buffer, BufferErr> {
+            let mut handle = 0;
+            match unsafe { crate::ffi::buffer_create(len, &mut handle) } {
+                0 => Ok(Buffer {
+                    handle: BufferHandle::from(handle),
+                }),
+                _ => Err(BufferErr),
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        fn imp(_: u32) -> Result<Buffer, BufferErr> {
+            unreachable!()
+        }
+        imp(len)
+    }
+
+    /// Creates a new buffer containing a copy of `data`.
+    ///
+    /// Returns an error if `data` is too large.
+    pub fn from_slice(data: &[u8]) -> Result<Self, BufferErr> {
+        let len = u32::try_from(data.len()).map_err(|_| BufferErr)?;
+        let mut buffer = Buffer::new(len)?;
+        buffer.write(0, data)?;
+        Ok(buffer)
+    }
+
+    /// Wraps around a handle received from another program.
+    ///
+    /// The current process becomes the owner of the buffer the first time it is accessed. If the
+    /// handle is invalid or if the buffer has already been claimed by someone else, accessing it
+    /// returns an error.
+    pub fn from_handle(handle: BufferHandle) -> Self {
+        Buffer { handle }
+    }
+
+    /// Returns the handle of the buffer.
+    pub fn handle(&self) -> BufferHandle {
+        self.handle
+    }
+
+    /// Returns the length of the buffer in bytes.
+    pub fn len(&self) -> Result<u32, BufferErr> {
+        #[cfg(target_arch = "wasm32")] // TODO: we should have a proper operating system name instead
+        fn imp(handle: BufferHandle) -> Result<u32, BufferErr> {
+            let mut len = 0;
+            match unsafe { crate::ffi::buffer_len(&u64::from(handle), &mut len) } {
+                0 => Ok(len),
+                _ => Err(BufferErr),
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        fn imp(_: BufferHandle) -> Result<u32, BufferErr> {
+            unreachable!()
+        }
+        imp(self.handle)
+    }
+
+    /// Copies the content of the buffer starting at `offset` into `out`.
+    ///
+    /// Returns an error if the range is out of the bounds of the buffer.
+    pub fn read(&self, offset: u32, out: &mut [u8]) -> Result<(), BufferErr> {
+        #[cfg(target_arch = "wasm32")] // TODO: we should have a proper operating system name instead
+        fn imp(handle: BufferHandle, offset: u32, out: &mut [u8]) -> Result<(), BufferErr> {
+            let out_len = u32::try_from(out.len()).map_err(|_| BufferErr)?;
+            let handle = u64::from(handle);
+            match unsafe { crate::ffi::buffer_read(&handle, offset, out.as_mut_ptr(), out_len) } {
+                0 => Ok(()),
+                _ => Err(BufferErr),
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        fn imp(_: BufferHandle, _: u32, _: &mut [u8]) -> Result<(), BufferErr> {
+            unreachable!()
+        }
+        imp(self.handle, offset, out)
+    }
+
+    /// Copies `data` into the buffer, starting at `offset`.
+    ///
+    /// Returns an error if the range is out of the bounds of the buffer. Buffers can't be
+    /// resized.
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), BufferErr> {
+        #[cfg(target_arch = "wasm32")] // TODO: we should have a proper operating system name instead
+        fn imp(handle: BufferHandle, offset: u32, data: &[u8]) -> Result<(), BufferErr> {
+            let data_len = u32::try_from(data.len()).map_err(|_| BufferErr)?;
+            let handle = u64::from(handle);
+            match unsafe { crate::ffi::buffer_write(&handle, offset, data.as_ptr(), data_len) } {
+                0 => Ok(()),
+                _ => Err(BufferErr),
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        fn imp(_: BufferHandle, _: u32, _: &[u8]) -> Result<(), BufferErr> {
+            unreachable!()
+        }
+        imp(self.handle, offset, data)
+    }
+
+    /// Copies the whole content of the buffer into a `Vec`.
+    pub fn to_vec(&self) -> Result<Vec<u8>, BufferErr> {
+        let mut out = alloc::vec![0; usize::try_from(self.len()?).map_err(|_| BufferErr)?];
+        self.read(0, &mut out)?;
+        Ok(out)
+    }
+
+    /// Relinquishes the ownership of the buffer and returns its handle.
+    ///
+    /// The next program that accesses the buffer becomes its owner. The handle should be sent
+    /// out right after this function has been called. If nobody claims the buffer, it is
+    /// destroyed when the current process terminates.
+    pub fn transfer(self) -> Result<BufferHandle, BufferErr> {
+        #[cfg(target_arch = "wasm32")] // TODO: we should have a proper operating system name instead
+        fn imp(handle: BufferHandle) -> Result<(), BufferErr> {
+            match unsafe { crate::ffi::buffer_transfer(&u64::from(handle)) } {
+                0 => Ok(()),
+                _ => Err(BufferErr),
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        fn imp(_: BufferHandle) -> Result<(), BufferErr> {
+            unreachable!()
+        }
+
+        let handle = self.handle;
+        // In case of error, the buffer isn't ours and destroying it is a no-op.
+        core::mem::forget(self);
+        imp(handle)?;
+        Ok(handle)
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Buffer").field(&self.handle).finish()
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        #[cfg(target_arch = "wasm32")] // TODO: we should have a proper operating system name instead
+        fn imp(handle: BufferHandle) {
+            // An error means that the buffer isn't ours, in which case there is nothing to do.
+            let _ = unsafe { crate::ffi::buffer_destroy(&u64::from(handle)) };
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        fn imp(_: BufferHandle) {
+            unreachable!()
+        }
+        imp(self.handle)
+    }
+}
+
+/// Data embedded in a message, either directly or through a buffer.
+///
+/// Interfaces that carry bulk data should use this type rather than a `Vec<u8>`.
+#[derive(Debug, Clone, PartialEq, Eq, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub enum Payload {
+    /// Data is embedded directly in the message.
+    Inline(Vec<u8>),
+    /// Data is in a buffer that has been transferred by the emitter of the message.
+    Buffer(BufferHandle),
+}
+
+impl Payload {
+    /// Builds a payload containing a copy of `data`.
+    ///
+    /// If the length of `data` is superior to [`PAYLOAD_INLINE_MAX`], the data is put in a buffer
+    /// that is immediately transferred. The payload should then be sent out.
+    ///
+    /// Returns an error if `data` is too large to fit in a buffer.
+    pub fn new(data: &[u8]) -> Result<Self, BufferErr> {
+        if data.len() <= PAYLOAD_INLINE_MAX {
+            Ok(Payload::Inline(data.to_vec()))
+        } else {
+            Ok(Payload::Buffer(Buffer::from_slice(data)?.transfer()?))
+        }
+    }
+
+    /// Extracts the data of the payload.
+    ///
+    /// If the data is in a buffer, the buffer is claimed then destroyed.
+    pub fn into_vec(self) -> Result<Vec<u8>, BufferErr> {
+        match self {
+            Payload::Inline(data) => Ok(data),
+            Payload::Buffer(handle) => Buffer::from_handle(handle).to_vec(),
+        }
+    }
+}
+
+/// Error when creating or accessing a [`Buffer`].
+///
+/// Either the handle is invalid, the buffer belongs to someone else, the range is out of the
+/// bounds of the buffer, or the requested length is too large.
+#[derive(Debug)]
+pub struct BufferErr;
+
+impl fmt::Display for BufferErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid buffer access")
+    }
+}
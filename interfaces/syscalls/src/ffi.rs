@@ -133,6 +133,82 @@ extern "C" {
     /// `message_id`. In particular, it is invalid to modify this buffer while the function is
     /// running.
     pub(crate) fn cancel_message(message_id: *const u64);
+
+    /// Creates a new buffer of `len` bytes, filled with zeroes, and owned by the current process.
+    ///
+    /// Returns `0` on success, and `1` if the buffers owned by the current process would be too
+    /// large in total. On success, the handle of the new buffer is written into the memory
+    /// pointed by `handle_out`.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `handle_out`. In particular, it is invalid to modify this buffer while the function is
+    /// running.
+    pub(crate) fn buffer_create(len: u32, handle_out: *mut u64) -> u32;
+
+    /// Writes into the memory pointed by `len_out` the length of the buffer whose handle is
+    /// pointed by `handle`.
+    ///
+    /// Returns `0` on success, and `1` if the handle is invalid.
+    ///
+    /// If the buffer has been transferred by another program, calling this function makes the
+    /// current process its owner. This is also the case for `buffer_read` and `buffer_write`.
+    /// Claiming a buffer fails, and the handle is considered invalid, if the buffers owned by the
+    /// current process would then be too large in total.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `handle` and `len_out`. In particular, it is invalid to modify these buffers while the
+    /// function is running.
+    pub(crate) fn buffer_len(handle: *const u64, len_out: *mut u32) -> u32;
+
+    /// Copies `out_len` bytes of the buffer whose handle is pointed by `handle`, starting at
+    /// `offset`, into `out`.
+    ///
+    /// Returns `0` on success, and `1` if the handle is invalid or if the range is out of the
+    /// bounds of the buffer.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `handle` and `out`. In particular, it is invalid to modify these buffers while the
+    /// function is running.
+    pub(crate) fn buffer_read(handle: *const u64, offset: u32, out: *mut u8, out_len: u32) -> u32;
+
+    /// Copies `data_len` bytes from `data` into the buffer whose handle is pointed by `handle`,
+    /// starting at `offset`.
+    ///
+    /// Returns `0` on success, and `1` if the handle is invalid or if the range is out of the
+    /// bounds of the buffer. Buffers can't be resized.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `handle` and `data`. In particular, it is invalid to modify these buffers while the
+    /// function is running.
+    pub(crate) fn buffer_write(
+        handle: *const u64,
+        offset: u32,
+        data: *const u8,
+        data_len: u32,
+    ) -> u32;
+
+    /// Relinquishes the ownership of the buffer whose handle is pointed by `handle`.
+    ///
+    /// The next program that accesses the buffer through its handle becomes its owner. The
+    /// handle is typically embedded in a message right after this function has been called.
+    /// If nobody claims the buffer, it is destroyed when the current process terminates.
+    ///
+    /// Returns `0` on success, and `1` if the handle is invalid.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `handle`. In particular, it is invalid to modify this buffer while the function is
+    /// running.
+    pub(crate) fn buffer_transfer(handle: *const u64) -> u32;
+
+    /// Destroys the buffer whose handle is pointed by `handle`.
+    ///
+    /// Returns `0` on success, and `1` if the handle is invalid. Buffers that have been
+    /// transferred by another program must be claimed before they can be destroyed.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `handle`. In particular, it is invalid to modify this buffer while the function is
+    /// running.
+    pub(crate) fn buffer_destroy(handle: *const u64) -> u32;
 }
 
 /// Prototype for a message.
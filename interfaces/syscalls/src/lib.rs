@@ -49,6 +49,13 @@
 //! can only be done as a response to a message. This must be taken into account when designing
 //! interfaces.
 //!
+//! # Buffers
+//!
+//! Messages and answers are copied by the kernel every time they are delivered. For bulk data,
+//! such as the pixels of a framebuffer, programs can instead put the data in a [`Buffer`], which
+//! is owned by the kernel, and embed its [`BufferHandle`] in the message. The [`Payload`] type
+//! automatically picks between embedding data directly and going through a buffer.
+//!
 //! # About threads
 //!
 //! Multithreading in WASM isn't specified yet, and Rust doesn't allow multithreaded WASM code.
@@ -84,6 +91,7 @@
 extern crate alloc;
 
 pub use block_on::block_on;
+pub use buffer::{Buffer, BufferErr, Payload, PAYLOAD_INLINE_MAX};
 pub use emit::{
     cancel_message, emit_message_with_response, emit_message_without_response, MessageBuilder,
 };
@@ -100,6 +108,7 @@ pub use traits::{Decode, Encode, EncodedMessage};
 use core::{cmp::PartialEq, convert::TryFrom, fmt, num::NonZeroU64};
 
 mod block_on;
+mod buffer;
 mod emit;
 mod interface_message;
 mod response;
@@ -229,6 +238,30 @@ impl fmt::Display for InvalidMessageIdErr {
     }
 }
 
+/// Identifier of a buffer of data owned by the kernel. See [`Buffer`].
+#[derive(
+    Copy, Clone, PartialEq, Eq, Hash, parity_scale_codec::Encode, parity_scale_codec::Decode,
+)]
+pub struct BufferHandle(u64);
+
+impl From<u64> for BufferHandle {
+    fn from(id: u64) -> BufferHandle {
+        BufferHandle(id)
+    }
+}
+
+impl From<BufferHandle> for u64 {
+    fn from(handle: BufferHandle) -> u64 {
+        handle.0
+    }
+}
+
+impl fmt::Debug for BufferHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:020}", self.0)
+    }
+}
+
 /// Hash of a module.
 #[derive(Clone, parity_scale_codec::Encode, parity_scale_codec::Decode, PartialEq, Eq, Hash)]
 pub struct InterfaceHash([u8; 32]);
@@ -14,7 +14,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use parity_scale_codec::{Decode, Encode};
-use redshirt_syscalls::InterfaceHash;
+use redshirt_syscalls::{InterfaceHash, Payload};
 
 // TODO: this has been randomly generated; instead should be a hash or something
 pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
@@ -75,13 +75,15 @@ pub struct TcpRead {
 
 #[derive(Debug, Encode, Decode)]
 pub struct TcpReadResponse {
-    pub result: Result<Vec<u8>, ()>,
+    /// Data that has been read. If it is in a buffer, the buffer belongs to the reader.
+    pub result: Result<Payload, ()>,
 }
 
 #[derive(Debug, Encode, Decode)]
 pub struct TcpWrite {
     pub socket_id: u32,
-    pub data: Vec<u8>,
+    /// Data to write. If it is in a buffer, the handler claims it even if the write fails.
+    pub data: Payload,
 }
 
 #[derive(Debug, Encode, Decode)]
@@ -19,7 +19,7 @@
 //! `async-std` libraries do.
 
 use futures::{lock::Mutex, prelude::*, ready};
-use redshirt_syscalls::{Encode as _, MessageResponseFuture};
+use redshirt_syscalls::{Encode as _, MessageResponseFuture, Payload};
 use std::{
     cmp, io, mem,
     net::{IpAddr, Ipv6Addr, SocketAddr},
@@ -117,7 +117,8 @@ impl AsyncRead for TcpStream {
     ) -> Poll<Result<usize, io::Error>> {
         loop {
             if let Some(pending_read) = self.pending_read.as_mut() {
-                self.read_buffer = match ready!(Future::poll(Pin::new(pending_read), cx)).result {
+                let result = ready!(Future::poll(Pin::new(pending_read), cx)).result;
+                self.read_buffer = match result.and_then(|d| d.into_vec().map_err(|_| ())) {
                     Ok(d) => d,
                     Err(_) => return Poll::Ready(Err(io::ErrorKind::Other.into())), // TODO:
                 };
@@ -172,16 +173,23 @@ impl AsyncWrite for TcpStream {
 
         debug_assert!(self.pending_write.is_none());
 
+        // Large writes go through a buffer, which avoids copying the data again when the message
+        // is delivered.
+        let data = match Payload::new(buf) {
+            Ok(data) => data,
+            Err(_) => return Poll::Ready(Err(io::ErrorKind::InvalidInput.into())),
+        };
+
         // Perform the write, and store into `self.pending_write` a future to when we can start
         // the next write.
         self.pending_write = {
             let tcp_write = ffi::TcpMessage::Write(ffi::TcpWrite {
                 socket_id: self.handle,
-                data: buf.to_vec(), // TODO: meh for cloning
+                data,
             });
 
             let msg_id = unsafe {
-                let msg = tcp_write.encode(); // TODO: meh because we clone data a second time here
+                let msg = tcp_write.encode();
                 redshirt_syscalls::MessageBuilder::new()
                     .add_data(&msg)
                     .emit_with_response_raw(&ffi::INTERFACE)
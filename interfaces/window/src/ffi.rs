@@ -0,0 +1,154 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Windows are framebuffers that share a screen with the windows of other processes. The
+//! interface handler, typically a compositor, decides where each window is shown, which window
+//! is on top of the others, and which window receives the keyboard input.
+//!
+//! Messages are SCALE-encoded [`WindowMessage`]s. Messages that the handler fails to decode are
+//! answered with an error, if they expect an answer.
+//!
+//! Each process allocates the identifiers of its windows. Before any other message can be sent
+//! about a window, it must be created with [`WindowMessage::Create`], which contains the version
+//! of this protocol that the process implements.
+//!
+//! All the messages except [`WindowMessage::NextEvent`] can optionally expect an answer, in
+//! which case the handler answers with a `Result<(), WindowError>`. Errors are silently ignored
+//! if no answer is expected.
+//!
+//! Pixel formats, rectangles and input events are the same as for the framebuffer interface.
+//! Positions are relative to the top-left corner of the content of the window. The handler
+//! reports with [`InputEvent::Focus`] when a window starts or stops receiving keyboard input.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls::InterfaceHash;
+
+pub use redshirt_framebuffer_interface::ffi::{
+    data_len, ElementState, InputEvent, Key, PixelFormat, PointerButton, Rect, WheelDelta,
+};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x15, 0x4c, 0xe7, 0x80, 0xae, 0x45, 0x52, 0x85, 0x88, 0x08, 0x3e, 0xc6, 0xda, 0x5a, 0xbc, 0x5f,
+    0x6b, 0xdf, 0x12, 0x52, 0x14, 0xec, 0xbe, 0xd1, 0x46, 0x66, 0x95, 0x05, 0x11, 0xf0, 0xb9, 0x8d,
+]);
+
+/// Version of the protocol described in this module.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Message sent on the window interface.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum WindowMessage {
+    /// Creates a new window, initially filled with black, on top of the other windows.
+    Create {
+        /// Version of the protocol. Should be [`PROTOCOL_VERSION`].
+        version: u32,
+        /// Identifier of the window, as chosen by the emitter.
+        id: u32,
+        /// Title of the window. Might be shown to the user.
+        title: String,
+        /// Width of the content of the window in pixels.
+        width: u32,
+        /// Height of the content of the window in pixels.
+        height: u32,
+        /// Format of the pixels sent for this window.
+        format: PixelFormat,
+    },
+
+    /// Destroys a window.
+    Destroy {
+        /// Identifier of the window.
+        id: u32,
+    },
+
+    /// Changes the title of a window.
+    SetTitle {
+        /// Identifier of the window.
+        id: u32,
+        /// New title.
+        title: String,
+    },
+
+    /// Replaces the whole content of a window.
+    SetData {
+        /// Identifier of the window.
+        id: u32,
+        /// Pixels of the window. Must be `width * height * format.bytes_per_pixel()` bytes.
+        data: Vec<u8>,
+    },
+
+    /// Replaces the content of a rectangle within a window.
+    Update {
+        /// Identifier of the window.
+        id: u32,
+        /// Rectangle to update. Must be within the window.
+        rect: Rect,
+        /// Pixels of the rectangle. Must be
+        /// `rect.width * rect.height * format.bytes_per_pixel()` bytes.
+        data: Vec<u8>,
+    },
+
+    /// Changes the dimensions of a window. Its content is reset to black.
+    Resize {
+        /// Identifier of the window.
+        id: u32,
+        /// New width in pixels.
+        width: u32,
+        /// New height in pixels.
+        height: u32,
+    },
+
+    /// Asks for the next input event that happens on the window. Must be answered with an
+    /// [`InputEvent`].
+    ///
+    /// Input events that happen while no such message is pending are buffered by the handler,
+    /// up to an implementation-defined limit.
+    NextEvent {
+        /// Identifier of the window.
+        id: u32,
+    },
+}
+
+/// Error answered to a message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum WindowError {
+    /// The version passed when creating the window isn't supported by the handler.
+    UnsupportedVersion,
+    /// A window with this identifier already exists.
+    AlreadyExists,
+    /// No window with this identifier exists.
+    UnknownWindow,
+    /// The width or height is zero or too large.
+    InvalidDimensions,
+    /// The rectangle isn't within the window.
+    OutOfBounds,
+    /// The length of the data doesn't match the dimensions and the pixel format.
+    InvalidDataLength,
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowError::UnsupportedVersion => write!(f, "Unsupported protocol version"),
+            WindowError::AlreadyExists => write!(f, "Window already exists"),
+            WindowError::UnknownWindow => write!(f, "Unknown window"),
+            WindowError::InvalidDimensions => write!(f, "Invalid dimensions"),
+            WindowError::OutOfBounds => write!(f, "Rectangle out of bounds"),
+            WindowError::InvalidDataLength => write!(f, "Invalid data length"),
+        }
+    }
+}
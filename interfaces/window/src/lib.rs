@@ -0,0 +1,187 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Window interface.
+//!
+//! Allows showing an image on a screen shared with other processes, and receiving the keyboard
+//! and pointer input that happens on it.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::future::Future;
+
+pub mod ffi;
+
+/// Window shown by the interface handler. Destroyed when dropped.
+pub struct Window {
+    id: u32,
+    width: u32,
+    height: u32,
+    format: ffi::PixelFormat,
+}
+
+impl Window {
+    /// Opens a new window with the given title, width and height, whose pixels are RGB triplets.
+    pub async fn new(
+        title: impl Into<String>,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, ffi::WindowError> {
+        Self::with_format(title, width, height, ffi::PixelFormat::Rgb24).await
+    }
+
+    /// Opens a new window with the given title, width, height, and pixel format.
+    pub async fn with_format(
+        title: impl Into<String>,
+        width: u32,
+        height: u32,
+        format: ffi::PixelFormat,
+    ) -> Result<Self, ffi::WindowError> {
+        let id = unsafe {
+            let mut out = [0; 4];
+            redshirt_random_interface::generate_in(&mut out).await;
+            u32::from_le_bytes(out)
+        };
+
+        let message = ffi::WindowMessage::Create {
+            version: ffi::PROTOCOL_VERSION,
+            id,
+            title: title.into(),
+            width,
+            height,
+            format,
+        };
+        send_with_answer(message).await?;
+
+        Ok(Window {
+            id,
+            width,
+            height,
+            format,
+        })
+    }
+
+    /// Returns the width of the window in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the window in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the format of the pixels of the window.
+    pub fn format(&self) -> ffi::PixelFormat {
+        self.format
+    }
+
+    /// Changes the title of the window.
+    pub fn set_title(&self, title: impl Into<String>) {
+        send(ffi::WindowMessage::SetTitle {
+            id: self.id,
+            title: title.into(),
+        });
+    }
+
+    /// Sets the content of the window.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the size of `data` isn't `width * height * bytes_per_pixel`.
+    ///
+    pub fn set_data(&self, data: &[u8]) {
+        assert_eq!(
+            Some(data.len()),
+            ffi::data_len(self.width, self.height, self.format)
+        );
+
+        send(ffi::WindowMessage::SetData {
+            id: self.id,
+            data: data.to_vec(),
+        });
+    }
+
+    /// Sets the content of a rectangle within the window. Much cheaper than
+    /// [`set_data`](Window::set_data) if only a small part of the window has changed.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the rectangle isn't within the window, or if the size of `data` isn't
+    /// `rect.width * rect.height * bytes_per_pixel`.
+    ///
+    pub fn update(&self, rect: ffi::Rect, data: &[u8]) {
+        assert!(rect.x.checked_add(rect.width).map_or(false, |r| r <= self.width));
+        assert!(rect.y.checked_add(rect.height).map_or(false, |b| b <= self.height));
+        assert_eq!(
+            Some(data.len()),
+            ffi::data_len(rect.width, rect.height, self.format)
+        );
+
+        send(ffi::WindowMessage::Update {
+            id: self.id,
+            rect,
+            data: data.to_vec(),
+        });
+    }
+
+    /// Changes the dimensions of the window. Its content is reset to black.
+    pub async fn resize(&mut self, width: u32, height: u32) -> Result<(), ffi::WindowError> {
+        let message = ffi::WindowMessage::Resize {
+            id: self.id,
+            width,
+            height,
+        };
+        send_with_answer(message).await?;
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Returns the next input event that happens on this window.
+    ///
+    /// Events that happen while no call to this method is pending are buffered by the handler.
+    pub fn next_event(&self) -> impl Future<Output = ffi::InputEvent> {
+        unsafe {
+            let message = ffi::WindowMessage::NextEvent { id: self.id };
+            redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message).unwrap()
+        }
+    }
+}
+
+impl Drop for Window {
+    fn drop(&mut self) {
+        send(ffi::WindowMessage::Destroy { id: self.id });
+    }
+}
+
+/// Sends a message that doesn't expect any answer.
+fn send(message: ffi::WindowMessage) {
+    unsafe {
+        redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, message).unwrap();
+    }
+}
+
+/// Sends a message and waits for the handler to report whether it has succeeded.
+async fn send_with_answer(message: ffi::WindowMessage) -> Result<(), ffi::WindowError> {
+    unsafe {
+        redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message)
+            .unwrap()
+            .await
+    }
+}
@@ -0,0 +1,468 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Writing IPC trace events in the Chrome tracing format.
+//!
+//! The output file is a JSON array of events, as described in the "Trace Event Format"
+//! specification, and can be opened with `chrome://tracing` or with Perfetto. Messages that
+//! expect an answer are shown as asynchronous slices going from their emission to their answer.
+//!
+//! Events are sent to a background thread that writes them as soon as possible, so that tracing
+//! doesn't slow down the kernel with file I/O. In order to not lose anything if the kernel is
+//! stopped abruptly, the closing `]` is never written, which the format explicitly allows.
+
+use futures::channel::mpsc;
+use redshirt_core::{
+    scheduler::{TraceEvent, TraceEventKind},
+    InterfaceHash, MessageId, Pid, ProcessesInfo,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, LineWriter, Write},
+    path::Path,
+    sync::Arc,
+    thread,
+};
+
+/// Writes [`TraceEvent`]s to a file.
+pub struct ChromeTraceWriter {
+    /// Sends events to the background thread, alongside with the name of the process that the
+    /// event concerns, if any.
+    events_tx: mpsc::UnboundedSender<(TraceEvent, Option<String>)>,
+    /// Used to find the names of processes.
+    processes_info: Arc<ProcessesInfo>,
+}
+
+/// Turns [`TraceEvent`]s into JSON values.
+#[derive(Default)]
+struct Formatter {
+    /// Interface of each message waiting for an answer. Used to give a name to the end of the
+    /// asynchronous slice.
+    active_messages: HashMap<MessageId, String>,
+    /// Processes whose name has already been written.
+    named_processes: HashSet<Pid>,
+}
+
+impl ChromeTraceWriter {
+    /// Creates the file at the given path, overwriting it if it already exists, and spawns the
+    /// background thread that writes to it.
+    pub fn create(path: &Path, processes_info: Arc<ProcessesInfo>) -> io::Result<Self> {
+        let file = LineWriter::new(File::create(path)?);
+        let (events_tx, events_rx) = mpsc::unbounded();
+
+        thread::Builder::new()
+            .name("ipc-trace".into())
+            .spawn(move || {
+                let events = futures::executor::block_on_stream(events_rx);
+                if let Err(err) = write_events(file, events) {
+                    eprintln!("Failed to write IPC trace: {}", err);
+                }
+            })?;
+
+        Ok(ChromeTraceWriter {
+            events_tx,
+            processes_info,
+        })
+    }
+
+    /// Sends an event to the background thread.
+    pub fn write(&self, event: TraceEvent) {
+        // The name is looked up now, as the process might no longer exist by the time the event
+        // is written.
+        let pid = match &event.kind {
+            TraceEventKind::Emit { emitter, .. } => Some(*emitter),
+            TraceEventKind::Deliver { handler, .. } => Some(*handler),
+            TraceEventKind::Answer { .. } | TraceEventKind::Cancel { .. } => None,
+        };
+        let process_name = pid
+            .and_then(|pid| self.processes_info.get(pid))
+            .and_then(|info| info.name);
+
+        // An error happens if the background thread has stopped, in which case it has already
+        // reported why.
+        let _ = self.events_tx.unbounded_send((event, process_name));
+    }
+}
+
+/// Writes the opening `[` to `out`, then each event followed with a `,`.
+fn write_events(
+    mut out: impl Write,
+    events: impl Iterator<Item = (TraceEvent, Option<String>)>,
+) -> io::Result<()> {
+    out.write_all(b"[\n")?;
+
+    let mut formatter = Formatter::default();
+    for (event, process_name) in events {
+        for value in formatter.format(event, process_name) {
+            writeln!(out, "{},", value)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Formatter {
+    /// Returns the JSON values corresponding to the given event.
+    ///
+    /// `process_name` is the name of the emitter for [`TraceEventKind::Emit`] events, and of
+    /// the handler for [`TraceEventKind::Deliver`] events.
+    fn format(
+        &mut self,
+        event: TraceEvent,
+        process_name: Option<String>,
+    ) -> Vec<serde_json::Value> {
+        let ts = event.timestamp.unwrap_or(0) as f64 / 1000.0;
+
+        let mut values = Vec::with_capacity(2);
+        match event.kind {
+            TraceEventKind::Emit {
+                emitter,
+                interface,
+                message_id,
+                size,
+            } => {
+                values.extend(self.process_name_metadata(emitter, process_name));
+                let name = interface_name(&interface);
+                if let Some(message_id) = message_id {
+                    self.active_messages.insert(message_id, name.clone());
+                    values.push(serde_json::json!({
+                        "name": name,
+                        "cat": "ipc",
+                        "ph": "b",
+                        "id": u64::from(message_id),
+                        "ts": ts,
+                        "pid": u64::from(emitter),
+                        "tid": u64::from(emitter),
+                        "args": { "size": size },
+                    }));
+                } else {
+                    values.push(serde_json::json!({
+                        "name": name,
+                        "cat": "ipc",
+                        "ph": "i",
+                        "s": "t",
+                        "ts": ts,
+                        "pid": u64::from(emitter),
+                        "tid": u64::from(emitter),
+                        "args": { "size": size },
+                    }));
+                }
+            }
+            TraceEventKind::Deliver {
+                handler,
+                emitter,
+                interface,
+                message_id,
+                size,
+            } => {
+                values.extend(self.process_name_metadata(handler, process_name));
+                let name = interface_name(&interface);
+                if let Some(message_id) = message_id {
+                    values.push(serde_json::json!({
+                        "name": name,
+                        "cat": "ipc",
+                        "ph": "n",
+                        "id": u64::from(message_id),
+                        "ts": ts,
+                        "pid": u64::from(emitter),
+                        "tid": u64::from(emitter),
+                        "args": { "handler": u64::from(handler), "size": size },
+                    }));
+                } else {
+                    values.push(serde_json::json!({
+                        "name": name,
+                        "cat": "ipc",
+                        "ph": "i",
+                        "s": "t",
+                        "ts": ts,
+                        "pid": u64::from(handler),
+                        "tid": u64::from(handler),
+                        "args": { "emitter": u64::from(emitter), "size": size },
+                    }));
+                }
+            }
+            TraceEventKind::Answer {
+                message_id,
+                emitter,
+                size,
+            } => {
+                let name = self
+                    .active_messages
+                    .remove(&message_id)
+                    .unwrap_or_default();
+                values.push(serde_json::json!({
+                    "name": name,
+                    "cat": "ipc",
+                    "ph": "e",
+                    "id": u64::from(message_id),
+                    "ts": ts,
+                    "pid": u64::from(emitter),
+                    "tid": u64::from(emitter),
+                    "args": { "size": size, "error": size.is_none() },
+                }));
+            }
+            TraceEventKind::Cancel {
+                message_id,
+                emitter,
+            } => {
+                let name = self
+                    .active_messages
+                    .remove(&message_id)
+                    .unwrap_or_default();
+                values.push(serde_json::json!({
+                    "name": name,
+                    "cat": "ipc",
+                    "ph": "e",
+                    "id": u64::from(message_id),
+                    "ts": ts,
+                    "pid": u64::from(emitter),
+                    "tid": u64::from(emitter),
+                    "args": { "cancelled": true },
+                }));
+            }
+        }
+
+        values
+    }
+
+    /// Returns a metadata event giving a name to the given process, if it has never been
+    /// returned before and the process has a name.
+    fn process_name_metadata(
+        &mut self,
+        pid: Pid,
+        name: Option<String>,
+    ) -> Option<serde_json::Value> {
+        if self.named_processes.contains(&pid) {
+            return None;
+        }
+
+        let name = name?;
+        self.named_processes.insert(pid);
+        Some(serde_json::json!({
+            "name": "process_name",
+            "ph": "M",
+            "pid": u64::from(pid),
+            "args": { "name": name },
+        }))
+    }
+}
+
+/// Returns the name to show for an interface.
+fn interface_name(interface: &InterfaceHash) -> String {
+    bs58::encode(<[u8; 32]>::from(interface.clone())).into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{interface_name, write_events};
+    use redshirt_core::{
+        scheduler::{TraceEvent, TraceEventKind},
+        InterfaceHash, MessageId, Pid,
+    };
+    use std::convert::TryFrom as _;
+
+    /// Writes the given events and parses the output back, after adding the missing `]`.
+    fn write_and_parse(events: Vec<(TraceEvent, Option<String>)>) -> Vec<serde_json::Value> {
+        let mut out = Vec::new();
+        write_events(&mut out, events.into_iter()).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let array = format!("{}]", out.trim_end().trim_end_matches(','));
+        match serde_json::from_str(&array).unwrap() {
+            serde_json::Value::Array(values) => values,
+            _ => panic!(),
+        }
+    }
+
+    fn event(timestamp: u64, kind: TraceEventKind) -> TraceEvent {
+        TraceEvent {
+            timestamp: Some(timestamp),
+            kind,
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let mut out = Vec::new();
+        write_events(&mut out, Vec::new().into_iter()).unwrap();
+        assert_eq!(out, b"[\n");
+    }
+
+    #[test]
+    fn answered_message() {
+        let interface = InterfaceHash::from_raw_hash([3; 32]);
+        let emitter = Pid::from(10u64);
+        let handler = Pid::from(20u64);
+        let message_id = MessageId::try_from(1234u64).unwrap();
+
+        let values = write_and_parse(vec![
+            (
+                event(
+                    5000,
+                    TraceEventKind::Emit {
+                        emitter,
+                        interface: interface.clone(),
+                        message_id: Some(message_id),
+                        size: 8,
+                    },
+                ),
+                Some(String::from("emitter")),
+            ),
+            (
+                event(
+                    6000,
+                    TraceEventKind::Deliver {
+                        handler,
+                        emitter,
+                        interface: interface.clone(),
+                        message_id: Some(message_id),
+                        size: 8,
+                    },
+                ),
+                None,
+            ),
+            (
+                event(
+                    7000,
+                    TraceEventKind::Answer {
+                        message_id,
+                        emitter,
+                        size: Some(2),
+                    },
+                ),
+                None,
+            ),
+        ]);
+
+        assert_eq!(values.len(), 4);
+
+        assert_eq!(values[0]["ph"], "M");
+        assert_eq!(values[0]["pid"], 10);
+        assert_eq!(values[0]["args"]["name"], "emitter");
+
+        assert_eq!(values[1]["ph"], "b");
+        assert_eq!(values[1]["name"], interface_name(&interface).as_str());
+        assert_eq!(values[1]["id"], 1234);
+        assert_eq!(values[1]["ts"], 5.0);
+        assert_eq!(values[1]["args"]["size"], 8);
+
+        assert_eq!(values[2]["ph"], "n");
+        assert_eq!(values[2]["id"], 1234);
+        assert_eq!(values[2]["args"]["handler"], 20);
+
+        assert_eq!(values[3]["ph"], "e");
+        assert_eq!(values[3]["name"], interface_name(&interface).as_str());
+        assert_eq!(values[3]["id"], 1234);
+        assert_eq!(values[3]["ts"], 7.0);
+        assert_eq!(values[3]["args"]["size"], 2);
+        assert_eq!(values[3]["args"]["error"], false);
+    }
+
+    #[test]
+    fn cancelled_message() {
+        let interface = InterfaceHash::from_raw_hash([3; 32]);
+        let emitter = Pid::from(10u64);
+        let message_id = MessageId::try_from(1234u64).unwrap();
+
+        let values = write_and_parse(vec![
+            (
+                event(
+                    5000,
+                    TraceEventKind::Emit {
+                        emitter,
+                        interface: interface.clone(),
+                        message_id: Some(message_id),
+                        size: 8,
+                    },
+                ),
+                None,
+            ),
+            (
+                event(
+                    6000,
+                    TraceEventKind::Cancel {
+                        message_id,
+                        emitter,
+                    },
+                ),
+                None,
+            ),
+        ]);
+
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[1]["ph"], "e");
+        assert_eq!(values[1]["name"], interface_name(&interface).as_str());
+        assert_eq!(values[1]["args"]["cancelled"], true);
+    }
+
+    #[test]
+    fn message_without_answer() {
+        let emitter = Pid::from(10u64);
+        let handler = Pid::from(20u64);
+
+        let values = write_and_parse(vec![
+            (
+                event(
+                    5000,
+                    TraceEventKind::Emit {
+                        emitter,
+                        interface: InterfaceHash::from_raw_hash([3; 32]),
+                        message_id: None,
+                        size: 8,
+                    },
+                ),
+                Some(String::from("emitter")),
+            ),
+            (
+                event(
+                    6000,
+                    TraceEventKind::Deliver {
+                        handler,
+                        emitter,
+                        interface: InterfaceHash::from_raw_hash([3; 32]),
+                        message_id: None,
+                        size: 8,
+                    },
+                ),
+                Some(String::from("handler")),
+            ),
+            (
+                event(
+                    7000,
+                    TraceEventKind::Emit {
+                        emitter,
+                        interface: InterfaceHash::from_raw_hash([3; 32]),
+                        message_id: None,
+                        size: 8,
+                    },
+                ),
+                Some(String::from("emitter")),
+            ),
+        ]);
+
+        // The name of each process is only written once.
+        assert_eq!(values.len(), 5);
+        assert_eq!(values[0]["ph"], "M");
+        assert_eq!(values[1]["ph"], "i");
+        assert_eq!(values[1]["pid"], 10);
+        assert_eq!(values[2]["ph"], "M");
+        assert_eq!(values[2]["args"]["name"], "handler");
+        assert_eq!(values[3]["ph"], "i");
+        assert_eq!(values[3]["pid"], 20);
+        assert_eq!(values[3]["args"]["emitter"], 10);
+        assert_eq!(values[4]["ph"], "i");
+    }
+}
@@ -14,10 +14,23 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use futures::{channel::mpsc, prelude::*};
-use redshirt_core::{build_wasm_module, module::ModuleHash};
-use std::{fs, path::PathBuf, process, sync::Arc};
+use redshirt_core::{
+    build_wasm_module, module::ModuleHash, process_info::ProcessInfo, Authorizations, Buffers,
+    InterfaceHash, ProcessesInfo,
+};
+use std::{
+    convert::TryFrom as _,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::Arc,
+    time::Instant,
+};
 use structopt::StructOpt;
 
+mod ipc_trace;
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "redshirt-cli", about = "Redshirt modules executor.")]
 struct CliOptions {
@@ -44,6 +57,208 @@ struct CliOptions {
     /// Contrary to `module_hash`, the kernel will not stop if this module stops.
     #[structopt(long, parse(try_from_str = ModuleHash::from_base58))]
     background_module_hash: Vec<ModuleHash>,
+
+    /// Directory containing modules to load, named after the base58 encoding of their hash
+    /// followed with `.wasm`.
+    ///
+    /// Modules that aren't in this directory are fetched from the public network, then written
+    /// to this directory, unless `--offline` is passed.
+    #[structopt(long, parse(from_os_str))]
+    module_store: Option<PathBuf>,
+
+    /// Never fetch modules from the public network. Requires `--module-store`.
+    #[structopt(long)]
+    offline: bool,
+
+    /// Base58 encoding of the Ed25519 public key of a publisher whose modules are allowed to run.
+    ///
+    /// If passed, modules loaded through `--module-hash`, `--background-module-hash`, or on
+    /// demand, must be signed by one of these publishers. Modules passed by path are always
+    /// allowed. Only the local module store provides signatures; modules downloaded from the
+    /// network are always rejected.
+    #[structopt(long, parse(try_from_str = parse_public_key))]
+    trusted_publisher: Vec<[u8; 32]>,
+
+    /// Which log messages to print, in the format `level,target=level,...`.
+    ///
+    /// Levels are `off`, `error`, `warn`, `info`, `debug` or `trace`. Defaults to printing
+    /// everything.
+    #[structopt(long)]
+    log_filter: Option<redshirt_log_interface::ffi::LogFilter>,
+
+    /// Print log messages as JSON objects, one per line.
+    #[structopt(long)]
+    log_json: bool,
+
+    /// Log the WASI calls made by a module, in the format `<module>[=<function>,...]`.
+    ///
+    /// `<module>` is either the path to a WASM file or the base58 encoding of the blake3 hash of
+    /// a module. If a list of functions is passed, only calls to these functions are logged.
+    #[structopt(long)]
+    log_extrinsics: Vec<LogExtrinsics>,
+
+    /// Write the messages exchanged between processes to this file, in a format that can be
+    /// opened with `chrome://tracing` or with Perfetto.
+    #[structopt(long, parse(from_os_str))]
+    ipc_trace: Option<PathBuf>,
+
+    /// Keep the framebuffers in memory instead of opening a window for each of them.
+    #[structopt(long)]
+    headless: bool,
+
+    /// When the kernel stops, write the framebuffers that still exist to this directory as PNG
+    /// files named `<pid>-<id>.png`.
+    #[structopt(long, parse(from_os_str), requires = "headless")]
+    framebuffer_dump_dir: Option<PathBuf>,
+
+    /// Script of input events to send to the framebuffers, and of screenshots to take.
+    ///
+    /// Each line is a command, for example `wait 500`, `key 28 pressed Enter`, `text hello`,
+    /// `pointer-move 20 30`, `pointer-button left pressed` or `screenshot out.png`.
+    #[structopt(long, parse(from_os_str), requires = "headless")]
+    input_script: Option<PathBuf>,
+
+    /// Where to play sounds: `device` for the default output device of the host, `null` to
+    /// discard them, or the path of a directory where to write each stream of sound as a WAV
+    /// file.
+    ///
+    /// Defaults to `null` if `--headless` is passed, and to `device` otherwise.
+    #[structopt(long, parse(from_str = parse_audio_output))]
+    audio_output: Option<redshirt_audio_hosted::AudioOutput>,
+
+    /// Allows a module to use an interface, in the format `<interface>=<module>`.
+    ///
+    /// `<interface>` is the name of the interface, for example `tcp`. `<module>` is either the
+    /// path to a WASM file or the base58 encoding of the blake3 hash of a module.
+    ///
+    /// By default, modules aren't allowed to use any interface that checks authorizations.
+    #[structopt(long)]
+    grant: Vec<Grant>,
+}
+
+/// Names that can be passed to `--grant`, and the interfaces they correspond to.
+///
+/// Only the interfaces whose handler checks authorizations are listed.
+const GRANTABLE_INTERFACES: &[(&str, InterfaceHash)] = &[
+    ("kernel-debug", redshirt_kernel_debug_interface::ffi::INTERFACE),
+    ("tcp", redshirt_tcp_interface::ffi::INTERFACE),
+];
+
+/// Authorization passed through `--grant`.
+#[derive(Debug)]
+struct Grant {
+    /// Interface to allow.
+    interface: InterfaceHash,
+    /// Path or base58-encoded hash of the module.
+    module: String,
+}
+
+impl FromStr for Grant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.splitn(2, '=');
+        let interface = split.next().unwrap_or("");
+        let module = split
+            .next()
+            .ok_or_else(|| format!("Expected `<interface>=<module>`, got `{}`", s))?;
+
+        let interface = GRANTABLE_INTERFACES
+            .iter()
+            .find(|(name, _)| *name == interface)
+            .map(|(_, hash)| hash.clone())
+            .ok_or_else(|| {
+                let names = GRANTABLE_INTERFACES
+                    .iter()
+                    .map(|(name, _)| format!("`{}`", name))
+                    .collect::<Vec<_>>();
+                format!(
+                    "Unknown interface `{}`; expected one of: {}",
+                    interface,
+                    names.join(", ")
+                )
+            })?;
+
+        Ok(Grant {
+            interface,
+            module: module.to_owned(),
+        })
+    }
+}
+
+impl Grant {
+    /// Returns the hash of the module this grant applies to.
+    fn module_hash(&self) -> Result<ModuleHash, String> {
+        module_hash(&self.module)
+    }
+}
+
+/// Module whose calls must be logged, passed through `--log-extrinsics`.
+#[derive(Debug)]
+struct LogExtrinsics {
+    /// Path or base58-encoded hash of the module.
+    module: String,
+    /// If `Some`, only these functions are logged.
+    functions: Option<Vec<String>>,
+}
+
+impl FromStr for LogExtrinsics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.splitn(2, '=');
+        let module = split.next().unwrap_or("").to_owned();
+        let functions = split.next().map(|list| {
+            list.split(',')
+                .filter(|f| !f.is_empty())
+                .map(String::from)
+                .collect()
+        });
+        Ok(LogExtrinsics { module, functions })
+    }
+}
+
+/// Returns the hash of a module passed on the command line, either as a path or as a
+/// base58-encoded hash.
+fn module_hash(module: &str) -> Result<ModuleHash, String> {
+    if Path::new(module).is_file() {
+        let content =
+            fs::read(module).map_err(|err| format!("Failed to read {}: {}", module, err))?;
+        Ok(ModuleHash::from_bytes(&content))
+    } else {
+        ModuleHash::from_base58(module)
+            .map_err(|_| format!("`{}` is neither a file nor a module hash", module))
+    }
+}
+
+/// Returns a human-readable description of a process, for error messages.
+fn process_description(pid: redshirt_core::Pid, info: &ProcessInfo) -> String {
+    match &info.name {
+        Some(name) => format!("Process {} ({:?}, module {})", name, pid, info.module_hash),
+        None => format!("Process {:?} (module {})", pid, info.module_hash),
+    }
+}
+
+/// Parses the value of `--trusted-publisher`.
+fn parse_public_key(s: &str) -> Result<[u8; 32], String> {
+    let bytes = bs58::decode(s)
+        .into_vec()
+        .map_err(|err| format!("Invalid base58 `{}`: {}", s, err))?;
+    if bytes.len() != 32 {
+        return Err(format!("`{}` isn't a 32 bytes public key", s));
+    }
+    let mut out = [0; 32];
+    out.copy_from_slice(&bytes);
+    Ok(out)
+}
+
+/// Parses the value of `--audio-output`.
+fn parse_audio_output(s: &str) -> redshirt_audio_hosted::AudioOutput {
+    match s {
+        "device" => redshirt_audio_hosted::AudioOutput::Device,
+        "null" => redshirt_audio_hosted::AudioOutput::Null,
+        path => redshirt_audio_hosted::AudioOutput::WavDir(PathBuf::from(path)),
+    }
 }
 
 fn main() {
@@ -65,40 +280,167 @@ fn main() {
         cli_requested_processes.push((module_path, module, false));
     }
 
-    let framebuffer_context = redshirt_framebuffer_hosted::FramebufferContext::new();
+    let authorizations = Arc::new(Authorizations::new());
+    for grant in &cli_opts.grant {
+        match grant.module_hash() {
+            Ok(hash) => authorizations.grant_module(hash, grant.interface.clone()),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    // The network loader fetches modules from the network. If a module store is passed, the
+    // network loader serves the fallback loader interface, and is only used for the modules that
+    // aren't in the store.
+    let network_loader = match (&cli_opts.module_store, cli_opts.offline) {
+        (None, true) => {
+            eprintln!("--offline requires --module-store, otherwise no module can be loaded");
+            process::exit(1);
+        }
+        (None, false) => Some(build_wasm_module!(
+            "../../../modules/p2p-loader",
+            "modules-loader"
+        )),
+        (Some(_), false) => Some(build_wasm_module!(
+            "../../../modules/p2p-loader",
+            "fallback-modules-loader"
+        )),
+        (Some(_), true) => None,
+    };
+    if let Some(loader) = &network_loader {
+        authorizations.grant_module(loader.hash().clone(), redshirt_tcp_interface::ffi::INTERFACE);
+    }
+
+    let buffers = Arc::new(Buffers::new());
 
-    let system = redshirt_core::system::SystemBuilder::new()
+    let framebuffer_context = if cli_opts.headless {
+        let config = redshirt_framebuffer_hosted::HeadlessConfig {
+            dump_dir: cli_opts.framebuffer_dump_dir,
+            script: cli_opts.input_script,
+        };
+        match redshirt_framebuffer_hosted::FramebufferContext::headless(config, buffers.clone()) {
+            Ok(ctxt) => ctxt,
+            Err(err) => {
+                eprintln!("Failed to load --input-script: {}", err);
+                process::exit(1);
+            }
+        }
+    } else {
+        redshirt_framebuffer_hosted::FramebufferContext::new(buffers.clone())
+    };
+
+    let audio_output = cli_opts.audio_output.unwrap_or(if cli_opts.headless {
+        redshirt_audio_hosted::AudioOutput::Null
+    } else {
+        redshirt_audio_hosted::AudioOutput::Device
+    });
+    let audio_handler = match redshirt_audio_hosted::AudioHandler::new(audio_output.clone()) {
+        Ok(handler) => handler,
+        Err(err) if audio_output == redshirt_audio_hosted::AudioOutput::Device => {
+            eprintln!("Failed to open the audio device, sounds are discarded: {}", err);
+            redshirt_audio_hosted::AudioHandler::new(redshirt_audio_hosted::AudioOutput::Null)
+                .unwrap()
+        }
+        Err(err) => {
+            eprintln!("Failed to prepare --audio-output: {}", err);
+            process::exit(1);
+        }
+    };
+
+    // Failing to load one of these modules stops the kernel.
+    let mut foreground_hashes = cli_opts.module_hash.clone();
+
+    let processes_info = Arc::new(ProcessesInfo::new());
+
+    let start_instant = Instant::now();
+    let mut system_builder = redshirt_core::system::SystemBuilder::new()
+        .with_authorizations(authorizations.clone())
+        .with_processes_info(processes_info.clone())
+        .with_buffers(buffers.clone())
+        .with_clock(move || u64::try_from(start_instant.elapsed().as_nanos()).unwrap())
         .with_native_program(redshirt_time_hosted::TimerHandler::new())
-        .with_native_program(redshirt_tcp_hosted::TcpHandler::new())
-        .with_native_program(redshirt_log_hosted::LogHandler::new())
+        .with_native_program(redshirt_tcp_hosted::TcpHandler::new(authorizations, buffers))
+        .with_native_program(
+            redshirt_log_hosted::LogHandler::new()
+                .with_processes_info(processes_info.clone())
+                .with_filter(cli_opts.log_filter.unwrap_or_default())
+                .with_format(if cli_opts.log_json {
+                    redshirt_log_hosted::OutputFormat::JsonLines
+                } else {
+                    redshirt_log_hosted::OutputFormat::Human
+                }),
+        )
         .with_native_program(redshirt_framebuffer_hosted::FramebufferHandler::new(
             &framebuffer_context,
         ))
+        .with_native_program(redshirt_framebuffer_hosted::HidDriver::new(&framebuffer_context))
+        .with_startup_process(build_wasm_module!("../../../modules/hid-hub"))
+        .with_startup_process(build_wasm_module!("../../../modules/compositor"))
         .with_native_program(redshirt_random_hosted::RandomNativeProgram::new())
-        .with_startup_process(build_wasm_module!(
-            "../../../modules/p2p-loader",
-            "modules-loader"
-        ))
+        .with_native_program(redshirt_console_hosted::ConsoleHandler::new())
+        .with_native_program(audio_handler)
         .with_main_programs(cli_opts.module_hash)
-        .with_main_programs(cli_opts.background_module_hash)
-        .build()
-        .expect("Failed to start system");
+        .with_main_programs(cli_opts.background_module_hash);
 
+    if let Some(path) = cli_opts.ipc_trace {
+        let writer = match ipc_trace::ChromeTraceWriter::create(&path, processes_info.clone()) {
+            Ok(w) => w,
+            Err(err) => {
+                eprintln!("Failed to create {}: {}", path.display(), err);
+                process::exit(1);
+            }
+        };
+        system_builder = system_builder.with_ipc_tracer(move |event| writer.write(event));
+    }
+
+    if let Some(module_store) = cli_opts.module_store {
+        system_builder = system_builder.with_native_program(
+            redshirt_loader_hosted::LocalLoader::new(module_store, network_loader.is_some()),
+        );
+    }
+
+    for log_extrinsics in cli_opts.log_extrinsics {
+        let hash = match module_hash(&log_extrinsics.module) {
+            Ok(hash) => hash,
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        };
+
+        let config = redshirt_core::extrinsics::log_calls::LogConfig {
+            level: redshirt_log_interface::Level::Info,
+            functions: log_extrinsics.functions,
+        };
+        system_builder = system_builder.with_extrinsics_logging(hash, config);
+    }
+
+    if !cli_opts.trusted_publisher.is_empty() {
+        let trusted = cli_opts.trusted_publisher;
+        system_builder = system_builder.with_module_policy(move |_, signature| {
+            signature.map_or(false, |s| trusted.contains(s.public_key()))
+        });
+    }
+
+    if let Some(network_loader) = network_loader {
+        system_builder = system_builder.with_startup_process(network_loader);
+    }
+
+    let system = system_builder.build().expect("Failed to start system");
+
+    // The kernel stops once all the foreground processes, and all the processes started from
+    // `foreground_hashes`, have finished. If there isn't any, the kernel runs forever.
     let mut cli_pids = Vec::with_capacity(cli_requested_processes.len());
-    // TODO: should also contain the `module_hash`es
     for (module_path, module, foreground) in cli_requested_processes {
-        match system.execute(&module) {
+        match system.execute_with_name(&module, module_path.display().to_string()) {
             Ok(pid) if foreground => cli_pids.push(pid),
             Ok(_) => {}
             Err(err) => panic!("Failed to load {}: {}", module_path.display(), err),
         }
     }
 
-    // TODO: uncomment after cli_pids contains the `module_hash`es
-    /*if cli_pids.is_empty() {
-        return;
-    }*/
-
     // We now spawn background tasks that run the scheduler.
     // Background tasks report all events to the main thread, which can then decide to stop
     // everything.
@@ -118,28 +460,53 @@ fn main() {
         });
     }
 
-    // All the background tasks events are grouped together and sent here.
+    // All the background tasks events are grouped together and sent here. The future produces
+    // the exit code of the kernel.
     framebuffer_context.run(async move {
         while let Some(event) = rx.next().await {
             match event {
                 redshirt_core::system::SystemRunOutcome::ProgramFinished {
                     pid,
-                    outcome: Err(err),
-                } if cli_pids.iter().any(|p| *p == pid) => {
-                    eprintln!("{:?}", err);
-                    process::exit(1);
-                }
-                redshirt_core::system::SystemRunOutcome::ProgramFinished {
-                    pid,
-                    outcome: Ok(()),
+                    info,
+                    outcome,
                 } => {
-                    cli_pids.retain(|p| *p != pid);
-                    if cli_pids.is_empty() {
-                        process::exit(0);
+                    let hash_pos = foreground_hashes.iter().position(|h| *h == info.module_hash);
+                    let foreground = if cli_pids.contains(&pid) {
+                        cli_pids.retain(|p| *p != pid);
+                        true
+                    } else if let (Some(pos), None) = (hash_pos, info.parent) {
+                        foreground_hashes.remove(pos);
+                        true
+                    } else {
+                        false
+                    };
+
+                    match outcome {
+                        Err(err) if foreground => {
+                            eprintln!("{} has crashed: {}", process_description(pid, &info), err);
+                            return 1;
+                        }
+                        Err(err) => {
+                            eprintln!("{} has crashed: {}", process_description(pid, &info), err)
+                        }
+                        Ok(()) => {}
+                    }
+
+                    if foreground && cli_pids.is_empty() && foreground_hashes.is_empty() {
+                        return 0;
+                    }
+                }
+                redshirt_core::system::SystemRunOutcome::ProgramLoadFailed { hash, error } => {
+                    if foreground_hashes.contains(&hash) {
+                        eprintln!("Failed to load --module-hash {}: {}", hash, error);
+                        return 1;
+                    } else {
+                        eprintln!("Failed to load module {}: {}", hash, error);
                     }
                 }
-                _ => panic!(),
             }
         }
+
+        0
     });
 }
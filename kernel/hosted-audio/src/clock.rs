@@ -0,0 +1,48 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Plays the streams without any audio device, at the pace at which a device would.
+
+use crate::streams::Streams;
+use std::{
+    sync::{Mutex, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Interval between two updates of the streams.
+const TICK: Duration = Duration::from_millis(10);
+
+/// Starts playing the streams in the background. Stops once the streams have been destroyed.
+pub(crate) fn spawn(streams: Weak<Mutex<Streams>>) {
+    thread::Builder::new()
+        .name("redshirt-audio-clock".into())
+        .spawn(move || {
+            let mut last_tick = Instant::now();
+            loop {
+                thread::sleep(TICK);
+
+                let streams = match streams.upgrade() {
+                    Some(s) => s,
+                    None => break,
+                };
+
+                let now = Instant::now();
+                streams.lock().unwrap().advance(now - last_tick);
+                last_tick = now;
+            }
+        })
+        .unwrap();
+}
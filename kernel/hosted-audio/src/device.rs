@@ -0,0 +1,120 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Plays the streams through the default output device of the host.
+
+use crate::streams::Streams;
+use cpal::traits::{DeviceTrait as _, EventLoopTrait as _, HostTrait as _};
+use cpal::{StreamData, UnknownTypeOutputBuffer};
+use std::{
+    fmt, io,
+    sync::{mpsc, Mutex, Weak},
+    thread,
+};
+
+/// Starts playing the streams on the default output device of the host, in the background.
+///
+/// Returns an error if the device can't be opened.
+pub(crate) fn spawn(streams: Weak<Mutex<Streams>>) -> io::Result<()> {
+    let (init_tx, init_rx) = mpsc::channel();
+
+    thread::Builder::new()
+        .name("redshirt-audio-device".into())
+        .spawn(move || {
+            let host = cpal::default_host();
+            let event_loop = host.event_loop();
+            let format = match open(&host, &event_loop) {
+                Ok(format) => {
+                    let _ = init_tx.send(Ok(()));
+                    format
+                }
+                Err(err) => {
+                    let _ = init_tx.send(Err(err));
+                    return;
+                }
+            };
+
+            let mut mixed = Vec::new();
+            event_loop.run(move |_, result| {
+                let buffer = match result {
+                    Ok(StreamData::Output { buffer }) => buffer,
+                    // TODO: report errors to the user
+                    _ => return,
+                };
+
+                let len = match &buffer {
+                    UnknownTypeOutputBuffer::U16(b) => b.len(),
+                    UnknownTypeOutputBuffer::I16(b) => b.len(),
+                    UnknownTypeOutputBuffer::F32(b) => b.len(),
+                };
+
+                // Once the streams have been destroyed, we keep playing silence, as the event
+                // loop can't be stopped.
+                mixed.clear();
+                mixed.resize(len, 0.0);
+                if let Some(streams) = streams.upgrade() {
+                    let mut streams = streams.lock().unwrap();
+                    streams.mix(&mut mixed, format.channels, format.sample_rate.0);
+                }
+
+                match buffer {
+                    UnknownTypeOutputBuffer::U16(mut b) => {
+                        for (out, sample) in b.iter_mut().zip(&mixed) {
+                            *out = (clamp(*sample) * 32767.0 + 32768.0) as u16;
+                        }
+                    }
+                    UnknownTypeOutputBuffer::I16(mut b) => {
+                        for (out, sample) in b.iter_mut().zip(&mixed) {
+                            *out = (clamp(*sample) * 32767.0) as i16;
+                        }
+                    }
+                    UnknownTypeOutputBuffer::F32(mut b) => {
+                        for (out, sample) in b.iter_mut().zip(&mixed) {
+                            *out = clamp(*sample);
+                        }
+                    }
+                }
+            });
+        })
+        .unwrap();
+
+    match init_rx.recv() {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(io::ErrorKind::Other, "audio thread has stopped")),
+    }
+}
+
+/// Opens the default output device and starts playing on it.
+fn open(host: &cpal::Host, event_loop: &cpal::EventLoop) -> io::Result<cpal::Format> {
+    let device = host
+        .default_output_device()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no output device available"))?;
+    let format = device.default_output_format().map_err(other_error)?;
+    let stream_id = event_loop
+        .build_output_stream(&device, &format)
+        .map_err(other_error)?;
+    event_loop.play_stream(stream_id).map_err(other_error)?;
+    Ok(format)
+}
+
+/// Turns an error of the audio library into an I/O error.
+fn other_error(err: impl fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err.to_string())
+}
+
+/// Limits a sample to the range that the device can play.
+fn clamp(sample: f32) -> f32 {
+    sample.max(-1.0).min(1.0)
+}
@@ -0,0 +1,162 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the audio interface by playing the streams on the host's output device, or by
+//! discarding them or writing them to WAV files at the pace at which a device would play them.
+//!
+//! # Usage
+//!
+//! - Create an [`AudioHandler`], passing where to play the streams. This type can be used as a
+//! native process with the kernel.
+//!
+//! Streams are mixed together when played on the host's output device. The samples are converted
+//! to the format of the device, which is poor quality if the sample rates or the number of
+//! channels are different.
+//!
+//! When writing WAV files, each stream is written to a file named `<pid>-<id>.wav`, where `<id>`
+//! is the identifier chosen by the process for its stream. The files contain what a device would
+//! have played, including the silence played during underruns.
+
+use futures::{channel::mpsc, lock::Mutex as FutureMutex, prelude::*};
+use redshirt_audio_interface::ffi::{AudioMessage, INTERFACE};
+use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use std::{
+    fs, io,
+    path::PathBuf,
+    pin::Pin,
+    sync::{atomic, Arc, Mutex},
+};
+
+mod clock;
+mod device;
+mod streams;
+mod wav;
+
+/// Answer to a message, waiting to be emitted.
+type Answer = (MessageId, Result<EncodedMessage, ()>);
+
+/// Where to play the streams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioOutput {
+    /// Play the streams on the default output device of the host.
+    Device,
+    /// Discard the streams.
+    Null,
+    /// Write each stream to a WAV file in this directory. The directory is created if it
+    /// doesn't exist.
+    WavDir(PathBuf),
+}
+
+/// State machine for `audio` interface messages handling.
+pub struct AudioHandler {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Streams, shared with the thread that plays them.
+    streams: Arc<Mutex<streams::Streams>>,
+    /// Message responses waiting to be emitted.
+    pending_messages_rx: FutureMutex<mpsc::UnboundedReceiver<Answer>>,
+    /// Sending side of `pending_messages_rx`.
+    pending_messages_tx: mpsc::UnboundedSender<Answer>,
+}
+
+impl AudioHandler {
+    /// Initializes the new state machine for audio messages handling, and starts playing the
+    /// streams in the background.
+    ///
+    /// Returns an error if the output device can't be opened, or if the directory of the WAV
+    /// files can't be created.
+    pub fn new(output: AudioOutput) -> io::Result<Self> {
+        let (pending_messages_tx, pending_messages_rx) = mpsc::unbounded();
+
+        let wav_dir = match &output {
+            AudioOutput::WavDir(dir) => {
+                fs::create_dir_all(dir)?;
+                Some(dir.clone())
+            }
+            AudioOutput::Device | AudioOutput::Null => None,
+        };
+
+        let streams = streams::Streams::new(pending_messages_tx.clone(), wav_dir);
+        let streams = Arc::new(Mutex::new(streams));
+
+        match output {
+            AudioOutput::Device => device::spawn(Arc::downgrade(&streams))?,
+            AudioOutput::Null | AudioOutput::WavDir(_) => clock::spawn(Arc::downgrade(&streams)),
+        }
+
+        Ok(AudioHandler {
+            registered: atomic::AtomicBool::new(false),
+            streams,
+            pending_messages_rx: FutureMutex::new(pending_messages_rx),
+            pending_messages_tx,
+        })
+    }
+}
+
+impl<'a> NativeProgramRef<'a> for &'a AudioHandler {
+    type Future =
+        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event(self) -> Self::Future {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(async move {
+            let mut pending_messages_rx = self.pending_messages_rx.lock().await;
+            let (message_id, answer) = pending_messages_rx.next().await.unwrap();
+            NativeProgramEvent::Answer { message_id, answer }
+        })
+    }
+
+    fn interface_message(
+        self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let answer = match AudioMessage::decode(message) {
+            Ok(msg) => {
+                let mut streams = self.streams.lock().unwrap();
+                streams.process_message(emitter_pid, message_id, msg).map(Ok)
+            }
+            Err(_) => Some(Err(())),
+        };
+
+        if let (Some(message_id), Some(answer)) = (message_id, answer) {
+            self.pending_messages_tx
+                .unbounded_send((message_id, answer))
+                .unwrap();
+        }
+    }
+
+    fn process_destroyed(self, pid: Pid) {
+        self.streams.lock().unwrap().process_destroyed(pid);
+    }
+
+    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Streams opened by processes, and the frames queued on them.
+
+use crate::{wav::WavWriter, Answer};
+use futures::channel::mpsc;
+use redshirt_audio_interface::ffi::{self, AudioError, AudioMessage, PushStatus, SampleFormat};
+use redshirt_core::{Encode, EncodedMessage, MessageId, Pid};
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+    path::PathBuf,
+    time::Duration,
+};
+
+/// Duration of the frames queued on a stream under which pushes are answered, in milliseconds.
+const MAX_QUEUED_MS: u64 = 100;
+/// Maximum number of frames per second of a stream.
+const MAX_SAMPLE_RATE: u32 = 192_000;
+/// Maximum number of channels of a stream.
+const MAX_CHANNELS: u16 = 8;
+
+/// Collection of all the streams.
+pub(crate) struct Streams {
+    /// Streams, indexed by the process that has opened them and their identifier.
+    list: HashMap<(Pid, u32), Stream>,
+    /// Where to send the answers to messages.
+    answers: mpsc::UnboundedSender<Answer>,
+    /// If `Some`, each stream is written to a WAV file in this directory.
+    wav_dir: Option<PathBuf>,
+}
+
+/// Stream opened by a process.
+struct Stream {
+    /// Number of frames per second.
+    sample_rate: u32,
+    /// Number of samples in each frame.
+    channels: u16,
+    /// Format of each sample.
+    format: SampleFormat,
+    /// Frames waiting to be played.
+    queue: VecDeque<u8>,
+    /// True if frames have been pushed since the stream has been opened or last drained.
+    playing: bool,
+    /// Number of frames of silence played since the last answer to a push.
+    underrun_frames: u64,
+    /// Pushes to answer once the queue is short enough, in order.
+    pending_pushes: VecDeque<MessageId>,
+    /// Drains to answer once the queue is empty.
+    pending_drains: Vec<MessageId>,
+    /// Fraction of a frame that is due to be played but hasn't been taken from the queue yet.
+    due_fraction: f64,
+    /// File where the played frames are written, if any.
+    wav: Option<WavWriter>,
+}
+
+impl Streams {
+    /// Initializes an empty collection.
+    pub fn new(answers: mpsc::UnboundedSender<Answer>, wav_dir: Option<PathBuf>) -> Self {
+        Streams {
+            list: HashMap::new(),
+            answers,
+            wav_dir,
+        }
+    }
+
+    /// Processes a message and returns the answer to send back, if it can be answered
+    /// immediately.
+    pub fn process_message(
+        &mut self,
+        emitter_pid: Pid,
+        message_id: Option<MessageId>,
+        message: AudioMessage,
+    ) -> Option<EncodedMessage> {
+        match message {
+            AudioMessage::Open {
+                version,
+                id,
+                sample_rate,
+                channels,
+                format,
+            } => {
+                let result = self.open(emitter_pid, id, version, sample_rate, channels, format);
+                Some(result.encode())
+            }
+            AudioMessage::Close { id } => match self.list.remove(&(emitter_pid, id)) {
+                Some(stream) => {
+                    for message_id in stream.pending_pushes {
+                        let answer = Err::<PushStatus, _>(AudioError::UnknownStream);
+                        send(&self.answers, message_id, answer);
+                    }
+                    for message_id in stream.pending_drains {
+                        send(&self.answers, message_id, Err::<(), _>(AudioError::UnknownStream));
+                    }
+                    Some(Ok::<(), AudioError>(()).encode())
+                }
+                None => Some(Err::<(), _>(AudioError::UnknownStream).encode()),
+            },
+            AudioMessage::Push { id, data } => match self.list.get_mut(&(emitter_pid, id)) {
+                Some(stream) if data.len() % stream.frame_len() != 0 => {
+                    Some(Err::<PushStatus, _>(AudioError::InvalidDataLength).encode())
+                }
+                Some(stream) => {
+                    stream.queue.extend(data);
+                    stream.playing = true;
+                    if let Some(message_id) = message_id {
+                        stream.pending_pushes.push_back(message_id);
+                    }
+                    stream.answer_pushes(&self.answers);
+                    None
+                }
+                None => Some(Err::<PushStatus, _>(AudioError::UnknownStream).encode()),
+            },
+            AudioMessage::Drain { id } => match self.list.get_mut(&(emitter_pid, id)) {
+                Some(stream) if stream.queue.is_empty() => {
+                    stream.playing = false;
+                    Some(Ok::<(), AudioError>(()).encode())
+                }
+                Some(stream) => {
+                    if let Some(message_id) = message_id {
+                        stream.pending_drains.push(message_id);
+                    }
+                    None
+                }
+                None => Some(Err::<(), _>(AudioError::UnknownStream).encode()),
+            },
+        }
+    }
+
+    /// Closes all the streams of the given process.
+    pub fn process_destroyed(&mut self, pid: Pid) {
+        self.list.retain(|(p, _), _| *p != pid);
+    }
+
+    /// Plays the frames of all the streams that are due after `duration` has passed, without
+    /// any audio device.
+    pub fn advance(&mut self, duration: Duration) {
+        for stream in self.list.values_mut() {
+            let due = duration.as_secs_f64() * f64::from(stream.sample_rate);
+            stream.take(due, &self.answers);
+        }
+    }
+
+    /// Plays the frames of all the streams that are due during `out`, by mixing them into `out`.
+    ///
+    /// `out` contains interleaved samples of `out_channels` channels, played at `out_rate` frames
+    /// per second. Its existing content is overwritten.
+    pub fn mix(&mut self, out: &mut [f32], out_channels: u16, out_rate: u32) {
+        for sample in out.iter_mut() {
+            *sample = 0.0;
+        }
+
+        let out_channels = usize::from(out_channels.max(1));
+        let out_frames = out.len() / out_channels;
+        if out_frames == 0 || out_rate == 0 {
+            return;
+        }
+
+        for stream in self.list.values_mut() {
+            let due = out_frames as f64 * f64::from(stream.sample_rate) / f64::from(out_rate);
+            let frames = match stream.take(due, &self.answers) {
+                Some(f) => f,
+                None => continue,
+            };
+
+            let frame_len = stream.frame_len();
+            let bytes_per_sample = stream.format.bytes_per_sample() as usize;
+            let count = frames.len() / frame_len;
+            if count == 0 {
+                continue;
+            }
+
+            // TODO: the resampling picks the nearest frame, and channels are neither up-mixed nor
+            // down-mixed; both are poor quality
+            for (n, out_frame) in out.chunks_exact_mut(out_channels).enumerate() {
+                let src_frame = &frames[(n * count / out_frames) * frame_len..][..frame_len];
+                for (channel, out_sample) in out_frame.iter_mut().enumerate() {
+                    let src_channel = channel % usize::from(stream.channels);
+                    let src_sample = &src_frame[src_channel * bytes_per_sample..];
+                    *out_sample += sample_to_f32(stream.format, src_sample);
+                }
+            }
+        }
+    }
+
+    /// Opens a new stream.
+    fn open(
+        &mut self,
+        pid: Pid,
+        id: u32,
+        version: u32,
+        sample_rate: u32,
+        channels: u16,
+        format: SampleFormat,
+    ) -> Result<(), AudioError> {
+        if version != ffi::PROTOCOL_VERSION {
+            return Err(AudioError::UnsupportedVersion);
+        }
+        if sample_rate == 0 || sample_rate > MAX_SAMPLE_RATE {
+            return Err(AudioError::UnsupportedFormat);
+        }
+        if channels == 0 || channels > MAX_CHANNELS {
+            return Err(AudioError::UnsupportedFormat);
+        }
+        if self.list.contains_key(&(pid, id)) {
+            return Err(AudioError::AlreadyExists);
+        }
+
+        let wav = self.wav_dir.as_ref().and_then(|dir| {
+            let path = dir.join(format!("{}-{}.wav", u64::from(pid), id));
+            match WavWriter::create(&path, sample_rate, channels, format) {
+                Ok(wav) => Some(wav),
+                Err(err) => {
+                    eprintln!("Failed to create {}: {}", path.display(), err);
+                    None
+                }
+            }
+        });
+
+        self.list.insert(
+            (pid, id),
+            Stream {
+                sample_rate,
+                channels,
+                format,
+                queue: VecDeque::new(),
+                playing: false,
+                underrun_frames: 0,
+                pending_pushes: VecDeque::new(),
+                pending_drains: Vec::new(),
+                due_fraction: 0.0,
+                wav,
+            },
+        );
+
+        Ok(())
+    }
+}
+
+impl Stream {
+    /// Returns the number of bytes of a frame.
+    fn frame_len(&self) -> usize {
+        ffi::frame_len(self.channels, self.format) as usize
+    }
+
+    /// Takes from the queue the frames to play now that `due` more frames, possibly a fraction,
+    /// are due.
+    ///
+    /// Returns `None` if the stream isn't playing. Otherwise, returns the frames to play,
+    /// completed with silence if the queue is too short.
+    fn take(&mut self, due: f64, answers: &mpsc::UnboundedSender<Answer>) -> Option<Vec<u8>> {
+        self.due_fraction += due;
+        let count = self.due_fraction as usize;
+        self.due_fraction -= count as f64;
+
+        if !self.playing {
+            return None;
+        }
+
+        let frame_len = self.frame_len();
+        let taken = count.min(self.queue.len() / frame_len);
+        let mut frames = self.queue.drain(..taken * frame_len).collect::<Vec<_>>();
+
+        // Number of frames that are actually played, as opposed to the silence after the end of
+        // a drained stream.
+        let mut played = count;
+        if self.queue.is_empty() && !self.pending_drains.is_empty() {
+            played = taken;
+            self.playing = false;
+            for message_id in self.pending_drains.drain(..) {
+                send(answers, message_id, Ok::<(), AudioError>(()));
+            }
+        } else if taken < count {
+            self.underrun_frames += (count - taken) as u64;
+        }
+
+        frames.resize(count * frame_len, 0);
+
+        if let Some(wav) = &mut self.wav {
+            if let Err(err) = wav.write(&frames[..played * frame_len]) {
+                eprintln!("Failed to write audio to WAV file: {}", err);
+                self.wav = None;
+            }
+        }
+
+        self.answer_pushes(answers);
+        Some(frames)
+    }
+
+    /// Answers the pending pushes for as long as the queue is short enough.
+    fn answer_pushes(&mut self, answers: &mpsc::UnboundedSender<Answer>) {
+        let max_queued_frames = u64::from(self.sample_rate) * MAX_QUEUED_MS / 1000;
+        let max_queued_len = max_queued_frames as usize * self.frame_len();
+
+        while self.queue.len() <= max_queued_len {
+            let message_id = match self.pending_pushes.pop_front() {
+                Some(id) => id,
+                None => break,
+            };
+
+            let status = PushStatus {
+                underrun_frames: mem::replace(&mut self.underrun_frames, 0),
+            };
+            send(answers, message_id, Ok::<_, AudioError>(status));
+        }
+    }
+}
+
+/// Sends the answer to a message.
+fn send(answers: &mpsc::UnboundedSender<Answer>, message_id: MessageId, answer: impl Encode) {
+    // The handler might have been destroyed, in which case the answer doesn't matter anymore.
+    let _ = answers.unbounded_send((message_id, Ok(answer.encode())));
+}
+
+/// Converts the sample at the start of `bytes` to a value between -1.0 and 1.0.
+fn sample_to_f32(format: SampleFormat, bytes: &[u8]) -> f32 {
+    match format {
+        SampleFormat::I16 => f32::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32768.0,
+        SampleFormat::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
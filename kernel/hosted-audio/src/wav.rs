@@ -0,0 +1,99 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Writing of WAV files.
+
+use redshirt_audio_interface::ffi::{self, SampleFormat};
+use std::{
+    convert::TryFrom as _,
+    fs,
+    io::{self, Seek as _, SeekFrom, Write as _},
+    path::Path,
+};
+
+/// Length of the header written by [`WavWriter`].
+const HEADER_LEN: u32 = 44;
+
+/// WAV file being written.
+///
+/// The header of the file is updated after each write, so that the file is always valid even if
+/// the program stops abruptly.
+pub(crate) struct WavWriter {
+    file: fs::File,
+    /// Number of bytes of samples written so far.
+    data_len: u32,
+}
+
+impl WavWriter {
+    /// Creates the file, or truncates it if it already exists, and writes the header.
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        channels: u16,
+        format: SampleFormat,
+    ) -> io::Result<Self> {
+        let (audio_format, bits_per_sample): (u16, u16) = match format {
+            SampleFormat::I16 => (1, 16),
+            SampleFormat::F32 => (3, 32),
+        };
+        let block_align = u16::try_from(ffi::frame_len(channels, format))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many channels"))?;
+        let byte_rate = sample_rate.saturating_mul(u32::from(block_align));
+
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_LEN - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&audio_format.to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&byte_rate.to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&bits_per_sample.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        debug_assert_eq!(header.len(), HEADER_LEN as usize);
+
+        let mut file = fs::File::create(path)?;
+        file.write_all(&header)?;
+        Ok(WavWriter { file, data_len: 0 })
+    }
+
+    /// Appends samples to the file.
+    ///
+    /// Samples that would make the file larger than what the format supports are discarded.
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let max_len = u32::max_value() - HEADER_LEN - self.data_len;
+        let len = u32::try_from(data.len())
+            .unwrap_or(u32::max_value())
+            .min(max_len);
+        if len == 0 {
+            return Ok(());
+        }
+
+        self.file.write_all(&data[..len as usize])?;
+        self.data_len += len;
+
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(u64::from(HEADER_LEN) - 4))?;
+        self.file.write_all(&self.data_len.to_le_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
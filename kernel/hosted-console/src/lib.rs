@@ -0,0 +1,212 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `console` interface using the standard input and output of
+//! the host.
+//!
+//! Text written to the console is passed through to the standard output as is, and it is
+//! therefore the host's terminal that interprets the escape sequences. The standard input is
+//! read line by line, and it is the host's terminal that echoes what the user types.
+
+use futures::{channel::mpsc, lock::Mutex as FutureMutex, prelude::*};
+use redshirt_console_interface::ffi::{ConsoleMessage, ConsoleSize, INTERFACE};
+use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use std::{
+    collections::VecDeque,
+    convert::TryFrom as _,
+    io::{self, BufRead as _, Write as _},
+    pin::Pin,
+    sync::{atomic, Arc, Mutex},
+    thread,
+};
+
+/// Size reported to processes. The standard library doesn't provide any way to know the size
+/// of the host's terminal, so we report the traditional default.
+const CONSOLE_SIZE: ConsoleSize = ConsoleSize {
+    columns: 80,
+    rows: 24,
+};
+
+/// Answer to a message, waiting to be emitted.
+type Answer = (MessageId, Result<EncodedMessage, ()>);
+
+/// State machine for `console` interface messages handling.
+pub struct ConsoleHandler {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Input and read requests, shared with the thread reading the standard input.
+    input: Arc<Mutex<Input>>,
+    /// Message responses waiting to be emitted.
+    pending_messages_rx: FutureMutex<mpsc::UnboundedReceiver<Answer>>,
+    /// Sending side of `pending_messages_rx`.
+    pending_messages_tx: mpsc::UnboundedSender<Answer>,
+}
+
+/// Input of the console.
+#[derive(Default)]
+struct Input {
+    /// Bytes read from the standard input that haven't been delivered yet.
+    buffer: VecDeque<u8>,
+    /// Read requests that haven't been answered yet, in order, with the process that has emitted
+    /// them and the maximum number of bytes to answer.
+    pending_reads: VecDeque<(MessageId, Pid, u32)>,
+}
+
+impl ConsoleHandler {
+    /// Initializes the new state machine for console messages handling, and starts reading the
+    /// standard input in the background.
+    pub fn new() -> Self {
+        let (pending_messages_tx, pending_messages_rx) = mpsc::unbounded();
+        let input = Arc::new(Mutex::new(Input::default()));
+
+        {
+            let input = input.clone();
+            let pending_messages_tx = pending_messages_tx.clone();
+            thread::Builder::new()
+                .name("redshirt-console-stdin".into())
+                .spawn(move || {
+                    let stdin = io::stdin();
+                    let mut stdin = stdin.lock();
+                    let mut line = Vec::new();
+                    loop {
+                        line.clear();
+                        // Reads that are pending when the standard input is closed are never
+                        // answered.
+                        match stdin.read_until(b'\n', &mut line) {
+                            Ok(0) | Err(_) => break,
+                            Ok(_) => {}
+                        }
+
+                        let mut input = input.lock().unwrap();
+                        input.buffer.extend(line.iter().cloned());
+                        for answer in input.answer_reads() {
+                            if pending_messages_tx.unbounded_send(answer).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                })
+                .unwrap();
+        }
+
+        ConsoleHandler {
+            registered: atomic::AtomicBool::new(false),
+            input,
+            pending_messages_tx,
+            pending_messages_rx: FutureMutex::new(pending_messages_rx),
+        }
+    }
+}
+
+impl Input {
+    /// Answers the pending read requests for as long as there is input available.
+    fn answer_reads(&mut self) -> Vec<Answer> {
+        let mut answers = Vec::new();
+        while !self.buffer.is_empty() {
+            let (message_id, _, max_len) = match self.pending_reads.pop_front() {
+                Some(r) => r,
+                None => break,
+            };
+
+            let len = usize::try_from(max_len).unwrap_or(usize::max_value());
+            let len = len.min(self.buffer.len());
+            let data = self.buffer.drain(..len).collect::<Vec<_>>();
+            answers.push((message_id, Ok(data.encode())));
+        }
+        answers
+    }
+}
+
+impl Default for ConsoleHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> NativeProgramRef<'a> for &'a ConsoleHandler {
+    type Future =
+        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event(self) -> Self::Future {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(async move {
+            let mut pending_messages_rx = self.pending_messages_rx.lock().await;
+            let (message_id, answer) = pending_messages_rx.next().await.unwrap();
+            NativeProgramEvent::Answer { message_id, answer }
+        })
+    }
+
+    fn interface_message(
+        self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        match (ConsoleMessage::decode(message), message_id) {
+            (Ok(ConsoleMessage::Write(data)), _) => {
+                let stdout = io::stdout();
+                let mut stdout = stdout.lock();
+                let _ = stdout.write_all(&data);
+                let _ = stdout.flush();
+            }
+            (Ok(ConsoleMessage::Read { max_len: 0 }), Some(message_id)) => {
+                let answer = Vec::<u8>::new().encode();
+                self.pending_messages_tx
+                    .unbounded_send((message_id, Ok(answer)))
+                    .unwrap();
+            }
+            (Ok(ConsoleMessage::Read { max_len }), Some(message_id)) => {
+                let mut input = self.input.lock().unwrap();
+                input.pending_reads.push_back((message_id, emitter_pid, max_len));
+                for answer in input.answer_reads() {
+                    self.pending_messages_tx.unbounded_send(answer).unwrap();
+                }
+            }
+            (Ok(ConsoleMessage::Size), Some(message_id)) => {
+                self.pending_messages_tx
+                    .unbounded_send((message_id, Ok(CONSOLE_SIZE.encode())))
+                    .unwrap();
+            }
+            (Ok(_), None) => {}
+            (Err(_), Some(message_id)) => self
+                .pending_messages_tx
+                .unbounded_send((message_id, Err(())))
+                .unwrap(),
+            (Err(_), None) => {}
+        }
+    }
+
+    fn process_destroyed(self, pid: Pid) {
+        let mut input = self.input.lock().unwrap();
+        input.pending_reads.retain(|(_, p, _)| *p != pid);
+    }
+
+    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
@@ -19,7 +19,8 @@ use glium::{
     texture::{ClientFormat, RawImage2d, Texture2d},
     uniform, Surface as _,
 };
-use std::{borrow::Cow, convert::TryFrom as _};
+use redshirt_framebuffer_interface::ffi::{self, PixelFormat};
+use std::borrow::Cow;
 
 /// Window and framebuffer. Implicitely associated to the event loop that has been passed when
 /// creating it.
@@ -30,17 +31,21 @@ pub struct Framebuffer {
     index_buffer: glium::IndexBuffer<u16>,
     program: glium::Program,
     texture: Texture2d,
+    /// Format of the data passed when writing to the framebuffer.
+    format: PixelFormat,
 }
 
 impl Framebuffer {
-    /// Creates a new window for displaying a framebuffer.
-    // TODO: check if zero dimensions
+    /// Creates a new window for displaying a framebuffer, initially filled with black.
+    ///
+    /// The dimensions must have been checked with `valid_dimensions`.
     // TODO: return Result
     pub fn new<T>(
         event_loop: &glium::glutin::event_loop::EventLoopWindowTarget<T>,
         title: &str,
         width: u32,
         height: u32,
+        format: PixelFormat,
     ) -> Framebuffer {
         let wb = glium::glutin::window::WindowBuilder::new()
             .with_inner_size(glium::glutin::dpi::LogicalSize::new(
@@ -119,7 +124,7 @@ impl Framebuffer {
         )
         .unwrap();
 
-        let texture = Texture2d::empty(&display, width, height).unwrap();
+        let texture = black_texture(&display, width, height);
 
         Framebuffer {
             window_id,
@@ -128,6 +133,7 @@ impl Framebuffer {
             index_buffer,
             program,
             texture,
+            format,
         }
     }
 
@@ -139,38 +145,12 @@ impl Framebuffer {
         self.window_id
     }
 
-    /// Sets the content of this framebuffer and dispatches a window redraw request on the window.
-    pub fn set_data(&mut self, data: &[u8]) {
-        if u32::try_from(data.len())
-            != Ok(3u32
-                .saturating_mul(self.texture.width())
-                .saturating_mul(self.texture.height()))
-        {
-            // TODO: log this?
-            return;
-        }
-
-        let rect = glium::Rect {
-            left: 0,
-            bottom: 0,
-            width: self.texture.width(),
-            height: self.texture.height(),
-        };
-
-        self.texture.write(
-            rect,
-            RawImage2d {
-                data: Cow::Borrowed(data),
-                width: self.texture.width(),
-                height: self.texture.height(),
-                format: ClientFormat::U8U8U8,
-            },
-        );
-
-        let window = self.display.gl_window();
-        let window = window.window();
-        window.set_visible(true);
-        window.request_redraw();
+    /// Returns the ratio between physical pixels and framebuffer pixels.
+    ///
+    /// Positions and sizes reported by the windowing system are in physical pixels and must be
+    /// divided by this value.
+    pub fn scale_factor(&self) -> f64 {
+        self.display.gl_window().window().scale_factor()
     }
 
     /// Refreshes the framebuffer.
@@ -196,6 +176,69 @@ impl Framebuffer {
     }
 }
 
+impl crate::Surface for Framebuffer {
+    /// Sets the content of a rectangle within this framebuffer and dispatches a window redraw
+    /// request on the window.
+    fn write(&mut self, rect: ffi::Rect, data: &[u8]) {
+        // Since the texture is displayed upside down, its bottom is the top of the framebuffer.
+        let texture_rect = glium::Rect {
+            left: rect.x,
+            bottom: rect.y,
+            width: rect.width,
+            height: rect.height,
+        };
+
+        let (data, format) = match self.format {
+            PixelFormat::Rgb24 => (Cow::Borrowed(data), ClientFormat::U8U8U8),
+            PixelFormat::Rgba32 => (Cow::Borrowed(data), ClientFormat::U8U8U8U8),
+            // OpenGL doesn't support BGRA data, so we swap the red and blue components.
+            PixelFormat::Bgra32 => {
+                let mut data = data.to_vec();
+                for pixel in data.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+                (Cow::Owned(data), ClientFormat::U8U8U8U8)
+            }
+        };
+
+        self.texture.write(
+            texture_rect,
+            RawImage2d {
+                data,
+                width: rect.width,
+                height: rect.height,
+                format,
+            },
+        );
+
+        let window = self.display.gl_window();
+        let window = window.window();
+        window.set_visible(true);
+        window.request_redraw();
+    }
+
+    /// Changes the dimensions of this framebuffer and of its window. The content is reset to
+    /// black.
+    fn resize(&mut self, width: u32, height: u32) {
+        self.texture = black_texture(&self.display, width, height);
+
+        let window = self.display.gl_window();
+        let window = window.window();
+        window.set_inner_size(glium::glutin::dpi::LogicalSize::new(
+            width as f64,
+            height as f64,
+        ));
+        window.request_redraw();
+    }
+}
+
+/// Builds a texture of the given dimensions filled with black.
+fn black_texture(display: &glium::Display, width: u32, height: u32) -> Texture2d {
+    let texture = Texture2d::empty(display, width, height).unwrap();
+    texture.as_surface().clear_color(0.0, 0.0, 0.0, 1.0);
+    texture
+}
+
 #[derive(Copy, Clone)]
 struct Vertex {
     position: [f32; 2],
@@ -0,0 +1,730 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Framebuffers kept in memory, for environments where no window can be opened.
+
+use crate::{process_message, send_answer, ContextToHandler, FramebufferState, HandlerToContext};
+use futures::{channel::mpsc, prelude::*};
+use redshirt_core::{Buffers, Pid};
+use redshirt_framebuffer_interface::ffi;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    str::{FromStr, SplitWhitespace},
+    thread,
+    time::Duration,
+};
+
+/// Configuration of a headless [`FramebufferContext`](crate::FramebufferContext).
+///
+/// # Input script
+///
+/// The input script is a text file containing one command per line. Empty lines and lines
+/// starting with `#` are ignored. The commands are executed one after the other, starting when
+/// the context starts running.
+///
+/// Input events are delivered to the most recently created framebuffer that still exists, and
+/// are discarded if there isn't any. They are also reported by the
+/// [`HidDriver`](crate::HidDriver)s of the context, whether or not a framebuffer exists. The
+/// available commands are:
+///
+/// - `wait <milliseconds>`: waits before executing the next command.
+/// - `screenshot <path>`: writes the content of the most recently created framebuffer to an image
+/// file. Files whose name ends with `.png` are written as PNG, and the other ones as binary PPM.
+/// - `key <scancode> <pressed|released> [<key>]`: a keyboard event. `<key>` is the name of one
+/// of the variants of [`ffi::Key`], for example `A` or `Enter`.
+/// - `text <text>`: text input. The rest of the line is the text.
+/// - `pointer-move <x> <y>`: the pointer has moved to the given position.
+/// - `pointer-left`: the pointer has left the framebuffer.
+/// - `pointer-button <left|right|middle|number> <pressed|released>`: a pointer button event.
+/// - `wheel-lines <x> <y>`: a wheel event, in hundredths of lines.
+/// - `wheel-pixels <x> <y>`: a wheel event, in pixels.
+/// - `resized <width> <height>`: the area where the framebuffer is shown has been resized.
+/// - `focus <true|false>`: the framebuffer has gained or lost the focus.
+/// - `close`: the user has asked to close the framebuffer.
+///
+/// For example:
+///
+/// ```text
+/// # Wait for the user interface to show up.
+/// wait 500
+/// pointer-move 20 30
+/// pointer-button left pressed
+/// pointer-button left released
+/// wait 100
+/// screenshot after-click.png
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HeadlessConfig {
+    /// If `Some`, each framebuffer is written to this directory when it is destroyed, either
+    /// explicitly, because its process has terminated, or because the context stops. Files are
+    /// named `<pid>-<id>.png`, where `<id>` is the identifier chosen by the process for its
+    /// framebuffer.
+    pub dump_dir: Option<PathBuf>,
+    /// If `Some`, path to an input script to execute.
+    pub script: Option<PathBuf>,
+}
+
+/// Headless backend of a [`FramebufferContext`](crate::FramebufferContext).
+pub(crate) struct Headless {
+    /// See [`HeadlessConfig::dump_dir`].
+    dump_dir: Option<PathBuf>,
+    /// Parsed input script.
+    script: Vec<Step>,
+}
+
+/// Line of the input script.
+enum Step {
+    /// Wait before executing the next step.
+    Wait(Duration),
+    /// Command to send to the context.
+    Command(Command),
+}
+
+/// Command of the input script sent to the context.
+enum Command {
+    /// Write the most recently created framebuffer to a file.
+    Screenshot(PathBuf),
+    /// Deliver an input event to the most recently created framebuffer.
+    Event(ffi::InputEvent),
+}
+
+/// Framebuffer stored in memory.
+struct Framebuffer {
+    /// Framebuffers with a higher value have been created more recently.
+    creation_index: u64,
+    /// Where to write the framebuffer when it is destroyed. See [`HeadlessConfig::dump_dir`].
+    dump_path: Option<PathBuf>,
+    /// Width of the framebuffer in pixels.
+    width: u32,
+    /// Height of the framebuffer in pixels.
+    height: u32,
+    /// Format of the data passed when writing to the framebuffer.
+    format: ffi::PixelFormat,
+    /// Pixels in RGB format, row by row from top to bottom.
+    pixels: Vec<u8>,
+}
+
+impl Headless {
+    /// Loads the input script of the configuration, if any.
+    pub fn new(config: HeadlessConfig) -> io::Result<Self> {
+        let script = match &config.script {
+            Some(path) => parse_script(&fs::read_to_string(path)?).map_err(|(line, err)| {
+                let msg = format!("{}:{}: {}", path.display(), line, err);
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            })?,
+            None => Vec::new(),
+        };
+
+        Ok(Headless {
+            dump_dir: config.dump_dir,
+            script,
+        })
+    }
+
+    /// Runs the given future and processes the framebuffer messages and the input script until
+    /// the future has finished. Then destroys the remaining framebuffers, which writes them to
+    /// the dump directory, and returns the value produced by the future.
+    pub fn run(
+        self,
+        from_handler: mpsc::UnboundedReceiver<HandlerToContext>,
+        mut to_handlers: Vec<mpsc::UnboundedSender<ContextToHandler>>,
+        mut hid: crate::hid::Forwarder,
+        buffers: &Buffers,
+        future: impl Future<Output = i32>,
+    ) -> i32 {
+        let dump_dir = self.dump_dir;
+
+        enum LocalEvent {
+            FromHandler(HandlerToContext),
+            Script(Command),
+            FutureFinished(i32),
+        }
+        let mut stream = Box::pin({
+            let main_future = stream::once(future.map(LocalEvent::FutureFinished));
+            let receiver_events = from_handler.map(LocalEvent::FromHandler);
+            let script_events = spawn_script(self.script).map(LocalEvent::Script);
+            stream::select(stream::select(main_future, receiver_events), script_events)
+        });
+
+        let mut framebuffers = HashMap::<(Pid, u32), FramebufferState<Framebuffer>>::new();
+        let mut next_creation_index = 0;
+
+        let code = futures::executor::block_on(async {
+            loop {
+                let ev = match stream.next().await {
+                    Some(LocalEvent::FromHandler(ev)) => ev,
+                    Some(LocalEvent::Script(Command::Event(event))) => {
+                        hid.forward(&event);
+                        let focused = framebuffers
+                            .values_mut()
+                            .max_by_key(|fb| fb.surface.creation_index);
+                        if let Some(state) = focused {
+                            if let Some((message_id, answer)) = state.push_event(event) {
+                                send_answer(&mut to_handlers, message_id, Ok(answer));
+                            }
+                        }
+                        continue;
+                    }
+                    Some(LocalEvent::Script(Command::Screenshot(path))) => {
+                        match framebuffers
+                            .values()
+                            .max_by_key(|fb| fb.surface.creation_index)
+                        {
+                            Some(state) => state.surface.write_to(&path),
+                            None => eprintln!("No framebuffer to write to {}", path.display()),
+                        }
+                        continue;
+                    }
+                    Some(LocalEvent::FutureFinished(code)) => break code,
+                    // The stream can only end after the future has finished.
+                    None => unreachable!(),
+                };
+
+                match ev {
+                    HandlerToContext::InterfaceMessage {
+                        emitter_pid,
+                        message_id,
+                        message,
+                    } => {
+                        let answers = process_message(
+                            emitter_pid,
+                            message_id,
+                            message,
+                            buffers,
+                            &mut framebuffers,
+                            |(pid, id), width, height, format| {
+                                next_creation_index += 1;
+                                let dump_path = dump_dir.as_ref().map(|dir| {
+                                    dir.join(format!("{}-{}.png", u64::from(pid), id))
+                                });
+                                Framebuffer::new(
+                                    next_creation_index,
+                                    dump_path,
+                                    width,
+                                    height,
+                                    format,
+                                )
+                            },
+                        );
+
+                        for (message_id, answer) in answers {
+                            send_answer(&mut to_handlers, message_id, answer);
+                        }
+                    }
+                    HandlerToContext::ProcessDestroyed(pid) => {
+                        framebuffers.retain(|(p, _), _| *p != pid)
+                    }
+                }
+            }
+        });
+
+        drop(framebuffers);
+        code
+    }
+}
+
+impl Framebuffer {
+    /// Creates a new framebuffer filled with black.
+    fn new(
+        creation_index: u64,
+        dump_path: Option<PathBuf>,
+        width: u32,
+        height: u32,
+        format: ffi::PixelFormat,
+    ) -> Self {
+        Framebuffer {
+            creation_index,
+            dump_path,
+            width,
+            height,
+            format,
+            pixels: vec![0; width as usize * height as usize * 3],
+        }
+    }
+
+    /// Writes the content of the framebuffer to a file, and prints an error if that fails.
+    fn write_to(&self, path: &Path) {
+        if let Err(err) = write_image(path, self.width, self.height, &self.pixels) {
+            eprintln!("Failed to write {}: {}", path.display(), err);
+        }
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        if let Some(path) = &self.dump_path {
+            self.write_to(path);
+        }
+    }
+}
+
+impl crate::Surface for Framebuffer {
+    fn write(&mut self, rect: ffi::Rect, data: &[u8]) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+
+        let bytes_per_pixel = self.format.bytes_per_pixel() as usize;
+        let src_row_len = rect.width as usize * bytes_per_pixel;
+
+        for (row, src) in data.chunks_exact(src_row_len).enumerate() {
+            let dst_start = ((rect.y as usize + row) * self.width as usize + rect.x as usize) * 3;
+            let dst = &mut self.pixels[dst_start..dst_start + rect.width as usize * 3];
+
+            for (dst, src) in dst.chunks_exact_mut(3).zip(src.chunks_exact(bytes_per_pixel)) {
+                match self.format {
+                    ffi::PixelFormat::Rgb24 | ffi::PixelFormat::Rgba32 => {
+                        dst.copy_from_slice(&src[..3])
+                    }
+                    ffi::PixelFormat::Bgra32 => {
+                        dst[0] = src[2];
+                        dst[1] = src[1];
+                        dst[2] = src[0];
+                    }
+                }
+            }
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width as usize * height as usize * 3];
+    }
+}
+
+/// Spawns a thread that executes the steps of the input script, and returns the commands that
+/// it produces.
+fn spawn_script(steps: Vec<Step>) -> mpsc::UnboundedReceiver<Command> {
+    let (tx, rx) = mpsc::unbounded();
+    if steps.is_empty() {
+        return rx;
+    }
+
+    thread::spawn(move || {
+        for step in steps {
+            match step {
+                Step::Wait(duration) => thread::sleep(duration),
+                Step::Command(command) => {
+                    if tx.unbounded_send(command).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+/// Writes RGB pixels to a file. The format is PNG if the name of the file ends with `.png`, and
+/// binary PPM otherwise.
+fn write_image(path: &Path, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+
+    if path.extension().map_or(false, |ext| ext == "png") {
+        let png_err = |err: png::EncodingError| io::Error::new(io::ErrorKind::Other, err);
+        let mut encoder = png::Encoder::new(file, width, height);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(png_err)?;
+        writer.write_image_data(pixels).map_err(png_err)?;
+    } else {
+        writeln!(file, "P6\n{} {}\n255", width, height)?;
+        file.write_all(pixels)?;
+        file.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Parses an input script. On error, returns the line number and a description of the problem.
+fn parse_script(script: &str) -> Result<Vec<Step>, (usize, String)> {
+    let mut steps = Vec::new();
+
+    for (line_num, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        steps.push(parse_step(line).map_err(|err| (line_num + 1, err))?);
+    }
+
+    Ok(steps)
+}
+
+/// Parses a non-empty line of an input script.
+fn parse_step(line: &str) -> Result<Step, String> {
+    let (command, rest) = match line.find(char::is_whitespace) {
+        Some(pos) => (&line[..pos], line[pos..].trim_start()),
+        None => (line, ""),
+    };
+
+    let mut args = rest.split_whitespace();
+
+    let step = match command {
+        "wait" => {
+            let duration = Duration::from_millis(arg(&mut args, "duration")?);
+            Step::Wait(duration)
+        }
+        "screenshot" if rest.is_empty() => return Err("missing path".to_owned()),
+        "screenshot" => return Ok(Step::Command(Command::Screenshot(PathBuf::from(rest)))),
+        "text" => {
+            let text = ffi::InputEvent::Text(rest.to_owned());
+            return Ok(Step::Command(Command::Event(text)));
+        }
+        "key" => {
+            let scancode = arg(&mut args, "scancode")?;
+            let state = element_state(&mut args)?;
+            let key = args.next().map(key_from_name).transpose()?;
+            Step::Command(Command::Event(ffi::InputEvent::Key {
+                scancode,
+                key,
+                state,
+            }))
+        }
+        "pointer-move" => Step::Command(Command::Event(ffi::InputEvent::PointerMoved {
+            x: arg(&mut args, "x")?,
+            y: arg(&mut args, "y")?,
+        })),
+        "pointer-left" => Step::Command(Command::Event(ffi::InputEvent::PointerLeft)),
+        "pointer-button" => {
+            let button = match args.next() {
+                Some("left") => ffi::PointerButton::Left,
+                Some("right") => ffi::PointerButton::Right,
+                Some("middle") => ffi::PointerButton::Middle,
+                Some(other) => ffi::PointerButton::Other(
+                    other
+                        .parse()
+                        .map_err(|_| format!("invalid button `{}`", other))?,
+                ),
+                None => return Err("missing button".to_owned()),
+            };
+            let state = element_state(&mut args)?;
+            Step::Command(Command::Event(ffi::InputEvent::PointerButton {
+                button,
+                state,
+            }))
+        }
+        "wheel-lines" => Step::Command(Command::Event(ffi::InputEvent::PointerWheel(
+            ffi::WheelDelta::Lines {
+                x: arg(&mut args, "x")?,
+                y: arg(&mut args, "y")?,
+            },
+        ))),
+        "wheel-pixels" => Step::Command(Command::Event(ffi::InputEvent::PointerWheel(
+            ffi::WheelDelta::Pixels {
+                x: arg(&mut args, "x")?,
+                y: arg(&mut args, "y")?,
+            },
+        ))),
+        "resized" => Step::Command(Command::Event(ffi::InputEvent::Resized {
+            width: arg(&mut args, "width")?,
+            height: arg(&mut args, "height")?,
+        })),
+        "focus" => {
+            let focused = arg(&mut args, "focus")?;
+            Step::Command(Command::Event(ffi::InputEvent::Focus(focused)))
+        }
+        "close" => Step::Command(Command::Event(ffi::InputEvent::CloseRequested)),
+        _ => return Err(format!("unknown command `{}`", command)),
+    };
+
+    if let Some(extra) = args.next() {
+        return Err(format!("unexpected `{}`", extra));
+    }
+
+    Ok(step)
+}
+
+/// Parses the next argument of a command.
+fn arg<T: FromStr>(args: &mut SplitWhitespace, name: &str) -> Result<T, String> {
+    let arg = args.next().ok_or_else(|| format!("missing {}", name))?;
+    arg.parse().map_err(|_| format!("invalid {} `{}`", name, arg))
+}
+
+/// Parses the next argument of a command as either `pressed` or `released`.
+fn element_state(args: &mut SplitWhitespace) -> Result<ffi::ElementState, String> {
+    match args.next() {
+        Some("pressed") => Ok(ffi::ElementState::Pressed),
+        Some("released") => Ok(ffi::ElementState::Released),
+        Some(other) => Err(format!("expected `pressed` or `released`, got `{}`", other)),
+        None => Err("missing `pressed` or `released`".to_owned()),
+    }
+}
+
+/// Finds the [`ffi::Key`] whose variant is named `name`.
+fn key_from_name(name: &str) -> Result<ffi::Key, String> {
+    Ok(match name {
+        "A" => ffi::Key::A,
+        "B" => ffi::Key::B,
+        "C" => ffi::Key::C,
+        "D" => ffi::Key::D,
+        "E" => ffi::Key::E,
+        "F" => ffi::Key::F,
+        "G" => ffi::Key::G,
+        "H" => ffi::Key::H,
+        "I" => ffi::Key::I,
+        "J" => ffi::Key::J,
+        "K" => ffi::Key::K,
+        "L" => ffi::Key::L,
+        "M" => ffi::Key::M,
+        "N" => ffi::Key::N,
+        "O" => ffi::Key::O,
+        "P" => ffi::Key::P,
+        "Q" => ffi::Key::Q,
+        "R" => ffi::Key::R,
+        "S" => ffi::Key::S,
+        "T" => ffi::Key::T,
+        "U" => ffi::Key::U,
+        "V" => ffi::Key::V,
+        "W" => ffi::Key::W,
+        "X" => ffi::Key::X,
+        "Y" => ffi::Key::Y,
+        "Z" => ffi::Key::Z,
+        "Digit0" => ffi::Key::Digit0,
+        "Digit1" => ffi::Key::Digit1,
+        "Digit2" => ffi::Key::Digit2,
+        "Digit3" => ffi::Key::Digit3,
+        "Digit4" => ffi::Key::Digit4,
+        "Digit5" => ffi::Key::Digit5,
+        "Digit6" => ffi::Key::Digit6,
+        "Digit7" => ffi::Key::Digit7,
+        "Digit8" => ffi::Key::Digit8,
+        "Digit9" => ffi::Key::Digit9,
+        "F1" => ffi::Key::F1,
+        "F2" => ffi::Key::F2,
+        "F3" => ffi::Key::F3,
+        "F4" => ffi::Key::F4,
+        "F5" => ffi::Key::F5,
+        "F6" => ffi::Key::F6,
+        "F7" => ffi::Key::F7,
+        "F8" => ffi::Key::F8,
+        "F9" => ffi::Key::F9,
+        "F10" => ffi::Key::F10,
+        "F11" => ffi::Key::F11,
+        "F12" => ffi::Key::F12,
+        "Escape" => ffi::Key::Escape,
+        "Enter" => ffi::Key::Enter,
+        "Tab" => ffi::Key::Tab,
+        "Backspace" => ffi::Key::Backspace,
+        "Space" => ffi::Key::Space,
+        "Insert" => ffi::Key::Insert,
+        "Delete" => ffi::Key::Delete,
+        "Home" => ffi::Key::Home,
+        "End" => ffi::Key::End,
+        "PageUp" => ffi::Key::PageUp,
+        "PageDown" => ffi::Key::PageDown,
+        "ArrowLeft" => ffi::Key::ArrowLeft,
+        "ArrowRight" => ffi::Key::ArrowRight,
+        "ArrowUp" => ffi::Key::ArrowUp,
+        "ArrowDown" => ffi::Key::ArrowDown,
+        "ShiftLeft" => ffi::Key::ShiftLeft,
+        "ShiftRight" => ffi::Key::ShiftRight,
+        "ControlLeft" => ffi::Key::ControlLeft,
+        "ControlRight" => ffi::Key::ControlRight,
+        "AltLeft" => ffi::Key::AltLeft,
+        "AltRight" => ffi::Key::AltRight,
+        "MetaLeft" => ffi::Key::MetaLeft,
+        "MetaRight" => ffi::Key::MetaRight,
+        "CapsLock" => ffi::Key::CapsLock,
+        "NumLock" => ffi::Key::NumLock,
+        "ScrollLock" => ffi::Key::ScrollLock,
+        "PrintScreen" => ffi::Key::PrintScreen,
+        "Pause" => ffi::Key::Pause,
+        "Minus" => ffi::Key::Minus,
+        "Equal" => ffi::Key::Equal,
+        "BracketLeft" => ffi::Key::BracketLeft,
+        "BracketRight" => ffi::Key::BracketRight,
+        "Backslash" => ffi::Key::Backslash,
+        "Semicolon" => ffi::Key::Semicolon,
+        "Quote" => ffi::Key::Quote,
+        "Backquote" => ffi::Key::Backquote,
+        "Comma" => ffi::Key::Comma,
+        "Period" => ffi::Key::Period,
+        "Slash" => ffi::Key::Slash,
+        _ => return Err(format!("unknown key `{}`", name)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{key_from_name, parse_script, parse_step, write_image, Command, Framebuffer, Step};
+    use crate::Surface as _;
+    use redshirt_framebuffer_interface::ffi;
+    use std::{fs, path::PathBuf, time::Duration};
+
+    /// Returns the event of a step, or panics if the step isn't an event.
+    fn event(step: Step) -> ffi::InputEvent {
+        match step {
+            Step::Command(Command::Event(event)) => event,
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn script() {
+        let script = "# Comment\n\nwait 500\n  screenshot  foo bar.png \nclose\n";
+        let steps = parse_script(script).unwrap();
+        assert_eq!(steps.len(), 3);
+
+        match &steps[0] {
+            Step::Wait(duration) => assert_eq!(*duration, Duration::from_millis(500)),
+            _ => panic!(),
+        }
+        match &steps[1] {
+            Step::Command(Command::Screenshot(path)) => {
+                assert_eq!(*path, PathBuf::from("foo bar.png"))
+            }
+            _ => panic!(),
+        }
+        match &steps[2] {
+            Step::Command(Command::Event(ffi::InputEvent::CloseRequested)) => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn script_error_line() {
+        let script = "wait 10\n# Comment\nwait ten\n";
+        match parse_script(script) {
+            Err((line, err)) => {
+                assert_eq!(line, 3);
+                assert_eq!(err, "invalid duration `ten`");
+            }
+            Ok(_) => panic!(),
+        }
+    }
+
+    #[test]
+    fn events() {
+        assert_eq!(
+            event(parse_step("key 30 pressed A").unwrap()),
+            ffi::InputEvent::Key {
+                scancode: 30,
+                key: Some(ffi::Key::A),
+                state: ffi::ElementState::Pressed,
+            }
+        );
+        assert_eq!(
+            event(parse_step("key 57 released").unwrap()),
+            ffi::InputEvent::Key {
+                scancode: 57,
+                key: None,
+                state: ffi::ElementState::Released,
+            }
+        );
+        assert_eq!(
+            event(parse_step("text hello  world").unwrap()),
+            ffi::InputEvent::Text("hello  world".to_owned())
+        );
+        assert_eq!(
+            event(parse_step("pointer-move 20 -30").unwrap()),
+            ffi::InputEvent::PointerMoved { x: 20, y: -30 }
+        );
+        assert_eq!(
+            event(parse_step("pointer-button 8 pressed").unwrap()),
+            ffi::InputEvent::PointerButton {
+                button: ffi::PointerButton::Other(8),
+                state: ffi::ElementState::Pressed,
+            }
+        );
+        assert_eq!(
+            event(parse_step("wheel-lines 0 -100").unwrap()),
+            ffi::InputEvent::PointerWheel(ffi::WheelDelta::Lines { x: 0, y: -100 })
+        );
+        assert_eq!(
+            event(parse_step("resized 800 600").unwrap()),
+            ffi::InputEvent::Resized {
+                width: 800,
+                height: 600,
+            }
+        );
+        assert_eq!(
+            event(parse_step("focus false").unwrap()),
+            ffi::InputEvent::Focus(false)
+        );
+    }
+
+    #[test]
+    fn step_errors() {
+        let err = |line| match parse_step(line) {
+            Err(err) => err,
+            Ok(_) => panic!(),
+        };
+
+        assert_eq!(err("jump"), "unknown command `jump`");
+        assert_eq!(err("screenshot"), "missing path");
+        assert_eq!(err("wait"), "missing duration");
+        assert_eq!(err("pointer-left now"), "unexpected `now`");
+        assert_eq!(err("key 30 down"), "expected `pressed` or `released`, got `down`");
+        assert_eq!(err("key 30 pressed Foo"), "unknown key `Foo`");
+        assert_eq!(err("pointer-button wheel pressed"), "invalid button `wheel`");
+    }
+
+    #[test]
+    fn key_names() {
+        assert_eq!(key_from_name("Enter"), Ok(ffi::Key::Enter));
+        assert_eq!(key_from_name("Digit0"), Ok(ffi::Key::Digit0));
+        assert_eq!(key_from_name("ArrowLeft"), Ok(ffi::Key::ArrowLeft));
+        assert!(key_from_name("enter").is_err());
+    }
+
+    #[test]
+    fn bgra_conversion() {
+        let mut framebuffer = Framebuffer::new(0, None, 2, 1, ffi::PixelFormat::Bgra32);
+        let rect = ffi::Rect {
+            x: 1,
+            y: 0,
+            width: 1,
+            height: 1,
+        };
+        framebuffer.write(rect, &[1, 2, 3, 4]);
+        assert_eq!(framebuffer.pixels, vec![0, 0, 0, 3, 2, 1]);
+    }
+
+    #[test]
+    fn image_files() {
+        let dir = std::env::temp_dir().join(format!(
+            "redshirt-framebuffer-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        let pixels = (0..2 * 3 * 3).collect::<Vec<u8>>();
+
+        let ppm = dir.join("image.ppm");
+        write_image(&ppm, 2, 3, &pixels).unwrap();
+        let mut expected = b"P6\n2 3\n255\n".to_vec();
+        expected.extend_from_slice(&pixels);
+        assert_eq!(fs::read(&ppm).unwrap(), expected);
+
+        let png = dir.join("image.png");
+        write_image(&png, 2, 3, &pixels).unwrap();
+        let decoder = png::Decoder::new(fs::File::open(&png).unwrap());
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!((info.width, info.height), (2, 3));
+        assert_eq!(info.color_type, png::ColorType::RGB);
+        let mut decoded = vec![0; info.buffer_size()];
+        reader.next_frame(&mut decoded).unwrap();
+        assert_eq!(decoded, pixels);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
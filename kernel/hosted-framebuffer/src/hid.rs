@@ -0,0 +1,290 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reports the keyboard and pointer input of the host on the hid interface.
+//!
+//! The input events that happen on the framebuffers, whether they come from the windows or from
+//! the input script, are translated into events of a keyboard and of a pointer. Keys are
+//! identified by their meaning according to the host's keyboard layout, as the host doesn't
+//! always report their position.
+
+use crate::FramebufferContext;
+
+use futures::{channel::mpsc, lock::Mutex as FutureMutex, prelude::*};
+use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
+use redshirt_core::{Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_framebuffer_interface::ffi;
+use redshirt_hid_interface::ffi as hid_ffi;
+use std::pin::Pin;
+
+/// Identifier of the keyboard towards the hid interface handler.
+const KEYBOARD_ID: u32 = 0;
+/// Identifier of the pointer towards the hid interface handler.
+const POINTER_ID: u32 = 1;
+
+/// Native program that reports the input of the host as a keyboard and a pointer on the hid
+/// interface.
+pub struct HidDriver {
+    /// Messages to emit on the hid interface.
+    from_context: FutureMutex<mpsc::UnboundedReceiver<EncodedMessage>>,
+}
+
+/// Translates the input events of the framebuffers and sends them to the [`HidDriver`]s.
+pub(crate) struct Forwarder {
+    to_drivers: Vec<mpsc::UnboundedSender<EncodedMessage>>,
+    /// Last position of the pointer within a framebuffer, used to calculate its movements.
+    /// `None` if the pointer isn't over any framebuffer.
+    pointer_position: Option<(i32, i32)>,
+}
+
+impl HidDriver {
+    /// Initializes the native program. It reports the input events that happen on the
+    /// framebuffers of the context passed as parameter.
+    pub fn new(ctxt: &FramebufferContext) -> Self {
+        let (to_driver, from_context) = mpsc::unbounded();
+
+        let devices = [
+            (KEYBOARD_ID, hid_ffi::DeviceKind::Keyboard, "Host keyboard"),
+            (POINTER_ID, hid_ffi::DeviceKind::Pointer, "Host pointer"),
+        ];
+        for (id, kind, name) in &devices {
+            let message = hid_ffi::HidMessage::RegisterDevice {
+                version: hid_ffi::PROTOCOL_VERSION,
+                id: *id,
+                kind: *kind,
+                name: name.to_string(),
+            };
+            to_driver.unbounded_send(message.encode()).unwrap();
+        }
+
+        ctxt.to_hid_drivers.lock().push(to_driver);
+
+        HidDriver {
+            from_context: FutureMutex::new(from_context),
+        }
+    }
+}
+
+impl<'a> NativeProgramRef<'a> for &'a HidDriver {
+    type Future =
+        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event(self) -> Self::Future {
+        Box::pin(async move {
+            let mut lock = self.from_context.lock().await;
+            let message = match lock.next().await {
+                Some(message) => message,
+                // The context has stopped, and no input will ever be reported again.
+                None => future::pending().await,
+            };
+            NativeProgramEvent::Emit {
+                interface: hid_ffi::INTERFACE,
+                message_id_write: None,
+                message,
+            }
+        })
+    }
+
+    fn interface_message(self, _: InterfaceHash, _: Option<MessageId>, _: Pid, _: EncodedMessage) {
+        unreachable!()
+    }
+
+    fn process_destroyed(self, _: Pid) {}
+
+    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
+
+impl Forwarder {
+    /// Creates a forwarder that sends the translated events to the given drivers.
+    pub(crate) fn new(to_drivers: Vec<mpsc::UnboundedSender<EncodedMessage>>) -> Self {
+        Forwarder {
+            to_drivers,
+            pointer_position: None,
+        }
+    }
+
+    /// Reports an input event that happened on a framebuffer.
+    pub(crate) fn forward(&mut self, event: &ffi::InputEvent) {
+        let (id, event) = match self.translate(event) {
+            Some(ev) => ev,
+            None => return,
+        };
+
+        let message = hid_ffi::HidMessage::ReportEvents {
+            id,
+            events: vec![event],
+        }
+        .encode();
+
+        self.to_drivers
+            .retain(|sender| sender.unbounded_send(message.clone()).is_ok());
+    }
+
+    /// Turns an input event of a framebuffer into an event of the keyboard or of the pointer.
+    /// `None` if there is no equivalent.
+    fn translate(&mut self, event: &ffi::InputEvent) -> Option<(u32, hid_ffi::DeviceEvent)> {
+        let button_state = |state: &ffi::ElementState| match state {
+            ffi::ElementState::Pressed => hid_ffi::ButtonState::Pressed,
+            ffi::ElementState::Released => hid_ffi::ButtonState::Released,
+        };
+
+        match event {
+            ffi::InputEvent::Key {
+                scancode,
+                key,
+                state,
+            } => Some((
+                KEYBOARD_ID,
+                hid_ffi::DeviceEvent::Key {
+                    usage: key.map(key_usage),
+                    scancode: *scancode,
+                    state: button_state(state),
+                },
+            )),
+            ffi::InputEvent::PointerMoved { x, y } => {
+                let previous = self.pointer_position.replace((*x, *y));
+                let (prev_x, prev_y) = previous?;
+                if (prev_x, prev_y) == (*x, *y) {
+                    return None;
+                }
+                Some((
+                    POINTER_ID,
+                    hid_ffi::DeviceEvent::PointerMotion {
+                        dx: x - prev_x,
+                        dy: y - prev_y,
+                    },
+                ))
+            }
+            ffi::InputEvent::PointerLeft => {
+                self.pointer_position = None;
+                None
+            }
+            ffi::InputEvent::PointerButton { button, state } => Some((
+                POINTER_ID,
+                hid_ffi::DeviceEvent::PointerButton {
+                    button: match button {
+                        ffi::PointerButton::Left => 0,
+                        ffi::PointerButton::Right => 1,
+                        ffi::PointerButton::Middle => 2,
+                        ffi::PointerButton::Other(n) => n.saturating_add(3),
+                    },
+                    state: button_state(state),
+                },
+            )),
+            // Lines and notches are both in hundredths and point in the same directions.
+            ffi::InputEvent::PointerWheel(ffi::WheelDelta::Lines { x, y }) => Some((
+                POINTER_ID,
+                hid_ffi::DeviceEvent::Wheel { dx: *x, dy: *y },
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the USB HID usage of the key at the position where a US keyboard has the given key.
+fn key_usage(key: ffi::Key) -> u16 {
+    match key {
+        ffi::Key::A => 0x04,
+        ffi::Key::B => 0x05,
+        ffi::Key::C => 0x06,
+        ffi::Key::D => 0x07,
+        ffi::Key::E => 0x08,
+        ffi::Key::F => 0x09,
+        ffi::Key::G => 0x0a,
+        ffi::Key::H => 0x0b,
+        ffi::Key::I => 0x0c,
+        ffi::Key::J => 0x0d,
+        ffi::Key::K => 0x0e,
+        ffi::Key::L => 0x0f,
+        ffi::Key::M => 0x10,
+        ffi::Key::N => 0x11,
+        ffi::Key::O => 0x12,
+        ffi::Key::P => 0x13,
+        ffi::Key::Q => 0x14,
+        ffi::Key::R => 0x15,
+        ffi::Key::S => 0x16,
+        ffi::Key::T => 0x17,
+        ffi::Key::U => 0x18,
+        ffi::Key::V => 0x19,
+        ffi::Key::W => 0x1a,
+        ffi::Key::X => 0x1b,
+        ffi::Key::Y => 0x1c,
+        ffi::Key::Z => 0x1d,
+        ffi::Key::Digit1 => 0x1e,
+        ffi::Key::Digit2 => 0x1f,
+        ffi::Key::Digit3 => 0x20,
+        ffi::Key::Digit4 => 0x21,
+        ffi::Key::Digit5 => 0x22,
+        ffi::Key::Digit6 => 0x23,
+        ffi::Key::Digit7 => 0x24,
+        ffi::Key::Digit8 => 0x25,
+        ffi::Key::Digit9 => 0x26,
+        ffi::Key::Digit0 => 0x27,
+        ffi::Key::Enter => 0x28,
+        ffi::Key::Escape => 0x29,
+        ffi::Key::Backspace => 0x2a,
+        ffi::Key::Tab => 0x2b,
+        ffi::Key::Space => 0x2c,
+        ffi::Key::Minus => 0x2d,
+        ffi::Key::Equal => 0x2e,
+        ffi::Key::BracketLeft => 0x2f,
+        ffi::Key::BracketRight => 0x30,
+        ffi::Key::Backslash => 0x31,
+        ffi::Key::Semicolon => 0x33,
+        ffi::Key::Quote => 0x34,
+        ffi::Key::Backquote => 0x35,
+        ffi::Key::Comma => 0x36,
+        ffi::Key::Period => 0x37,
+        ffi::Key::Slash => 0x38,
+        ffi::Key::CapsLock => 0x39,
+        ffi::Key::F1 => 0x3a,
+        ffi::Key::F2 => 0x3b,
+        ffi::Key::F3 => 0x3c,
+        ffi::Key::F4 => 0x3d,
+        ffi::Key::F5 => 0x3e,
+        ffi::Key::F6 => 0x3f,
+        ffi::Key::F7 => 0x40,
+        ffi::Key::F8 => 0x41,
+        ffi::Key::F9 => 0x42,
+        ffi::Key::F10 => 0x43,
+        ffi::Key::F11 => 0x44,
+        ffi::Key::F12 => 0x45,
+        ffi::Key::PrintScreen => 0x46,
+        ffi::Key::ScrollLock => 0x47,
+        ffi::Key::Pause => 0x48,
+        ffi::Key::Insert => 0x49,
+        ffi::Key::Home => 0x4a,
+        ffi::Key::PageUp => 0x4b,
+        ffi::Key::Delete => 0x4c,
+        ffi::Key::End => 0x4d,
+        ffi::Key::PageDown => 0x4e,
+        ffi::Key::ArrowRight => 0x4f,
+        ffi::Key::ArrowLeft => 0x50,
+        ffi::Key::ArrowDown => 0x51,
+        ffi::Key::ArrowUp => 0x52,
+        ffi::Key::NumLock => 0x53,
+        ffi::Key::ControlLeft => 0xe0,
+        ffi::Key::ShiftLeft => 0xe1,
+        ffi::Key::AltLeft => 0xe2,
+        ffi::Key::MetaLeft => 0xe3,
+        ffi::Key::ControlRight => 0xe4,
+        ffi::Key::ShiftRight => 0xe5,
+        ffi::Key::AltRight => 0xe6,
+        ffi::Key::MetaRight => 0xe7,
+    }
+}
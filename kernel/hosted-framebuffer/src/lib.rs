@@ -13,41 +13,72 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-//! Implements the framebuffer interface by displaying each framebuffer in a window.
+//! Implements the framebuffer interface by displaying each framebuffer in a window, or by keeping
+//! them in memory and writing them to image files.
 //!
 //! # Usage
 //!
 //! - Create a [`FramebufferContext`]. This represents a collection of all the windows and
-//! resources required to display the framebuffers.
+//! resources required to display the framebuffers. Use [`FramebufferContext::headless`] instead
+//! of [`FramebufferContext::new`] in order to not open any window. The context must be passed
+//! the [`Buffers`] of the system, through which processes send large frames.
 //! - Create a [`FramebufferHandler`], passing a reference to the context. This type can be used
 //! as a native process with the kernel.
+//! - Optionally, create a [`HidDriver`], passing a reference to the context. This type can be
+//! used as a native process that reports the input of the host on the hid interface.
 //! - Call [`FramebufferContext::run`] for it to take control of your application and start
 //! showing the framebuffers.
 //!
 
 use futures::{channel::mpsc, lock::Mutex as FutureMutex, prelude::*};
-use glium::glutin::event::{ElementState, Event, StartCause, WindowEvent};
-use glium::glutin::event_loop::{ControlFlow, EventLoop, EventLoopProxy, EventLoopWindowTarget};
+use glium::glutin::event::{
+    ElementState, Event, MouseButton, MouseScrollDelta, StartCause, VirtualKeyCode, WindowEvent,
+};
+use glium::glutin::event_loop::{ControlFlow, EventLoop, EventLoopProxy};
 use parking_lot::Mutex;
 use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
-use redshirt_core::{Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
-use redshirt_framebuffer_interface::ffi::INTERFACE;
+use redshirt_core::{
+    Buffers, Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid,
+};
+use redshirt_framebuffer_interface::ffi::{self, INTERFACE};
 use std::{
     collections::{hash_map::Entry, HashMap, VecDeque},
-    convert::TryFrom as _,
+    io,
     pin::Pin,
+    process,
     sync::{atomic, Arc},
     task::{Context, Poll},
 };
 
+pub use headless::HeadlessConfig;
+pub use hid::HidDriver;
+
 mod framebuffer;
+mod headless;
+mod hid;
+
+/// Maximum number of input events buffered for each framebuffer while the process isn't asking
+/// for them. Older events are discarded.
+const MAX_BUFFERED_EVENTS: usize = 256;
 
 /// Collection of all the resources required to display the framebuffers.
 pub struct FramebufferContext {
-    event_loop: EventLoop<()>,
+    backend: Backend,
     to_context: mpsc::UnboundedSender<HandlerToContext>,
     from_handler: mpsc::UnboundedReceiver<HandlerToContext>,
     to_handlers: Mutex<Vec<mpsc::UnboundedSender<ContextToHandler>>>,
+    /// Senders of the messages that the [`HidDriver`]s must emit.
+    to_hid_drivers: Mutex<Vec<mpsc::UnboundedSender<EncodedMessage>>>,
+    /// Buffers containing the pixels sent by processes.
+    buffers: Arc<Buffers>,
+}
+
+/// Where the framebuffers are shown.
+enum Backend {
+    /// Each framebuffer is shown in a window.
+    Windowed(EventLoop<()>),
+    /// Framebuffers are kept in memory.
+    Headless(headless::Headless),
 }
 
 /// Native program for `log` interface messages handling.
@@ -68,6 +99,36 @@ enum HandlerToContext {
     ProcessDestroyed(Pid),
 }
 
+/// Storage for the pixels of a framebuffer.
+trait Surface {
+    /// Sets the content of a rectangle within the framebuffer.
+    ///
+    /// The rectangle and the length of `data` have already been checked against the dimensions
+    /// and format of the framebuffer.
+    fn write(&mut self, rect: ffi::Rect, data: &[u8]);
+
+    /// Changes the dimensions of the framebuffer. The content is reset to black.
+    ///
+    /// The dimensions have already been checked with [`valid_dimensions`].
+    fn resize(&mut self, width: u32, height: u32);
+}
+
+/// Framebuffer and its input events.
+struct FramebufferState<S> {
+    /// Where the pixels are stored.
+    surface: S,
+    /// Width of the framebuffer in pixels.
+    width: u32,
+    /// Height of the framebuffer in pixels.
+    height: u32,
+    /// Format of the data sent by the process.
+    format: ffi::PixelFormat,
+    /// Messages asking for the next input event that haven't been answered yet.
+    pending_requests: VecDeque<MessageId>,
+    /// Input events that haven't been requested yet.
+    pending_events: VecDeque<ffi::InputEvent>,
+}
+
 /// Message from the context to the handler.
 enum ContextToHandler {
     /// A message answer is ready.
@@ -78,204 +139,364 @@ enum ContextToHandler {
 }
 
 impl FramebufferContext {
-    /// Creates a new context for the framebuffers.
-    pub fn new() -> FramebufferContext {
-        let event_loop = EventLoop::new();
+    /// Creates a new context where each framebuffer is shown in a window.
+    pub fn new(buffers: Arc<Buffers>) -> FramebufferContext {
+        FramebufferContext::with_backend(Backend::Windowed(EventLoop::new()), buffers)
+    }
+
+    /// Creates a new context where framebuffers are kept in memory instead of being shown.
+    ///
+    /// Returns an error if the input script of the configuration can't be loaded.
+    pub fn headless(
+        config: HeadlessConfig,
+        buffers: Arc<Buffers>,
+    ) -> io::Result<FramebufferContext> {
+        let headless = headless::Headless::new(config)?;
+        Ok(FramebufferContext::with_backend(Backend::Headless(headless), buffers))
+    }
+
+    fn with_backend(backend: Backend, buffers: Arc<Buffers>) -> FramebufferContext {
         let (to_context, from_handler) = mpsc::unbounded();
 
         FramebufferContext {
-            event_loop,
+            backend,
             to_context,
             from_handler,
             to_handlers: Mutex::new(Vec::new()),
+            to_hid_drivers: Mutex::new(Vec::new()),
+            buffers,
         }
     }
 
-    /// Runs the given future and processes the window's events loop.
+    /// Runs the given future and processes the window's events loop. Once the future has
+    /// finished, exits the process with the code that the future has produced.
+    ///
+    /// In headless mode, the framebuffers are written to the dump directory of the configuration
+    /// before exiting.
     ///
     /// > **Note**: The idea behind this function is to take control of the entire application.
-    /// >           In particular, the `Future` is expected to produce a value as a way to shut
-    /// >           down the entire program.
-    pub fn run<T: 'static>(self, future: impl Future<Output = T> + 'static) -> T {
-        // Futures waker that sends an event to the window when it is waken up.
-        let waker = {
-            let proxy = self.event_loop.create_proxy();
-
-            struct Waker(Mutex<EventLoopProxy<()>>);
-            impl futures::task::ArcWake for Waker {
-                fn wake_by_ref(arc_self: &Arc<Self>) {
-                    let _ = arc_self.0.lock().send_event(());
-                }
+    pub fn run(self, future: impl Future<Output = i32> + 'static) -> ! {
+        let to_handlers = self.to_handlers.into_inner();
+        let hid = hid::Forwarder::new(self.to_hid_drivers.into_inner());
+        match self.backend {
+            Backend::Windowed(event_loop) => run_windowed(
+                event_loop,
+                self.from_handler,
+                to_handlers,
+                hid,
+                self.buffers,
+                future,
+            ),
+            Backend::Headless(headless) => {
+                let code = headless.run(self.from_handler, to_handlers, hid, &self.buffers, future);
+                process::exit(code)
             }
+        }
+    }
+}
 
-            futures::task::waker(Arc::new(Waker(Mutex::new(proxy))))
-        };
+/// Implementation of [`FramebufferContext::run`] for windows.
+fn run_windowed(
+    event_loop: EventLoop<()>,
+    from_handler: mpsc::UnboundedReceiver<HandlerToContext>,
+    mut to_handlers: Vec<mpsc::UnboundedSender<ContextToHandler>>,
+    mut hid: hid::Forwarder,
+    buffers: Arc<Buffers>,
+    future: impl Future<Output = i32> + 'static,
+) -> ! {
+    // Futures waker that sends an event to the window when it is waken up.
+    let waker = {
+        let proxy = event_loop.create_proxy();
 
-        // Creates an implementation of the `Stream` trait that produces events.
-        enum LocalEvent<T> {
-            FromHandler(HandlerToContext),
-            FutureFinished(T),
+        struct Waker(Mutex<EventLoopProxy<()>>);
+        impl futures::task::ArcWake for Waker {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                let _ = arc_self.0.lock().send_event(());
+            }
         }
-        let mut stream = Box::pin({
-            let main_future = stream::once(async move { LocalEvent::FutureFinished(future.await) });
-            let receiver_events = self.from_handler.map(LocalEvent::FromHandler);
-            stream::select(main_future, receiver_events)
-        });
 
-        // Active list of framebuffers.
-        let mut framebuffers =
-            HashMap::<(Pid, u32), (framebuffer::Framebuffer, VecDeque<MessageId>)>::new();
-
-        // How to send messages to all the handlers.
-        let mut to_handlers = self.to_handlers.into_inner();
-
-        self.event_loop
-            .run(move |event, window_target, control_flow| {
-                match event {
-                    Event::RedrawRequested(window_id) => {
-                        framebuffers
-                            .values_mut()
-                            .find(|(fb, _)| fb.window_id() == window_id)
-                            .unwrap()
-                            .0
-                            .draw();
+        futures::task::waker(Arc::new(Waker(Mutex::new(proxy))))
+    };
+
+    // Creates an implementation of the `Stream` trait that produces events.
+    enum LocalEvent {
+        FromHandler(HandlerToContext),
+        FutureFinished(i32),
+    }
+    let mut stream = Box::pin({
+        let main_future = stream::once(async move { LocalEvent::FutureFinished(future.await) });
+        let receiver_events = from_handler.map(LocalEvent::FromHandler);
+        stream::select(main_future, receiver_events)
+    });
+
+    // Active list of framebuffers.
+    let mut framebuffers = HashMap::<(Pid, u32), FramebufferState<framebuffer::Framebuffer>>::new();
+
+    event_loop.run(move |event, window_target, control_flow| {
+        match event {
+            Event::RedrawRequested(window_id) => {
+                framebuffers
+                    .values_mut()
+                    .find(|fb| fb.surface.window_id() == window_id)
+                    .unwrap()
+                    .surface
+                    .draw();
+            }
+            Event::WindowEvent { window_id, event } => {
+                let state = match framebuffers
+                    .values_mut()
+                    .find(|fb| fb.surface.window_id() == window_id)
+                {
+                    Some(fb) => fb,
+                    None => return,
+                };
+
+                let scale_factor = state.surface.scale_factor();
+                if let Some(guest_event) = host_event_to_guest(&event, scale_factor) {
+                    hid.forward(&guest_event);
+                    if let Some((message_id, answer)) = state.push_event(guest_event) {
+                        send_answer(&mut to_handlers, message_id, Ok(answer));
                     }
-                    Event::WindowEvent { window_id, event } => {
-                        if let Some(guest_event) = host_event_to_guest(&event) {
-                            let framebuffer = framebuffers
-                                .values_mut()
-                                .find(|(fb, _)| fb.window_id() == window_id)
-                                .unwrap();
-                            if let Some(message_id) = framebuffer.1.pop_front() {
-                                to_handlers.retain(|sender| {
-                                    let msg = ContextToHandler::MessageAnswer {
-                                        message_id,
-                                        answer: Ok(guest_event.clone()),
-                                    };
-                                    sender.unbounded_send(msg).is_ok()
-                                });
-                            } else {
-                                // TODO: log something?
-                            }
-                        }
+                }
+            }
+            Event::RedrawEventsCleared => {
+                // The control flow is always set to `Wait`. What we want to achieve is
+                // wake up the events loop whenever the stream is ready by sending a
+                // dummy event to it.
+                *control_flow = ControlFlow::Wait;
+            }
+            Event::NewEvents(StartCause::Init) | Event::UserEvent(()) => {
+                let mut context = Context::from_waker(&waker);
+                while let Poll::Ready(ev) = stream.poll_next_unpin(&mut context) {
+                    let ev = match ev {
+                        Some(LocalEvent::FromHandler(ev)) => ev,
+                        Some(LocalEvent::FutureFinished(code)) => process::exit(code),
+                        // The stream can only end after the future has finished.
+                        None => unreachable!(),
+                    };
+
+                    match ev {
+                        HandlerToContext::InterfaceMessage {
+                            emitter_pid,
+                            message_id,
+                            message,
+                        } => {
+                            let answers = process_message(
+                                emitter_pid,
+                                message_id,
+                                message,
+                                &buffers,
+                                &mut framebuffers,
+                                |(pid, id), width, height, format| {
+                                    let title =
+                                        format!("redshirt - {:?} - framebuffer#{}", pid, id);
+                                    framebuffer::Framebuffer::new(
+                                        window_target,
+                                        &title,
+                                        width,
+                                        height,
+                                        format,
+                                    )
+                                },
+                            );
 
-                        match event {
-                            WindowEvent::CloseRequested | WindowEvent::Destroyed => {
-                                // TODO: kill process?
+                            for (message_id, answer) in answers {
+                                send_answer(&mut to_handlers, message_id, answer);
                             }
-                            _ => {}
                         }
-                    }
-                    Event::RedrawEventsCleared => {
-                        // The control flow is always set to `Wait`. What we want to achieve is
-                        // wake up the events loop whenever the stream is ready by sending a
-                        // dummy event to it.
-                        *control_flow = ControlFlow::Wait;
-                    }
-                    Event::NewEvents(StartCause::Init) | Event::UserEvent(()) => {
-                        let mut context = Context::from_waker(&waker);
-                        while let Poll::Ready(ev) = stream.poll_next_unpin(&mut context) {
-                            let ev = match ev {
-                                Some(LocalEvent::FromHandler(ev)) => ev,
-                                Some(LocalEvent::FutureFinished(_)) | None => {
-                                    *control_flow = ControlFlow::Exit;
-                                    return;
-                                }
-                            };
-
-                            match ev {
-                                HandlerToContext::InterfaceMessage {
-                                    emitter_pid,
-                                    message_id,
-                                    message,
-                                } => {
-                                    process_message(
-                                        emitter_pid,
-                                        message_id,
-                                        message,
-                                        &window_target,
-                                        &mut framebuffers,
-                                    );
-                                }
-                                HandlerToContext::ProcessDestroyed(pid) => {
-                                    framebuffers.retain(|(p, _), _| *p != pid)
-                                }
-                            }
+                        HandlerToContext::ProcessDestroyed(pid) => {
+                            framebuffers.retain(|(p, _), _| *p != pid)
                         }
                     }
-                    _ => {}
                 }
-            })
+            }
+            _ => {}
+        }
+    })
+}
+
+impl<S: Surface> FramebufferState<S> {
+    /// Checks the rectangle and the data against the framebuffer, then updates its content.
+    fn update(&mut self, rect: ffi::Rect, data: &[u8]) -> Result<(), ffi::FramebufferError> {
+        let right = rect.x.checked_add(rect.width);
+        let bottom = rect.y.checked_add(rect.height);
+        if right.map_or(true, |r| r > self.width) || bottom.map_or(true, |b| b > self.height) {
+            return Err(ffi::FramebufferError::OutOfBounds);
+        }
+
+        if Some(data.len()) != ffi::data_len(rect.width, rect.height, self.format) {
+            return Err(ffi::FramebufferError::InvalidDataLength);
+        }
+
+        self.surface.write(rect, data);
+        Ok(())
+    }
+
+    /// Delivers an input event to the framebuffer. If a message is waiting for an event, returns
+    /// its identifier and the answer to send back. Otherwise the event is buffered.
+    fn push_event(&mut self, event: ffi::InputEvent) -> Option<(MessageId, EncodedMessage)> {
+        if let Some(message_id) = self.pending_requests.pop_front() {
+            return Some((message_id, event.encode()));
+        }
+
+        if self.pending_events.len() >= MAX_BUFFERED_EVENTS {
+            self.pending_events.pop_front();
+        }
+        self.pending_events.push_back(event);
+        None
     }
 }
 
-fn process_message<T>(
+/// Sends an answer to a message to all the handlers, and removes the handlers that no longer
+/// exist.
+fn send_answer(
+    to_handlers: &mut Vec<mpsc::UnboundedSender<ContextToHandler>>,
+    message_id: MessageId,
+    answer: Result<EncodedMessage, ()>,
+) {
+    to_handlers.retain(|sender| {
+        let msg = ContextToHandler::MessageAnswer {
+            message_id,
+            answer: answer.clone(),
+        };
+        sender.unbounded_send(msg).is_ok()
+    });
+}
+
+/// Returns true if a framebuffer can have the given dimensions.
+fn valid_dimensions(width: u32, height: u32) -> bool {
+    // Textures larger than this are not guaranteed to be supported by graphics cards.
+    const MAX_DIMENSION: u32 = 8192;
+    width != 0 && height != 0 && width <= MAX_DIMENSION && height <= MAX_DIMENSION
+}
+
+/// Processes a message sent on the framebuffer interface. Returns the answers to send back,
+/// which include the answer to this message if it can be answered immediately.
+///
+/// `create_surface` is called with the emitter and identifier, the dimensions and the format of
+/// the framebuffer when a new one is created.
+///
+/// The buffers containing pixels are claimed from `buffers` even if the message is refused.
+fn process_message<S: Surface>(
     emitter_pid: Pid,
     message_id: Option<MessageId>,
     message: EncodedMessage,
-    window_target: &EventLoopWindowTarget<T>,
-    framebuffers: &mut HashMap<(Pid, u32), (framebuffer::Framebuffer, VecDeque<MessageId>)>,
-) {
-    if message.0.len() < 1 {
-        return;
-    }
+    buffers: &Buffers,
+    framebuffers: &mut HashMap<(Pid, u32), FramebufferState<S>>,
+    create_surface: impl FnOnce((Pid, u32), u32, u32, ffi::PixelFormat) -> S,
+) -> Vec<(MessageId, Result<EncodedMessage, ()>)> {
+    let mut answers = Vec::new();
 
-    match message.0[0] {
-        0 => {
-            // Create framebuffer message.
-            if message.0.len() != 13 {
-                return;
-            }
+    let message = match ffi::FramebufferMessage::decode(message) {
+        Ok(msg) => msg,
+        Err(_) => {
+            answers.extend(message_id.map(|message_id| (message_id, Err(()))));
+            return answers;
+        }
+    };
 
-            let fb_id = u32::from_le_bytes(<[u8; 4]>::try_from(&message.0[1..5]).unwrap());
-            let width = u32::from_le_bytes(<[u8; 4]>::try_from(&message.0[5..9]).unwrap());
-            let height = u32::from_le_bytes(<[u8; 4]>::try_from(&message.0[9..13]).unwrap());
-            if let Entry::Vacant(entry) = framebuffers.entry((emitter_pid, fb_id)) {
-                let title = format!("redshirt - {:?} - framebuffer#{}", emitter_pid, fb_id);
-                entry.insert((
-                    framebuffer::Framebuffer::new(window_target, &title, width, height),
-                    VecDeque::new(),
-                ));
+    let result = match message {
+        ffi::FramebufferMessage::Create {
+            version,
+            id,
+            width,
+            height,
+            format,
+        } => {
+            if version != ffi::PROTOCOL_VERSION {
+                Err(ffi::FramebufferError::UnsupportedVersion)
+            } else if !valid_dimensions(width, height) {
+                Err(ffi::FramebufferError::InvalidDimensions)
+            } else if let Entry::Vacant(entry) = framebuffers.entry((emitter_pid, id)) {
+                entry.insert(FramebufferState {
+                    surface: create_surface((emitter_pid, id), width, height, format),
+                    width,
+                    height,
+                    format,
+                    pending_requests: VecDeque::new(),
+                    pending_events: VecDeque::new(),
+                });
+                Ok(())
+            } else {
+                Err(ffi::FramebufferError::AlreadyExists)
             }
         }
-        1 => {
-            // Destroy framebuffer message.
-            if message.0.len() != 5 {
-                return;
+        ffi::FramebufferMessage::Destroy { id } => match framebuffers.remove(&(emitter_pid, id)) {
+            Some(state) => {
+                // No event will ever be delivered to the requests that are still pending.
+                answers.extend(
+                    state
+                        .pending_requests
+                        .into_iter()
+                        .map(|message_id| (message_id, Err(()))),
+                );
+                Ok(())
+            }
+            None => Err(ffi::FramebufferError::UnknownFramebuffer),
+        },
+        ffi::FramebufferMessage::SetData { id, data } => {
+            let data = buffers.take_payload(data);
+            match (framebuffers.get_mut(&(emitter_pid, id)), data) {
+                (Some(state), Ok(data)) => {
+                    let rect = ffi::Rect {
+                        x: 0,
+                        y: 0,
+                        width: state.width,
+                        height: state.height,
+                    };
+                    state.update(rect, &data)
+                }
+                (Some(_), Err(_)) => Err(ffi::FramebufferError::InvalidBuffer),
+                (None, _) => Err(ffi::FramebufferError::UnknownFramebuffer),
             }
-
-            let fb_id = u32::from_le_bytes(<[u8; 4]>::try_from(&message.0[1..5]).unwrap());
-            framebuffers.remove(&(emitter_pid, fb_id));
         }
-        2 => {
-            // Update framebuffer message.
-            if message.0.len() < 5 {
-                return;
+        ffi::FramebufferMessage::Update { id, rect, data } => {
+            let data = buffers.take_payload(data);
+            match (framebuffers.get_mut(&(emitter_pid, id)), data) {
+                (Some(state), Ok(data)) => state.update(rect, &data),
+                (Some(_), Err(_)) => Err(ffi::FramebufferError::InvalidBuffer),
+                (None, _) => Err(ffi::FramebufferError::UnknownFramebuffer),
             }
-
-            let fb_id = u32::from_le_bytes(<[u8; 4]>::try_from(&message.0[1..5]).unwrap());
-            let framebuffer = match framebuffers.get_mut(&(emitter_pid, fb_id)) {
-                Some(fb) => &mut fb.0,
-                None => return,
-            };
-
-            framebuffer.set_data(&message.0[5..]);
         }
-        3 => {
-            if let Some(message_id) = message_id {
-                // Ask for the next input event message.
-                if message.0.len() != 5 {
-                    return;
+        ffi::FramebufferMessage::Resize { id, width, height } => {
+            match framebuffers.get_mut(&(emitter_pid, id)) {
+                Some(_) if !valid_dimensions(width, height) => {
+                    Err(ffi::FramebufferError::InvalidDimensions)
                 }
+                Some(state) => {
+                    state.surface.resize(width, height);
+                    state.width = width;
+                    state.height = height;
+                    Ok(())
+                }
+                None => Err(ffi::FramebufferError::UnknownFramebuffer),
+            }
+        }
+        ffi::FramebufferMessage::NextEvent { id } => {
+            let message_id = match message_id {
+                Some(message_id) => message_id,
+                None => return answers,
+            };
 
-                let fb_id = u32::from_le_bytes(<[u8; 4]>::try_from(&message.0[1..5]).unwrap());
-                if let Some((_, messages)) = framebuffers.get_mut(&(emitter_pid, fb_id)) {
-                    messages.push_back(message_id);
+            match framebuffers.get_mut(&(emitter_pid, id)) {
+                Some(state) => {
+                    if let Some(event) = state.pending_events.pop_front() {
+                        answers.push((message_id, Ok(event.encode())));
+                    } else {
+                        state.pending_requests.push_back(message_id);
+                    }
                 }
+                None => answers.push((message_id, Err(()))),
             }
+
+            return answers;
         }
-        _ => {} // TODO:
+    };
+
+    if let Some(message_id) = message_id {
+        answers.push((message_id, Ok(result.encode())));
     }
+    answers
 }
 
 impl FramebufferHandler {
@@ -313,14 +534,13 @@ impl<'a> NativeProgramRef<'a> for &'a FramebufferHandler {
                 };
             }
 
-            loop {
-                let mut lock = self.from_context.lock().await;
-                // TODO: document the behaviour of this unwrap()
-                match lock.next().await.unwrap() {
-                    ContextToHandler::MessageAnswer { message_id, answer } => {
-                        return NativeProgramEvent::Answer { message_id, answer };
-                    }
+            let mut lock = self.from_context.lock().await;
+            match lock.next().await {
+                Some(ContextToHandler::MessageAnswer { message_id, answer }) => {
+                    NativeProgramEvent::Answer { message_id, answer }
                 }
+                // The context has stopped, and no answer will ever come.
+                None => future::pending().await,
             }
         })
     }
@@ -333,19 +553,19 @@ impl<'a> NativeProgramRef<'a> for &'a FramebufferHandler {
         message: EncodedMessage,
     ) {
         debug_assert_eq!(interface, INTERFACE);
-        self.to_context
-            .unbounded_send(HandlerToContext::InterfaceMessage {
-                emitter_pid,
-                message_id,
-                message,
-            })
-            .unwrap(); // TODO: document the behaviour of this unwrap()
+        // An error happens if the context has stopped, in which case the message will never be
+        // answered.
+        let _ = self.to_context.unbounded_send(HandlerToContext::InterfaceMessage {
+            emitter_pid,
+            message_id,
+            message,
+        });
     }
 
     fn process_destroyed(self, pid: Pid) {
-        self.to_context
-            .unbounded_send(HandlerToContext::ProcessDestroyed(pid))
-            .unwrap(); // TODO: document the behaviour of this unwrap()
+        // An error happens if the context has stopped, in which case there is nothing to clean
+        // up.
+        let _ = self.to_context.unbounded_send(HandlerToContext::ProcessDestroyed(pid));
     }
 
     fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
@@ -354,23 +574,465 @@ impl<'a> NativeProgramRef<'a> for &'a FramebufferHandler {
 }
 
 /// Turns a windowing event from the host format to the guest. `None` if there is no equivalent.
-fn host_event_to_guest(ev: &WindowEvent) -> Option<EncodedMessage> {
+///
+/// Positions and sizes are divided by `scale_factor` in order to be in framebuffer pixels.
+fn host_event_to_guest(ev: &WindowEvent, scale_factor: f64) -> Option<ffi::InputEvent> {
+    let element_state = |state: &ElementState| match state {
+        ElementState::Pressed => ffi::ElementState::Pressed,
+        ElementState::Released => ffi::ElementState::Released,
+    };
+
     match ev {
-        WindowEvent::KeyboardInput { input, .. } => {
-            if let Some(keycode) = input.virtual_keycode {
-                let first_byte = match input.state {
-                    ElementState::Pressed => 1,
-                    ElementState::Released => 0,
-                };
+        WindowEvent::KeyboardInput { input, .. } => Some(ffi::InputEvent::Key {
+            scancode: input.scancode,
+            key: input.virtual_keycode.and_then(host_key_to_guest),
+            state: element_state(&input.state),
+        }),
+        WindowEvent::ReceivedCharacter(chr) if !chr.is_control() => {
+            Some(ffi::InputEvent::Text(chr.to_string()))
+        }
+        WindowEvent::CursorMoved { position, .. } => Some(ffi::InputEvent::PointerMoved {
+            x: (position.x / scale_factor) as i32,
+            y: (position.y / scale_factor) as i32,
+        }),
+        WindowEvent::CursorLeft { .. } => Some(ffi::InputEvent::PointerLeft),
+        WindowEvent::MouseInput { state, button, .. } => Some(ffi::InputEvent::PointerButton {
+            button: match button {
+                MouseButton::Left => ffi::PointerButton::Left,
+                MouseButton::Right => ffi::PointerButton::Right,
+                MouseButton::Middle => ffi::PointerButton::Middle,
+                MouseButton::Other(n) => ffi::PointerButton::Other(*n),
+            },
+            state: element_state(state),
+        }),
+        WindowEvent::MouseWheel { delta, .. } => {
+            Some(ffi::InputEvent::PointerWheel(match delta {
+                MouseScrollDelta::LineDelta(x, y) => ffi::WheelDelta::Lines {
+                    x: (x * 100.0) as i32,
+                    y: (y * 100.0) as i32,
+                },
+                MouseScrollDelta::PixelDelta(pos) => ffi::WheelDelta::Pixels {
+                    x: pos.x as i32,
+                    y: pos.y as i32,
+                },
+            }))
+        }
+        WindowEvent::Resized(size) => Some(ffi::InputEvent::Resized {
+            width: (f64::from(size.width) / scale_factor) as u32,
+            height: (f64::from(size.height) / scale_factor) as u32,
+        }),
+        WindowEvent::Focused(focused) => Some(ffi::InputEvent::Focus(*focused)),
+        WindowEvent::CloseRequested => Some(ffi::InputEvent::CloseRequested),
+        _ => None,
+    }
+}
 
-                let rest = (keycode as u32).to_le_bytes();
-                Some(EncodedMessage(vec![
-                    first_byte, rest[0], rest[1], rest[2], rest[3],
-                ]))
-            } else {
-                None
+/// Turns a key code from the host format to the guest. `None` if there is no equivalent.
+fn host_key_to_guest(key: VirtualKeyCode) -> Option<ffi::Key> {
+    Some(match key {
+        VirtualKeyCode::A => ffi::Key::A,
+        VirtualKeyCode::B => ffi::Key::B,
+        VirtualKeyCode::C => ffi::Key::C,
+        VirtualKeyCode::D => ffi::Key::D,
+        VirtualKeyCode::E => ffi::Key::E,
+        VirtualKeyCode::F => ffi::Key::F,
+        VirtualKeyCode::G => ffi::Key::G,
+        VirtualKeyCode::H => ffi::Key::H,
+        VirtualKeyCode::I => ffi::Key::I,
+        VirtualKeyCode::J => ffi::Key::J,
+        VirtualKeyCode::K => ffi::Key::K,
+        VirtualKeyCode::L => ffi::Key::L,
+        VirtualKeyCode::M => ffi::Key::M,
+        VirtualKeyCode::N => ffi::Key::N,
+        VirtualKeyCode::O => ffi::Key::O,
+        VirtualKeyCode::P => ffi::Key::P,
+        VirtualKeyCode::Q => ffi::Key::Q,
+        VirtualKeyCode::R => ffi::Key::R,
+        VirtualKeyCode::S => ffi::Key::S,
+        VirtualKeyCode::T => ffi::Key::T,
+        VirtualKeyCode::U => ffi::Key::U,
+        VirtualKeyCode::V => ffi::Key::V,
+        VirtualKeyCode::W => ffi::Key::W,
+        VirtualKeyCode::X => ffi::Key::X,
+        VirtualKeyCode::Y => ffi::Key::Y,
+        VirtualKeyCode::Z => ffi::Key::Z,
+        VirtualKeyCode::Key0 => ffi::Key::Digit0,
+        VirtualKeyCode::Key1 => ffi::Key::Digit1,
+        VirtualKeyCode::Key2 => ffi::Key::Digit2,
+        VirtualKeyCode::Key3 => ffi::Key::Digit3,
+        VirtualKeyCode::Key4 => ffi::Key::Digit4,
+        VirtualKeyCode::Key5 => ffi::Key::Digit5,
+        VirtualKeyCode::Key6 => ffi::Key::Digit6,
+        VirtualKeyCode::Key7 => ffi::Key::Digit7,
+        VirtualKeyCode::Key8 => ffi::Key::Digit8,
+        VirtualKeyCode::Key9 => ffi::Key::Digit9,
+        VirtualKeyCode::F1 => ffi::Key::F1,
+        VirtualKeyCode::F2 => ffi::Key::F2,
+        VirtualKeyCode::F3 => ffi::Key::F3,
+        VirtualKeyCode::F4 => ffi::Key::F4,
+        VirtualKeyCode::F5 => ffi::Key::F5,
+        VirtualKeyCode::F6 => ffi::Key::F6,
+        VirtualKeyCode::F7 => ffi::Key::F7,
+        VirtualKeyCode::F8 => ffi::Key::F8,
+        VirtualKeyCode::F9 => ffi::Key::F9,
+        VirtualKeyCode::F10 => ffi::Key::F10,
+        VirtualKeyCode::F11 => ffi::Key::F11,
+        VirtualKeyCode::F12 => ffi::Key::F12,
+        VirtualKeyCode::Escape => ffi::Key::Escape,
+        VirtualKeyCode::Return => ffi::Key::Enter,
+        VirtualKeyCode::Tab => ffi::Key::Tab,
+        VirtualKeyCode::Back => ffi::Key::Backspace,
+        VirtualKeyCode::Space => ffi::Key::Space,
+        VirtualKeyCode::Insert => ffi::Key::Insert,
+        VirtualKeyCode::Delete => ffi::Key::Delete,
+        VirtualKeyCode::Home => ffi::Key::Home,
+        VirtualKeyCode::End => ffi::Key::End,
+        VirtualKeyCode::PageUp => ffi::Key::PageUp,
+        VirtualKeyCode::PageDown => ffi::Key::PageDown,
+        VirtualKeyCode::Left => ffi::Key::ArrowLeft,
+        VirtualKeyCode::Right => ffi::Key::ArrowRight,
+        VirtualKeyCode::Up => ffi::Key::ArrowUp,
+        VirtualKeyCode::Down => ffi::Key::ArrowDown,
+        VirtualKeyCode::LShift => ffi::Key::ShiftLeft,
+        VirtualKeyCode::RShift => ffi::Key::ShiftRight,
+        VirtualKeyCode::LControl => ffi::Key::ControlLeft,
+        VirtualKeyCode::RControl => ffi::Key::ControlRight,
+        VirtualKeyCode::LAlt => ffi::Key::AltLeft,
+        VirtualKeyCode::RAlt => ffi::Key::AltRight,
+        VirtualKeyCode::LWin => ffi::Key::MetaLeft,
+        VirtualKeyCode::RWin => ffi::Key::MetaRight,
+        VirtualKeyCode::Capital => ffi::Key::CapsLock,
+        VirtualKeyCode::Numlock => ffi::Key::NumLock,
+        VirtualKeyCode::Scroll => ffi::Key::ScrollLock,
+        VirtualKeyCode::Snapshot => ffi::Key::PrintScreen,
+        VirtualKeyCode::Pause => ffi::Key::Pause,
+        VirtualKeyCode::Minus => ffi::Key::Minus,
+        VirtualKeyCode::Equals => ffi::Key::Equal,
+        VirtualKeyCode::LBracket => ffi::Key::BracketLeft,
+        VirtualKeyCode::RBracket => ffi::Key::BracketRight,
+        VirtualKeyCode::Backslash => ffi::Key::Backslash,
+        VirtualKeyCode::Semicolon => ffi::Key::Semicolon,
+        VirtualKeyCode::Apostrophe => ffi::Key::Quote,
+        VirtualKeyCode::Grave => ffi::Key::Backquote,
+        VirtualKeyCode::Comma => ffi::Key::Comma,
+        VirtualKeyCode::Period => ffi::Key::Period,
+        VirtualKeyCode::Slash => ffi::Key::Slash,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{process_message, FramebufferState, Surface};
+    use redshirt_core::{Buffers, Decode as _, Encode as _, EncodedMessage, MessageId, Pid};
+    use redshirt_framebuffer_interface::ffi;
+    use std::{collections::HashMap, convert::TryFrom as _};
+
+    /// Surface that records what is written to it.
+    struct TestSurface {
+        writes: Vec<(ffi::Rect, Vec<u8>)>,
+    }
+
+    impl Surface for TestSurface {
+        fn write(&mut self, rect: ffi::Rect, data: &[u8]) {
+            self.writes.push((rect, data.to_vec()));
+        }
+
+        fn resize(&mut self, _: u32, _: u32) {
+            self.writes.clear();
+        }
+    }
+
+    /// Framebuffers and buffers of a test.
+    struct Test {
+        buffers: Buffers,
+        framebuffers: HashMap<(Pid, u32), FramebufferState<TestSurface>>,
+        next_message_id: u64,
+    }
+
+    impl Test {
+        fn new() -> Self {
+            Test {
+                buffers: Buffers::new(),
+                framebuffers: HashMap::new(),
+                next_message_id: 1,
             }
         }
-        _ => None,
+
+        fn message_id(&mut self) -> MessageId {
+            let id = MessageId::try_from(self.next_message_id).unwrap();
+            self.next_message_id += 1;
+            id
+        }
+
+        /// Sends a message that doesn't expect an answer, and returns the answers to other
+        /// messages.
+        fn send(
+            &mut self,
+            pid: Pid,
+            message: ffi::FramebufferMessage,
+        ) -> Vec<(MessageId, Result<EncodedMessage, ()>)> {
+            process_message(
+                pid,
+                None,
+                message.encode(),
+                &self.buffers,
+                &mut self.framebuffers,
+                |_, _, _, _| TestSurface { writes: Vec::new() },
+            )
+        }
+
+        /// Sends a message that expects a `Result<(), FramebufferError>` as answer, and returns
+        /// this answer.
+        fn request(
+            &mut self,
+            pid: Pid,
+            message: ffi::FramebufferMessage,
+        ) -> Result<(), ffi::FramebufferError> {
+            let message_id = self.message_id();
+            let answers = process_message(
+                pid,
+                Some(message_id),
+                message.encode(),
+                &self.buffers,
+                &mut self.framebuffers,
+                |_, _, _, _| TestSurface { writes: Vec::new() },
+            );
+            assert_eq!(answers.len(), 1);
+            assert_eq!(answers[0].0, message_id);
+            Result::<(), ffi::FramebufferError>::decode(answers[0].1.clone().unwrap()).unwrap()
+        }
+    }
+
+    fn create(id: u32, width: u32, height: u32) -> ffi::FramebufferMessage {
+        ffi::FramebufferMessage::Create {
+            version: ffi::PROTOCOL_VERSION,
+            id,
+            width,
+            height,
+            format: ffi::PixelFormat::Rgb24,
+        }
+    }
+
+    #[test]
+    fn create_and_destroy() {
+        let mut test = Test::new();
+        let pid = Pid::from(1u64);
+
+        assert_eq!(test.request(pid, create(5, 4, 2)), Ok(()));
+        assert_eq!(
+            test.request(pid, create(5, 4, 2)),
+            Err(ffi::FramebufferError::AlreadyExists)
+        );
+        // Identifiers are specific to each process.
+        assert_eq!(test.request(Pid::from(2u64), create(5, 4, 2)), Ok(()));
+
+        let destroy = ffi::FramebufferMessage::Destroy { id: 5 };
+        assert_eq!(test.request(pid, destroy.clone()), Ok(()));
+        assert_eq!(
+            test.request(pid, destroy),
+            Err(ffi::FramebufferError::UnknownFramebuffer)
+        );
+        assert_eq!(test.framebuffers.len(), 1);
+    }
+
+    #[test]
+    fn create_refused() {
+        let mut test = Test::new();
+        let pid = Pid::from(1u64);
+
+        let message = ffi::FramebufferMessage::Create {
+            version: ffi::PROTOCOL_VERSION + 1,
+            id: 0,
+            width: 4,
+            height: 2,
+            format: ffi::PixelFormat::Rgb24,
+        };
+        assert_eq!(
+            test.request(pid, message),
+            Err(ffi::FramebufferError::UnsupportedVersion)
+        );
+        assert_eq!(
+            test.request(pid, create(0, 0, 2)),
+            Err(ffi::FramebufferError::InvalidDimensions)
+        );
+        assert!(test.framebuffers.is_empty());
+    }
+
+    #[test]
+    fn update() {
+        let mut test = Test::new();
+        let pid = Pid::from(1u64);
+        assert_eq!(test.request(pid, create(0, 4, 2)), Ok(()));
+
+        let rect = ffi::Rect {
+            x: 1,
+            y: 1,
+            width: 3,
+            height: 1,
+        };
+        let data = test.buffers.make_payload(pid, vec![0xaa; 9]);
+        let message = ffi::FramebufferMessage::Update { id: 0, rect, data };
+        assert_eq!(test.request(pid, message), Ok(()));
+
+        let data = test.buffers.make_payload(pid, vec![0xaa; 8]);
+        let message = ffi::FramebufferMessage::Update { id: 0, rect, data };
+        assert_eq!(
+            test.request(pid, message),
+            Err(ffi::FramebufferError::InvalidDataLength)
+        );
+
+        let rect = ffi::Rect {
+            x: 2,
+            y: 1,
+            width: 3,
+            height: 1,
+        };
+        let data = test.buffers.make_payload(pid, vec![0xaa; 9]);
+        let message = ffi::FramebufferMessage::Update { id: 0, rect, data };
+        assert_eq!(
+            test.request(pid, message),
+            Err(ffi::FramebufferError::OutOfBounds)
+        );
+
+        let data = test.buffers.make_payload(pid, vec![0xaa; 24]);
+        let message = ffi::FramebufferMessage::SetData { id: 0, data };
+        assert_eq!(test.request(pid, message), Ok(()));
+
+        let writes = &test.framebuffers[&(pid, 0)].surface.writes;
+        assert_eq!(writes.len(), 2);
+        assert_eq!(writes[0].0.x, 1);
+        assert_eq!(writes[0].1.len(), 9);
+        assert_eq!(writes[1].0.width, 4);
+        assert_eq!(writes[1].1.len(), 24);
+    }
+
+    #[test]
+    fn resize() {
+        let mut test = Test::new();
+        let pid = Pid::from(1u64);
+        assert_eq!(test.request(pid, create(0, 4, 2)), Ok(()));
+
+        let message = ffi::FramebufferMessage::Resize {
+            id: 0,
+            width: 0,
+            height: 8,
+        };
+        assert_eq!(
+            test.request(pid, message),
+            Err(ffi::FramebufferError::InvalidDimensions)
+        );
+
+        let message = ffi::FramebufferMessage::Resize {
+            id: 0,
+            width: 8,
+            height: 8,
+        };
+        assert_eq!(test.request(pid, message), Ok(()));
+
+        // The whole framebuffer must now be 8x8 pixels.
+        let data = test.buffers.make_payload(pid, vec![0xaa; 24]);
+        let message = ffi::FramebufferMessage::SetData { id: 0, data };
+        assert_eq!(
+            test.request(pid, message),
+            Err(ffi::FramebufferError::InvalidDataLength)
+        );
+    }
+
+    #[test]
+    fn next_event() {
+        let mut test = Test::new();
+        let pid = Pid::from(1u64);
+        assert_eq!(test.request(pid, create(0, 4, 2)), Ok(()));
+
+        // Events that happen before being requested are buffered.
+        let state = test.framebuffers.get_mut(&(pid, 0)).unwrap();
+        assert!(state.push_event(ffi::InputEvent::Focus(true)).is_none());
+
+        let message_id = test.message_id();
+        let answers = process_message(
+            pid,
+            Some(message_id),
+            ffi::FramebufferMessage::NextEvent { id: 0 }.encode(),
+            &test.buffers,
+            &mut test.framebuffers,
+            |_, _, _, _| unreachable!(),
+        );
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].0, message_id);
+        let event = ffi::InputEvent::decode(answers[0].1.clone().unwrap()).unwrap();
+        assert_eq!(event, ffi::InputEvent::Focus(true));
+
+        // Requests that happen before the event are answered when the event happens.
+        let message_id = test.message_id();
+        let answers = process_message(
+            pid,
+            Some(message_id),
+            ffi::FramebufferMessage::NextEvent { id: 0 }.encode(),
+            &test.buffers,
+            &mut test.framebuffers,
+            |_, _, _, _| unreachable!(),
+        );
+        assert!(answers.is_empty());
+
+        let state = test.framebuffers.get_mut(&(pid, 0)).unwrap();
+        let (answered_id, answer) = state.push_event(ffi::InputEvent::PointerLeft).unwrap();
+        assert_eq!(answered_id, message_id);
+        let event = ffi::InputEvent::decode(answer).unwrap();
+        assert_eq!(event, ffi::InputEvent::PointerLeft);
+    }
+
+    #[test]
+    fn destroy_answers_pending_next_event() {
+        let mut test = Test::new();
+        let pid = Pid::from(1u64);
+        assert_eq!(test.request(pid, create(0, 4, 2)), Ok(()));
+
+        let message_id = test.message_id();
+        let answers = process_message(
+            pid,
+            Some(message_id),
+            ffi::FramebufferMessage::NextEvent { id: 0 }.encode(),
+            &test.buffers,
+            &mut test.framebuffers,
+            |_, _, _, _| unreachable!(),
+        );
+        assert!(answers.is_empty());
+
+        let answers = test.send(pid, ffi::FramebufferMessage::Destroy { id: 0 });
+        assert_eq!(answers, vec![(message_id, Err(()))]);
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let mut test = Test::new();
+        let pid = Pid::from(1u64);
+
+        let message_id = test.message_id();
+        let answers = process_message(
+            pid,
+            Some(message_id),
+            EncodedMessage(vec![0xff, 0xff]),
+            &test.buffers,
+            &mut test.framebuffers,
+            |_, _, _, _| unreachable!(),
+        );
+        assert_eq!(answers, vec![(message_id, Err(()))]);
+
+        let message_id = test.message_id();
+        let answers = process_message(
+            pid,
+            Some(message_id),
+            ffi::FramebufferMessage::NextEvent { id: 3 }.encode(),
+            &test.buffers,
+            &mut test.framebuffers,
+            |_, _, _, _| unreachable!(),
+        );
+        assert_eq!(answers, vec![(message_id, Err(()))]);
+
+        // Messages that don't expect an answer are silently ignored.
+        assert!(test
+            .send(pid, ffi::FramebufferMessage::Destroy { id: 3 })
+            .is_empty());
     }
 }
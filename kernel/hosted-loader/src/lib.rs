@@ -0,0 +1,397 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the loader interface by reading modules from a directory of the local disk.
+//!
+//! Modules are stored in files named after the base58 encoding of their hash, with a `.wasm`
+//! extension. The signature of a module, if any, is stored next to it in a file with the same
+//! name and a `.sig` extension, containing the 32 bytes of the public key of the publisher
+//! followed with the 64 bytes of the signature.
+//!
+//! If the fallback is enabled, requests for modules that can't be found locally are forwarded to
+//! the handler of the [`FALLBACK_INTERFACE`](redshirt_loader_interface::ffi::FALLBACK_INTERFACE)
+//! interface, and the modules it returns are written to the directory.
+
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
+use redshirt_core::module::ModuleHash;
+use redshirt_core::native::{NativeProgramEvent, NativeProgramMessageIdWrite, NativeProgramRef};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_loader_interface::ffi::{
+    LoadResponse, LoaderMessage, PublisherSignature, FALLBACK_INTERFACE, INTERFACE,
+};
+use std::{collections::HashMap, fs, path::PathBuf, pin::Pin, sync::atomic};
+
+/// Native program for `loader` interface messages handling.
+pub struct LocalLoader {
+    /// Directory where modules are stored.
+    directory: PathBuf,
+    /// If true, modules that aren't in `directory` are requested from the fallback loader.
+    fallback: bool,
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Sending side of `events_rx`.
+    events_tx: mpsc::UnboundedSender<Event>,
+    /// Receives the events to report from `next_event`.
+    events_rx: Mutex<mpsc::UnboundedReceiver<Event>>,
+    /// Messages sent to the fallback loader, with the message that has been forwarded and the
+    /// requested hash.
+    forwarded: parking_lot::Mutex<HashMap<MessageId, (MessageId, ModuleHash)>>,
+}
+
+/// Event to report from [`LocalLoader::next_event`].
+enum Event {
+    /// Answer the given message.
+    Answer(MessageId, Result<EncodedMessage, ()>),
+    /// Request the given module from the fallback loader, then answer the given message.
+    Forward(MessageId, ModuleHash),
+}
+
+/// Implementation of [`NativeProgramMessageIdWrite`] for messages sent to the fallback loader.
+pub struct ForwardMessageIdWrite<'a> {
+    loader: &'a LocalLoader,
+    /// Message that has been forwarded.
+    original: MessageId,
+    /// Hash that has been requested.
+    hash: ModuleHash,
+}
+
+impl LocalLoader {
+    /// Initializes a loader that loads modules from the given directory.
+    ///
+    /// If `fallback` is true, modules that can't be found are requested from the fallback
+    /// loader, and the directory acts as a cache.
+    pub fn new(directory: impl Into<PathBuf>, fallback: bool) -> Self {
+        let (events_tx, events_rx) = mpsc::unbounded();
+
+        LocalLoader {
+            directory: directory.into(),
+            fallback,
+            registered: atomic::AtomicBool::new(false),
+            events_tx,
+            events_rx: Mutex::new(events_rx),
+            forwarded: parking_lot::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the path of the file containing the module with the given hash.
+    fn path(&self, hash: &ModuleHash) -> PathBuf {
+        self.directory.join(format!("{}.wasm", hash))
+    }
+
+    /// Returns the path of the file containing the signature of the module with the given hash.
+    fn signature_path(&self, hash: &ModuleHash) -> PathBuf {
+        self.directory.join(format!("{}.sig", hash))
+    }
+
+    /// Reads the signature of the module with the given hash from the directory, if any.
+    fn read_signature(&self, hash: &ModuleHash) -> Option<PublisherSignature> {
+        let data = fs::read(self.signature_path(hash)).ok()?;
+        if data.len() != 96 {
+            return None;
+        }
+
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(&data[..32]);
+        Some(PublisherSignature {
+            public_key,
+            signature: data[32..].to_vec(),
+        })
+    }
+
+    /// Reads the module with the given hash from the directory. Returns `None` if the module
+    /// isn't there, or if the file doesn't match the hash.
+    fn read(&self, hash: &ModuleHash) -> Option<Vec<u8>> {
+        let data = fs::read(self.path(hash)).ok()?;
+        if ModuleHash::from_bytes(&data) == *hash {
+            Some(data)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> NativeProgramRef<'a> for &'a LocalLoader {
+    type Future =
+        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+    type MessageIdWrite = ForwardMessageIdWrite<'a>;
+
+    fn next_event(self) -> Self::Future {
+        Box::pin(async move {
+            if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+                return NativeProgramEvent::Emit {
+                    interface: redshirt_interface_interface::ffi::INTERFACE,
+                    message_id_write: None,
+                    message: redshirt_interface_interface::ffi::InterfaceMessage::Register(
+                        INTERFACE,
+                    )
+                    .encode(),
+                };
+            }
+
+            let mut events_rx = self.events_rx.lock().await;
+            match events_rx.next().await {
+                Some(Event::Answer(message_id, answer)) => {
+                    NativeProgramEvent::Answer { message_id, answer }
+                }
+                Some(Event::Forward(original, hash)) => NativeProgramEvent::Emit {
+                    interface: FALLBACK_INTERFACE,
+                    message_id_write: Some(ForwardMessageIdWrite {
+                        loader: self,
+                        original,
+                        hash: hash.clone(),
+                    }),
+                    message: LoaderMessage::Load(From::from(hash)).encode(),
+                },
+                // We hold a sender in `self`.
+                None => unreachable!(),
+            }
+        })
+    }
+
+    fn interface_message(
+        self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        _: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let message_id = match message_id {
+            Some(m) => m,
+            None => return,
+        };
+
+        let hash = match LoaderMessage::decode(message) {
+            Ok(LoaderMessage::Load(hash)) => ModuleHash::from(hash),
+            Err(_) => {
+                let _ = self
+                    .events_tx
+                    .unbounded_send(Event::Answer(message_id, Err(())));
+                return;
+            }
+        };
+
+        let event = match self.read(&hash) {
+            Some(data) => {
+                let response = LoadResponse {
+                    result: Ok(data),
+                    signature: self.read_signature(&hash),
+                };
+                Event::Answer(message_id, Ok(response.encode()))
+            }
+            None if self.fallback => Event::Forward(message_id, hash),
+            None => {
+                let response = LoadResponse {
+                    result: Err(()),
+                    signature: None,
+                };
+                Event::Answer(message_id, Ok(response.encode()))
+            }
+        };
+
+        let _ = self.events_tx.unbounded_send(event);
+    }
+
+    fn process_destroyed(self, _: Pid) {}
+
+    fn message_response(self, message_id: MessageId, response: Result<EncodedMessage, ()>) {
+        let (original, hash) = match self.forwarded.lock().remove(&message_id) {
+            Some(v) => v,
+            None => return,
+        };
+
+        // Store the module in the directory so that we don't need the fallback next time.
+        if let Ok(response) = &response {
+            if let Ok(LoadResponse {
+                result: Ok(data),
+                signature,
+            }) = LoadResponse::decode(response.clone())
+            {
+                if ModuleHash::from_bytes(&data) == hash {
+                    let _ = fs::create_dir_all(&self.directory);
+                    let _ = fs::write(self.path(&hash), &data);
+                    if let Some(signature) = signature {
+                        let mut sig_file = signature.public_key.to_vec();
+                        sig_file.extend_from_slice(&signature.signature);
+                        let _ = fs::write(self.signature_path(&hash), &sig_file);
+                    }
+                }
+            }
+        }
+
+        let _ = self
+            .events_tx
+            .unbounded_send(Event::Answer(original, response));
+    }
+}
+
+impl<'a> NativeProgramMessageIdWrite for ForwardMessageIdWrite<'a> {
+    fn acknowledge(self, message_id: MessageId) {
+        self.loader
+            .forwarded
+            .lock()
+            .insert(message_id, (self.original, self.hash));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalLoader;
+    use redshirt_core::module::ModuleHash;
+    use redshirt_core::native::{
+        NativeProgramEvent, NativeProgramMessageIdWrite as _, NativeProgramRef as _,
+    };
+    use redshirt_core::{Decode as _, Encode as _, MessageId, Pid};
+    use redshirt_loader_interface::ffi::{
+        LoadResponse, LoaderMessage, FALLBACK_INTERFACE, INTERFACE,
+    };
+    use std::{convert::TryFrom as _, fs, path::PathBuf};
+
+    /// Returns an empty directory dedicated to the given test.
+    fn test_directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "redshirt-hosted-loader-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Sends a `Load` message for `hash` to the loader, and returns the answer.
+    fn load(loader: &LocalLoader, hash: &ModuleHash) -> LoadResponse {
+        let message_id = MessageId::try_from(1234u64).unwrap();
+        let message = LoaderMessage::Load(From::from(hash.clone())).encode();
+        loader.interface_message(INTERFACE, Some(message_id), Pid::from(1), message);
+
+        match next_event(loader) {
+            NativeProgramEvent::Answer {
+                message_id: answered,
+                answer,
+            } => {
+                assert_eq!(answered, message_id);
+                LoadResponse::decode(answer.unwrap()).unwrap()
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Returns the next event of the loader, skipping the interface registration.
+    fn next_event(loader: &LocalLoader) -> NativeProgramEvent<super::ForwardMessageIdWrite> {
+        loop {
+            match futures::executor::block_on(loader.next_event()) {
+                NativeProgramEvent::Emit { interface, .. }
+                    if interface == redshirt_interface_interface::ffi::INTERFACE => {}
+                ev => return ev,
+            }
+        }
+    }
+
+    #[test]
+    fn loads_from_directory() {
+        let dir = test_directory("loads_from_directory");
+        let module = b"hello world".to_vec();
+        let hash = ModuleHash::from_bytes(&module);
+        fs::write(dir.join(format!("{}.wasm", hash)), &module).unwrap();
+
+        let loader = LocalLoader::new(&dir, false);
+        let response = load(&loader, &hash);
+        assert_eq!(response.result, Ok(module));
+        assert!(response.signature.is_none());
+    }
+
+    #[test]
+    fn reads_signature() {
+        let dir = test_directory("reads_signature");
+        let module = b"hello world".to_vec();
+        let hash = ModuleHash::from_bytes(&module);
+        fs::write(dir.join(format!("{}.wasm", hash)), &module).unwrap();
+        let mut signature = vec![1; 32];
+        signature.extend_from_slice(&[2; 64]);
+        fs::write(dir.join(format!("{}.sig", hash)), &signature).unwrap();
+
+        let loader = LocalLoader::new(&dir, false);
+        let signature = load(&loader, &hash).signature.unwrap();
+        assert_eq!(signature.public_key, [1; 32]);
+        assert_eq!(signature.signature, vec![2; 64]);
+    }
+
+    #[test]
+    fn missing_module() {
+        let dir = test_directory("missing_module");
+        let hash = ModuleHash::from_bytes(b"hello world");
+
+        let loader = LocalLoader::new(&dir, false);
+        assert_eq!(load(&loader, &hash).result, Err(()));
+    }
+
+    #[test]
+    fn hash_mismatch() {
+        let dir = test_directory("hash_mismatch");
+        let hash = ModuleHash::from_bytes(b"hello world");
+        fs::write(dir.join(format!("{}.wasm", hash)), b"corrupted").unwrap();
+
+        let loader = LocalLoader::new(&dir, false);
+        assert_eq!(load(&loader, &hash).result, Err(()));
+    }
+
+    #[test]
+    fn fallback_lookup() {
+        let dir = test_directory("fallback_lookup");
+        let module = b"hello world".to_vec();
+        let hash = ModuleHash::from_bytes(&module);
+
+        let loader = LocalLoader::new(&dir, true);
+        let original = MessageId::try_from(1234u64).unwrap();
+        let message = LoaderMessage::Load(From::from(hash.clone())).encode();
+        loader.interface_message(INTERFACE, Some(original), Pid::from(1), message);
+
+        // The request must be forwarded to the fallback loader.
+        let forwarded = MessageId::try_from(5678u64).unwrap();
+        match next_event(&loader) {
+            NativeProgramEvent::Emit {
+                interface,
+                message_id_write,
+                message,
+            } => {
+                assert_eq!(interface, FALLBACK_INTERFACE);
+                let LoaderMessage::Load(requested) = LoaderMessage::decode(message).unwrap();
+                assert_eq!(ModuleHash::from(requested), hash);
+                message_id_write.unwrap().acknowledge(forwarded);
+            }
+            _ => panic!(),
+        }
+
+        let response = LoadResponse {
+            result: Ok(module.clone()),
+            signature: None,
+        };
+        loader.message_response(forwarded, Ok(response.encode()));
+
+        match next_event(&loader) {
+            NativeProgramEvent::Answer { message_id, answer } => {
+                assert_eq!(message_id, original);
+                let response = LoadResponse::decode(answer.unwrap()).unwrap();
+                assert_eq!(response.result, Ok(module.clone()));
+            }
+            _ => panic!(),
+        }
+
+        // The module is now cached in the directory.
+        assert_eq!(fs::read(dir.join(format!("{}.wasm", hash))).unwrap(), module);
+    }
+}
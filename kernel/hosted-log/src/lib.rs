@@ -14,12 +14,25 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 //! Implements the log interface by printing logs to stdout.
+//!
+//! Logs can be printed either in a human-readable way, or as JSON objects, one per line. See
+//! [`OutputFormat`].
 
-use futures::prelude::*;
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
 use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
-use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
-use redshirt_log_interface::ffi::{DecodedLogMessage, Level, INTERFACE};
-use std::{borrow::Cow, pin::Pin, sync::atomic};
+use redshirt_core::{
+    Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid, ProcessesInfo,
+};
+use redshirt_log_interface::ffi::{
+    DecodedLogInterfaceMessage, DecodedLogMessage, Level, LogFilter, INTERFACE,
+};
+use std::{
+    borrow::Cow,
+    convert::TryFrom as _,
+    pin::Pin,
+    sync::{atomic, Arc},
+    time::Instant,
+};
 
 /// Native program for `log` interface messages handling.
 pub struct LogHandler {
@@ -27,15 +40,139 @@ pub struct LogHandler {
     registered: atomic::AtomicBool,
     /// If true, enable terminal colors when printing the log messages.
     enable_colors: bool,
+    /// How to print the log messages.
+    format: OutputFormat,
+    /// Messages that don't pass this filter are discarded. Also sent to the processes that ask
+    /// for it.
+    filter: LogFilter,
+    /// Time when the handler has been created. Timestamps are relative to this moment.
+    start: Instant,
+    /// If `Some`, used to print the names of the processes that emit the log messages.
+    processes_info: Option<Arc<ProcessesInfo>>,
+    /// Sending side of `answers_rx`.
+    answers_tx: mpsc::UnboundedSender<(MessageId, EncodedMessage)>,
+    /// Receives the answers to send back to the processes.
+    answers_rx: Mutex<mpsc::UnboundedReceiver<(MessageId, EncodedMessage)>>,
+}
+
+/// How to print log messages.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable lines.
+    Human,
+    /// One JSON object per line.
+    JsonLines,
 }
 
 impl LogHandler {
     /// Initializes the new state machine for logging.
     pub fn new() -> Self {
+        let (answers_tx, answers_rx) = mpsc::unbounded();
+
         LogHandler {
             registered: atomic::AtomicBool::new(false),
             enable_colors: atty::is(atty::Stream::Stdout),
+            format: OutputFormat::Human,
+            filter: LogFilter::all(),
+            start: Instant::now(),
+            processes_info: None,
+            answers_tx,
+            answers_rx: Mutex::new(answers_rx),
+        }
+    }
+
+    /// Sets which log messages must be printed. By default, all messages are printed.
+    pub fn with_filter(mut self, filter: LogFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets the table of information about processes, used to print the name of the process that
+    /// emits each message. Should be the same table as the one passed to the `System`.
+    pub fn with_processes_info(mut self, processes_info: Arc<ProcessesInfo>) -> Self {
+        self.processes_info = Some(processes_info);
+        self
+    }
+
+    /// Returns the name of the given process, if known.
+    fn process_name(&self, pid: Pid) -> Option<String> {
+        self.processes_info.as_ref()?.get(pid)?.name
+    }
+
+    /// Sets how to print the log messages. Defaults to [`OutputFormat::Human`].
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Prints the given message in a human-readable way.
+    fn print_human(&self, emitter_pid: Pid, decoded: &DecodedLogMessage) {
+        let mut header_style = ansi_term::Style::default();
+        let level = match decoded.level() {
+            Level::Error => "ERR ",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBG",
+            Level::Trace => "TRCE",
+        };
+        if self.enable_colors {
+            header_style.is_dimmed = true;
+        }
+
+        let mut line = String::new();
+        if !decoded.target().is_empty() {
+            line.push_str(&strip_control(decoded.target()));
+            line.push_str(": ");
         }
+        line.push_str(&strip_control(decoded.message()));
+        for (key, value) in decoded.fields() {
+            line.push_str(&format!(" {}={}", strip_control(key), strip_control(value)));
+        }
+
+        let process = match self.process_name(emitter_pid) {
+            Some(name) => format!("{:?} {}", emitter_pid, strip_control(&name)),
+            None => format!("{:?}", emitter_pid),
+        };
+
+        println!(
+            "{}[{}] [{}]{} {}",
+            header_style.prefix(),
+            process,
+            level,
+            header_style.suffix(),
+            line
+        );
+    }
+
+    /// Prints the given message as a JSON object.
+    fn print_json(&self, emitter_pid: Pid, decoded: &DecodedLogMessage) {
+        let timestamp = decoded
+            .timestamp()
+            .unwrap_or_else(|| self.start.elapsed().as_nanos());
+        let level = match decoded.level() {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        let fields = decoded
+            .fields()
+            .iter()
+            .map(|(k, v)| (k.clone(), serde_json::Value::from(&v[..])))
+            .collect::<serde_json::Map<_, _>>();
+
+        let line = serde_json::json!({
+            "timestamp": u64::try_from(timestamp).unwrap_or(u64::max_value()),
+            "pid": u64::from(emitter_pid),
+            "process": self.process_name(emitter_pid),
+            "level": level,
+            "target": decoded.target(),
+            "module_path": decoded.module_path(),
+            "message": decoded.message(),
+            "fields": fields,
+        });
+        println!("{}", line);
     }
 }
 
@@ -57,8 +194,14 @@ impl<'a> NativeProgramRef<'a> for &'a LogHandler {
                 };
             }
 
-            loop {
-                futures::pending!()
+            let mut answers_rx = self.answers_rx.lock().await;
+            match answers_rx.next().await {
+                Some((message_id, answer)) => NativeProgramEvent::Answer {
+                    message_id,
+                    answer: Ok(answer),
+                },
+                // We hold a sender in `self`.
+                None => unreachable!(),
             }
         })
     }
@@ -66,46 +209,28 @@ impl<'a> NativeProgramRef<'a> for &'a LogHandler {
     fn interface_message(
         self,
         interface: InterfaceHash,
-        _: Option<MessageId>,
+        message_id: Option<MessageId>,
         emitter_pid: Pid,
         message: EncodedMessage,
     ) {
         debug_assert_eq!(interface, INTERFACE);
 
-        match DecodedLogMessage::decode(message) {
-            Ok(decoded) => {
-                // Remove any control character from log messages, in order to prevent programs
-                // from polluting the terminal.
-                let message = if decoded.message().chars().any(|c| c.is_control()) {
-                    Cow::Owned(
-                        decoded
-                            .message()
-                            .chars()
-                            .filter(|c| !c.is_control())
-                            .collect(),
-                    )
-                } else {
-                    Cow::Borrowed(decoded.message())
-                };
-                let mut header_style = ansi_term::Style::default();
-                let level = match decoded.level() {
-                    Level::Error => "ERR ",
-                    Level::Warn => "WARN",
-                    Level::Info => "INFO",
-                    Level::Debug => "DEBG",
-                    Level::Trace => "TRCE",
-                };
-                if self.enable_colors {
-                    header_style.is_dimmed = true;
+        match DecodedLogInterfaceMessage::decode(message) {
+            Ok(DecodedLogInterfaceMessage::Log(decoded)) => {
+                if !self.filter.enabled(decoded.target(), decoded.level()) {
+                    return;
+                }
+
+                match self.format {
+                    OutputFormat::Human => self.print_human(emitter_pid, &decoded),
+                    OutputFormat::JsonLines => self.print_json(emitter_pid, &decoded),
+                }
+            }
+            Ok(DecodedLogInterfaceMessage::FilterRequest) => {
+                if let Some(message_id) = message_id {
+                    let answer = self.filter.clone().encode();
+                    let _ = self.answers_tx.unbounded_send((message_id, answer));
                 }
-                println!(
-                    "{}[{:?}] [{}]{} {}",
-                    header_style.prefix(),
-                    emitter_pid,
-                    level,
-                    header_style.suffix(),
-                    message
-                );
             }
             Err(_) => println!("bad log message from {:?}", emitter_pid),
         }
@@ -117,3 +242,13 @@ impl<'a> NativeProgramRef<'a> for &'a LogHandler {
         unreachable!()
     }
 }
+
+/// Removes any control character from log messages, in order to prevent programs from polluting
+/// the terminal.
+fn strip_control(s: &str) -> Cow<str> {
+    if s.chars().any(|c| c.is_control()) {
+        Cow::Owned(s.chars().filter(|c| !c.is_control()).collect())
+    } else {
+        Cow::Borrowed(s)
+    }
+}
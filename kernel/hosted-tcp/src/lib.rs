@@ -23,14 +23,17 @@ use async_std::{
 use fnv::FnvHashMap;
 use futures::{channel::mpsc, prelude::*};
 use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
-use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_core::{
+    Authorizations, Buffers, Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId,
+    Pid,
+};
 use redshirt_tcp_interface::ffi;
 use std::{
     collections::{hash_map::Entry, VecDeque},
     fmt, mem,
     net::{Ipv6Addr, SocketAddr},
     pin::Pin,
-    sync::atomic,
+    sync::{atomic, Arc},
 };
 
 /// Native process for TCP/IP connections that use the host operating system.
@@ -41,14 +44,21 @@ pub struct TcpHandler {
     /// Receives messages from the sockets background tasks.
     receiver: Mutex<mpsc::Receiver<BackToFront>>,
 
-    /// List of all active sockets. Contains both open and non-open sockets.
-    sockets: parking_lot::Mutex<FnvHashMap<u32, FrontSocketState>>,
+    /// List of all active sockets. Contains both open and non-open sockets, and the process
+    /// that has opened each of them. Only this process is allowed to use the socket.
+    sockets: parking_lot::Mutex<FnvHashMap<u32, (Pid, FrontSocketState)>>,
 
     /// List of open TCP listeners by port.
     listeners: parking_lot::Mutex<FnvHashMap<u16, mpsc::UnboundedSender<FrontToBackListener>>>,
 
     /// Sending side of `receiver`. Meant to be cloned and sent to background tasks.
     sender: mpsc::Sender<BackToFront>,
+
+    /// Processes that are allowed to use TCP.
+    authorizations: Arc<Authorizations>,
+
+    /// Buffers through which large amounts of data are received and sent.
+    buffers: Arc<Buffers>,
 }
 
 /// State of a socket known from the front state.
@@ -67,6 +77,7 @@ enum FrontSocketState {
 enum FrontToBackSocket {
     Read {
         message_id: MessageId,
+        emitter_pid: Pid,
     },
     Write {
         message_id: MessageId,
@@ -95,17 +106,26 @@ enum BackToFront {
     },
     Read {
         message_id: MessageId,
+        emitter_pid: Pid,
         result: Result<Vec<u8>, ()>,
     },
     Write {
         message_id: MessageId,
         result: Result<(), ()>,
     },
+    /// The emitter of the message isn't allowed to use TCP, or the socket it refers to.
+    Denied {
+        message_id: MessageId,
+    },
 }
 
 impl TcpHandler {
     /// Initializes a new empty [`TcpHandler`].
-    pub fn new() -> Self {
+    ///
+    /// Only the processes that `authorizations` allows to use the `tcp` interface can open
+    /// sockets. Large amounts of data are exchanged through `buffers`, which must be the same
+    /// collection as the one of the system.
+    pub fn new(authorizations: Arc<Authorizations>, buffers: Arc<Buffers>) -> Self {
         let (sender, receiver) = mpsc::channel(32);
 
         TcpHandler {
@@ -114,6 +134,8 @@ impl TcpHandler {
             listeners: parking_lot::Mutex::new(FnvHashMap::default()),
             receiver: Mutex::new(receiver),
             sender,
+            authorizations,
+            buffers,
         }
     }
 }
@@ -147,10 +169,13 @@ impl<'a> NativeProgramRef<'a> for &'a TcpHandler {
                     socket_id,
                     sender,
                 } => {
+                    // The socket might have been closed while it was being opened, in which case
+                    // dropping `sender` closes the connection.
                     let mut sockets = self.sockets.lock();
-                    let front_state = sockets.get_mut(&socket_id).unwrap();
-                    // TODO: debug_assert is orphan
-                    *front_state = FrontSocketState::Connected(sender);
+                    if let Some((_, front_state)) = sockets.get_mut(&socket_id) {
+                        // TODO: debug_assert is orphan
+                        *front_state = FrontSocketState::Connected(sender);
+                    }
 
                     return NativeProgramEvent::Answer {
                         message_id: open_message_id,
@@ -174,7 +199,7 @@ impl<'a> NativeProgramRef<'a> for &'a TcpHandler {
                     let mut sockets = self.sockets.lock();
                     let _front_state = sockets.remove(&socket_id);
                     debug_assert!(match _front_state {
-                        Some(FrontSocketState::Orphan) => true,
+                        Some((_, FrontSocketState::Orphan)) => true,
                         _ => false,
                     });
 
@@ -187,11 +212,16 @@ impl<'a> NativeProgramRef<'a> for &'a TcpHandler {
                     };
                 }
 
-                BackToFront::Read { message_id, result } => {
+                BackToFront::Read {
+                    message_id,
+                    emitter_pid,
+                    result,
+                } => {
+                    let result = result.map(|data| self.buffers.make_payload(emitter_pid, data));
                     return NativeProgramEvent::Answer {
                         message_id,
                         answer: Ok(redshirt_tcp_interface::ffi::TcpReadResponse { result }.encode()),
-                    }
+                    };
                 }
 
                 BackToFront::Write { message_id, result } => {
@@ -202,6 +232,13 @@ impl<'a> NativeProgramRef<'a> for &'a TcpHandler {
                         ),
                     }
                 }
+
+                BackToFront::Denied { message_id } => {
+                    return NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Err(()),
+                    }
+                }
             }
         })
     }
@@ -210,11 +247,18 @@ impl<'a> NativeProgramRef<'a> for &'a TcpHandler {
         self,
         interface: InterfaceHash,
         message_id: Option<MessageId>,
-        _emitter_pid: Pid, // TODO: use to check ownership of sockets
+        emitter_pid: Pid,
         message: EncodedMessage,
     ) {
         debug_assert_eq!(interface, ffi::INTERFACE);
 
+        if !self.authorizations.is_authorized(emitter_pid, &ffi::INTERFACE) {
+            if let Some(message_id) = message_id {
+                self.deny(message_id);
+            }
+            return;
+        }
+
         let message = match ffi::TcpMessage::decode(message) {
             Ok(msg) => msg,
             Err(_) => return, // TODO: produce error
@@ -271,7 +315,7 @@ impl<'a> NativeProgramRef<'a> for &'a TcpHandler {
                             open_message_id: message_id,
                         })
                         .unwrap();
-                    vacant_entry.insert(FrontSocketState::Listener(listener_sender));
+                    vacant_entry.insert((emitter_pid, FrontSocketState::Listener(listener_sender)));
                 } else {
                     task::spawn(socket_task(
                         *vacant_entry.key(),
@@ -280,12 +324,16 @@ impl<'a> NativeProgramRef<'a> for &'a TcpHandler {
                         self.sender.clone(),
                     ));
 
-                    vacant_entry.insert(FrontSocketState::Orphan);
+                    vacant_entry.insert((emitter_pid, FrontSocketState::Orphan));
                 }
             }
 
             ffi::TcpMessage::Close(close) => {
-                let _ = sockets.remove(&close.socket_id);
+                if let Some((owner, _)) = sockets.get(&close.socket_id) {
+                    if *owner == emitter_pid {
+                        let _ = sockets.remove(&close.socket_id);
+                    }
+                }
             }
 
             ffi::TcpMessage::Read(read) => {
@@ -294,31 +342,65 @@ impl<'a> NativeProgramRef<'a> for &'a TcpHandler {
                     None => return,
                 };
 
-                sockets
-                    .get_mut(&read.socket_id)
-                    .unwrap() // TODO: don't unwrap; but what to do?
-                    .as_mut_connected()
-                    .unwrap()
-                    .unbounded_send(FrontToBackSocket::Read { message_id })
-                    .unwrap(); // TODO: don't unwrap; but what to do?
+                let socket = match sockets.get_mut(&read.socket_id) {
+                    Some((owner, socket)) if *owner == emitter_pid => socket,
+                    _ => return self.deny(message_id),
+                };
+
+                // Listening sockets, sockets that aren't connected yet, and sockets whose
+                // background task has stopped can't be read from.
+                let sent = socket.as_mut_connected().map_or(false, |socket| {
+                    socket
+                        .unbounded_send(FrontToBackSocket::Read {
+                            message_id,
+                            emitter_pid,
+                        })
+                        .is_ok()
+                });
+                if !sent {
+                    self.send_to_front(BackToFront::Read {
+                        message_id,
+                        emitter_pid,
+                        result: Err(()),
+                    });
+                }
             }
 
             ffi::TcpMessage::Write(write) => {
+                // The buffer, if any, is claimed even if the message is refused.
+                let data = self.buffers.take_payload(write.data);
                 let message_id = match message_id {
                     Some(m) => m,
                     None => return,
                 };
 
-                sockets
-                    .get_mut(&write.socket_id)
-                    .unwrap() // TODO: don't unwrap; but what to do?
-                    .as_mut_connected()
-                    .unwrap()
-                    .unbounded_send(FrontToBackSocket::Write {
+                let data = match data {
+                    Ok(data) => data,
+                    Err(_) => {
+                        return self.send_to_front(BackToFront::Write {
+                            message_id,
+                            result: Err(()),
+                        })
+                    }
+                };
+
+                let socket = match sockets.get_mut(&write.socket_id) {
+                    Some((owner, socket)) if *owner == emitter_pid => socket,
+                    _ => return self.deny(message_id),
+                };
+
+                // Same as for reading.
+                let sent = socket.as_mut_connected().map_or(false, |socket| {
+                    socket
+                        .unbounded_send(FrontToBackSocket::Write { message_id, data })
+                        .is_ok()
+                });
+                if !sent {
+                    self.send_to_front(BackToFront::Write {
                         message_id,
-                        data: write.data,
-                    })
-                    .unwrap(); // TODO: don't unwrap; but what to do?
+                        result: Err(()),
+                    });
+                }
             }
         }
     }
@@ -332,9 +414,19 @@ impl<'a> NativeProgramRef<'a> for &'a TcpHandler {
     }
 }
 
-impl Default for TcpHandler {
-    fn default() -> Self {
-        TcpHandler::new()
+impl TcpHandler {
+    /// Answers the given message with an error, for example because the emitter isn't allowed
+    /// to send it.
+    fn deny(&self, message_id: MessageId) {
+        self.send_to_front(BackToFront::Denied { message_id });
+    }
+
+    /// Queues a message as if it had been sent by a background task.
+    fn send_to_front(&self, message: BackToFront) {
+        let mut sender = self.sender.clone();
+        task::spawn(async move {
+            let _ = sender.send(message).await;
+        });
     }
 }
 
@@ -411,7 +503,7 @@ async fn open_socket_task(
     let mut write_message = None;
     // Buffer where to read data into.
     let mut read_buffer = Vec::new();
-    // Message to answer if we read data.
+    // Message to answer if we read data, and the process that has emitted it.
     let mut read_message = None;
 
     // Now that we're connected and we have a `socket` and `commands_rx`, we can start reading
@@ -420,6 +512,7 @@ async fn open_socket_task(
         enum WhatHappened {
             ReadCmd {
                 message_id: MessageId,
+                emitter_pid: Pid,
             },
             WriteCmd {
                 message_id: MessageId,
@@ -463,9 +556,16 @@ async fn open_socket_task(
             futures::pin_mut!(next_command);
 
             match future::select(future::select(partial_write, read), next_command).await {
-                future::Either::Right((Some(FrontToBackSocket::Read { message_id }), _)) => {
-                    WhatHappened::ReadCmd { message_id }
-                }
+                future::Either::Right((
+                    Some(FrontToBackSocket::Read {
+                        message_id,
+                        emitter_pid,
+                    }),
+                    _,
+                )) => WhatHappened::ReadCmd {
+                    message_id,
+                    emitter_pid,
+                },
                 future::Either::Right((Some(FrontToBackSocket::Write { message_id, data }), _)) => {
                     WhatHappened::WriteCmd { message_id, data }
                 }
@@ -483,14 +583,17 @@ async fn open_socket_task(
         };
 
         match what_happened {
-            WhatHappened::ReadCmd { message_id } => {
+            WhatHappened::ReadCmd {
+                message_id,
+                emitter_pid,
+            } => {
                 // Read already in progress.
                 if read_message.is_some() {
                     panic!(); // TODO: don't panic
                 }
 
                 assert!(read_buffer.is_empty());
-                read_message = Some(message_id);
+                read_message = Some((message_id, emitter_pid));
                 read_buffer = vec![0; 512];
             }
 
@@ -525,10 +628,11 @@ async fn open_socket_task(
 
             WhatHappened::ReadFinished => {
                 // Finished a read.
-                let read_message = read_message.take().unwrap();
+                let (message_id, emitter_pid) = read_message.take().unwrap();
                 let buf = mem::replace(&mut read_buffer, Vec::new());
                 let msg_to_front = BackToFront::Read {
-                    message_id: read_message,
+                    message_id,
+                    emitter_pid,
                     result: Ok(buf),
                 };
                 if back_to_front.send(msg_to_front).await.is_err() {
@@ -79,7 +79,7 @@ pub fn run_kernel(cfg: Config) -> Result<(), Error> {
                 .arg("-cdrom")
                 .arg(build_dir.path().join("image"))
                 .args(&["-netdev", "bridge,id=nd0,br=virbr0"])
-                .args(&["-device", "ne2k_pci,netdev=nd0"])
+                .args(&["-device", "virtio-net-pci,netdev=nd0"])
                 .args(&["-smp", "cpus=4"])
                 .status()
                 .map_err(Error::EmulatorNotFound)?;
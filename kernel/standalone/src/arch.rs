@@ -28,8 +28,11 @@
 //! After everything has been initialized, the entry point creates a struct that implements the
 //! [`PlatformSpecific`] trait, and initializes and runs a [`Kernel`](crate::kernel::Kernel).
 
+use alloc::vec::Vec;
 use core::{fmt, future::Future, num::NonZeroU32, pin::Pin};
-use redshirt_kernel_log_interface::ffi::KernelLogMethod;
+use futures::stream::Stream;
+use redshirt_hardware_interface::ffi::{InterruptSource, MsiMessage, PciConfigRegion};
+use redshirt_kernel_log_interface::ffi::{FramebufferInfo, KernelLogMethod};
 
 mod arm;
 mod riscv;
@@ -41,6 +44,11 @@ pub trait PlatformSpecific: Send + Sync + 'static {
     /// `Future` that fires when the monotonic clock reaches a certain value.
     // TODO: remove `'static` requirement
     type TimerFuture: Future<Output = ()> + Send + 'static;
+    /// `Stream` of hardware interrupts returned by [`PlatformSpecific::subscribe_interrupt`].
+    ///
+    /// Each item is the number of times the interrupt has been triggered since the previous
+    /// item. Interrupts that happen while the stream isn't being polled are buffered.
+    type InterruptStream: Stream<Item = u32> + Send + Unpin + 'static;
 
     /// Returns the number of CPUs available.
     fn num_cpus(self: Pin<&Self>) -> NonZeroU32;
@@ -53,6 +61,12 @@ pub trait PlatformSpecific: Send + Sync + 'static {
     /// Even if you are not using the logging system, it is important to call this method when for
     /// example the video mode changes, so that the kernel knows how to print panic messages.
     fn set_logger_method(&self, method: KernelLogMethod);
+    /// Returns the framebuffer that has been set up by the bootloader or firmware, if any.
+    fn boot_framebuffer(self: Pin<&Self>) -> Option<FramebufferInfo>;
+    /// Stops printing logs on the framebuffer returned by
+    /// [`PlatformSpecific::boot_framebuffer`], so that its content can be controlled by something
+    /// else. Logs are still printed through the other methods, such as UARTs.
+    fn release_boot_framebuffer(&self);
 
     /// Returns the number of nanoseconds that happened since an undeterminate moment in time.
     ///
@@ -80,6 +94,35 @@ pub trait PlatformSpecific: Send + Sync + 'static {
     /// Reads a `u32` from a port. Returns an error if the operation is not supported or if the
     /// port is out of range.
     unsafe fn read_port_u32(self: Pin<&Self>, port: u32) -> Result<u32, PortErr>;
+
+    /// Starts listening to a source of hardware interrupts.
+    ///
+    /// Only one subscription to any given source can exist at a time. Subscribing to a source
+    /// that is already subscribed to returns [`InterruptErr::Reserved`]. Callers that want to
+    /// deliver the same interrupt to multiple listeners must share the stream themselves. The
+    /// interrupt stops being delivered when the stream is destroyed.
+    fn subscribe_interrupt(
+        self: Pin<&Self>,
+        source: InterruptSource,
+    ) -> Result<Self::InterruptStream, InterruptErr>;
+    /// Returns the write that a device must perform in order to trigger the interrupts of the
+    /// given stream.
+    ///
+    /// Returns `None` if the stream hasn't been created from [`InterruptSource::Msi`].
+    fn interrupt_msi_message(
+        self: Pin<&Self>,
+        stream: &Self::InterruptStream,
+    ) -> Option<MsiMessage>;
+    /// Indicates that the devices that trigger the interrupts of the given stream have been
+    /// serviced.
+    ///
+    /// Level-triggered interrupts stop being delivered after they have been triggered, until
+    /// this method is called.
+    fn interrupt_acknowledge(self: Pin<&Self>, stream: &Self::InterruptStream);
+
+    /// Returns the list of memory regions where the configuration space of PCI devices is
+    /// mapped, as reported by the firmware.
+    fn pci_config_regions(self: Pin<&Self>) -> Vec<PciConfigRegion>;
 }
 
 /// Error when requesting to read/write a hardware port.
@@ -99,3 +142,24 @@ impl fmt::Display for PortErr {
         }
     }
 }
+
+/// Error when subscribing to a hardware interrupt.
+#[derive(Debug)]
+pub enum InterruptErr {
+    /// Interrupts from this source can't be delivered.
+    Unsupported,
+    /// The interrupt is already used by the kernel.
+    Reserved,
+    /// No more interrupt vector is available.
+    Exhausted,
+}
+
+impl fmt::Display for InterruptErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterruptErr::Unsupported => write!(f, "Interrupt source is not supported"),
+            InterruptErr::Reserved => write!(f, "Interrupt is already in use"),
+            InterruptErr::Exhausted => write!(f, "No interrupt vector available"),
+        }
+    }
+}
@@ -15,13 +15,14 @@
 
 #![cfg(any(target_arch = "arm", target_arch = "aarch64"))]
 
-use crate::arch::{PlatformSpecific, PortErr};
+use crate::arch::{InterruptErr, PlatformSpecific, PortErr};
 use crate::klog::KLogger;
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 use core::{convert::TryFrom as _, iter, num::NonZeroU32, pin::Pin};
 use futures::prelude::*;
-use redshirt_kernel_log_interface::ffi::{KernelLogMethod, UartInfo};
+use redshirt_hardware_interface::ffi::{InterruptSource, MsiMessage, PciConfigRegion};
+use redshirt_kernel_log_interface::ffi::{FramebufferInfo, KernelLogMethod, UartInfo};
 
 #[cfg(target_arch = "aarch64")]
 use time_aarch64 as time;
@@ -141,6 +142,7 @@ struct PlatformSpecificImpl {
 
 impl PlatformSpecific for PlatformSpecificImpl {
     type TimerFuture = time::TimerFuture;
+    type InterruptStream = stream::Pending<u32>;
 
     fn num_cpus(self: Pin<&Self>) -> NonZeroU32 {
         NonZeroU32::new(1).unwrap()
@@ -164,6 +166,12 @@ impl PlatformSpecific for PlatformSpecificImpl {
         }
     }
 
+    fn boot_framebuffer(self: Pin<&Self>) -> Option<FramebufferInfo> {
+        None
+    }
+
+    fn release_boot_framebuffer(&self) {}
+
     unsafe fn write_port_u8(self: Pin<&Self>, _: u32, _: u8) -> Result<(), PortErr> {
         Err(PortErr::Unsupported)
     }
@@ -187,6 +195,24 @@ impl PlatformSpecific for PlatformSpecificImpl {
     unsafe fn read_port_u32(self: Pin<&Self>, _: u32) -> Result<u32, PortErr> {
         Err(PortErr::Unsupported)
     }
+
+    fn subscribe_interrupt(
+        self: Pin<&Self>,
+        _: InterruptSource,
+    ) -> Result<Self::InterruptStream, InterruptErr> {
+        // TODO: implement
+        Err(InterruptErr::Unsupported)
+    }
+
+    fn interrupt_msi_message(self: Pin<&Self>, _: &Self::InterruptStream) -> Option<MsiMessage> {
+        None
+    }
+
+    fn interrupt_acknowledge(self: Pin<&Self>, _: &Self::InterruptStream) {}
+
+    fn pci_config_regions(self: Pin<&Self>) -> Vec<PciConfigRegion> {
+        Vec::new()
+    }
 }
 
 // TODO: no_mangle and naked because it's called at initialization; attributes should eventually be removed
@@ -15,10 +15,10 @@
 
 #![cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
 
-use crate::arch::{PlatformSpecific, PortErr};
+use crate::arch::{InterruptErr, PlatformSpecific, PortErr};
 use crate::klog::KLogger;
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 use core::{
     convert::TryFrom as _,
     fmt::{self, Write as _},
@@ -27,7 +27,8 @@ use core::{
     pin::Pin,
 };
 use futures::prelude::*;
-use redshirt_kernel_log_interface::ffi::{KernelLogMethod, UartInfo};
+use redshirt_hardware_interface::ffi::{InterruptSource, MsiMessage, PciConfigRegion};
+use redshirt_kernel_log_interface::ffi::{FramebufferInfo, KernelLogMethod, UartInfo};
 
 mod executor;
 mod interrupts;
@@ -159,6 +160,7 @@ struct PlatformSpecificImpl {}
 
 impl PlatformSpecific for PlatformSpecificImpl {
     type TimerFuture = future::Pending<()>;
+    type InterruptStream = stream::Pending<u32>;
 
     fn num_cpus(self: Pin<&Self>) -> NonZeroU32 {
         // TODO:
@@ -216,6 +218,12 @@ impl PlatformSpecific for PlatformSpecificImpl {
         }
     }
 
+    fn boot_framebuffer(self: Pin<&Self>) -> Option<FramebufferInfo> {
+        None
+    }
+
+    fn release_boot_framebuffer(&self) {}
+
     unsafe fn write_port_u8(self: Pin<&Self>, _: u32, _: u8) -> Result<(), PortErr> {
         Err(PortErr::Unsupported)
     }
@@ -239,4 +247,22 @@ impl PlatformSpecific for PlatformSpecificImpl {
     unsafe fn read_port_u32(self: Pin<&Self>, _: u32) -> Result<u32, PortErr> {
         Err(PortErr::Unsupported)
     }
+
+    fn subscribe_interrupt(
+        self: Pin<&Self>,
+        _: InterruptSource,
+    ) -> Result<Self::InterruptStream, InterruptErr> {
+        // TODO: implement
+        Err(InterruptErr::Unsupported)
+    }
+
+    fn interrupt_msi_message(self: Pin<&Self>, _: &Self::InterruptStream) -> Option<MsiMessage> {
+        None
+    }
+
+    fn interrupt_acknowledge(self: Pin<&Self>, _: &Self::InterruptStream) {}
+
+    fn pci_config_regions(self: Pin<&Self>) -> Vec<PciConfigRegion> {
+        Vec::new()
+    }
 }
@@ -15,7 +15,7 @@
 
 #![cfg(target_arch = "x86_64")]
 
-use crate::arch::{PlatformSpecific, PortErr};
+use crate::arch::{InterruptErr, PlatformSpecific, PortErr};
 use crate::klog::KLogger;
 
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
@@ -24,6 +24,7 @@ use core::{
     time::Duration,
 };
 use futures::channel::oneshot;
+use redshirt_hardware_interface::ffi::{InterruptSource, MsiMessage, PciConfigRegion};
 use redshirt_kernel_log_interface::ffi::{FramebufferFormat, FramebufferInfo, KernelLogMethod};
 use x86_64::structures::port::{PortRead as _, PortWrite as _};
 
@@ -33,6 +34,7 @@ mod apic;
 mod boot;
 mod executor;
 mod interrupts;
+mod irqs;
 mod panic;
 mod pit;
 
@@ -85,40 +87,43 @@ unsafe extern "C" fn after_boot(multiboot_info: usize) -> ! {
         }
     };
 
+    // Framebuffer set up by the bootloader, if any.
+    let boot_framebuffer = multiboot_info
+        .framebuffer_tag()
+        .map(|fb_info| FramebufferInfo {
+            address: fb_info.address,
+            width: fb_info.width,
+            height: fb_info.height,
+            pitch: u64::from(fb_info.pitch),
+            bytes_per_character: fb_info.bpp / 8,
+            format: match fb_info.buffer_type {
+                multiboot2::FramebufferType::Text => FramebufferFormat::Text,
+                multiboot2::FramebufferType::Indexed { .. } => FramebufferFormat::Rgb {
+                    // FIXME: that is completely wrong
+                    red_size: 8,
+                    red_position: 0,
+                    green_size: 8,
+                    green_position: 16,
+                    blue_size: 8,
+                    blue_position: 24,
+                },
+                multiboot2::FramebufferType::RGB { red, green, blue } => FramebufferFormat::Rgb {
+                    red_size: red.size,
+                    red_position: red.position,
+                    green_size: green.size,
+                    green_position: green.position,
+                    blue_size: blue.size,
+                    blue_position: blue.position,
+                },
+            },
+        });
+
     // Now that we have a memory allocator, initialize the logging system .
     let logger = Arc::new(KLogger::new({
-        if let Some(fb_info) = multiboot_info.framebuffer_tag() {
+        if let Some(framebuffer) = &boot_framebuffer {
             KernelLogMethod {
                 enabled: true,
-                framebuffer: Some(FramebufferInfo {
-                    address: fb_info.address,
-                    width: fb_info.width,
-                    height: fb_info.height,
-                    pitch: u64::from(fb_info.pitch),
-                    bytes_per_character: fb_info.bpp / 8,
-                    format: match fb_info.buffer_type {
-                        multiboot2::FramebufferType::Text => FramebufferFormat::Text,
-                        multiboot2::FramebufferType::Indexed { .. } => FramebufferFormat::Rgb {
-                            // FIXME: that is completely wrong
-                            red_size: 8,
-                            red_position: 0,
-                            green_size: 8,
-                            green_position: 16,
-                            blue_size: 8,
-                            blue_position: 24,
-                        },
-                        multiboot2::FramebufferType::RGB { red, green, blue } => {
-                            FramebufferFormat::Rgb {
-                                red_size: red.size,
-                                red_position: red.position,
-                                green_size: green.size,
-                                green_position: green.position,
-                                blue_size: blue.size,
-                                blue_position: blue.position,
-                            }
-                        }
-                    },
-                }),
+                framebuffer: Some(framebuffer.clone()),
                 uart: None,
             }
         } else {
@@ -156,6 +161,26 @@ unsafe extern "C" fn after_boot(multiboot_info: usize) -> ! {
         None => panic!("Interrupt model ACPI table not found"),
     };
 
+    // The ACPI tables also indicate where the configuration space of PCI devices is mapped in
+    // memory. This information is later passed to the PCI driver.
+    // TODO: only segment group 0 is supported
+    let pci_config_regions = acpi_tables
+        .pci_config_regions
+        .as_ref()
+        .map(|regions| {
+            (0..=255u8)
+                .filter_map(|bus| {
+                    let physical_address = regions.physical_address(0, bus, 0, 0)?;
+                    Some(PciConfigRegion {
+                        segment_group: 0,
+                        bus,
+                        physical_address,
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
     // We then initialize the local APIC.
     // `Box::leak` gives us a `&'static` reference to the object.
     let local_apics = Box::leak(Box::new(apic::local::init()));
@@ -167,6 +192,9 @@ unsafe extern "C" fn after_boot(multiboot_info: usize) -> ! {
     // Despite being old, it is still present on all hardware.
     let mut pit = pit::init_pit(&*local_apics, &mut io_apics);
 
+    // The remaining IRQs can be subscribed to by drivers through the `hardware` interface.
+    let irqs = Box::leak(Box::new(irqs::init(&*local_apics, io_apics)));
+
     // Initialize interrupts so that all the elements initialize above function properly.
     // TODO: make this more fool-proof
     interrupts::load_idt();
@@ -233,6 +261,8 @@ unsafe extern "C" fn after_boot(multiboot_info: usize) -> ! {
     let kernel = {
         let platform_specific = PlatformSpecificImpl {
             timers,
+            irqs,
+            pci_config_regions,
             num_cpus: NonZeroU32::new(
                 u32::try_from(kernel_channels.len())
                     .unwrap()
@@ -241,6 +271,7 @@ unsafe extern "C" fn after_boot(multiboot_info: usize) -> ! {
             )
             .unwrap(),
             logger: logger.clone(),
+            boot_framebuffer,
         };
 
         Arc::new(crate::kernel::Kernel::init(platform_specific))
@@ -363,12 +394,16 @@ fn find_free_memory_ranges<'a>(
 /// Implementation of [`PlatformSpecific`].
 struct PlatformSpecificImpl {
     timers: &'static apic::timers::Timers<'static>,
+    irqs: &'static irqs::Irqs,
+    pci_config_regions: Vec<PciConfigRegion>,
     num_cpus: NonZeroU32,
     logger: Arc<KLogger>,
+    boot_framebuffer: Option<FramebufferInfo>,
 }
 
 impl PlatformSpecific for PlatformSpecificImpl {
     type TimerFuture = apic::timers::TimerFuture<'static>;
+    type InterruptStream = irqs::IrqStream;
 
     fn num_cpus(self: Pin<&Self>) -> NonZeroU32 {
         self.num_cpus
@@ -394,6 +429,14 @@ impl PlatformSpecific for PlatformSpecificImpl {
         self.logger.set_method(method)
     }
 
+    fn boot_framebuffer(self: Pin<&Self>) -> Option<FramebufferInfo> {
+        self.boot_framebuffer.clone()
+    }
+
+    fn release_boot_framebuffer(&self) {
+        self.logger.disable_framebuffer()
+    }
+
     unsafe fn write_port_u8(self: Pin<&Self>, port: u32, data: u8) -> Result<(), PortErr> {
         if let Ok(port) = u16::try_from(port) {
             u8::write_to_port(port, data);
@@ -444,4 +487,26 @@ impl PlatformSpecific for PlatformSpecificImpl {
             Err(PortErr::OutOfRange)
         }
     }
+
+    fn subscribe_interrupt(
+        self: Pin<&Self>,
+        source: InterruptSource,
+    ) -> Result<Self::InterruptStream, InterruptErr> {
+        self.irqs.subscribe(source)
+    }
+
+    fn interrupt_msi_message(
+        self: Pin<&Self>,
+        stream: &Self::InterruptStream,
+    ) -> Option<MsiMessage> {
+        stream.msi_message().cloned()
+    }
+
+    fn interrupt_acknowledge(self: Pin<&Self>, stream: &Self::InterruptStream) {
+        stream.acknowledge()
+    }
+
+    fn pci_config_regions(self: Pin<&Self>) -> Vec<PciConfigRegion> {
+        self.pci_config_regions.clone()
+    }
 }
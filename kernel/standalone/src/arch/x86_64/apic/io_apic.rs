@@ -46,6 +46,14 @@
 
 use crate::arch::x86_64::apic::ApicId;
 use core::convert::TryFrom as _;
+use spinning_top::Spinlock;
+
+/// Locked while the registers of any I/O APIC are being accessed, with interrupts disabled.
+///
+/// Accessing a register requires two memory accesses, which must not be interleaved with the
+/// ones of another CPU or of an interrupt handler. Interrupt handlers can mask IRQs through an
+/// [`IrqMask`], which doesn't go through the [`IoApicControl`].
+static REGISTERS_LOCK: Spinlock<()> = Spinlock::new(());
 
 /// Control over a single I/O APIC.
 pub struct IoApicControl {
@@ -66,6 +74,10 @@ pub struct IoApicControl {
     maximum_redirection_entry: u8,
 }
 
+// The registers are memory-mapped and can be accessed from any CPU. The `&mut self` requirement
+// of the methods that access them guarantees the absence of concurrent accesses.
+unsafe impl Send for IoApicControl {}
+
 /// Description of an I/O APIC on the hardware.
 ///
 /// Correct description is normally obtained from the ACPI tables provided by the firmware.
@@ -80,12 +92,46 @@ pub struct IoApicDescription {
     pub global_system_interrupt_base: u8,
 }
 
+/// How the I/O APIC detects that an IRQ has been emitted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// The IRQ is emitted when the line changes from inactive to active.
+    Edge,
+    /// The IRQ is emitted as long as the line is active.
+    Level,
+}
+
+/// Which electrical level of the IRQ line is considered as active.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
 /// Access to the configuration of an IRQ in this controller.
 pub struct Irq<'a> {
     control: &'a mut IoApicControl,
     irq_offset: u8,
 }
 
+/// Allows masking and unmasking an IRQ, including from within an interrupt handler.
+///
+/// Contrary to [`Irq::mask`], masking and unmasking preserve the rest of the configuration of
+/// the IRQ.
+#[derive(Copy, Clone)]
+pub struct IrqMask {
+    /// Pointer to the memory-mapped selection register.
+    io_reg_sel_register: *mut u32,
+    /// Pointer to the memory-mapped window register.
+    io_win_register: *mut u32,
+    /// Register containing the lowest 32 bits of the configuration of the IRQ.
+    register: u8,
+}
+
+// Accesses to the registers are synchronized through `REGISTERS_LOCK`.
+unsafe impl Send for IrqMask {}
+unsafe impl Sync for IrqMask {}
+
 /// Initializes a single I/O APIC.
 ///
 /// # Safety
@@ -147,13 +193,25 @@ impl IoApicControl {
     /// Modifies the IRQ definition.
     ///
     /// Keep in mind that `irq_offset` is relative to `self.global_system_interrupt_base`.
-    // TODO: do we need to be able to set Edge/Level and that kind of stuff?
-    fn set_irq(&mut self, irq_offset: u8, destination: ApicId, destination_interrupt: u8) {
+    fn set_irq(
+        &mut self,
+        irq_offset: u8,
+        destination: ApicId,
+        destination_interrupt: u8,
+        trigger_mode: TriggerMode,
+        polarity: Polarity,
+    ) {
         assert!(irq_offset <= self.maximum_redirection_entry);
         assert!(destination_interrupt >= 32);
 
         assert!(destination.get() < (1 << 4)); // Only 4bits are valid.
-        let value = (u64::from(destination.get()) << 56) | u64::from(destination_interrupt);
+        let mut value = (u64::from(destination.get()) << 56) | u64::from(destination_interrupt);
+        if polarity == Polarity::ActiveLow {
+            value |= 1 << 13;
+        }
+        if trigger_mode == TriggerMode::Level {
+            value |= 1 << 15;
+        }
 
         let register_base = 0x10u8
             .checked_add(irq_offset.checked_mul(2).unwrap())
@@ -174,12 +232,57 @@ impl IoApicControl {
         }
     }
 
+    /// Returns true if the given IRQ is masked, in other words if it doesn't trigger any
+    /// interrupt.
+    ///
+    /// Keep in mind that `irq_offset` is relative to `self.global_system_interrupt_base`.
+    fn is_irq_masked(&mut self, irq_offset: u8) -> bool {
+        assert!(irq_offset <= self.maximum_redirection_entry);
+        let register_base = 0x10u8
+            .checked_add(irq_offset.checked_mul(2).unwrap())
+            .unwrap();
+        let value = unsafe { self.read_register(register_base) };
+        (value & (1 << 16)) != 0
+    }
+
+    /// Masks the given IRQ.
+    ///
+    /// Keep in mind that `irq_offset` is relative to `self.global_system_interrupt_base`.
+    fn mask_irq(&mut self, irq_offset: u8) {
+        assert!(irq_offset <= self.maximum_redirection_entry);
+        let register_base = 0x10u8
+            .checked_add(irq_offset.checked_mul(2).unwrap())
+            .unwrap();
+        unsafe {
+            self.write_register(register_base, 1 << 16);
+        }
+    }
+
+    unsafe fn read_register(&mut self, reg_num: u8) -> u32 {
+        let (sel, win) = (self.io_reg_sel_register, self.io_win_register);
+        with_registers(|| {
+            sel.write_volatile(u32::from(reg_num));
+            win.read_volatile()
+        })
+    }
+
     unsafe fn write_register(&mut self, reg_num: u8, value: u32) {
-        self.io_reg_sel_register.write_volatile(u32::from(reg_num));
-        self.io_win_register.write_volatile(value)
+        let (sel, win) = (self.io_reg_sel_register, self.io_win_register);
+        with_registers(|| {
+            sel.write_volatile(u32::from(reg_num));
+            win.write_volatile(value)
+        })
     }
 }
 
+/// Runs `f` with interrupts disabled and [`REGISTERS_LOCK`] locked.
+fn with_registers<T>(f: impl FnOnce() -> T) -> T {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _lock = REGISTERS_LOCK.lock();
+        f()
+    })
+}
+
 impl<'a> Irq<'a> {
     /// Sets what happens when this IRQ is triggered.
     ///
@@ -187,8 +290,67 @@ impl<'a> Irq<'a> {
     ///
     /// Panics if `destination_interrupt` is inferior to 32.
     ///
-    pub fn set_destination(&mut self, destination: ApicId, destination_interrupt: u8) {
-        self.control
-            .set_irq(self.irq_offset, destination, destination_interrupt)
+    pub fn set_destination(
+        &mut self,
+        destination: ApicId,
+        destination_interrupt: u8,
+        trigger_mode: TriggerMode,
+        polarity: Polarity,
+    ) {
+        self.control.set_irq(
+            self.irq_offset,
+            destination,
+            destination_interrupt,
+            trigger_mode,
+            polarity,
+        )
+    }
+
+    /// Returns true if this IRQ is masked, in other words if it doesn't trigger any interrupt.
+    ///
+    /// All IRQs are masked when the machine starts.
+    pub fn is_masked(&mut self) -> bool {
+        self.control.is_irq_masked(self.irq_offset)
+    }
+
+    /// Masks this IRQ, so that it no longer triggers any interrupt.
+    ///
+    /// Calling [`Irq::set_destination`] unmasks it.
+    pub fn mask(&mut self) {
+        self.control.mask_irq(self.irq_offset)
+    }
+
+    /// Returns an object that allows masking and unmasking this IRQ later.
+    pub fn mask_handle(&self) -> IrqMask {
+        IrqMask {
+            io_reg_sel_register: self.control.io_reg_sel_register,
+            io_win_register: self.control.io_win_register,
+            register: 0x10u8
+                .checked_add(self.irq_offset.checked_mul(2).unwrap())
+                .unwrap(),
+        }
+    }
+}
+
+impl IrqMask {
+    /// Masks the IRQ, so that it no longer triggers any interrupt.
+    pub fn mask(&self) {
+        self.update(|value| value | (1 << 16))
+    }
+
+    /// Unmasks the IRQ.
+    pub fn unmask(&self) {
+        self.update(|value| value & !(1 << 16))
+    }
+
+    /// Reads the lowest 32 bits of the configuration of the IRQ, and writes back the value
+    /// returned by `f`.
+    fn update(&self, f: impl FnOnce(u32) -> u32) {
+        with_registers(|| unsafe {
+            self.io_reg_sel_register
+                .write_volatile(u32::from(self.register));
+            let value = self.io_win_register.read_volatile();
+            self.io_win_register.write_volatile(f(value));
+        })
     }
 }
@@ -19,7 +19,10 @@
 //!
 //! See also the documentation of the [`../io_apic`] module for more information.
 
-use crate::arch::x86_64::apic::{io_apic, ApicId};
+use crate::arch::x86_64::apic::{
+    io_apic::{self, Polarity, TriggerMode},
+    ApicId,
+};
 
 use core::convert::TryFrom as _;
 use smallvec::SmallVec;
@@ -39,11 +42,17 @@ pub struct IsaRedirectConfig {
     pub isa_interrupt: u8,
     /// What it's redirected to.
     pub new_interrupt: u8,
+    /// Trigger mode of the new interrupt.
+    pub trigger_mode: TriggerMode,
+    /// Polarity of the new interrupt.
+    pub polarity: Polarity,
 }
 
 /// Access to the configuration of an IRQ.
 pub struct Irq<'a> {
     inner: io_apic::Irq<'a>,
+    trigger_mode: TriggerMode,
+    polarity: Polarity,
 }
 
 /// Initializes all the I/O APICs.
@@ -92,6 +101,18 @@ pub unsafe fn init_from_acpi(info: &acpi::interrupt::Apic) -> IoApicsControl {
             .map(|ov| IsaRedirectConfig {
                 isa_interrupt: ov.isa_source,
                 new_interrupt: u8::try_from(ov.global_system_interrupt).unwrap(),
+                // "Same as bus" refers to the ISA bus, whose IRQs are edge-triggered and
+                // active high.
+                trigger_mode: match ov.trigger_mode {
+                    acpi::interrupt::TriggerMode::SameAsBus
+                    | acpi::interrupt::TriggerMode::Edge => TriggerMode::Edge,
+                    acpi::interrupt::TriggerMode::Level => TriggerMode::Level,
+                },
+                polarity: match ov.polarity {
+                    acpi::interrupt::Polarity::SameAsBus
+                    | acpi::interrupt::Polarity::ActiveHigh => Polarity::ActiveHigh,
+                    acpi::interrupt::Polarity::ActiveLow => Polarity::ActiveLow,
+                },
             }),
     )
 }
@@ -101,17 +122,17 @@ impl IoApicsControl {
     ///
     /// ISA IRQs are considered legacy, but are still used by some hardware.
     pub fn isa_irq(&mut self, isa_irq: u8) -> Option<Irq> {
-        let target = self
-            .legacy_redirects
+        let irq = self.isa_irq_redirect(isa_irq);
+        self.irq(irq)
+    }
+
+    /// Returns the IRQ that the given ISA IRQ is redirected to.
+    pub fn isa_irq_redirect(&self, isa_irq: u8) -> u8 {
+        self.legacy_redirects
             .iter()
             .find(|red| red.isa_interrupt == isa_irq)
-            .map(|red| red.new_interrupt);
-
-        if let Some(dest) = target {
-            self.irq(dest)
-        } else {
-            self.irq(isa_irq)
-        }
+            .map(|red| red.new_interrupt)
+            .unwrap_or(isa_irq)
     }
 
     /// Returns all the IRQs supported by the I/O APICs.
@@ -124,11 +145,38 @@ impl IoApicsControl {
 
     /// Gives access to an object designating the configuration of an IRQ.
     ///
+    /// If an ISA IRQ is redirected to this IRQ, the trigger mode and polarity of the
+    /// redirection are used. The first 16 IRQs that aren't the target of a redirection
+    /// correspond to the ISA IRQ of the same number, and are configured as edge-triggered and
+    /// active high.
+    ///
+    /// All the other IRQs are assumed to be used by PCI devices, and are configured as
+    /// level-triggered and active low. The firmware describes the actual routing of PCI
+    /// interrupts through the `_PRT` objects of the ACPI namespace, which we can't read without
+    /// an AML interpreter.
+    ///
     /// Returns `None` if none of the I/O APICs can handle the given IRQ.
     pub fn irq(&mut self, irq: u8) -> Option<Irq> {
+        let redirect = self
+            .legacy_redirects
+            .iter()
+            .find(|red| red.new_interrupt == irq)
+            .map(|red| (red.trigger_mode, red.polarity));
+        let (trigger_mode, polarity) = match redirect {
+            Some(config) => config,
+            None if irq < 16 && self.isa_irq_redirect(irq) == irq => {
+                (TriggerMode::Edge, Polarity::ActiveHigh)
+            }
+            None => (TriggerMode::Level, Polarity::ActiveLow),
+        };
+
         for io_apic in self.io_apics.iter_mut() {
             if let Some(inner) = io_apic.irq(irq) {
-                return Some(Irq { inner });
+                return Some(Irq {
+                    inner,
+                    trigger_mode,
+                    polarity,
+                });
             }
         }
 
@@ -145,7 +193,34 @@ impl<'a> Irq<'a> {
     ///
     // TODO: add some kind of assignment system, so that we don't accidentally erase a previous assignment
     pub fn set_destination(&mut self, destination: ApicId, destination_interrupt: u8) {
-        self.inner
-            .set_destination(destination, destination_interrupt);
+        self.inner.set_destination(
+            destination,
+            destination_interrupt,
+            self.trigger_mode,
+            self.polarity,
+        );
+    }
+
+    /// Returns the trigger mode that this IRQ is configured with by
+    /// [`Irq::set_destination`].
+    pub fn trigger_mode(&self) -> TriggerMode {
+        self.trigger_mode
+    }
+
+    /// Returns true if this IRQ is masked, in other words if it doesn't trigger any interrupt.
+    pub fn is_masked(&mut self) -> bool {
+        self.inner.is_masked()
+    }
+
+    /// Masks this IRQ, so that it no longer triggers any interrupt.
+    ///
+    /// Calling [`Irq::set_destination`] unmasks it.
+    pub fn mask(&mut self) {
+        self.inner.mask()
+    }
+
+    /// Returns an object that allows masking and unmasking this IRQ later.
+    pub fn mask_handle(&self) -> io_apic::IrqMask {
+        self.inner.mask_handle()
     }
 }
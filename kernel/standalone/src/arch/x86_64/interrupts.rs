@@ -29,16 +29,22 @@
 //! the next one can be issued. By re-registering a `Waker` before looking for the interrupt
 //! reason, there is no risk of losing information.
 //!
+//! Additionally, each interrupt vector has a counter that is incremented every time the
+//! interrupt happens. See [`ReservedInterruptVector::num_triggered`]. By comparing the value of
+//! this counter with a previous value after the `Waker` has been registered, it is possible to
+//! know for sure whether an interrupt has happened in-between.
+//!
 
-use crate::arch::x86_64::apic::local;
+use crate::arch::x86_64::apic::{io_apic, local};
 
 use core::{
     convert::TryFrom as _,
     fmt,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     task::Waker,
 };
 use futures::task::AtomicWaker;
+use spinning_top::Spinlock;
 use x86_64::structures::idt;
 
 /// Reserves an interrupt in the table.
@@ -100,6 +106,29 @@ impl ReservedInterruptVector {
         debug_assert!(self.interrupt >= 32);
         WAKERS[usize::from(self.interrupt - 32)].register(waker);
     }
+
+    /// Returns the number of times this interrupt vector has been triggered since the machine
+    /// has booted, wrapping around on overflow.
+    ///
+    /// The value is only meaningful when compared to a previous value returned by this method.
+    pub fn num_triggered(&self) -> u64 {
+        debug_assert!(self.interrupt >= 32);
+        COUNTERS[usize::from(self.interrupt - 32)].load(Ordering::SeqCst)
+    }
+
+    /// Configures the interrupt handler to mask the given IRQ whenever this interrupt happens,
+    /// or stops doing so if `None`.
+    ///
+    /// This is necessary for level-triggered IRQs, which would otherwise be triggered again as
+    /// soon as the handler returns, until the device is serviced.
+    pub fn set_mask_on_trigger(&self, irq: Option<io_apic::IrqMask>) {
+        debug_assert!(self.interrupt >= 32);
+        // Interrupts are disabled in order to prevent the handler from running on this CPU
+        // while the lock is held.
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            MASK_ON_TRIGGER.lock()[usize::from(self.interrupt - 32)] = irq;
+        });
+    }
 }
 
 impl fmt::Debug for ReservedInterruptVector {
@@ -114,6 +143,7 @@ impl Drop for ReservedInterruptVector {
     fn drop(&mut self) {
         // TODO: should we actually unreserve interrupts? it means that it could later be
         //       re-registered, and spurious interrupts triggered
+        self.set_mask_on_trigger(None);
         let _was_reserved =
             RESERVATIONS[usize::from(self.interrupt - 32)].swap(false, Ordering::Relaxed);
         debug_assert!(_was_reserved);
@@ -165,6 +195,10 @@ lazy_static::lazy_static! {
             }};
             ($entry:expr, $n:expr) => {{
                 extern "x86-interrupt" fn handler(_: &mut idt::InterruptStackFrame) {
+                    if let Some(irq) = MASK_ON_TRIGGER.lock()[$n - 32] {
+                        irq.mask();
+                    }
+                    COUNTERS[$n - 32].fetch_add(1, Ordering::SeqCst);
                     WAKERS[$n - 32].wake();
                     if END_OF_INTERRUPT[$n - 32].load(Ordering::Relaxed) {
                         unsafe { local::end_of_interrupt(); }
@@ -761,6 +795,234 @@ static WAKERS: [AtomicWaker; 256 - 32] = [
     AtomicWaker::new(),
 ];
 
+/// For each interrupt vector, the number of times this interrupt has been triggered.
+static COUNTERS: [AtomicU64; 256 - 32] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
 /// For each interrupt vector, a boolean indicating whether or not this vector is reserved.
 ///
 /// Note that this could be a smaller array by grouping all the booleans into bytes, for this
@@ -1220,3 +1482,9 @@ static END_OF_INTERRUPT: [AtomicBool; 256 - 32] = [
     AtomicBool::new(false),
     AtomicBool::new(false),
 ];
+
+/// For each interrupt vector, an IRQ to mask when the interrupt happens.
+///
+/// Must only be locked while interrupts are disabled.
+static MASK_ON_TRIGGER: Spinlock<[Option<io_apic::IrqMask>; 256 - 32]> =
+    Spinlock::new([None; 256 - 32]);
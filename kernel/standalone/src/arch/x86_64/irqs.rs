@@ -0,0 +1,223 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Subscriptions to hardware IRQs.
+//!
+//! This module ties together the I/O APICs and the interrupts table. Subscribing to an IRQ
+//! reserves an interrupt vector, configures the I/O APICs to deliver the IRQ to this vector, and
+//! returns an [`IrqStream`] that yields an item whenever the IRQ has been triggered.
+//!
+//! Interrupts are counted by the interrupt handler, and are therefore never missed, even if the
+//! [`IrqStream`] isn't polled.
+//!
+//! Only one subscription can exist for any given IRQ. IRQs that have been configured by other
+//! parts of the kernel, such as the PIT, can't be subscribed to.
+//!
+//! Message-signalled interrupts (MSI) don't go through the I/O APICs. Subscribing to them only
+//! reserves an interrupt vector. The device must then be configured to write the value returned
+//! by [`IrqStream::msi_message`] in order to trigger the interrupt.
+//!
+//! # Trigger mode
+//!
+//! IRQs that an ISA IRQ is redirected to use the trigger mode and polarity that the ACPI tables
+//! report for this redirection, and the other ISA IRQs are edge-triggered. All the other IRQs
+//! are assumed to come from PCI devices, and are configured as level-triggered and active low.
+//! See [`io_apics::IoApicsControl::irq`].
+//!
+//! A level-triggered IRQ keeps being triggered as long as the device hasn't been serviced.
+//! The interrupt handler therefore masks it whenever it is triggered, and it stays masked until
+//! [`IrqStream::acknowledge`] is called.
+
+use crate::arch::{
+    x86_64::{
+        apic::{io_apic, io_apics, local},
+        interrupts,
+    },
+    InterruptErr,
+};
+
+use core::{
+    convert::TryFrom as _,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures::stream::Stream;
+use hashbrown::HashSet;
+use nohash_hasher::BuildNoHashHasher;
+use redshirt_hardware_interface::ffi::{InterruptSource, MsiMessage};
+use spinning_top::Spinlock;
+
+/// Control over the subscriptions to IRQs.
+pub struct Irqs {
+    /// Used to determine the destination of the IRQs.
+    local_apics: &'static local::LocalApicsControl,
+    /// Configuration of the IRQs.
+    io_apics: Spinlock<io_apics::IoApicsControl>,
+    /// List of IRQs that have an [`IrqStream`] alive.
+    subscribed: Spinlock<HashSet<u8, BuildNoHashHasher<u8>>>,
+}
+
+/// Active subscription to an IRQ. Masks the IRQ when destroyed.
+pub struct IrqStream {
+    /// Where the IRQ has been subscribed.
+    irqs: &'static Irqs,
+    /// IRQ that is routed to [`IrqStream::vector`], or `None` for message-signalled
+    /// interrupts.
+    irq: Option<u8>,
+    /// If this is a message-signalled interrupt, what the device must write.
+    msi: Option<MsiMessage>,
+    /// If the IRQ is level-triggered, allows unmasking it after the interrupt handler has
+    /// masked it.
+    level_triggered: Option<io_apic::IrqMask>,
+    /// Interrupt vector that the IRQ is routed to.
+    vector: interrupts::ReservedInterruptVector,
+    /// Value of [`interrupts::ReservedInterruptVector::num_triggered`] the last time we yielded
+    /// an item.
+    last_seen: u64,
+}
+
+/// Initializes the IRQs subscription system.
+///
+/// IRQs that are already configured in `io_apics` are considered as being used by the kernel
+/// and can't be subscribed to.
+pub fn init(
+    local_apics: &'static local::LocalApicsControl,
+    io_apics: io_apics::IoApicsControl,
+) -> Irqs {
+    Irqs {
+        local_apics,
+        io_apics: Spinlock::new(io_apics),
+        subscribed: Spinlock::new(HashSet::default()),
+    }
+}
+
+impl Irqs {
+    /// Starts listening to the given source of interrupts.
+    ///
+    /// You must call [`interrupts::load_idt`] on at least one CPU for the stream to work.
+    pub fn subscribe(&'static self, source: InterruptSource) -> Result<IrqStream, InterruptErr> {
+        let mut subscribed = self.subscribed.lock();
+        let mut io_apics = self.io_apics.lock();
+
+        let irq = match source {
+            InterruptSource::IsaIrq(isa_irq) => io_apics.isa_irq_redirect(isa_irq),
+            InterruptSource::Irq(irq) => u8::try_from(irq).map_err(|_| InterruptErr::Unsupported)?,
+            InterruptSource::Msi => {
+                let vector =
+                    interrupts::reserve_any_vector(true).map_err(|_| InterruptErr::Exhausted)?;
+                // Destination is in bits 12 to 19 of the address, and the vector in the lowest
+                // 8 bits of the data. All other fields are left to 0, which corresponds to a
+                // fixed, edge-triggered delivery mode.
+                let destination = self.local_apics.current_apic_id(); // TODO: dispatch to any CPU?
+                let msi = MsiMessage {
+                    address: 0xfee0_0000 | (u64::from(destination.get()) << 12),
+                    data: u32::from(vector.interrupt_num()),
+                };
+                let last_seen = vector.num_triggered();
+                return Ok(IrqStream {
+                    irqs: self,
+                    irq: None,
+                    msi: Some(msi),
+                    level_triggered: None,
+                    vector,
+                    last_seen,
+                });
+            }
+        };
+
+        let mut irq_config = io_apics.irq(irq).ok_or(InterruptErr::Unsupported)?;
+        if subscribed.contains(&irq) || !irq_config.is_masked() {
+            return Err(InterruptErr::Reserved);
+        }
+
+        let vector = interrupts::reserve_any_vector(true).map_err(|_| InterruptErr::Exhausted)?;
+        let level_triggered = if irq_config.trigger_mode() == io_apic::TriggerMode::Level {
+            let mask = irq_config.mask_handle();
+            vector.set_mask_on_trigger(Some(mask));
+            Some(mask)
+        } else {
+            None
+        };
+        // Note that the counter must be read before the IRQ is routed, otherwise we might miss
+        // an interrupt.
+        let last_seen = vector.num_triggered();
+        irq_config.set_destination(
+            self.local_apics.current_apic_id(), // TODO: instead dispatch to any CPU?
+            vector.interrupt_num(),
+        );
+        subscribed.insert(irq);
+
+        Ok(IrqStream {
+            irqs: self,
+            irq: Some(irq),
+            msi: None,
+            level_triggered,
+            vector,
+            last_seen,
+        })
+    }
+}
+
+impl IrqStream {
+    /// If this stream is for message-signalled interrupts, returns the write that the device
+    /// must perform in order to trigger an interrupt.
+    pub fn msi_message(&self) -> Option<&MsiMessage> {
+        self.msi.as_ref()
+    }
+
+    /// Indicates that the device has been serviced. Unmasks the IRQ if it is level-triggered.
+    ///
+    /// Has no effect for other interrupts.
+    pub fn acknowledge(&self) {
+        if let Some(mask) = &self.level_triggered {
+            mask.unmask();
+        }
+    }
+}
+
+impl Stream for IrqStream {
+    type Item = u32;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u32>> {
+        // The waker must be registered before reading the counter, otherwise an interrupt
+        // happening in-between would not wake up the task.
+        self.vector.register_waker(cx.waker());
+
+        let num_triggered = self.vector.num_triggered();
+        let diff = num_triggered.wrapping_sub(self.last_seen);
+        if diff == 0 {
+            return Poll::Pending;
+        }
+
+        self.last_seen = num_triggered;
+        Poll::Ready(Some(u32::try_from(diff).unwrap_or(u32::max_value())))
+    }
+}
+
+impl Drop for IrqStream {
+    fn drop(&mut self) {
+        let irq = match self.irq {
+            Some(irq) => irq,
+            None => return,
+        };
+
+        let mut subscribed = self.irqs.subscribed.lock();
+        if let Some(mut irq_config) = self.irqs.io_apics.lock().irq(irq) {
+            irq_config.mask();
+        }
+        let _was_in = subscribed.remove(&irq);
+        debug_assert!(_was_in);
+    }
+}
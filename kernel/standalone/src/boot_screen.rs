@@ -0,0 +1,79 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Arbitration of the screen set up by the bootloader.
+//!
+//! The screen initially displays the kernel logs. The console takes it over the first time a
+//! process reads from the console, and the framebuffer handler the first time a process creates
+//! a framebuffer. The framebuffer handler has precedence over the console: once it has taken
+//! over the screen, the console stops drawing on it and goes back to printing through the
+//! kernel logs.
+
+use crate::arch::PlatformSpecific;
+
+use spinning_top::{Spinlock, SpinlockGuard};
+
+/// Which component draws on the screen set up by the bootloader.
+///
+/// Components that come later in this list have precedence over the ones before.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Owner {
+    /// The kernel logs.
+    Logs,
+    /// The native program that handles the `console` interface.
+    Console,
+    /// The native program that handles the `framebuffer` interface.
+    Framebuffer,
+}
+
+/// Keeps track of the [`Owner`] of the screen set up by the bootloader.
+pub struct BootScreen {
+    /// Component that currently draws on the screen.
+    owner: Spinlock<Owner>,
+}
+
+impl BootScreen {
+    /// Initializes a new `BootScreen`. The screen belongs to the kernel logs.
+    pub fn new() -> Self {
+        BootScreen {
+            owner: Spinlock::new(Owner::Logs),
+        }
+    }
+
+    /// Returns the component that currently draws on the screen.
+    ///
+    /// [`BootScreen::take`] blocks as long as the returned guard is alive, which lets the owner
+    /// finish drawing before the screen is taken over.
+    pub fn lock(&self) -> SpinlockGuard<Owner> {
+        self.owner.lock()
+    }
+
+    /// Gives the screen to `owner`, unless a component with precedence over it already has it.
+    ///
+    /// Returns `true` if `owner` didn't have the screen and now has it. The kernel logs are
+    /// stopped the first time the screen is taken over.
+    pub fn take(&self, owner: Owner, platform_specific: &impl PlatformSpecific) -> bool {
+        let mut current = self.owner.lock();
+        if *current >= owner {
+            return false;
+        }
+
+        if *current == Owner::Logs {
+            platform_specific.release_boot_framebuffer();
+        }
+        *current = owner;
+        true
+    }
+}
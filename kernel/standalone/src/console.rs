@@ -0,0 +1,352 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `console` interface.
+//!
+//! The console is drawn on the screen set up by the bootloader, and reads the keyboard through
+//! the `hid` interface.
+//!
+//! In order for the kernel logs to remain visible while no process is interacting with the
+//! console, the screen is only taken over the first time a process reads from the console.
+//! Until then, if there is no screen, or if the `framebuffer` interface handler has taken over
+//! the screen (see the [`boot_screen`](crate::boot_screen) module), the text written to the
+//! console is printed through the kernel logs, without its escape sequences.
+
+use crate::arch::PlatformSpecific;
+use crate::boot_screen::{BootScreen, Owner};
+use crate::klog::Terminal;
+
+use alloc::{boxed::Box, collections::VecDeque, string::String, sync::Arc, vec::Vec};
+use core::{convert::TryFrom as _, fmt::Write as _, pin::Pin, sync::atomic, task::Poll};
+use crossbeam_queue::SegQueue;
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_console_interface::ffi::{ConsoleMessage, ConsoleSize, INTERFACE};
+use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_hid_interface::ffi as hid_ffi;
+use spinning_top::Spinlock;
+
+mod ansi;
+mod keyboard;
+
+/// Size reported to processes when there is no screen.
+const LOG_CONSOLE_SIZE: ConsoleSize = ConsoleSize {
+    columns: 80,
+    rows: 24,
+};
+
+/// State machine for `console` interface messages handling.
+pub struct ConsoleHandler<TPlat> {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// If true, we have emitted a message asking for the next input event, and are waiting for
+    /// its response.
+    hid_request_pending: atomic::AtomicBool,
+    /// Platform-specific hooks.
+    platform_specific: Pin<Arc<TPlat>>,
+    /// List of messages waiting to be emitted with `next_event`.
+    pending_messages: SegQueue<(MessageId, Result<EncodedMessage, ()>)>,
+    /// Waken up when `pending_messages` is modified.
+    wakeup: AtomicWaker,
+    /// Output and input of the console.
+    inner: Spinlock<Inner>,
+}
+
+struct Inner {
+    /// Terminal on the screen set up by the bootloader, if any.
+    terminal: Option<Terminal>,
+    /// Arbitrates the screen with the kernel logs and the `framebuffer` interface handler.
+    boot_screen: Arc<BootScreen>,
+    /// Line being written, when the text is printed through the kernel logs.
+    log_line: String,
+    /// Parser for the text written by processes.
+    parser: ansi::Parser,
+    /// Color of the characters printed on the terminal.
+    color: [u8; 3],
+    /// State of the modifier keys.
+    keyboard: keyboard::Keyboard,
+    /// Line being typed by the user, not including the final `\n`.
+    line: Vec<u8>,
+    /// Lines completed by the user that haven't been delivered yet.
+    input: VecDeque<u8>,
+    /// Read requests that haven't been answered yet, in order, with the process that has emitted
+    /// them and the maximum number of bytes to answer.
+    pending_reads: VecDeque<(MessageId, Pid, u32)>,
+}
+
+impl<TPlat> ConsoleHandler<TPlat>
+where
+    TPlat: PlatformSpecific,
+{
+    /// Initializes the new state machine for console messages handling.
+    pub fn new(platform_specific: Pin<Arc<TPlat>>, boot_screen: Arc<BootScreen>) -> Self {
+        // Safety: we only draw on the framebuffer while we own it, after having disabled the
+        // kernel logs on it.
+        let terminal = platform_specific
+            .as_ref()
+            .boot_framebuffer()
+            .map(|info| unsafe { Terminal::new(info) });
+
+        ConsoleHandler {
+            registered: atomic::AtomicBool::new(false),
+            hid_request_pending: atomic::AtomicBool::new(false),
+            platform_specific,
+            pending_messages: SegQueue::new(),
+            wakeup: AtomicWaker::new(),
+            inner: Spinlock::new(Inner {
+                terminal,
+                boot_screen,
+                log_line: String::new(),
+                parser: ansi::Parser::new(),
+                color: ansi::DEFAULT_COLOR,
+                keyboard: keyboard::Keyboard::new(),
+                line: Vec::new(),
+                input: VecDeque::new(),
+                pending_reads: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Processes a message, and queues its answer if it can be answered immediately.
+    fn process_message(
+        &self,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: ConsoleMessage,
+    ) {
+        let mut inner = self.inner.lock();
+
+        match (message, message_id) {
+            (ConsoleMessage::Write(data), _) => {
+                for byte in data {
+                    if let Some(action) = inner.parser.push(byte) {
+                        inner.apply(action, &*self.platform_specific);
+                    }
+                }
+            }
+            (ConsoleMessage::Read { max_len }, Some(message_id)) => {
+                if inner.terminal.is_some()
+                    && inner
+                        .boot_screen
+                        .take(Owner::Console, &*self.platform_specific)
+                {
+                    inner.terminal.as_mut().unwrap().clear();
+                }
+
+                if max_len == 0 {
+                    self.push_answer(message_id, Ok(Vec::<u8>::new().encode()));
+                } else {
+                    inner.pending_reads.push_back((message_id, emitter_pid, max_len));
+                    inner.answer_reads(|id, answer| self.push_answer(id, answer));
+                }
+            }
+            (ConsoleMessage::Size, Some(message_id)) => {
+                let size = match &inner.terminal {
+                    Some(terminal) => {
+                        let (columns, rows) = terminal.size();
+                        ConsoleSize { columns, rows }
+                    }
+                    None => LOG_CONSOLE_SIZE,
+                };
+                self.push_answer(message_id, Ok(size.encode()));
+            }
+            (_, None) => {}
+        }
+    }
+
+    /// Processes an event received from the `hid` interface.
+    fn process_hid_event(&self, event: hid_ffi::HidEvent) {
+        let (usage, state) = match event {
+            hid_ffi::HidEvent::Input {
+                event:
+                    hid_ffi::DeviceEvent::Key {
+                        usage: Some(usage),
+                        state,
+                        ..
+                    },
+                ..
+            } => (usage, state),
+            _ => return,
+        };
+
+        let mut inner = self.inner.lock();
+        let chr = match inner.keyboard.key(usage, state) {
+            Some(c) => c,
+            None => return,
+        };
+
+        // Echo what the user types.
+        match chr {
+            b'\n' => {
+                inner.apply(ansi::Action::NewLine, &*self.platform_specific);
+                let inner = &mut *inner;
+                inner.input.extend(inner.line.drain(..));
+                inner.input.push_back(b'\n');
+                inner.answer_reads(|id, answer| self.push_answer(id, answer));
+            }
+            0x08 => {
+                if inner.line.pop().is_some() {
+                    inner.apply(ansi::Action::Backspace, &*self.platform_specific);
+                    inner.apply(ansi::Action::Print(b' '), &*self.platform_specific);
+                    inner.apply(ansi::Action::Backspace, &*self.platform_specific);
+                }
+            }
+            b'\t' => {
+                inner.line.push(chr);
+                inner.apply(ansi::Action::Tab, &*self.platform_specific);
+            }
+            _ => {
+                inner.line.push(chr);
+                inner.apply(ansi::Action::Print(chr), &*self.platform_specific);
+            }
+        }
+    }
+
+    /// Queues an answer to a message and wakes up the task that calls `next_event`.
+    fn push_answer(&self, message_id: MessageId, answer: Result<EncodedMessage, ()>) {
+        self.pending_messages.push((message_id, answer));
+        self.wakeup.wake();
+    }
+}
+
+impl Inner {
+    /// Performs an action on the terminal if we have taken over the screen, or on the kernel
+    /// logs otherwise.
+    fn apply(&mut self, action: ansi::Action, platform_specific: &impl PlatformSpecific) {
+        // Keeping the owner locked prevents the screen from being taken over while we draw.
+        let owner = self.boot_screen.lock();
+        let terminal = match (&mut self.terminal, *owner == Owner::Console) {
+            (Some(terminal), true) => terminal,
+            _ => {
+                match action {
+                    ansi::Action::Print(chr) => self.log_line.push(char::from(chr)),
+                    ansi::Action::NewLine => {
+                        platform_specific.write_log(&self.log_line);
+                        self.log_line.clear();
+                    }
+                    _ => {}
+                }
+                return;
+            }
+        };
+
+        let (column, row) = terminal.cursor();
+        match action {
+            ansi::Action::Print(chr) => {
+                let _ = terminal.printer(self.color).write_char(char::from(chr));
+            }
+            ansi::Action::NewLine => {
+                let _ = terminal.printer(self.color).write_char('\n');
+            }
+            ansi::Action::CarriageReturn => terminal.set_cursor(0, row),
+            ansi::Action::Backspace => terminal.set_cursor(column.saturating_sub(1), row),
+            ansi::Action::Tab => terminal.set_cursor((column / 8 + 1) * 8, row),
+            ansi::Action::SetColor(color) => self.color = color,
+            ansi::Action::MoveCursor { column, row } => terminal.set_cursor(column, row),
+            ansi::Action::ClearScreen => terminal.clear(),
+            ansi::Action::ClearToEndOfLine => terminal.clear_to_end_of_line(),
+        }
+    }
+
+    /// Answers the pending read requests for as long as there is input available.
+    fn answer_reads(&mut self, mut push_answer: impl FnMut(MessageId, Result<EncodedMessage, ()>)) {
+        while !self.input.is_empty() {
+            let (message_id, _, max_len) = match self.pending_reads.pop_front() {
+                Some(r) => r,
+                None => break,
+            };
+
+            let len = usize::try_from(max_len).unwrap_or(usize::max_value());
+            let len = len.min(self.input.len());
+            let data = self.input.drain(..len).collect::<Vec<_>>();
+            push_answer(message_id, Ok(data.encode()));
+        }
+    }
+}
+
+impl<'a, TPlat> NativeProgramRef<'a> for &'a ConsoleHandler<TPlat>
+where
+    TPlat: PlatformSpecific,
+{
+    type Future =
+        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event(self) -> Self::Future {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.wakeup.register(cx.waker());
+
+            if !self.hid_request_pending.swap(true, atomic::Ordering::Relaxed) {
+                return Poll::Ready(NativeProgramEvent::Emit {
+                    interface: hid_ffi::INTERFACE,
+                    message_id_write: Some(DummyMessageIdWrite),
+                    message: hid_ffi::HidMessage::NextEvent.encode(),
+                });
+            }
+
+            if let Ok((message_id, answer)) = self.pending_messages.pop() {
+                return Poll::Ready(NativeProgramEvent::Answer {
+                    message_id,
+                    answer,
+                });
+            }
+
+            Poll::Pending
+        }))
+    }
+
+    fn interface_message(
+        self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        match ConsoleMessage::decode(message) {
+            Ok(msg) => self.process_message(message_id, emitter_pid, msg),
+            Err(_) => {
+                if let Some(message_id) = message_id {
+                    self.push_answer(message_id, Err(()));
+                }
+            }
+        }
+    }
+
+    fn process_destroyed(self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        inner.pending_reads.retain(|(_, p, _)| *p != pid);
+    }
+
+    fn message_response(self, _: MessageId, response: Result<EncodedMessage, ()>) {
+        // The only messages we emit that expect a response are the `hid` interface requests.
+        // If the `hid` interface handler returns an error, we stop reading the keyboard.
+        if let Ok(event) = response.and_then(|r| hid_ffi::HidEvent::decode(r).map_err(|_| ())) {
+            self.process_hid_event(event);
+            self.hid_request_pending.store(false, atomic::Ordering::Relaxed);
+            self.wakeup.wake();
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Parsing of the control characters and escape sequences supported by the `console` interface.
+
+use smallvec::SmallVec;
+
+/// Color used when no color has been set.
+pub const DEFAULT_COLOR: [u8; 3] = [0xdd, 0xdd, 0xdd];
+
+/// Colors corresponding to `ESC [ 30 m` to `ESC [ 37 m`.
+const COLORS: [[u8; 3]; 8] = [
+    [0x00, 0x00, 0x00],
+    [0xaa, 0x00, 0x00],
+    [0x00, 0xaa, 0x00],
+    [0xaa, 0x55, 0x00],
+    [0x00, 0x00, 0xaa],
+    [0xaa, 0x00, 0xaa],
+    [0x00, 0xaa, 0xaa],
+    [0xaa, 0xaa, 0xaa],
+];
+
+/// Colors corresponding to `ESC [ 90 m` to `ESC [ 97 m`.
+const BRIGHT_COLORS: [[u8; 3]; 8] = [
+    [0x55, 0x55, 0x55],
+    [0xff, 0x55, 0x55],
+    [0x55, 0xff, 0x55],
+    [0xff, 0xff, 0x55],
+    [0x55, 0x55, 0xff],
+    [0xff, 0x55, 0xff],
+    [0x55, 0xff, 0xff],
+    [0xff, 0xff, 0xff],
+];
+
+/// Something to do on the terminal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Prints a printable ASCII character at the cursor position and moves the cursor.
+    Print(u8),
+    /// Moves the cursor to the beginning of the next line.
+    NewLine,
+    /// Moves the cursor to the beginning of the current line.
+    CarriageReturn,
+    /// Moves the cursor one column to the left.
+    Backspace,
+    /// Moves the cursor to the next column that is a multiple of 8.
+    Tab,
+    /// Changes the color of the characters printed afterwards.
+    SetColor([u8; 3]),
+    /// Moves the cursor to the given position. Columns and rows start at 0.
+    MoveCursor { column: u32, row: u32 },
+    /// Clears the screen.
+    ClearScreen,
+    /// Clears the line from the cursor to its end.
+    ClearToEndOfLine,
+}
+
+/// Turns a stream of bytes into [`Action`]s.
+pub struct Parser {
+    state: State,
+    /// Parameters of the control sequence being parsed that have been completed.
+    params: SmallVec<[u32; 4]>,
+    /// Parameter of the control sequence currently being parsed, if any digit has been received.
+    current_param: Option<u32>,
+}
+
+enum State {
+    /// Not within an escape sequence.
+    Ground,
+    /// The last byte was `ESC`.
+    Escape,
+    /// Within a sequence starting with `ESC [`.
+    ControlSequence,
+}
+
+impl Parser {
+    /// Initializes a parser that isn't within an escape sequence.
+    pub fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            params: SmallVec::new(),
+            current_param: None,
+        }
+    }
+
+    /// Processes the next byte of the stream and returns the action it triggers, if any.
+    ///
+    /// Characters that aren't ASCII can't be printed, and are turned into `?`.
+    pub fn push(&mut self, byte: u8) -> Option<Action> {
+        match self.state {
+            State::Ground => match byte {
+                0x1b => {
+                    self.state = State::Escape;
+                    None
+                }
+                b'\n' => Some(Action::NewLine),
+                b'\r' => Some(Action::CarriageReturn),
+                0x08 => Some(Action::Backspace),
+                b'\t' => Some(Action::Tab),
+                0x20..=0x7e => Some(Action::Print(byte)),
+                // First byte of a non-ASCII UTF-8 character. The bytes that follow it are in the
+                // range `0x80..=0xbf`, and are ignored.
+                0xc0..=0xff => Some(Action::Print(b'?')),
+                _ => None,
+            },
+            State::Escape => {
+                if byte == b'[' {
+                    self.state = State::ControlSequence;
+                    self.params.clear();
+                    self.current_param = None;
+                } else {
+                    // Escape sequences other than control sequences aren't supported.
+                    self.state = State::Ground;
+                }
+                None
+            }
+            State::ControlSequence => match byte {
+                b'0'..=b'9' => {
+                    let digit = u32::from(byte - b'0');
+                    let param = self.current_param.unwrap_or(0);
+                    self.current_param = Some(param.saturating_mul(10).saturating_add(digit));
+                    None
+                }
+                b';' => {
+                    self.params.push(self.current_param.take().unwrap_or(0));
+                    None
+                }
+                0x40..=0x7e => {
+                    self.state = State::Ground;
+                    if self.current_param.is_some() || !self.params.is_empty() {
+                        self.params.push(self.current_param.take().unwrap_or(0));
+                    }
+                    self.finish_control_sequence(byte)
+                }
+                _ => None,
+            },
+        }
+    }
+
+    /// Returns the action corresponding to the control sequence that has just been parsed,
+    /// given its final byte.
+    fn finish_control_sequence(&self, final_byte: u8) -> Option<Action> {
+        let param = |n: usize| self.params.get(n).cloned().unwrap_or(0);
+
+        match final_byte {
+            b'm' => {
+                if self.params.is_empty() {
+                    return Some(Action::SetColor(DEFAULT_COLOR));
+                }
+
+                // Only the last color of the sequence matters.
+                self.params.iter().fold(None, |action, param| match *param {
+                    0 | 39 => Some(Action::SetColor(DEFAULT_COLOR)),
+                    p @ 30..=37 => Some(Action::SetColor(COLORS[(p - 30) as usize])),
+                    p @ 90..=97 => Some(Action::SetColor(BRIGHT_COLORS[(p - 90) as usize])),
+                    _ => action,
+                })
+            }
+            b'H' | b'f' => Some(Action::MoveCursor {
+                column: param(1).saturating_sub(1),
+                row: param(0).saturating_sub(1),
+            }),
+            b'J' if param(0) == 2 => Some(Action::ClearScreen),
+            b'K' if param(0) == 0 => Some(Action::ClearToEndOfLine),
+            _ => None,
+        }
+    }
+}
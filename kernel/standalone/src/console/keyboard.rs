@@ -0,0 +1,121 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Conversion of key presses into characters, using the US keyboard layout.
+
+use redshirt_hid_interface::ffi::ButtonState;
+
+/// Characters produced by keys other than letters, without and with shift, indexed by usage
+/// code as defined in the USB HID Usage Tables.
+const KEYS: &[(u16, u8, u8)] = &[
+    (0x1e, b'1', b'!'),
+    (0x1f, b'2', b'@'),
+    (0x20, b'3', b'#'),
+    (0x21, b'4', b'$'),
+    (0x22, b'5', b'%'),
+    (0x23, b'6', b'^'),
+    (0x24, b'7', b'&'),
+    (0x25, b'8', b'*'),
+    (0x26, b'9', b'('),
+    (0x27, b'0', b')'),
+    (0x28, b'\n', b'\n'),
+    (0x2a, 0x08, 0x08),
+    (0x2b, b'\t', b'\t'),
+    (0x2c, b' ', b' '),
+    (0x2d, b'-', b'_'),
+    (0x2e, b'=', b'+'),
+    (0x2f, b'[', b'{'),
+    (0x30, b']', b'}'),
+    (0x31, b'\\', b'|'),
+    (0x33, b';', b':'),
+    (0x34, b'\'', b'"'),
+    (0x35, b'`', b'~'),
+    (0x36, b',', b'<'),
+    (0x37, b'.', b'>'),
+    (0x38, b'/', b'?'),
+    // Keypad. Num Lock is assumed to be on.
+    (0x54, b'/', b'/'),
+    (0x55, b'*', b'*'),
+    (0x56, b'-', b'-'),
+    (0x57, b'+', b'+'),
+    (0x58, b'\n', b'\n'),
+    (0x59, b'1', b'1'),
+    (0x5a, b'2', b'2'),
+    (0x5b, b'3', b'3'),
+    (0x5c, b'4', b'4'),
+    (0x5d, b'5', b'5'),
+    (0x5e, b'6', b'6'),
+    (0x5f, b'7', b'7'),
+    (0x60, b'8', b'8'),
+    (0x61, b'9', b'9'),
+    (0x62, b'0', b'0'),
+    (0x63, b'.', b'.'),
+];
+
+/// Usage code of Caps Lock.
+const CAPS_LOCK: u16 = 0x39;
+/// Usage code of the left shift key.
+const LEFT_SHIFT: u16 = 0xe1;
+/// Usage code of the right shift key.
+const RIGHT_SHIFT: u16 = 0xe5;
+
+/// State of the modifier keys.
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    left_shift: bool,
+    right_shift: bool,
+    caps_lock: bool,
+}
+
+impl Keyboard {
+    /// Initializes the state, with no modifier active.
+    pub fn new() -> Self {
+        Keyboard::default()
+    }
+
+    /// Updates the state with a key press or release, and returns the character it produces.
+    ///
+    /// The character is either printable ASCII, `\n` for Enter, `\t` for Tab, or `0x08` for
+    /// Backspace.
+    pub fn key(&mut self, usage: u16, state: ButtonState) -> Option<u8> {
+        let pressed = state == ButtonState::Pressed;
+        match usage {
+            LEFT_SHIFT => self.left_shift = pressed,
+            RIGHT_SHIFT => self.right_shift = pressed,
+            CAPS_LOCK if pressed => self.caps_lock = !self.caps_lock,
+            _ => {}
+        }
+
+        if !pressed {
+            return None;
+        }
+
+        let shift = self.left_shift || self.right_shift;
+        match usage {
+            0x04..=0x1d => {
+                let letter = b'a' + (usage - 0x04) as u8;
+                if shift != self.caps_lock {
+                    Some(letter.to_ascii_uppercase())
+                } else {
+                    Some(letter)
+                }
+            }
+            _ => KEYS
+                .iter()
+                .find(|(u, _, _)| *u == usage)
+                .map(|(_, normal, shifted)| if shift { *shifted } else { *normal }),
+        }
+    }
+}
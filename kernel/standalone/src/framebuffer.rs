@@ -0,0 +1,225 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `framebuffer` interface by compositing the framebuffers on
+//! the screen set up by the bootloader.
+//!
+//! The framebuffers are managed by the
+//! [`composite`](redshirt_framebuffer_interface::composite) module of the interface, which also
+//! describes how they are placed and how input events are handled.
+//!
+//! The first time a framebuffer is created, the screen is taken over from the kernel logs and
+//! from the console. See the [`boot_screen`](crate::boot_screen) module.
+
+use crate::arch::PlatformSpecific;
+use crate::boot_screen::{BootScreen, Owner};
+
+use alloc::{boxed::Box, sync::Arc, vec};
+use core::{pin::Pin, sync::atomic, task::Poll};
+use crossbeam_queue::SegQueue;
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
+use redshirt_core::{
+    Buffers, Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid,
+};
+use redshirt_framebuffer_interface::composite::Framebuffers;
+use redshirt_framebuffer_interface::ffi::{self, FramebufferMessage, INTERFACE};
+use spinning_top::Spinlock;
+
+mod screen;
+
+/// Offset in pixels between a framebuffer and the one created before it.
+const CASCADE_OFFSET: u32 = 32;
+
+/// State machine for `framebuffer` interface messages handling.
+pub struct FramebufferHandler<TPlat> {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Platform-specific hooks.
+    platform_specific: Pin<Arc<TPlat>>,
+    /// Buffers containing the pixels sent by processes.
+    buffers: Arc<Buffers>,
+    /// Arbitrates the screen with the kernel logs and the `console` interface handler.
+    boot_screen: Arc<BootScreen>,
+    /// List of messages waiting to be emitted with `next_event`.
+    pending_messages: SegQueue<(MessageId, Result<EncodedMessage, ()>)>,
+    /// Waken up when `pending_messages` is modified.
+    wakeup: AtomicWaker,
+    /// Screen and framebuffers drawn on it.
+    inner: Spinlock<Inner>,
+}
+
+struct Inner {
+    /// Where to draw the framebuffers.
+    screen: screen::Screen,
+    /// Framebuffers of the processes.
+    framebuffers: Framebuffers,
+}
+
+impl<TPlat> FramebufferHandler<TPlat>
+where
+    TPlat: PlatformSpecific,
+{
+    /// Initializes the new state machine for framebuffer messages handling.
+    ///
+    /// Returns `None` if the platform doesn't have a framebuffer whose format is supported.
+    pub fn new(
+        platform_specific: Pin<Arc<TPlat>>,
+        buffers: Arc<Buffers>,
+        boot_screen: Arc<BootScreen>,
+    ) -> Option<Self> {
+        let info = platform_specific.as_ref().boot_framebuffer()?;
+        // Safety: we only draw on the framebuffer after having taken it over from the kernel
+        // logs and the console.
+        let screen = unsafe { screen::Screen::new(&info)? };
+        let framebuffers = Framebuffers::new(screen.width(), screen.height(), CASCADE_OFFSET);
+
+        Some(FramebufferHandler {
+            registered: atomic::AtomicBool::new(false),
+            platform_specific,
+            buffers,
+            boot_screen,
+            pending_messages: SegQueue::new(),
+            wakeup: AtomicWaker::new(),
+            inner: Spinlock::new(Inner {
+                screen,
+                framebuffers,
+            }),
+        })
+    }
+
+    /// Processes a message and queues the answers to send back.
+    fn process_message(
+        &self,
+        emitter_pid: Pid,
+        message_id: Option<MessageId>,
+        message: FramebufferMessage,
+    ) {
+        let mut inner = self.inner.lock();
+        let outcome =
+            inner
+                .framebuffers
+                .handle_message(emitter_pid, message_id, message, |payload| {
+                    self.buffers.take_payload(payload).ok()
+                });
+
+        // The screen only needs to be taken over once a framebuffer exists. Until then, nothing
+        // is drawn.
+        if !inner.framebuffers.is_empty()
+            && self
+                .boot_screen
+                .take(Owner::Framebuffer, &*self.platform_specific)
+        {
+            inner.redraw_all();
+        } else if let Some(rect) = outcome.damage {
+            inner.redraw(rect);
+        }
+
+        for (message_id, answer) in outcome.answers {
+            self.push_answer(message_id, answer);
+        }
+    }
+
+    /// Queues an answer to a message and wakes up the task that calls `next_event`.
+    fn push_answer(&self, message_id: MessageId, answer: Result<EncodedMessage, ()>) {
+        self.pending_messages.push((message_id, answer));
+        self.wakeup.wake();
+    }
+}
+
+impl Inner {
+    /// Draws the entire screen.
+    fn redraw_all(&mut self) {
+        let rect = ffi::Rect {
+            x: 0,
+            y: 0,
+            width: self.screen.width(),
+            height: self.screen.height(),
+        };
+        self.redraw(rect);
+    }
+
+    /// Draws the given area of the screen, which must be within the screen.
+    fn redraw(&mut self, rect: ffi::Rect) {
+        let mut row = vec![[0, 0, 0]; rect.width as usize];
+        for y in rect.y..rect.y + rect.height {
+            self.framebuffers.draw_row(rect.x, y, &mut row);
+            self.screen.set_row(rect.x, y, &row);
+        }
+    }
+}
+
+impl<'a, TPlat> NativeProgramRef<'a> for &'a FramebufferHandler<TPlat>
+where
+    TPlat: PlatformSpecific,
+{
+    type Future =
+        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event(self) -> Self::Future {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.wakeup.register(cx.waker());
+
+            if let Ok((message_id, answer)) = self.pending_messages.pop() {
+                return Poll::Ready(NativeProgramEvent::Answer {
+                    message_id,
+                    answer,
+                });
+            }
+
+            Poll::Pending
+        }))
+    }
+
+    fn interface_message(
+        self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        match FramebufferMessage::decode(message) {
+            Ok(msg) => self.process_message(emitter_pid, message_id, msg),
+            Err(_) => {
+                if let Some(message_id) = message_id {
+                    self.push_answer(message_id, Err(()));
+                }
+            }
+        }
+    }
+
+    fn process_destroyed(self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        if let Some(rect) = inner.framebuffers.process_destroyed(pid) {
+            inner.redraw(rect);
+        }
+    }
+
+    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Writing pixels on the framebuffer set up by the bootloader.
+
+use core::{convert::TryFrom as _, ptr};
+use redshirt_kernel_log_interface::ffi::{FramebufferFormat, FramebufferInfo};
+
+/// Access to a framebuffer whose pixels are RGB colors.
+pub struct Screen {
+    /// Memory address of the top-left pixel.
+    address: usize,
+    /// Width of the screen in pixels.
+    width: u32,
+    /// Height of the screen in pixels.
+    height: u32,
+    /// Number of bytes between the start of a row and the start of the next row.
+    pitch: usize,
+    /// Number of bytes that each pixel occupies. Between 1 and 4.
+    bytes_per_pixel: usize,
+    /// Size in bits and position of the red, green and blue components within a pixel.
+    components: [(u8, u8); 3],
+}
+
+impl Screen {
+    /// Builds a [`Screen`] from the description of a framebuffer. Returns `None` if the format
+    /// of the framebuffer isn't supported.
+    ///
+    /// # Safety
+    ///
+    /// The framebuffer described by `info` must be valid and accessible, and nothing else must
+    /// write to it while the [`Screen`] is alive.
+    pub unsafe fn new(info: &FramebufferInfo) -> Option<Screen> {
+        let components = match info.format {
+            FramebufferFormat::Rgb {
+                red_size,
+                red_position,
+                green_size,
+                green_position,
+                blue_size,
+                blue_position,
+            } => [
+                (red_size, red_position),
+                (green_size, green_position),
+                (blue_size, blue_position),
+            ],
+            FramebufferFormat::Text => return None,
+        };
+
+        if info.bytes_per_character == 0 || info.bytes_per_character > 4 {
+            return None;
+        }
+
+        Some(Screen {
+            address: usize::try_from(info.address).ok()?,
+            width: info.width,
+            height: info.height,
+            pitch: usize::try_from(info.pitch).ok()?,
+            bytes_per_pixel: usize::from(info.bytes_per_character),
+            components,
+        })
+    }
+
+    /// Returns the width of the screen in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the screen in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Writes consecutive pixels of a row of the screen, starting at the given position. Pixels
+    /// that are out of the screen are ignored.
+    pub fn set_row(&mut self, x: u32, y: u32, colors: &[[u8; 3]]) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        let len = colors.len().min((self.width - x) as usize);
+        let start = (y as usize)
+            .saturating_mul(self.pitch)
+            .saturating_add((x as usize).saturating_mul(self.bytes_per_pixel));
+
+        for (n, color) in colors[..len].iter().enumerate() {
+            let pixel = self.encode(*color).to_le_bytes();
+            // Safety is covered by `Screen::new`.
+            unsafe {
+                let dst = (self.address as *mut u8).add(start + n * self.bytes_per_pixel);
+                ptr::copy_nonoverlapping(pixel.as_ptr(), dst, self.bytes_per_pixel);
+            }
+        }
+    }
+
+    /// Turns a color into the value of a pixel in the format of the screen.
+    fn encode(&self, color: [u8; 3]) -> u32 {
+        let mut pixel = 0u32;
+        for ((size, position), value) in self.components.iter().zip(color.iter()) {
+            let value = if *size >= 8 {
+                u32::from(*value).wrapping_shl(u32::from(*size) - 8)
+            } else {
+                u32::from(*value).wrapping_shr(8 - u32::from(*size))
+            };
+            pixel |= value.wrapping_shl(u32::from(*position));
+        }
+        pixel
+    }
+}
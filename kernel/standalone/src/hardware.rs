@@ -17,45 +17,123 @@
 //!
 //! The `hardware` interface is particular in that it can only be implemented using a "hosted"
 //! implementation.
+//!
+//! Interrupts are delivered through [`PlatformSpecific::subscribe_interrupt`]. The handler
+//! subscribes to each source of interrupts at most once, and dispatches the interrupts between
+//! all the processes that have subscribed to this source. Interrupts are counted for each
+//! subscription, so that none is missed in-between two `InterruptWait` messages.
+//!
+//! A source is acknowledged, which unmasks level-triggered interrupts, once all the processes
+//! that have subscribed to it are waiting again without any interrupt left to report.
 
-use crate::arch::PlatformSpecific;
+use crate::arch::{InterruptErr, PlatformSpecific};
 
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
-use core::{convert::TryFrom as _, pin::Pin, sync::atomic};
+use core::{
+    convert::TryFrom as _,
+    mem,
+    pin::Pin,
+    sync::atomic,
+    task::{Context, Poll},
+};
 use crossbeam_queue::SegQueue;
-use futures::prelude::*;
+use futures::{prelude::*, task::AtomicWaker};
 use hashbrown::HashMap;
 use nohash_hasher::BuildNoHashHasher;
 use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
-use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_core::{
+    Authorizations, Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid,
+};
 use redshirt_hardware_interface::ffi::{
-    HardwareAccessResponse, HardwareMessage, Operation, INTERFACE,
+    HardwareAccessResponse, HardwareMessage, InterruptError, InterruptSource, Operation, INTERFACE,
 };
+use smallvec::SmallVec;
 use spinning_top::Spinlock;
 
 /// State machine for `hardware` interface messages handling.
-pub struct HardwareHandler<TPlat> {
+pub struct HardwareHandler<TPlat>
+where
+    TPlat: PlatformSpecific,
+{
     /// If true, we have sent the interface registration message.
     registered: atomic::AtomicBool,
     /// Platform-specific hooks.
     platform_specific: Pin<Arc<TPlat>>,
+    /// Processes that are allowed to access the hardware.
+    authorizations: Arc<Authorizations>,
     /// For each PID, a list of memory allocations.
     // TODO: optimize
     allocations: Spinlock<HashMap<Pid, Vec<Vec<u8>>, BuildNoHashHasher<u64>>>,
+    /// Subscriptions to interrupts.
+    interrupts: Spinlock<Interrupts<TPlat>>,
     /// List of messages waiting to be emitted with `next_event`.
     pending_messages: SegQueue<(MessageId, Result<EncodedMessage, ()>)>,
+    /// Waken up when `pending_messages` or `interrupts` is modified.
+    wakeup: AtomicWaker,
+}
+
+/// Subscriptions to interrupts.
+struct Interrupts<TPlat>
+where
+    TPlat: PlatformSpecific,
+{
+    /// Identifier to assign to the next subscription.
+    next_subscription_id: u64,
+    /// List of subscriptions, indexed by their identifier.
+    subscriptions: HashMap<u64, Subscription, BuildNoHashHasher<u64>>,
+    /// List of sources of interrupts that have at least one subscription.
+    sources: Vec<Source<TPlat::InterruptStream>>,
 }
 
-impl<TPlat> HardwareHandler<TPlat> {
+/// Source of interrupts that has at least one subscription.
+struct Source<TStream> {
+    /// Identifies the source.
+    source: InterruptSource,
+    /// Stream of interrupts provided by the platform.
+    stream: TStream,
+    /// List of subscriptions to this source. Never empty.
+    subscriptions: SmallVec<[u64; 2]>,
+}
+
+/// Subscription of a process to a source of interrupts.
+struct Subscription {
+    /// Process that has created the subscription.
+    owner: Pid,
+    /// Number of interrupts triggered since the last time we answered an `InterruptWait`.
+    num_triggered: u32,
+    /// If `Some`, the owner is waiting for an interrupt to be triggered.
+    wait: Option<MessageId>,
+}
+
+impl<TPlat> HardwareHandler<TPlat>
+where
+    TPlat: PlatformSpecific,
+{
     /// Initializes the new state machine for hardware accesses.
-    pub fn new(platform_specific: Pin<Arc<TPlat>>) -> Self {
+    ///
+    /// Only the processes that `authorizations` allows to use the `hardware` interface can
+    /// access the hardware.
+    pub fn new(platform_specific: Pin<Arc<TPlat>>, authorizations: Arc<Authorizations>) -> Self {
         HardwareHandler {
             registered: atomic::AtomicBool::new(false),
             platform_specific,
+            authorizations,
             allocations: Spinlock::new(HashMap::default()),
+            interrupts: Spinlock::new(Interrupts {
+                next_subscription_id: 0,
+                subscriptions: HashMap::default(),
+                sources: Vec::new(),
+            }),
             pending_messages: SegQueue::new(),
+            wakeup: AtomicWaker::new(),
         }
     }
+
+    /// Queues an answer to a message and wakes up the task that calls `next_event`.
+    fn push_answer(&self, message_id: MessageId, answer: Result<EncodedMessage, ()>) {
+        self.pending_messages.push((message_id, answer));
+        self.wakeup.wake();
+    }
 }
 
 impl<'a, TPlat> NativeProgramRef<'a> for &'a HardwareHandler<TPlat>
@@ -76,15 +154,25 @@ where
             }));
         }
 
-        // TODO: wrong; if a message gets pushed, we don't wake up the task
-        if let Ok((message_id, answer)) = self.pending_messages.pop() {
-            Box::pin(future::ready(NativeProgramEvent::Answer {
-                message_id,
-                answer,
-            }))
-        } else {
-            Box::pin(future::pending())
-        }
+        Box::pin(future::poll_fn(move |cx| {
+            self.wakeup.register(cx.waker());
+
+            if let Ok((message_id, answer)) = self.pending_messages.pop() {
+                return Poll::Ready(NativeProgramEvent::Answer {
+                    message_id,
+                    answer,
+                });
+            }
+
+            if let Some((message_id, num_triggered)) = self.interrupts.lock().poll(cx) {
+                return Poll::Ready(NativeProgramEvent::Answer {
+                    message_id,
+                    answer: Ok(Ok::<_, ()>(num_triggered).encode()),
+                });
+            }
+
+            Poll::Pending
+        }))
     }
 
     fn interface_message(
@@ -96,6 +184,13 @@ where
     ) {
         debug_assert_eq!(interface, INTERFACE);
 
+        if !self.authorizations.is_authorized(emitter_pid, &INTERFACE) {
+            if let Some(message_id) = message_id {
+                self.push_answer(message_id, Err(()))
+            }
+            return;
+        }
+
         match HardwareMessage::decode(message) {
             Ok(HardwareMessage::HardwareAccess(operations)) => {
                 let mut response = Vec::with_capacity(operations.len());
@@ -111,8 +206,7 @@ where
 
                 if let Some(message_id) = message_id {
                     if !response.is_empty() {
-                        self.pending_messages
-                            .push((message_id, Ok(response.encode())));
+                        self.push_answer(message_id, Ok(response.encode()));
                     }
                 }
             }
@@ -135,7 +229,7 @@ where
                 allocations.entry(emitter_pid).or_default().push(buffer);
 
                 if let Some(message_id) = message_id {
-                    self.pending_messages.push((message_id, Ok(ptr.encode())));
+                    self.push_answer(message_id, Ok(ptr.encode()));
                 }
             }
             Ok(HardwareMessage::Free { ptr }) => {
@@ -149,10 +243,65 @@ where
                     }
                 }
             }
-            Ok(HardwareMessage::InterruptWait(_int_id)) => unimplemented!(), // TODO:
+            Ok(HardwareMessage::InterruptSubscribe(source)) => {
+                if let Some(message_id) = message_id {
+                    let response = self.interrupts.lock().subscribe(
+                        self.platform_specific.as_ref(),
+                        emitter_pid,
+                        source,
+                    );
+                    self.push_answer(message_id, Ok(response.encode()));
+                }
+            }
+            Ok(HardwareMessage::InterruptUnsubscribe(subscription_id)) => {
+                let interrupted_wait = self
+                    .interrupts
+                    .lock()
+                    .unsubscribe(emitter_pid, subscription_id);
+                if let Some(wait) = interrupted_wait {
+                    self.push_answer(wait, Ok(Err::<u32, _>(()).encode()));
+                }
+            }
+            Ok(HardwareMessage::InterruptWait(subscription_id)) => {
+                if let Some(message_id) = message_id {
+                    let accepted = self.interrupts.lock().wait(
+                        self.platform_specific.as_ref(),
+                        emitter_pid,
+                        subscription_id,
+                        message_id,
+                    );
+
+                    if accepted {
+                        self.wakeup.wake();
+                    } else {
+                        self.push_answer(message_id, Ok(Err::<u32, _>(()).encode()));
+                    }
+                }
+            }
+            Ok(HardwareMessage::InterruptMsiMessage(subscription_id)) => {
+                if let Some(message_id) = message_id {
+                    let response = self
+                        .interrupts
+                        .lock()
+                        .source(emitter_pid, subscription_id)
+                        .and_then(|source| {
+                            self.platform_specific
+                                .as_ref()
+                                .interrupt_msi_message(&source.stream)
+                        })
+                        .ok_or(());
+                    self.push_answer(message_id, Ok(response.encode()));
+                }
+            }
+            Ok(HardwareMessage::PciConfigRegions) => {
+                if let Some(message_id) = message_id {
+                    let response = self.platform_specific.as_ref().pci_config_regions();
+                    self.push_answer(message_id, Ok(response.encode()));
+                }
+            }
             Err(_) => {
                 if let Some(message_id) = message_id {
-                    self.pending_messages.push((message_id, Err(())))
+                    self.push_answer(message_id, Err(()))
                 }
             }
         }
@@ -160,6 +309,18 @@ where
 
     fn process_destroyed(self, pid: Pid) {
         self.allocations.lock().remove(&pid);
+
+        let mut interrupts = self.interrupts.lock();
+        let owned = interrupts
+            .subscriptions
+            .iter()
+            .filter(|(_, sub)| sub.owner == pid)
+            .map(|(id, _)| *id)
+            .collect::<SmallVec<[_; 4]>>();
+        for subscription_id in owned {
+            // Pending waits don't need to be answered, as the process no longer exists.
+            let _ = interrupts.unsubscribe(pid, subscription_id);
+        }
     }
 
     fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
@@ -167,6 +328,163 @@ where
     }
 }
 
+impl<TPlat> Interrupts<TPlat>
+where
+    TPlat: PlatformSpecific,
+{
+    /// Creates a new subscription. Subscribes to the source through the platform if this is the
+    /// first subscription to it.
+    fn subscribe(
+        &mut self,
+        platform_specific: Pin<&TPlat>,
+        owner: Pid,
+        source: InterruptSource,
+    ) -> Result<u64, InterruptError> {
+        // Message-signalled interrupts can't be shared, as each subscription corresponds to a
+        // different interrupt.
+        let existing = if source != InterruptSource::Msi {
+            self.sources.iter().position(|s| s.source == source)
+        } else {
+            None
+        };
+
+        let source_index = match existing {
+            Some(index) => index,
+            None => {
+                let stream = platform_specific
+                    .subscribe_interrupt(source.clone())
+                    .map_err(|err| match err {
+                        InterruptErr::Unsupported => InterruptError::Unsupported,
+                        InterruptErr::Reserved => InterruptError::Reserved,
+                        InterruptErr::Exhausted => InterruptError::Exhausted,
+                    })?;
+                self.sources.push(Source {
+                    source: source.clone(),
+                    stream,
+                    subscriptions: SmallVec::new(),
+                });
+                self.sources.len() - 1
+            }
+        };
+
+        let subscription_id = self.next_subscription_id;
+        self.next_subscription_id = self.next_subscription_id.checked_add(1).unwrap();
+
+        self.sources[source_index]
+            .subscriptions
+            .push(subscription_id);
+        self.subscriptions.insert(
+            subscription_id,
+            Subscription {
+                owner,
+                num_triggered: 0,
+                wait: None,
+            },
+        );
+
+        Ok(subscription_id)
+    }
+
+    /// Destroys a subscription, if it exists and belongs to `owner`. Returns the wait that was
+    /// in progress, if any.
+    ///
+    /// Unsubscribes from the source through the platform if this was the last subscription to
+    /// it.
+    fn unsubscribe(&mut self, owner: Pid, subscription_id: u64) -> Option<MessageId> {
+        match self.subscriptions.get(&subscription_id) {
+            Some(sub) if sub.owner == owner => {}
+            _ => return None,
+        }
+
+        let subscription = self.subscriptions.remove(&subscription_id).unwrap();
+        let source_index = self
+            .sources
+            .iter()
+            .position(|s| s.subscriptions.contains(&subscription_id))
+            .unwrap();
+        let source = &mut self.sources[source_index];
+        source.subscriptions.retain(|id| *id != subscription_id);
+        if source.subscriptions.is_empty() {
+            // Dropping the stream unsubscribes from the platform.
+            self.sources.remove(source_index);
+        }
+
+        subscription.wait
+    }
+
+    /// Starts waiting for the interrupt of a subscription, if it exists, belongs to `owner`, and
+    /// isn't already waiting. Returns `false` if the wait has been refused.
+    ///
+    /// Waiting again indicates that the owner has serviced its device. Once all the
+    /// subscriptions to a source are waiting, the source is acknowledged through the platform.
+    fn wait(
+        &mut self,
+        platform_specific: Pin<&TPlat>,
+        owner: Pid,
+        subscription_id: u64,
+        message_id: MessageId,
+    ) -> bool {
+        match self.subscriptions.get_mut(&subscription_id) {
+            Some(sub) if sub.owner == owner && sub.wait.is_none() => {
+                sub.wait = Some(message_id);
+            }
+            _ => return false,
+        }
+
+        let source = self
+            .sources
+            .iter()
+            .find(|s| s.subscriptions.contains(&subscription_id))
+            .unwrap();
+        let all_serviced = source.subscriptions.iter().all(|id| {
+            let sub = &self.subscriptions[id];
+            sub.wait.is_some() && sub.num_triggered == 0
+        });
+        if all_serviced {
+            platform_specific.interrupt_acknowledge(&source.stream);
+        }
+
+        true
+    }
+
+    /// Returns the source of a subscription, if it exists and belongs to `owner`.
+    fn source(&self, owner: Pid, subscription_id: u64) -> Option<&Source<TPlat::InterruptStream>> {
+        match self.subscriptions.get(&subscription_id) {
+            Some(sub) if sub.owner == owner => {}
+            _ => return None,
+        }
+
+        self.sources
+            .iter()
+            .find(|s| s.subscriptions.contains(&subscription_id))
+    }
+
+    /// Polls the interrupt streams. Returns a wait to answer, alongside with the number of
+    /// interrupts to report.
+    fn poll(&mut self, cx: &mut Context) -> Option<(MessageId, u32)> {
+        for source in &mut self.sources {
+            while let Poll::Ready(Some(num)) = Pin::new(&mut source.stream).poll_next(cx) {
+                for subscription_id in &source.subscriptions {
+                    let sub = self.subscriptions.get_mut(subscription_id).unwrap();
+                    sub.num_triggered = sub.num_triggered.saturating_add(num);
+                }
+            }
+        }
+
+        for sub in self.subscriptions.values_mut() {
+            if sub.num_triggered == 0 {
+                continue;
+            }
+
+            if let Some(message_id) = sub.wait.take() {
+                return Some((message_id, mem::replace(&mut sub.num_triggered, 0)));
+            }
+        }
+
+        None
+    }
+}
+
 unsafe fn perform_operation<TPlat>(
     platform_specific: Pin<&TPlat>,
     operation: Operation,
@@ -23,21 +23,22 @@
 //!
 
 use crate::arch::PlatformSpecific;
+use crate::boot_screen::BootScreen;
 
-use alloc::sync::Arc;
+use alloc::{format, sync::Arc};
 use core::{
-    marker::PhantomData,
+    pin::Pin,
     sync::atomic::{AtomicBool, Ordering},
 };
-use redshirt_core::{build_wasm_module, System};
+use redshirt_core::{build_wasm_module, Authorizations, Buffers, InterfaceHash, Module, System};
 
 /// Main struct of this crate. Runs everything.
 pub struct Kernel<TPlat> {
     system: System<'static>,
     /// If true, the kernel has started running from a different thread already.
     running: AtomicBool,
-    /// Phantom data so that we can keep the platform specific generic parameter.
-    marker: PhantomData<TPlat>,
+    /// Platform-specific code. Used to report failures to the user.
+    platform_specific: Pin<Arc<TPlat>>,
 }
 
 impl<TPlat> Kernel<TPlat>
@@ -47,10 +48,16 @@ where
     /// Initializes a new `Kernel`.
     pub fn init(platform_specific: TPlat) -> Self {
         let platform_specific = Arc::pin(platform_specific);
+        let authorizations = Arc::new(Authorizations::new());
+        let buffers = Arc::new(Buffers::new());
+        let boot_screen = Arc::new(BootScreen::new());
 
         let mut system_builder = redshirt_core::system::SystemBuilder::new()
+            .with_authorizations(authorizations.clone())
+            .with_buffers(buffers.clone())
             .with_native_program(crate::hardware::HardwareHandler::new(
                 platform_specific.clone(),
+                authorizations.clone(),
             ))
             .with_native_program(crate::time::TimeHandler::new(platform_specific.clone()))
             .with_native_program(crate::random::native::RandomNativeProgram::new(
@@ -58,31 +65,74 @@ where
             ))
             .with_native_program(crate::klog::KernelLogNativeProgram::new(
                 platform_specific.clone(),
+                authorizations.clone(),
+            ))
+            .with_native_program(crate::console::ConsoleHandler::new(
+                platform_specific.clone(),
+                boot_screen.clone(),
             ))
             .with_startup_process(build_wasm_module!(
                 "../../../modules/p2p-loader",
                 "passive-node"
             ))
             .with_startup_process(build_wasm_module!("../../../modules/log-to-kernel"))
+            .with_startup_process(build_wasm_module!("../../../modules/hid-hub"))
+            .with_startup_process(build_wasm_module!("../../../modules/compositor"))
+            .with_startup_process(build_wasm_module!("../../../modules/network-manager"))
             .with_startup_process(build_wasm_module!("../../../modules/hello-world"));
 
+        // The framebuffer handler is only available if the bootloader has set up a framebuffer
+        // that we can draw on.
+        let framebuffer = crate::framebuffer::FramebufferHandler::new(
+            platform_specific.clone(),
+            buffers,
+            boot_screen,
+        );
+        if let Some(framebuffer) = framebuffer {
+            system_builder = system_builder.with_native_program(framebuffer);
+        }
+
         // TODO: use a better system than cfgs
         #[cfg(target_arch = "x86_64")]
         {
             system_builder = system_builder
-                .with_startup_process(build_wasm_module!("../../../modules/x86-pci"))
-                .with_startup_process(build_wasm_module!("../../../modules/ne2000"))
+                .with_startup_process(granted(
+                    &authorizations,
+                    build_wasm_module!("../../../modules/x86-pci"),
+                    &[redshirt_hardware_interface::ffi::INTERFACE],
+                ))
+                .with_startup_process(granted(
+                    &authorizations,
+                    build_wasm_module!("../../../modules/ps2"),
+                    &[redshirt_hardware_interface::ffi::INTERFACE],
+                ))
+                .with_startup_process(granted(
+                    &authorizations,
+                    build_wasm_module!("../../../modules/ne2000"),
+                    &[redshirt_hardware_interface::ffi::INTERFACE],
+                ))
+                .with_startup_process(granted(
+                    &authorizations,
+                    build_wasm_module!("../../../modules/virtio-net"),
+                    &[redshirt_hardware_interface::ffi::INTERFACE],
+                ))
         }
         #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
         {
-            system_builder = system_builder
-                .with_startup_process(build_wasm_module!("../../../modules/rpi-framebuffer"))
+            system_builder = system_builder.with_startup_process(granted(
+                &authorizations,
+                build_wasm_module!("../../../modules/rpi-framebuffer"),
+                &[
+                    redshirt_hardware_interface::ffi::INTERFACE,
+                    redshirt_kernel_log_interface::ffi::INTERFACE,
+                ],
+            ))
         }
 
         Kernel {
             system: system_builder.build().expect("failed to start kernel"),
             running: AtomicBool::new(false),
-            marker: PhantomData,
+            platform_specific,
         }
     }
 
@@ -98,8 +148,23 @@ where
         loop {
             match self.system.run().await {
                 redshirt_core::system::SystemRunOutcome::ProgramFinished { .. } => {}
-                _ => panic!(),
+                redshirt_core::system::SystemRunOutcome::ProgramLoadFailed { hash, error } => {
+                    self.platform_specific
+                        .write_log(&format!("Failed to load module {}: {}", hash, error));
+                }
             }
         }
     }
 }
+
+/// Grants the given interfaces to every process started from `module`, then returns `module`.
+fn granted(
+    authorizations: &Authorizations,
+    module: Module,
+    interfaces: &[InterfaceHash],
+) -> Module {
+    for interface in interfaces {
+        authorizations.grant_module(module.hash().clone(), interface.clone());
+    }
+    module
+}
@@ -33,6 +33,7 @@
 
 pub use logger::KLogger;
 pub use native::KernelLogNativeProgram;
+pub use video::Terminal;
 
 mod logger;
 mod native;
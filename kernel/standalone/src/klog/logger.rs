@@ -73,6 +73,14 @@ impl KLogger {
         }
     }
 
+    /// Stops printing logs on the framebuffer. The other methods are unaffected.
+    pub fn disable_framebuffer(&self) {
+        match &mut *self.inner.lock() {
+            Inner::Disabled(method) => method.framebuffer = None,
+            Inner::Enabled { terminal, .. } => *terminal = None,
+        }
+    }
+
     /// Modifies the way logs should be printed.
     pub fn set_method(&self, _method: KernelLogMethod) {
         unimplemented!() // TODO:
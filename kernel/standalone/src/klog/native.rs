@@ -24,7 +24,7 @@ use core::{pin::Pin, str, sync::atomic};
 use crossbeam_queue::SegQueue;
 use futures::prelude::*;
 use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
-use redshirt_core::{Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_core::{Authorizations, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
 use redshirt_kernel_log_interface::ffi::{KernelLogMethod, INTERFACE};
 
 /// State machine for `random` interface messages handling.
@@ -35,15 +35,21 @@ pub struct KernelLogNativeProgram<TPlat> {
     pending_messages: SegQueue<(MessageId, Result<EncodedMessage, ()>)>,
     /// Platform-specific hooks.
     platform_specific: Pin<Arc<TPlat>>,
+    /// Processes that are allowed to change the way the kernel logs are written.
+    authorizations: Arc<Authorizations>,
 }
 
 impl<TPlat> KernelLogNativeProgram<TPlat> {
     /// Initializes the native program.
-    pub fn new(platform_specific: Pin<Arc<TPlat>>) -> Self {
+    ///
+    /// Any process can write logs, but only the processes that `authorizations` allows to use
+    /// the `kernel-log` interface can reconfigure the logs.
+    pub fn new(platform_specific: Pin<Arc<TPlat>>, authorizations: Arc<Authorizations>) -> Self {
         KernelLogNativeProgram {
             registered: atomic::AtomicBool::new(false),
             pending_messages: SegQueue::new(),
             platform_specific,
+            authorizations,
         }
     }
 }
@@ -80,7 +86,7 @@ where
         self,
         interface: InterfaceHash,
         message_id: Option<MessageId>,
-        _emitter_pid: Pid,
+        emitter_pid: Pid,
         message: EncodedMessage,
     ) {
         debug_assert_eq!(interface, INTERFACE);
@@ -93,6 +99,11 @@ where
                         .write_log(str::from_utf8(message).unwrap());
                 }
             }
+            Some(1) if !self.authorizations.is_authorized(emitter_pid, &INTERFACE) => {
+                if let Some(message_id) = message_id {
+                    self.pending_messages.push((message_id, Err(())))
+                }
+            }
             Some(1) => {
                 // New log method.
                 unimplemented!(); // TODO:
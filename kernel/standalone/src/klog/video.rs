@@ -70,6 +70,48 @@ impl Terminal {
         Printer { klog: self, color }
     }
 
+    /// Returns the number of columns and rows of characters that fit on the screen.
+    pub fn size(&self) -> (u32, u32) {
+        (
+            self.framebuffer.width / self.character_width,
+            self.framebuffer.height / self.character_height,
+        )
+    }
+
+    /// Returns the column and row where the next character will be printed.
+    pub fn cursor(&self) -> (u32, u32) {
+        (self.cursor_x / self.character_width, self.cursor_y / self.character_height)
+    }
+
+    /// Moves the cursor to the given column and row. Positions outside of the screen are moved
+    /// to its closest border.
+    pub fn set_cursor(&mut self, column: u32, row: u32) {
+        let (columns, rows) = self.size();
+        let column = column.min(columns.saturating_sub(1));
+        let row = row.min(rows.saturating_sub(1));
+        self.cursor_x = column.saturating_mul(self.character_width);
+        self.cursor_y = row.saturating_mul(self.character_height);
+    }
+
+    /// Clears the entire screen. Doesn't move the cursor.
+    pub fn clear(&mut self) {
+        // Safety is covered by `Terminal::new`.
+        unsafe {
+            clear_screen(&self.framebuffer);
+            self.screen_cleared = true;
+        }
+    }
+
+    /// Clears the current line from the cursor to its end. Doesn't move the cursor.
+    pub fn clear_to_end_of_line(&mut self) {
+        let cursor_x = self.cursor_x;
+        while self.cursor_x <= self.framebuffer.width.saturating_sub(self.character_width) {
+            self.print_at_cursor(b' ', [0, 0, 0]);
+            self.cursor_x = self.cursor_x.saturating_add(self.character_width);
+        }
+        self.cursor_x = cursor_x;
+    }
+
     /// Adds a message to the terminal.
     fn print(&mut self, message: &str, color: [u8; 3]) {
         for chr in message.chars() {
@@ -31,6 +31,9 @@ extern crate alloc;
 extern crate rlibc;
 
 mod arch;
+mod boot_screen;
+mod console;
+mod framebuffer;
 mod hardware;
 mod kernel;
 mod klog;
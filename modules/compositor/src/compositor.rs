@@ -0,0 +1,811 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! State of the screen and of the windows shown on it.
+//!
+//! Windows are stacked in the order in which they have been created, and the window at the top
+//! of the stack has the focus. Clicking on a window brings it to the top, and dragging the bar
+//! above the content of a window moves it.
+//!
+//! Keyboard events are delivered to the focused window. Pointer events are delivered to the
+//! window whose content is under the pointer, in coordinates relative to that content.
+
+use redshirt_syscalls::{MessageId, Pid};
+use redshirt_window_interface::ffi::{self, InputEvent, WindowError, WindowMessage};
+use std::collections::VecDeque;
+
+/// Height in pixels of the bar drawn above the content of each window.
+const TITLE_BAR_HEIGHT: u32 = 16;
+
+/// Offset in pixels between a window and the one created before it.
+const CASCADE_OFFSET: i32 = 32;
+
+/// Maximum number of input events buffered for each window while its owner isn't asking for
+/// them. Older events are discarded.
+const MAX_BUFFERED_EVENTS: usize = 256;
+
+/// Color of the parts of the screen where there isn't any window.
+const BACKGROUND_COLOR: [u8; 3] = [0x20, 0x24, 0x30];
+/// Color of the title bar of the focused window.
+const FOCUSED_TITLE_BAR_COLOR: [u8; 3] = [0x3a, 0x6e, 0xc4];
+/// Color of the title bar of the other windows.
+const TITLE_BAR_COLOR: [u8; 3] = [0x5a, 0x5a, 0x5a];
+
+/// Owner and identifier of a window.
+type WindowKey = (Pid, u32);
+
+/// Content of the screen and list of windows.
+pub struct Compositor {
+    /// Width of the screen in pixels.
+    width: u32,
+    /// Height of the screen in pixels.
+    height: u32,
+    /// Pixels of the screen in RGB format, row by row from top to bottom.
+    pixels: Vec<u8>,
+    /// List of windows, from bottom to top. The top-most window has the focus.
+    windows: Vec<Window>,
+    /// Last known position of the pointer. `None` if the pointer isn't on the screen.
+    pointer: Option<(i32, i32)>,
+    /// Window whose content is under the pointer.
+    hovered: Option<WindowKey>,
+    /// If `Some`, a window is being moved by dragging its title bar. Contains the window and the
+    /// position of the pointer relative to the top-left corner of the window.
+    drag: Option<(WindowKey, i32, i32)>,
+    /// Area of the screen that has changed since the last call to [`Compositor::take_damage`].
+    damage: Option<Area>,
+    /// Position of the next window to create.
+    next_position: (i32, i32),
+}
+
+/// Window of a process.
+struct Window {
+    /// Process that has created the window.
+    owner: Pid,
+    /// Identifier chosen by the owner.
+    id: u32,
+    /// Horizontal position of the left of the window on the screen.
+    x: i32,
+    /// Vertical position of the top of the title bar of the window on the screen.
+    y: i32,
+    /// Width of the content in pixels.
+    width: u32,
+    /// Height of the content in pixels.
+    height: u32,
+    /// Format of [`Window::pixels`].
+    format: ffi::PixelFormat,
+    /// Pixels of the content, row by row from top to bottom, as sent by the owner.
+    pixels: Vec<u8>,
+    /// Messages asking for the next input event that haven't been answered yet.
+    pending_requests: VecDeque<MessageId>,
+    /// Input events that haven't been requested yet.
+    pending_events: VecDeque<InputEvent>,
+}
+
+/// Area of the screen, possibly partially outside of it.
+#[derive(Debug, Copy, Clone)]
+struct Area {
+    left: i32,
+    top: i32,
+    /// Exclusive.
+    right: i32,
+    /// Exclusive.
+    bottom: i32,
+}
+
+impl Compositor {
+    /// Initializes a screen of the given dimensions, without any window.
+    pub fn new(width: u32, height: u32) -> Compositor {
+        let mut compositor = Compositor {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 3],
+            windows: Vec::new(),
+            pointer: None,
+            hovered: None,
+            drag: None,
+            damage: None,
+            next_position: (0, 0),
+        };
+
+        compositor.redraw(Area {
+            left: 0,
+            top: 0,
+            right: width as i32,
+            bottom: height as i32,
+        });
+        compositor
+    }
+
+    /// Processes a message sent on the window interface, and answers it if necessary.
+    pub fn handle_message(
+        &mut self,
+        emitter_pid: Pid,
+        message_id: Option<MessageId>,
+        message: WindowMessage,
+    ) {
+        let result = match message {
+            WindowMessage::Create {
+                version,
+                id,
+                width,
+                height,
+                format,
+                ..
+            } => self.create(emitter_pid, id, version, width, height, format),
+            WindowMessage::Destroy { id } => match self.position((emitter_pid, id)) {
+                Some(pos) => {
+                    let previous_top = self.top();
+                    let window = self.windows.remove(pos);
+                    self.redraw(window.frame());
+                    self.focus_changed(previous_top);
+                    Ok(())
+                }
+                None => Err(WindowError::UnknownWindow),
+            },
+            // TODO: titles aren't shown, as we don't have any font to draw them with
+            WindowMessage::SetTitle { id, .. } => self
+                .position((emitter_pid, id))
+                .map(|_| ())
+                .ok_or(WindowError::UnknownWindow),
+            WindowMessage::SetData { id, data } => match self.position((emitter_pid, id)) {
+                Some(pos) => {
+                    let rect = ffi::Rect {
+                        x: 0,
+                        y: 0,
+                        width: self.windows[pos].width,
+                        height: self.windows[pos].height,
+                    };
+                    self.update(pos, rect, &data)
+                }
+                None => Err(WindowError::UnknownWindow),
+            },
+            WindowMessage::Update { id, rect, data } => match self.position((emitter_pid, id)) {
+                Some(pos) => self.update(pos, rect, &data),
+                None => Err(WindowError::UnknownWindow),
+            },
+            WindowMessage::Resize { id, width, height } => {
+                match self.position((emitter_pid, id)) {
+                    Some(_) if !self.valid_dimensions(width, height) => {
+                        Err(WindowError::InvalidDimensions)
+                    }
+                    Some(pos) => {
+                        let window = &mut self.windows[pos];
+                        let old_frame = window.frame();
+                        window.width = width;
+                        window.height = height;
+                        window.pixels = vec![0; window.data_len()];
+                        let new_frame = window.frame();
+                        self.redraw(old_frame);
+                        self.redraw(new_frame);
+                        Ok(())
+                    }
+                    None => Err(WindowError::UnknownWindow),
+                }
+            }
+            WindowMessage::NextEvent { id } => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+
+                let window = match self.position((emitter_pid, id)) {
+                    Some(pos) => &mut self.windows[pos],
+                    None => {
+                        redshirt_syscalls::emit_message_error(message_id);
+                        return;
+                    }
+                };
+
+                if let Some(event) = window.pending_events.pop_front() {
+                    redshirt_syscalls::emit_answer(message_id, &event);
+                } else {
+                    window.pending_requests.push_back(message_id);
+                }
+                return;
+            }
+        };
+
+        if let Some(message_id) = message_id {
+            redshirt_syscalls::emit_answer(message_id, &result);
+        }
+    }
+
+    /// Removes all the windows of the given process.
+    pub fn process_destroyed(&mut self, pid: Pid) {
+        let previous_top = self.top();
+
+        let mut pos = 0;
+        while pos < self.windows.len() {
+            if self.windows[pos].owner == pid {
+                let window = self.windows.remove(pos);
+                self.redraw(window.frame());
+            } else {
+                pos += 1;
+            }
+        }
+
+        if self.hovered.map_or(false, |(owner, _)| owner == pid) {
+            self.hovered = None;
+        }
+        if self.drag.map_or(false, |((owner, _), _, _)| owner == pid) {
+            self.drag = None;
+        }
+
+        self.focus_changed(previous_top);
+    }
+
+    /// Processes an input event that happened on the screen and delivers it to the appropriate
+    /// window.
+    pub fn input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::PointerMoved { x, y } => {
+                self.pointer = Some((x, y));
+
+                if let Some((key, offset_x, offset_y)) = self.drag {
+                    if let Some(pos) = self.position(key) {
+                        let old_frame = self.windows[pos].frame();
+                        self.windows[pos].x = x - offset_x;
+                        self.windows[pos].y = y - offset_y;
+                        let new_frame = self.windows[pos].frame();
+                        self.redraw(old_frame);
+                        self.redraw(new_frame);
+                    }
+                    return;
+                }
+
+                let hovered = self.content_at(x, y);
+                let hovered_key = hovered.map(|pos| self.windows[pos].key());
+                if hovered_key != self.hovered {
+                    if let Some(previous) = self.hovered.and_then(|key| self.position(key)) {
+                        self.send_event(previous, InputEvent::PointerLeft);
+                    }
+                    self.hovered = hovered_key;
+                }
+
+                if let Some(pos) = hovered {
+                    let content = self.windows[pos].content();
+                    let event = InputEvent::PointerMoved {
+                        x: x - content.left,
+                        y: y - content.top,
+                    };
+                    self.send_event(pos, event);
+                }
+            }
+            InputEvent::PointerLeft => {
+                self.pointer = None;
+                if let Some(previous) = self.hovered.take().and_then(|key| self.position(key)) {
+                    self.send_event(previous, InputEvent::PointerLeft);
+                }
+            }
+            InputEvent::PointerButton { button, state } => {
+                let (x, y) = match self.pointer {
+                    Some(p) => p,
+                    None => return,
+                };
+
+                if state == ffi::ElementState::Released
+                    && button == ffi::PointerButton::Left
+                    && self.drag.take().is_some()
+                {
+                    return;
+                }
+
+                let mut pos = match self.window_at(x, y) {
+                    Some(pos) => pos,
+                    None => return,
+                };
+
+                if state == ffi::ElementState::Pressed {
+                    pos = self.raise(pos);
+                }
+
+                let window = &self.windows[pos];
+                if window.title_bar().contains(x, y) {
+                    if state == ffi::ElementState::Pressed && button == ffi::PointerButton::Left {
+                        self.drag = Some((window.key(), x - window.x, y - window.y));
+                    }
+                } else {
+                    self.send_event(pos, InputEvent::PointerButton { button, state });
+                }
+            }
+            InputEvent::PointerWheel(delta) => {
+                if let Some(pos) = self.pointer.and_then(|(x, y)| self.content_at(x, y)) {
+                    self.send_event(pos, InputEvent::PointerWheel(delta));
+                }
+            }
+            ev @ InputEvent::Key { .. } | ev @ InputEvent::Text(_) | ev @ InputEvent::Focus(_) => {
+                if !self.windows.is_empty() {
+                    self.send_event(self.windows.len() - 1, ev);
+                }
+            }
+            // The screen has a fixed size, and closing it isn't something that windows can
+            // react to.
+            InputEvent::Resized { .. } | InputEvent::CloseRequested => {}
+        }
+    }
+
+    /// Returns the area of the screen that has changed since the last call, and its pixels in
+    /// RGB format. Returns `None` if nothing has changed.
+    pub fn take_damage(&mut self) -> Option<(ffi::Rect, Vec<u8>)> {
+        let area = self.damage.take()?.clip(self.width, self.height)?;
+        let rect = ffi::Rect {
+            x: area.left as u32,
+            y: area.top as u32,
+            width: (area.right - area.left) as u32,
+            height: (area.bottom - area.top) as u32,
+        };
+
+        let mut data = Vec::with_capacity(rect.width as usize * rect.height as usize * 3);
+        for y in rect.y..rect.y + rect.height {
+            let start = (y as usize * self.width as usize + rect.x as usize) * 3;
+            data.extend_from_slice(&self.pixels[start..start + rect.width as usize * 3]);
+        }
+
+        Some((rect, data))
+    }
+
+    /// Creates a new window on top of the others.
+    fn create(
+        &mut self,
+        owner: Pid,
+        id: u32,
+        version: u32,
+        width: u32,
+        height: u32,
+        format: ffi::PixelFormat,
+    ) -> Result<(), WindowError> {
+        if version != ffi::PROTOCOL_VERSION {
+            return Err(WindowError::UnsupportedVersion);
+        }
+        if !self.valid_dimensions(width, height) {
+            return Err(WindowError::InvalidDimensions);
+        }
+        if self.position((owner, id)).is_some() {
+            return Err(WindowError::AlreadyExists);
+        }
+
+        let (mut x, mut y) = self.next_position;
+        if x + width as i32 > self.width as i32 {
+            x = 0;
+        }
+        if y + (TITLE_BAR_HEIGHT + height) as i32 > self.height as i32 {
+            y = 0;
+        }
+        self.next_position = (
+            (x + CASCADE_OFFSET) % self.width as i32,
+            (y + CASCADE_OFFSET) % self.height as i32,
+        );
+
+        let mut window = Window {
+            owner,
+            id,
+            x,
+            y,
+            width,
+            height,
+            format,
+            pixels: Vec::new(),
+            pending_requests: VecDeque::new(),
+            pending_events: VecDeque::new(),
+        };
+        window.pixels = vec![0; window.data_len()];
+
+        let previous_top = self.top();
+        let frame = window.frame();
+        self.windows.push(window);
+        self.redraw(frame);
+        self.focus_changed(previous_top);
+        Ok(())
+    }
+
+    /// Checks the rectangle and the data against a window, then updates its content.
+    fn update(&mut self, pos: usize, rect: ffi::Rect, data: &[u8]) -> Result<(), WindowError> {
+        let window = &mut self.windows[pos];
+
+        let right = rect.x.checked_add(rect.width);
+        let bottom = rect.y.checked_add(rect.height);
+        if right.map_or(true, |r| r > window.width) || bottom.map_or(true, |b| b > window.height)
+        {
+            return Err(WindowError::OutOfBounds);
+        }
+
+        if Some(data.len()) != ffi::data_len(rect.width, rect.height, window.format) {
+            return Err(WindowError::InvalidDataLength);
+        }
+
+        if rect.width == 0 || rect.height == 0 {
+            return Ok(());
+        }
+
+        let bytes_per_pixel = window.format.bytes_per_pixel() as usize;
+        let row_len = rect.width as usize * bytes_per_pixel;
+        for (row, src) in data.chunks_exact(row_len).enumerate() {
+            let dst_pixel = (rect.y as usize + row) * window.width as usize + rect.x as usize;
+            let dst_start = dst_pixel * bytes_per_pixel;
+            window.pixels[dst_start..dst_start + row_len].copy_from_slice(src);
+        }
+
+        let content = window.content();
+        self.redraw(Area {
+            left: content.left + rect.x as i32,
+            top: content.top + rect.y as i32,
+            right: content.left + right.unwrap() as i32,
+            bottom: content.top + bottom.unwrap() as i32,
+        });
+        Ok(())
+    }
+
+    /// Returns true if a window can have the given dimensions.
+    ///
+    /// Windows, including their title bar, can't be larger than the screen.
+    fn valid_dimensions(&self, width: u32, height: u32) -> bool {
+        width != 0
+            && height != 0
+            && width <= self.width
+            && height.saturating_add(TITLE_BAR_HEIGHT) <= self.height
+    }
+
+    /// Returns the index within `windows` of the given window.
+    fn position(&self, key: WindowKey) -> Option<usize> {
+        self.windows.iter().position(|w| w.key() == key)
+    }
+
+    /// Returns the window at the top of the stack, which has the focus.
+    fn top(&self) -> Option<WindowKey> {
+        self.windows.last().map(|w| w.key())
+    }
+
+    /// Returns the index of the top-most window under the given position.
+    fn window_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.windows.iter().rposition(|w| w.frame().contains(x, y))
+    }
+
+    /// Returns the index of the window whose content is under the given position, if any.
+    fn content_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.window_at(x, y)
+            .filter(|pos| self.windows[*pos].content().contains(x, y))
+    }
+
+    /// Moves a window to the top of the stack, and returns its new index.
+    fn raise(&mut self, pos: usize) -> usize {
+        let previous_top = self.top();
+        let window = self.windows.remove(pos);
+        let frame = window.frame();
+        self.windows.push(window);
+        self.redraw(frame);
+        self.focus_changed(previous_top);
+        self.windows.len() - 1
+    }
+
+    /// Must be called after the stack of windows has been modified. If the window at the top is
+    /// no longer `previous_top`, notifies both windows and redraws their title bars.
+    fn focus_changed(&mut self, previous_top: Option<WindowKey>) {
+        let new_top = self.top();
+        if new_top == previous_top {
+            return;
+        }
+
+        if let Some(pos) = previous_top.and_then(|key| self.position(key)) {
+            self.send_event(pos, InputEvent::Focus(false));
+            self.redraw(self.windows[pos].title_bar());
+        }
+
+        if !self.windows.is_empty() {
+            let pos = self.windows.len() - 1;
+            self.send_event(pos, InputEvent::Focus(true));
+            self.redraw(self.windows[pos].title_bar());
+        }
+    }
+
+    /// Delivers an input event to a window. If its owner is waiting for an event, answers the
+    /// message. Otherwise the event is buffered.
+    fn send_event(&mut self, pos: usize, event: InputEvent) {
+        let window = &mut self.windows[pos];
+
+        if let Some(message_id) = window.pending_requests.pop_front() {
+            redshirt_syscalls::emit_answer(message_id, &event);
+            return;
+        }
+
+        if window.pending_events.len() >= MAX_BUFFERED_EVENTS {
+            window.pending_events.pop_front();
+        }
+        window.pending_events.push_back(event);
+    }
+
+    /// Recomputes the pixels of the given area of the screen and adds it to the damaged area.
+    fn redraw(&mut self, area: Area) {
+        let area = match area.clip(self.width, self.height) {
+            Some(a) => a,
+            None => return,
+        };
+
+        let focused = self.windows.len().checked_sub(1);
+
+        for y in area.top..area.bottom {
+            for x in area.left..area.right {
+                let color = match self.window_at(x, y) {
+                    Some(pos) if self.windows[pos].title_bar().contains(x, y) => {
+                        if Some(pos) == focused {
+                            FOCUSED_TITLE_BAR_COLOR
+                        } else {
+                            TITLE_BAR_COLOR
+                        }
+                    }
+                    Some(pos) => self.windows[pos].content_pixel(x, y),
+                    None => BACKGROUND_COLOR,
+                };
+
+                let offset = (y as usize * self.width as usize + x as usize) * 3;
+                self.pixels[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+
+        self.damage = Some(match self.damage {
+            Some(damage) => damage.union(area),
+            None => area,
+        });
+    }
+}
+
+impl Window {
+    /// Returns the owner and identifier of this window.
+    fn key(&self) -> WindowKey {
+        (self.owner, self.id)
+    }
+
+    /// Returns the number of bytes that [`Window::pixels`] must contain.
+    fn data_len(&self) -> usize {
+        // The dimensions are bounded by the size of the screen, which fits in memory.
+        ffi::data_len(self.width, self.height, self.format).unwrap()
+    }
+
+    /// Returns the area covered by the window, including its title bar.
+    fn frame(&self) -> Area {
+        Area {
+            left: self.x,
+            top: self.y,
+            right: self.x + self.width as i32,
+            bottom: self.y + (TITLE_BAR_HEIGHT + self.height) as i32,
+        }
+    }
+
+    /// Returns the area covered by the title bar of the window.
+    fn title_bar(&self) -> Area {
+        Area {
+            bottom: self.y + TITLE_BAR_HEIGHT as i32,
+            ..self.frame()
+        }
+    }
+
+    /// Returns the area covered by the content of the window.
+    fn content(&self) -> Area {
+        Area {
+            top: self.y + TITLE_BAR_HEIGHT as i32,
+            ..self.frame()
+        }
+    }
+
+    /// Returns the color of the content of the window at the given screen position, which must
+    /// be within [`Window::content`].
+    fn content_pixel(&self, x: i32, y: i32) -> [u8; 3] {
+        let content = self.content();
+        let x = (x - content.left) as usize;
+        let y = (y - content.top) as usize;
+
+        let bytes_per_pixel = self.format.bytes_per_pixel() as usize;
+        let offset = (y * self.width as usize + x) * bytes_per_pixel;
+        let pixel = &self.pixels[offset..offset + bytes_per_pixel];
+        match self.format {
+            ffi::PixelFormat::Rgb24 | ffi::PixelFormat::Rgba32 => [pixel[0], pixel[1], pixel[2]],
+            ffi::PixelFormat::Bgra32 => [pixel[2], pixel[1], pixel[0]],
+        }
+    }
+}
+
+impl Area {
+    /// Returns true if the given position is within the area.
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.left && x < self.right && y >= self.top && y < self.bottom
+    }
+
+    /// Returns the smallest area that contains both areas.
+    fn union(self, other: Area) -> Area {
+        Area {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    /// Returns the part of the area that is within a screen of the given dimensions, or `None`
+    /// if it is entirely outside.
+    fn clip(self, width: u32, height: u32) -> Option<Area> {
+        let area = Area {
+            left: self.left.max(0),
+            top: self.top.max(0),
+            right: self.right.min(width as i32),
+            bottom: self.bottom.min(height as i32),
+        };
+
+        if area.left < area.right && area.top < area.bottom {
+            Some(area)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compositor, WindowKey, BACKGROUND_COLOR, FOCUSED_TITLE_BAR_COLOR};
+    use redshirt_syscalls::Pid;
+    use redshirt_window_interface::ffi::{self, ElementState, InputEvent, PointerButton};
+
+    /// Creates a window of the given dimensions. Returns its key.
+    fn create(
+        compositor: &mut Compositor,
+        pid: u64,
+        id: u32,
+        width: u32,
+        height: u32,
+    ) -> WindowKey {
+        let message = ffi::WindowMessage::Create {
+            version: ffi::PROTOCOL_VERSION,
+            id,
+            title: String::new(),
+            width,
+            height,
+            format: ffi::PixelFormat::Rgb24,
+        };
+        compositor.handle_message(Pid::from(pid), None, message);
+        (Pid::from(pid), id)
+    }
+
+    /// Returns the events buffered for a window, and clears them.
+    fn events(compositor: &mut Compositor, key: WindowKey) -> Vec<InputEvent> {
+        let pos = compositor.position(key).unwrap();
+        compositor.windows[pos].pending_events.drain(..).collect()
+    }
+
+    /// Presses or releases the left button of the pointer.
+    fn left_button(compositor: &mut Compositor, state: ElementState) {
+        compositor.input(InputEvent::PointerButton {
+            button: PointerButton::Left,
+            state,
+        });
+    }
+
+    #[test]
+    fn focus_events() {
+        let mut compositor = Compositor::new(200, 200);
+
+        let first = create(&mut compositor, 1, 0, 50, 50);
+        assert_eq!(
+            events(&mut compositor, first),
+            vec![InputEvent::Focus(true)]
+        );
+
+        let second = create(&mut compositor, 2, 0, 50, 50);
+        assert_eq!(
+            events(&mut compositor, first),
+            vec![InputEvent::Focus(false)]
+        );
+        assert_eq!(
+            events(&mut compositor, second),
+            vec![InputEvent::Focus(true)]
+        );
+
+        compositor.input(InputEvent::Key {
+            scancode: 30,
+            key: Some(ffi::Key::A),
+            state: ElementState::Pressed,
+        });
+        assert!(events(&mut compositor, first).is_empty());
+        assert_eq!(events(&mut compositor, second).len(), 1);
+
+        let destroy = ffi::WindowMessage::Destroy { id: 0 };
+        compositor.handle_message(Pid::from(2), None, destroy);
+        assert_eq!(
+            events(&mut compositor, first),
+            vec![InputEvent::Focus(true)]
+        );
+    }
+
+    #[test]
+    fn raise_on_click() {
+        let mut compositor = Compositor::new(200, 200);
+        let first = create(&mut compositor, 1, 0, 50, 50);
+        let second = create(&mut compositor, 2, 0, 50, 50);
+        events(&mut compositor, first);
+        events(&mut compositor, second);
+        assert_eq!(compositor.top(), Some(second));
+
+        // The second window is placed at (32, 32), and doesn't cover this position.
+        compositor.input(InputEvent::PointerMoved { x: 10, y: 30 });
+        left_button(&mut compositor, ElementState::Pressed);
+
+        assert_eq!(compositor.top(), Some(first));
+        assert_eq!(
+            events(&mut compositor, first),
+            vec![
+                InputEvent::PointerMoved { x: 10, y: 14 },
+                InputEvent::Focus(true),
+                InputEvent::PointerButton {
+                    button: PointerButton::Left,
+                    state: ElementState::Pressed,
+                },
+            ]
+        );
+        assert_eq!(
+            events(&mut compositor, second),
+            vec![InputEvent::Focus(false)]
+        );
+    }
+
+    #[test]
+    fn process_destroyed() {
+        let mut compositor = Compositor::new(200, 200);
+        let first = create(&mut compositor, 1, 0, 50, 50);
+        create(&mut compositor, 1, 1, 50, 50);
+        let other = create(&mut compositor, 2, 0, 50, 50);
+        events(&mut compositor, first);
+
+        // Starts dragging the window of the second process by its title bar.
+        compositor.input(InputEvent::PointerMoved { x: 70, y: 70 });
+        left_button(&mut compositor, ElementState::Pressed);
+        assert_eq!(compositor.drag.map(|(key, _, _)| key), Some(other));
+
+        compositor.process_destroyed(Pid::from(2));
+        assert_eq!(compositor.windows.len(), 2);
+        assert!(compositor.drag.is_none());
+        assert_eq!(compositor.top(), Some((Pid::from(1), 1)));
+
+        compositor.process_destroyed(Pid::from(1));
+        assert!(compositor.windows.is_empty());
+        assert!(compositor.top().is_none());
+    }
+
+    #[test]
+    fn take_damage_clipped() {
+        let mut compositor = Compositor::new(100, 100);
+        let (rect, data) = compositor.take_damage().unwrap();
+        assert_eq!((rect.x, rect.y, rect.width, rect.height), (0, 0, 100, 100));
+        assert!(data.chunks(3).all(|p| p == BACKGROUND_COLOR));
+        assert!(compositor.take_damage().is_none());
+
+        create(&mut compositor, 1, 0, 50, 50);
+        compositor.take_damage().unwrap();
+
+        // Drags the window by its title bar so that it goes past the right of the screen.
+        compositor.input(InputEvent::PointerMoved { x: 10, y: 5 });
+        left_button(&mut compositor, ElementState::Pressed);
+        compositor.input(InputEvent::PointerMoved { x: 95, y: 5 });
+
+        let (rect, data) = compositor.take_damage().unwrap();
+        assert_eq!((rect.x, rect.y, rect.width, rect.height), (0, 0, 100, 66));
+        assert_eq!(data.len(), 100 * 66 * 3);
+        assert_eq!(data[60 * 3..61 * 3], BACKGROUND_COLOR);
+        assert_eq!(data[99 * 3..100 * 3], FOCUSED_TITLE_BAR_COLOR);
+
+        // Entirely outside of the screen.
+        compositor.input(InputEvent::PointerMoved { x: -200, y: -200 });
+        let (rect, _) = compositor.take_damage().unwrap();
+        assert_eq!((rect.x, rect.y, rect.width, rect.height), (85, 0, 15, 66));
+        left_button(&mut compositor, ElementState::Released);
+        assert!(compositor.take_damage().is_none());
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the window interface by compositing all the windows on a single framebuffer.
+//!
+//! The compositor creates one framebuffer covering the screen, then draws on it the windows that
+//! other processes create, and routes the input events of the framebuffer to these windows.
+//! See the [`compositor`] module for how windows are stacked and focused.
+//!
+//! The framebuffer is only created when the first message arrives. Both kernels start the
+//! compositor unconditionally, and the screen is therefore left to the kernel logs, or to other
+//! framebuffers, as long as no process uses the window interface.
+
+use futures::prelude::*;
+use redshirt_framebuffer_interface::{ffi::FramebufferError, Framebuffer};
+use redshirt_syscalls::Decode as _;
+use redshirt_window_interface::ffi;
+
+mod compositor;
+
+/// Dimensions of the screen to try, in order of preference.
+///
+/// The framebuffer interface doesn't provide any way to know the size of the screen. Handlers
+/// refuse framebuffers that are too large, so we start with the largest size.
+const SCREEN_SIZES: &[(u32, u32)] = &[(1024, 768), (800, 600), (640, 480)];
+
+fn main() {
+    redshirt_syscalls::block_on(async_main());
+}
+
+async fn async_main() {
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let first_message = loop {
+        match redshirt_syscalls::next_interface_message().await {
+            redshirt_syscalls::DecodedInterfaceOrDestroyed::Interface(m) => break m,
+            // No window can exist before the first message.
+            redshirt_syscalls::DecodedInterfaceOrDestroyed::ProcessDestroyed(_) => {}
+        }
+    };
+
+    let screen = create_screen().await;
+    let mut compositor = compositor::Compositor::new(screen.width(), screen.height());
+    handle_message(&mut compositor, first_message);
+
+    let mut next_message = Box::pin(redshirt_syscalls::next_interface_message());
+    let mut next_input = Box::pin(screen.next_event());
+
+    loop {
+        if let Some((rect, data)) = compositor.take_damage() {
+            screen.update(rect, &data);
+        }
+
+        match future::select(next_message, next_input).await {
+            future::Either::Left((message, input)) => {
+                next_input = input;
+                next_message = Box::pin(redshirt_syscalls::next_interface_message());
+
+                let message = match message {
+                    redshirt_syscalls::DecodedInterfaceOrDestroyed::Interface(m) => m,
+                    redshirt_syscalls::DecodedInterfaceOrDestroyed::ProcessDestroyed(p) => {
+                        compositor.process_destroyed(p.pid);
+                        continue;
+                    }
+                };
+
+                handle_message(&mut compositor, message);
+            }
+            future::Either::Right((event, message)) => {
+                next_message = message;
+                next_input = Box::pin(screen.next_event());
+                compositor.input(event);
+            }
+        }
+    }
+}
+
+/// Decodes a message sent on the window interface and passes it to the compositor.
+fn handle_message(
+    compositor: &mut compositor::Compositor,
+    message: redshirt_syscalls::DecodedInterfaceNotification,
+) {
+    assert_eq!(message.interface, ffi::INTERFACE);
+    match ffi::WindowMessage::decode(message.actual_data) {
+        Ok(msg) => compositor.handle_message(message.emitter_pid, message.message_id, msg),
+        Err(_) => {
+            if let Some(message_id) = message.message_id {
+                redshirt_syscalls::emit_message_error(message_id);
+            }
+        }
+    }
+}
+
+/// Creates the framebuffer on which the windows are drawn.
+async fn create_screen() -> Framebuffer {
+    for (width, height) in SCREEN_SIZES {
+        match Framebuffer::new(*width, *height).await {
+            Ok(fb) => return fb,
+            Err(FramebufferError::InvalidDimensions) => continue,
+            Err(err) => panic!("Failed to create framebuffer: {}", err),
+        }
+    }
+
+    panic!("No supported screen size")
+}
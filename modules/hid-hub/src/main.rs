@@ -0,0 +1,186 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the hid interface by relaying the events reported by drivers to the processes
+//! that ask for them.
+//!
+//! Each event is delivered to every process that has asked for events at least once.
+
+use redshirt_hid_interface::ffi;
+use redshirt_syscalls::{Decode as _, MessageId, Pid};
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
+
+/// Maximum number of events buffered for each process while it isn't asking for them. Older
+/// events are discarded.
+const MAX_BUFFERED_EVENTS: usize = 256;
+
+fn main() {
+    redshirt_syscalls::block_on(async_main());
+}
+
+async fn async_main() {
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut hub = Hub::default();
+
+    loop {
+        let message = match redshirt_syscalls::next_interface_message().await {
+            redshirt_syscalls::DecodedInterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls::DecodedInterfaceOrDestroyed::ProcessDestroyed(p) => {
+                hub.process_destroyed(p.pid);
+                continue;
+            }
+        };
+
+        assert_eq!(message.interface, ffi::INTERFACE);
+        match ffi::HidMessage::decode(message.actual_data) {
+            Ok(msg) => hub.handle_message(message.emitter_pid, message.message_id, msg),
+            Err(_) => {
+                if let Some(message_id) = message.message_id {
+                    redshirt_syscalls::emit_message_error(message_id);
+                }
+            }
+        }
+    }
+}
+
+/// State of the hub.
+#[derive(Default)]
+struct Hub {
+    /// Registered devices, indexed by driver and by identifier chosen by the driver.
+    devices: HashMap<(Pid, u32), ffi::DeviceInfo>,
+    /// Identifier to assign to the next registered device.
+    next_device_id: u64,
+    /// Processes that have asked for events.
+    consumers: HashMap<Pid, Consumer>,
+}
+
+/// Process that has asked for events.
+#[derive(Default)]
+struct Consumer {
+    /// Messages asking for the next event that haven't been answered yet.
+    pending_requests: VecDeque<MessageId>,
+    /// Events that haven't been requested yet.
+    pending_events: VecDeque<ffi::HidEvent>,
+}
+
+impl Hub {
+    /// Processes a message sent on the hid interface.
+    fn handle_message(
+        &mut self,
+        emitter_pid: Pid,
+        message_id: Option<MessageId>,
+        message: ffi::HidMessage,
+    ) {
+        match message {
+            ffi::HidMessage::RegisterDevice {
+                version,
+                id,
+                kind,
+                name,
+            } => {
+                let result = if version != ffi::PROTOCOL_VERSION {
+                    Err(ffi::HidError::UnsupportedVersion)
+                } else if let Entry::Vacant(entry) = self.devices.entry((emitter_pid, id)) {
+                    let info = ffi::DeviceInfo {
+                        id: ffi::DeviceId(self.next_device_id),
+                        kind,
+                        name,
+                    };
+                    self.next_device_id += 1;
+                    entry.insert(info.clone());
+                    self.broadcast(ffi::HidEvent::DeviceAdded(info));
+                    Ok(())
+                } else {
+                    Err(ffi::HidError::AlreadyExists)
+                };
+
+                if let Some(message_id) = message_id {
+                    redshirt_syscalls::emit_answer(message_id, &result);
+                }
+            }
+            ffi::HidMessage::UnregisterDevice { id } => {
+                if let Some(info) = self.devices.remove(&(emitter_pid, id)) {
+                    self.broadcast(ffi::HidEvent::DeviceRemoved(info.id));
+                }
+            }
+            ffi::HidMessage::ReportEvents { id, events } => {
+                let device = match self.devices.get(&(emitter_pid, id)) {
+                    Some(info) => info.id,
+                    None => return,
+                };
+
+                for event in events {
+                    self.broadcast(ffi::HidEvent::Input { device, event });
+                }
+            }
+            ffi::HidMessage::ListDevices => {
+                if let Some(message_id) = message_id {
+                    let mut devices = self.devices.values().cloned().collect::<Vec<_>>();
+                    devices.sort_by_key(|info| info.id);
+                    redshirt_syscalls::emit_answer(message_id, &devices);
+                }
+            }
+            ffi::HidMessage::NextEvent => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+
+                let consumer = self.consumers.entry(emitter_pid).or_default();
+                if let Some(event) = consumer.pending_events.pop_front() {
+                    redshirt_syscalls::emit_answer(message_id, &event);
+                } else {
+                    consumer.pending_requests.push_back(message_id);
+                }
+            }
+        }
+    }
+
+    /// Unregisters the devices of a process and stops buffering events for it.
+    fn process_destroyed(&mut self, pid: Pid) {
+        self.consumers.remove(&pid);
+
+        let mut removed = self
+            .devices
+            .iter()
+            .filter(|((owner, _), _)| *owner == pid)
+            .map(|(key, info)| (*key, info.id))
+            .collect::<Vec<_>>();
+        removed.sort_by_key(|(_, id)| *id);
+
+        for (key, id) in removed {
+            self.devices.remove(&key);
+            self.broadcast(ffi::HidEvent::DeviceRemoved(id));
+        }
+    }
+
+    /// Delivers an event to all the consumers.
+    fn broadcast(&mut self, event: ffi::HidEvent) {
+        for consumer in self.consumers.values_mut() {
+            if let Some(message_id) = consumer.pending_requests.pop_front() {
+                redshirt_syscalls::emit_answer(message_id, &event);
+                continue;
+            }
+
+            if consumer.pending_events.len() >= MAX_BUFFERED_EVENTS {
+                consumer.pending_events.pop_front();
+            }
+            consumer.pending_events.push_back(event.clone());
+        }
+    }
+}
@@ -16,7 +16,7 @@
 //! Implements the log interface by redirecting the logs as kernel logs.
 
 use redshirt_log_interface::ffi;
-use redshirt_syscalls::{Decode, EncodedMessage};
+use redshirt_syscalls::Decode;
 
 fn main() {
     redshirt_syscalls::block_on(async_main());
@@ -35,21 +35,36 @@ async fn async_main() -> ! {
 
         assert_eq!(msg.interface, ffi::INTERFACE);
 
-        if let Ok(message) = ffi::DecodedLogMessage::decode(msg.actual_data) {
-            let level = match message.level() {
-                ffi::Level::Error => "ERR ",
-                ffi::Level::Warn => "WARN",
-                ffi::Level::Info => "INFO",
-                ffi::Level::Debug => "DEBG",
-                ffi::Level::Trace => "TRCE",
-            };
-
-            let kernel_message =
-                format!("[{:?}] [{}] {}", msg.emitter_pid, level, message.message());
-            redshirt_kernel_log_interface::log(kernel_message.as_bytes());
-        } else {
-            let kernel_message = format!("[{:?}] Bad log message", msg.emitter_pid);
-            redshirt_kernel_log_interface::log(kernel_message.as_bytes());
+        match ffi::DecodedLogInterfaceMessage::decode(msg.actual_data) {
+            Ok(ffi::DecodedLogInterfaceMessage::Log(message)) => {
+                let level = match message.level() {
+                    ffi::Level::Error => "ERR ",
+                    ffi::Level::Warn => "WARN",
+                    ffi::Level::Info => "INFO",
+                    ffi::Level::Debug => "DEBG",
+                    ffi::Level::Trace => "TRCE",
+                };
+
+                let mut kernel_message = format!("[{:?}] [{}] ", msg.emitter_pid, level);
+                if !message.target().is_empty() {
+                    kernel_message.push_str(message.target());
+                    kernel_message.push_str(": ");
+                }
+                kernel_message.push_str(message.message());
+                for (key, value) in message.fields() {
+                    kernel_message.push_str(&format!(" {}={}", key, value));
+                }
+                redshirt_kernel_log_interface::log(kernel_message.as_bytes());
+            }
+            Ok(ffi::DecodedLogInterfaceMessage::FilterRequest) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls::emit_answer(message_id, &ffi::LogFilter::all());
+                }
+            }
+            Err(_) => {
+                let kernel_message = format!("[{:?}] Bad log message", msg.emitter_pid);
+                redshirt_kernel_log_interface::log(kernel_message.as_bytes());
+            }
         }
     }
 }
@@ -352,28 +352,27 @@ impl Device {
         Ok(())
     }
 
-    // TODO:
-    /*pub async unsafe fn on_interrupt(&mut self) {
+    /// Reads and clears the interrupt status of the device, and updates the state of the
+    /// transmission accordingly.
+    ///
+    /// Must be called every time the device has triggered an interrupt. Frames received by the
+    /// device after this method has returned will trigger a new interrupt, so it should be called
+    /// before reading incoming packets with [`Device::read_one_incoming`].
+    pub async unsafe fn acknowledge_interrupts(&self) {
         // Read the ISR (Interrupt Status Register) to determine why an interrupt has been raised.
-        let status = redshirt_hardware_interface::port_read_u8(self.base_port + 7).await;
+        let status = redshirt_hardware_interface::port_read_u8(self.base.base_port + 7).await;
         // Write back the same status in order to clear the bits and allow further interrupts to
         // happen.
-        redshirt_hardware_interface::port_write_u8(self.base_port + 7, status);
-
-        if (status & (1 << 0)) != 0 {
-            // Packet received with no error.
-            if let Some(packet) = self.read_one_incoming().await {
-                // TODO: implement
-            }
-        }
+        redshirt_hardware_interface::port_write_u8(self.base.base_port + 7, status);
 
         if (status & (1 << 1)) != 0 || (status & (1 << 3)) != 0 {
             // Packet transmission successful or aborted. We don't treat the "aborted" situation
             // differently than the successful situation.
-            self.transmitting = None;
-            self.flush_out();
+            let mut writing = self.writing.borrow_mut();
+            writing.transmitting = None;
+            flush_out(&self.base, &mut writing);
         }
-    }*/
+    }
 }
 
 impl fmt::Debug for Device {
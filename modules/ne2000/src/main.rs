@@ -28,10 +28,17 @@
 
 use futures::prelude::*;
 use redshirt_ethernet_interface::interface;
-use std::convert::TryFrom as _;
+use redshirt_hardware_interface::interrupts;
+use std::{cell::RefCell, convert::TryFrom as _, time::Duration};
 
 mod device;
 
+/// Interval between two statistics reports to the network manager.
+const STATISTICS_INTERVAL: Duration = Duration::from_secs(10);
+/// Interval between two checks for incoming packets, if the device's interrupts can't be
+/// subscribed to.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 fn main() {
     redshirt_syscalls::block_on(async_main());
 }
@@ -45,8 +52,9 @@ async fn async_main() {
             let port_number = device
                 .base_address_registers
                 .iter()
+                .flatten()
                 .filter_map(|bar| match bar {
-                    redshirt_pci_interface::PciBaseAddressRegister::Io { base_address }
+                    redshirt_pci_interface::PciBaseAddressRegister::Io { base_address, .. }
                         if *base_address != 0 =>
                     {
                         Some(*base_address)
@@ -56,13 +64,57 @@ async fn async_main() {
                 .next();
 
             if let Some(port_number) = port_number {
+                let interrupt_line = device.interrupt_line;
                 let device = unsafe { device::Device::reset(port_number) }.await;
-                let registered_device_id = redshirt_random_interface::generate_u64().await;
-                let registration = interface::register_interface(interface::InterfaceConfig {
+                let registration = match interface::register_interface(interface::InterfaceConfig {
                     mac_address: device.mac_address(),
+                    mtu: 1500,
+                    num_queues: 1,
+                    // The device can only hold one packet waiting to be transmitted.
+                    tx_batch_size: 1,
+                    rx_checksum_offload: Default::default(),
+                    tx_checksum_offload: Default::default(),
                 })
-                .await;
-                ne2k_devices.push((registration, device));
+                .await
+                {
+                    Ok(r) => r,
+                    Err(err) => {
+                        redshirt_log_interface::emit_log(
+                            redshirt_log_interface::Level::Error,
+                            &format!("Failed to register ne2000 at 0x{:x}: {:?}", port_number, err),
+                        );
+                        continue;
+                    }
+                };
+
+                // The ne2000 has no way to detect whether a cable is plugged.
+                registration.set_link_status(interface::LinkStatus::Unknown);
+
+                let interrupt = if let Some(interrupt_line) = interrupt_line {
+                    let source = interrupts::InterruptSource::IsaIrq(interrupt_line);
+                    match interrupts::subscribe(source).await {
+                        Ok(interrupt) => Some(interrupt),
+                        Err(err) => {
+                            redshirt_log_interface::emit_log(
+                                redshirt_log_interface::Level::Warn,
+                                &format!(
+                                    "Failed to subscribe to IRQ {} of ne2000 at 0x{:x}: {:?}",
+                                    interrupt_line, port_number, err
+                                ),
+                            );
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                ne2k_devices.push((
+                    registration,
+                    device,
+                    interrupt,
+                    RefCell::new(interface::Statistics::default()),
+                ));
                 redshirt_log_interface::emit_log(
                     redshirt_log_interface::Level::Info,
                     &format!("Initialized ne2000 at 0x{:x}", port_number),
@@ -78,28 +130,70 @@ async fn async_main() {
     ne2k_devices.shrink_to_fit();
 
     let mut tasks = stream::FuturesUnordered::new();
-    for (registration, device) in &ne2k_devices {
+    for (registration, device, interrupt, statistics) in &ne2k_devices {
         tasks.push(
             async move {
-                let packet = registration.packet_to_send().await;
-                unsafe {
-                    device.send_packet(packet).unwrap();
-                } // TODO: unwrap?
+                loop {
+                    for frame in registration.packets_to_send().await {
+                        let len = u64::try_from(frame.data.len()).unwrap();
+                        let mut statistics = statistics.borrow_mut();
+                        match unsafe { device.send_packet(frame.data) } {
+                            Ok(()) => {
+                                statistics.tx_frames += 1;
+                                statistics.tx_bytes += len;
+                            }
+                            Err(()) => statistics.tx_errors += 1,
+                        }
+                    }
+                }
             }
             .boxed_local(),
         );
 
         tasks.push(
             async move {
-                // TODO: wake up only on interrupt
                 loop {
-                    if let Some(packet) = unsafe { device.read_one_incoming().await } {
-                        registration.packet_from_network().await.send(packet)
+                    // Interrupts must be acknowledged before reading the incoming packets, so
+                    // that a packet arriving in the meantime triggers a new interrupt.
+                    unsafe { device.acknowledge_interrupts().await };
+
+                    // Gather all the frames that are available in order to deliver them at once.
+                    let mut frames = Vec::new();
+                    while let Some(packet) = unsafe { device.read_one_incoming().await } {
+                        let mut statistics = statistics.borrow_mut();
+                        statistics.rx_frames += 1;
+                        statistics.rx_bytes += u64::try_from(packet.len()).unwrap();
+                        frames.push(interface::Frame {
+                            data: packet,
+                            checksums: Default::default(),
+                        });
+                    }
+
+                    if !frames.is_empty() {
+                        registration.packet_from_network().await.send_frames(frames);
+                    }
+
+                    let interrupted = match interrupt {
+                        Some(interrupt) => interrupt.wait().await.is_ok(),
+                        None => false,
+                    };
+                    if !interrupted {
+                        redshirt_time_interface::monotonic_wait(POLL_INTERVAL).await;
                     }
                 }
             }
             .boxed_local(),
         );
+
+        tasks.push(
+            async move {
+                loop {
+                    redshirt_time_interface::monotonic_wait(STATISTICS_INTERVAL).await;
+                    registration.report_statistics(&statistics.borrow());
+                }
+            }
+            .boxed_local(),
+        );
     }
 
     while let Some(_) = tasks.next().await {}
@@ -0,0 +1,85 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the ethernet interface.
+//!
+//! Keeps track of the Ethernet interfaces registered by the network drivers, along with their
+//! link status and statistics.
+//!
+//! There is no network stack yet, and no interface through which other programs could receive
+//! frames. The frames received from the network are therefore discarded, and nothing is ever
+//! sent to the network.
+
+use redshirt_ethernet_interface::{ffi, manager};
+use redshirt_syscalls::Decode as _;
+
+fn main() {
+    redshirt_syscalls::block_on(async_main());
+}
+
+async fn async_main() {
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut manager = manager::NetworkManager::new();
+
+    loop {
+        let message = match redshirt_syscalls::next_interface_message().await {
+            redshirt_syscalls::DecodedInterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls::DecodedInterfaceOrDestroyed::ProcessDestroyed(p) => {
+                // The process is dead, so there's no point in answering its messages.
+                let _ = manager.process_destroyed(p.pid);
+                continue;
+            }
+        };
+
+        assert_eq!(message.interface, ffi::INTERFACE);
+        let answers = match ffi::NetworkMessage::decode(message.actual_data) {
+            Ok(msg) => manager.handle_message(message.emitter_pid, message.message_id, msg),
+            Err(_) => {
+                if let Some(message_id) = message.message_id {
+                    redshirt_syscalls::emit_message_error(message_id);
+                }
+                continue;
+            }
+        };
+
+        for (message_id, answer) in answers {
+            match answer {
+                manager::Answer::Register(response) => {
+                    redshirt_syscalls::emit_answer(message_id, &response)
+                }
+                manager::Answer::DataProcessed => redshirt_syscalls::emit_answer(message_id, &()),
+                manager::Answer::DataToSend(frames) => {
+                    redshirt_syscalls::emit_answer(message_id, &frames)
+                }
+                manager::Answer::Error => redshirt_syscalls::emit_message_error(message_id),
+            }
+        }
+
+        // TODO: pass the frames to a network stack
+        while let Some(received) = manager.pop_received() {
+            redshirt_log_interface::emit_log(
+                redshirt_log_interface::Level::Trace,
+                &format!(
+                    "Discarding frame of {} bytes received on {:?}",
+                    received.frame.data.len(),
+                    received.interface
+                ),
+            );
+        }
+    }
+}
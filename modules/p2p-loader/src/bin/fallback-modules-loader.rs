@@ -0,0 +1,25 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Same as `modules-loader`, but serves the fallback loader interface. Used when modules are
+//! primarily loaded from a local store, in which case only the modules missing from the store
+//! are fetched from the network.
+
+fn main() {
+    redshirt_log_interface::init();
+    redshirt_syscalls::block_on(p2p_loader::loader::serve(
+        redshirt_loader_interface::ffi::FALLBACK_INTERFACE,
+    ))
+}
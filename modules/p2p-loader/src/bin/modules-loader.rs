@@ -13,58 +13,9 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use futures::prelude::*;
-use p2p_loader::{Network, NetworkEvent};
-use parity_scale_codec::DecodeAll;
-use std::time::Duration;
-
 fn main() {
     redshirt_log_interface::init();
-    redshirt_syscalls::block_on(async_main())
-}
-
-async fn async_main() {
-    redshirt_time_interface::monotonic_wait(Duration::from_secs(5)).await;
-
-    redshirt_interface_interface::register_interface(redshirt_loader_interface::ffi::INTERFACE)
-        .await
-        .unwrap();
-
-    let mut network = Network::start(Default::default()).unwrap();
-
-    loop {
-        let next_interface = redshirt_syscalls::next_interface_message();
-        let event = {
-            let next_net_event = network.next_event();
-            futures::pin_mut!(next_net_event);
-            match future::select(next_interface, next_net_event).await {
-                future::Either::Left((v, _)) => future::Either::Left(v),
-                future::Either::Right((v, _)) => future::Either::Right(v),
-            }
-        };
-
-        let msg = match event {
-            future::Either::Left(redshirt_syscalls::DecodedInterfaceOrDestroyed::Interface(m)) => m,
-            future::Either::Left(
-                redshirt_syscalls::DecodedInterfaceOrDestroyed::ProcessDestroyed(_),
-            ) => continue,
-            future::Either::Right(NetworkEvent::FetchSuccess { data, user_data }) => {
-                let rp = redshirt_loader_interface::ffi::LoadResponse { result: Ok(data) };
-                redshirt_syscalls::emit_answer(user_data, &rp);
-                continue;
-            }
-            future::Either::Right(NetworkEvent::FetchFail { user_data }) => {
-                let rp = redshirt_loader_interface::ffi::LoadResponse { result: Err(()) };
-                redshirt_syscalls::emit_answer(user_data, &rp);
-                continue;
-            }
-        };
-
-        assert_eq!(msg.interface, redshirt_loader_interface::ffi::INTERFACE);
-        let msg_data =
-            redshirt_loader_interface::ffi::LoaderMessage::decode_all(&msg.actual_data.0).unwrap();
-        let redshirt_loader_interface::ffi::LoaderMessage::Load(hash_to_load) = msg_data;
-        log::info!("loading {}", bs58::encode(hash_to_load).into_string());
-        network.start_fetch(&hash_to_load, msg.message_id.unwrap());
-    }
+    redshirt_syscalls::block_on(p2p_loader::loader::serve(
+        redshirt_loader_interface::ffi::INTERFACE,
+    ))
 }
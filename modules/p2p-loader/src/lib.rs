@@ -33,6 +33,9 @@ use libp2p::plaintext::PlainText2Config;
 use libp2p::swarm::{Swarm, SwarmEvent};
 use std::{collections::VecDeque, io, path::PathBuf, pin::Pin, time::Duration};
 
+#[cfg(target_arch = "wasm32")]
+pub mod loader;
+
 mod git_clones;
 mod notifier;
 
@@ -0,0 +1,87 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implementation of the `loader` interface on top of a [`Network`].
+//!
+//! The `modules-loader` program registers the main `loader` interface, while the
+//! `fallback-modules-loader` program registers the fallback interface, and is meant to be used
+//! alongside with a loader that stores the modules it obtains.
+
+use crate::{Network, NetworkEvent};
+
+use futures::prelude::*;
+use parity_scale_codec::DecodeAll;
+use redshirt_syscalls::InterfaceHash;
+use std::time::Duration;
+
+/// Registers `interface`, then answers the requests for modules made on it by fetching them from
+/// the network.
+///
+/// `interface` must be either [`INTERFACE`](redshirt_loader_interface::ffi::INTERFACE) or
+/// [`FALLBACK_INTERFACE`](redshirt_loader_interface::ffi::FALLBACK_INTERFACE).
+pub async fn serve(interface: InterfaceHash) {
+    redshirt_time_interface::monotonic_wait(Duration::from_secs(5)).await;
+
+    redshirt_interface_interface::register_interface(interface.clone())
+        .await
+        .unwrap();
+
+    let mut network = Network::start(Default::default()).unwrap();
+
+    loop {
+        let next_interface = redshirt_syscalls::next_interface_message();
+        let event = {
+            let next_net_event = network.next_event();
+            futures::pin_mut!(next_net_event);
+            match future::select(next_interface, next_net_event).await {
+                future::Either::Left((v, _)) => future::Either::Left(v),
+                future::Either::Right((v, _)) => future::Either::Right(v),
+            }
+        };
+
+        let msg = match event {
+            future::Either::Left(redshirt_syscalls::DecodedInterfaceOrDestroyed::Interface(m)) => m,
+            future::Either::Left(
+                redshirt_syscalls::DecodedInterfaceOrDestroyed::ProcessDestroyed(_),
+            ) => continue,
+            future::Either::Right(NetworkEvent::FetchSuccess { data, user_data }) => {
+                // Signatures aren't published on the network. Modules fetched here are
+                // rejected if only trusted publishers are allowed.
+                // TODO: fetch signatures from the network as well
+                let rp = redshirt_loader_interface::ffi::LoadResponse {
+                    result: Ok(data),
+                    signature: None,
+                };
+                redshirt_syscalls::emit_answer(user_data, &rp);
+                continue;
+            }
+            future::Either::Right(NetworkEvent::FetchFail { user_data }) => {
+                let rp = redshirt_loader_interface::ffi::LoadResponse {
+                    result: Err(()),
+                    signature: None,
+                };
+                redshirt_syscalls::emit_answer(user_data, &rp);
+                continue;
+            }
+        };
+
+        assert_eq!(msg.interface, interface);
+        let msg_data =
+            redshirt_loader_interface::ffi::LoaderMessage::decode_all(&msg.actual_data.0).unwrap();
+        let redshirt_loader_interface::ffi::LoaderMessage::Load(hash_to_load) = msg_data;
+        log::info!("loading {}", bs58::encode(hash_to_load).into_string());
+        network.start_fetch(&hash_to_load, msg.message_id.unwrap());
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Access to the PS/2 controller, also known as the 8042.
+//!
+//! The controller has two ports: the first one is normally connected to a keyboard, and the
+//! second one, called the auxiliary port, to a mouse. Bytes sent by both devices are read from
+//! the same data port, and the status register indicates which device each byte comes from.
+
+use redshirt_hardware_interface::{port_read_u8, port_write_u8};
+
+/// Port used to exchange data with the controller and the devices.
+const DATA_PORT: u32 = 0x60;
+/// Port that contains the status register when read, and accepts commands when written.
+const STATUS_COMMAND_PORT: u32 = 0x64;
+
+/// Bit of the status register indicating that a byte can be read from [`DATA_PORT`].
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+/// Bit of the status register indicating that the controller hasn't processed the previous
+/// write yet.
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// Bit of the status register indicating that the byte to read comes from the auxiliary port.
+const STATUS_AUX_DATA: u8 = 1 << 5;
+
+/// Bit of the configuration byte enabling IRQ 1 when the keyboard sends a byte.
+const CONFIG_KEYBOARD_INTERRUPT: u8 = 1 << 0;
+/// Bit of the configuration byte enabling IRQ 12 when the mouse sends a byte.
+const CONFIG_AUX_INTERRUPT: u8 = 1 << 1;
+/// Bit of the configuration byte enabling the translation of the keyboard's scancodes to set 1.
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const COMMAND_READ_CONFIG: u8 = 0x20;
+const COMMAND_WRITE_CONFIG: u8 = 0x60;
+const COMMAND_DISABLE_AUX: u8 = 0xa7;
+const COMMAND_ENABLE_AUX: u8 = 0xa8;
+const COMMAND_TEST_AUX: u8 = 0xa9;
+const COMMAND_DISABLE_KEYBOARD: u8 = 0xad;
+const COMMAND_ENABLE_KEYBOARD: u8 = 0xae;
+const COMMAND_WRITE_AUX: u8 = 0xd4;
+
+const DEVICE_GET_ID: u8 = 0xf2;
+const DEVICE_SET_SAMPLE_RATE: u8 = 0xf3;
+const DEVICE_ENABLE_REPORTING: u8 = 0xf4;
+const DEVICE_SET_DEFAULTS: u8 = 0xf6;
+/// Byte sent by devices to acknowledge a command.
+const DEVICE_ACK: u8 = 0xfa;
+
+/// Maximum number of times the status register is read while waiting for the controller.
+const MAX_POLLS: u32 = 10_000;
+
+/// Device connected to the controller.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Port {
+    Keyboard,
+    Mouse,
+}
+
+/// Initialized controller.
+pub struct Controller {
+    /// True if a mouse has been initialized on the auxiliary port.
+    has_mouse: bool,
+    /// True if the mouse reports the movements of its wheel.
+    mouse_has_wheel: bool,
+}
+
+impl Controller {
+    /// Initializes the controller and the devices connected to it.
+    ///
+    /// Interrupts are disabled during the initialization, and enabled only for the ports that
+    /// have been successfully initialized.
+    ///
+    /// Returns an error if there is no controller or if it doesn't respond.
+    ///
+    /// # Safety
+    ///
+    /// There must be a PS/2 controller at the standard ports, and no other code must access it.
+    pub async unsafe fn init() -> Result<Controller, ()> {
+        // Disable the devices, so that they don't send bytes while we configure the controller.
+        write_command(COMMAND_DISABLE_KEYBOARD).await?;
+        write_command(COMMAND_DISABLE_AUX).await?;
+        flush().await;
+
+        write_command(COMMAND_READ_CONFIG).await?;
+        let mut config = read_data().await?;
+        config &= !(CONFIG_KEYBOARD_INTERRUPT | CONFIG_AUX_INTERRUPT);
+        config |= CONFIG_TRANSLATION;
+        write_command(COMMAND_WRITE_CONFIG).await?;
+        write_data(config).await?;
+
+        write_command(COMMAND_ENABLE_KEYBOARD).await?;
+        let has_keyboard = send_to_device(Port::Keyboard, DEVICE_ENABLE_REPORTING).await;
+        if has_keyboard {
+            config |= CONFIG_KEYBOARD_INTERRUPT;
+        }
+
+        write_command(COMMAND_TEST_AUX).await?;
+        let (has_mouse, mouse_has_wheel) = if read_data().await? == 0 {
+            write_command(COMMAND_ENABLE_AUX).await?;
+            match init_mouse().await {
+                Some(has_wheel) => (true, has_wheel),
+                None => (false, false),
+            }
+        } else {
+            (false, false)
+        };
+        if has_mouse {
+            config |= CONFIG_AUX_INTERRUPT;
+        }
+
+        write_command(COMMAND_WRITE_CONFIG).await?;
+        write_data(config).await?;
+
+        Ok(Controller {
+            has_mouse,
+            mouse_has_wheel,
+        })
+    }
+
+    /// Returns true if a mouse is connected and has been initialized.
+    pub fn has_mouse(&self) -> bool {
+        self.has_mouse
+    }
+
+    /// Returns true if the mouse sends 4-byte packets containing the movement of its wheel.
+    pub fn mouse_has_wheel(&self) -> bool {
+        self.mouse_has_wheel
+    }
+
+    /// Reads all the bytes that the devices have sent and that haven't been read yet.
+    ///
+    /// # Safety
+    ///
+    /// No other code must access the controller.
+    pub async unsafe fn read_available(&self) -> Vec<(Port, u8)> {
+        let mut out = Vec::new();
+        loop {
+            let status = port_read_u8(STATUS_COMMAND_PORT).await;
+            if status & STATUS_OUTPUT_FULL == 0 {
+                break out;
+            }
+
+            let port = if status & STATUS_AUX_DATA != 0 {
+                Port::Mouse
+            } else {
+                Port::Keyboard
+            };
+            out.push((port, port_read_u8(DATA_PORT).await));
+        }
+    }
+}
+
+/// Resets the mouse to its default settings and enables it. Returns whether the mouse reports
+/// the movements of its wheel, or `None` if no mouse responds.
+async unsafe fn init_mouse() -> Option<bool> {
+    if !send_to_device(Port::Mouse, DEVICE_SET_DEFAULTS).await {
+        return None;
+    }
+
+    // Mice that support a wheel, known as "IntelliMouse", switch to reporting it when they
+    // receive this specific sequence of sample rates. They then identify themselves as device
+    // type 3.
+    for rate in &[200, 100, 80] {
+        if !send_to_device(Port::Mouse, DEVICE_SET_SAMPLE_RATE).await
+            || !send_to_device(Port::Mouse, *rate).await
+        {
+            return None;
+        }
+    }
+    if !send_to_device(Port::Mouse, DEVICE_GET_ID).await {
+        return None;
+    }
+    let has_wheel = read_data().await.ok()? == 3;
+
+    if !send_to_device(Port::Mouse, DEVICE_ENABLE_REPORTING).await {
+        return None;
+    }
+
+    Some(has_wheel)
+}
+
+/// Sends a byte to a device and waits for it to be acknowledged. Returns false if the device
+/// doesn't respond or doesn't acknowledge.
+async unsafe fn send_to_device(port: Port, byte: u8) -> bool {
+    if port == Port::Mouse && write_command(COMMAND_WRITE_AUX).await.is_err() {
+        return false;
+    }
+
+    if write_data(byte).await.is_err() {
+        return false;
+    }
+
+    read_data().await == Ok(DEVICE_ACK)
+}
+
+/// Discards the bytes that are waiting to be read.
+async unsafe fn flush() {
+    for _ in 0..MAX_POLLS {
+        if port_read_u8(STATUS_COMMAND_PORT).await & STATUS_OUTPUT_FULL == 0 {
+            break;
+        }
+        port_read_u8(DATA_PORT).await;
+    }
+}
+
+/// Sends a command to the controller.
+async unsafe fn write_command(command: u8) -> Result<(), ()> {
+    wait_status(STATUS_INPUT_FULL, false).await?;
+    port_write_u8(STATUS_COMMAND_PORT, command);
+    Ok(())
+}
+
+/// Writes a byte to the data port, destined to either the controller or a device depending on
+/// the previous command.
+async unsafe fn write_data(byte: u8) -> Result<(), ()> {
+    wait_status(STATUS_INPUT_FULL, false).await?;
+    port_write_u8(DATA_PORT, byte);
+    Ok(())
+}
+
+/// Waits for a byte to be available on the data port, then reads it.
+async unsafe fn read_data() -> Result<u8, ()> {
+    wait_status(STATUS_OUTPUT_FULL, true).await?;
+    Ok(port_read_u8(DATA_PORT).await)
+}
+
+/// Polls the status register until the given bit has the given value. Returns an error if it
+/// takes too long.
+async unsafe fn wait_status(bit: u8, value: bool) -> Result<(), ()> {
+    for _ in 0..MAX_POLLS {
+        let status = port_read_u8(STATUS_COMMAND_PORT).await;
+        if (status & bit != 0) == value {
+            return Ok(());
+        }
+    }
+
+    Err(())
+}
@@ -0,0 +1,155 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Decoding of the bytes sent by a keyboard.
+//!
+//! The controller translates the scancodes sent by the keyboard to scancode set 1, in which
+//! each key is identified by a byte whose most significant bit is set when the key is released.
+//! Some keys are prefixed with `0xe0`, and the Pause key sends a sequence prefixed with `0xe1`.
+
+use redshirt_hid_interface::ffi::{ButtonState, DeviceEvent};
+
+/// USB HID usages of the scancodes of set 1 that aren't prefixed, indexed by scancode. `0` if
+/// the scancode doesn't correspond to any key.
+const USAGES: [u16; 0x59] = [
+    // 0x00
+    0x00, 0x29, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x2d, 0x2e, 0x2a, 0x2b,
+    // 0x10
+    0x14, 0x1a, 0x08, 0x15, 0x17, 0x1c, 0x18, 0x0c, 0x12, 0x13, 0x2f, 0x30, 0x28, 0xe0, 0x04, 0x16,
+    // 0x20
+    0x07, 0x09, 0x0a, 0x0b, 0x0d, 0x0e, 0x0f, 0x33, 0x34, 0x35, 0xe1, 0x31, 0x1d, 0x1b, 0x06, 0x19,
+    // 0x30
+    0x05, 0x11, 0x10, 0x36, 0x37, 0x38, 0xe5, 0x55, 0xe2, 0x2c, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e,
+    // 0x40
+    0x3f, 0x40, 0x41, 0x42, 0x43, 0x53, 0x47, 0x5f, 0x60, 0x61, 0x56, 0x5c, 0x5d, 0x5e, 0x57, 0x59,
+    // 0x50
+    0x5a, 0x5b, 0x62, 0x63, 0x00, 0x00, 0x64, 0x44, 0x45,
+];
+
+/// USB HID usage of the Pause key, which sends its own sequence.
+const PAUSE_USAGE: u16 = 0x48;
+
+/// Decodes the bytes sent by a keyboard into events.
+#[derive(Default)]
+pub struct Decoder {
+    /// Prefix received before the current byte.
+    prefix: Prefix,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Prefix {
+    None,
+    /// `0xe0` has been received.
+    Extended,
+    /// `0xe1` and the given number of bytes of the Pause sequence have been received.
+    Pause(u8),
+}
+
+impl Default for Prefix {
+    fn default() -> Self {
+        Prefix::None
+    }
+}
+
+impl Decoder {
+    /// Processes a byte sent by the keyboard. Returns an event if the byte completes a scancode.
+    pub fn push(&mut self, byte: u8) -> Option<DeviceEvent> {
+        let state = if byte & 0x80 == 0 {
+            ButtonState::Pressed
+        } else {
+            ButtonState::Released
+        };
+
+        match (self.prefix, byte) {
+            (Prefix::None, 0xe0) => {
+                self.prefix = Prefix::Extended;
+                None
+            }
+            (Prefix::None, 0xe1) => {
+                self.prefix = Prefix::Pause(0);
+                None
+            }
+            // Answers to commands rather than keys.
+            (Prefix::None, 0x00)
+            | (Prefix::None, 0xfa)
+            | (Prefix::None, 0xfe)
+            | (Prefix::None, 0xff) => None,
+            (Prefix::None, _) => {
+                let code = byte & 0x7f;
+                Some(DeviceEvent::Key {
+                    usage: USAGES
+                        .get(usize::from(code))
+                        .cloned()
+                        .filter(|usage| *usage != 0),
+                    scancode: u32::from(code),
+                    state,
+                })
+            }
+            (Prefix::Extended, _) => {
+                self.prefix = Prefix::None;
+                let code = byte & 0x7f;
+                // Some keyboards surround the extended keys with fake presses and releases of
+                // the shift keys.
+                if code == 0x2a || code == 0x36 {
+                    return None;
+                }
+
+                Some(DeviceEvent::Key {
+                    usage: extended_usage(code),
+                    scancode: 0xe000 | u32::from(code),
+                    state,
+                })
+            }
+            (Prefix::Pause(0), _) => {
+                self.prefix = Prefix::Pause(1);
+                None
+            }
+            (Prefix::Pause(_), _) => {
+                self.prefix = Prefix::None;
+                // The key is "pressed" by `e1 1d 45` and "released" by `e1 9d c5`.
+                Some(DeviceEvent::Key {
+                    usage: Some(PAUSE_USAGE),
+                    scancode: 0xe11d45,
+                    state,
+                })
+            }
+        }
+    }
+}
+
+/// Returns the USB HID usage of a scancode of set 1 prefixed with `0xe0`.
+fn extended_usage(code: u8) -> Option<u16> {
+    Some(match code {
+        0x1c => 0x58, // Keypad Enter
+        0x1d => 0xe4, // Right Control
+        0x35 => 0x54, // Keypad /
+        0x37 => 0x46, // Print Screen
+        0x38 => 0xe6, // Right Alt
+        0x47 => 0x4a, // Home
+        0x48 => 0x52, // Up Arrow
+        0x49 => 0x4b, // Page Up
+        0x4b => 0x50, // Left Arrow
+        0x4d => 0x4f, // Right Arrow
+        0x4f => 0x4d, // End
+        0x50 => 0x51, // Down Arrow
+        0x51 => 0x4e, // Page Down
+        0x52 => 0x49, // Insert
+        0x53 => 0x4c, // Delete
+        0x5b => 0xe3, // Left GUI
+        0x5c => 0xe7, // Right GUI
+        0x5d => 0x65, // Application
+        _ => return None,
+    })
+}
@@ -0,0 +1,149 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for the keyboard and mouse connected to the PS/2 controller of x86 machines.
+//!
+//! This program initializes the controller, registers the keyboard and the mouse towards the
+//! hid interface, then waits for IRQ 1 and IRQ 12 and reports the bytes sent by the devices.
+//!
+//! Bibliography:
+//!
+//! - https://wiki.osdev.org/%228042%22_PS/2_Controller
+//! - https://wiki.osdev.org/PS/2_Keyboard
+//! - https://wiki.osdev.org/PS/2_Mouse
+//!
+
+use futures::prelude::*;
+use redshirt_hardware_interface::interrupts;
+use redshirt_hid_interface::{ffi, Device};
+use std::pin::Pin;
+
+mod controller;
+mod keyboard;
+mod mouse;
+
+/// ISA IRQ triggered when the keyboard sends a byte.
+const KEYBOARD_IRQ: u8 = 1;
+/// ISA IRQ triggered when the mouse sends a byte.
+const MOUSE_IRQ: u8 = 12;
+
+fn main() {
+    redshirt_syscalls::block_on(async_main());
+}
+
+async fn async_main() {
+    let controller = match unsafe { controller::Controller::init() }.await {
+        Ok(c) => c,
+        Err(()) => {
+            redshirt_log_interface::emit_log(
+                redshirt_log_interface::Level::Info,
+                "No PS/2 controller found",
+            );
+            return;
+        }
+    };
+
+    let keyboard = Device::register(ffi::DeviceKind::Keyboard, "PS/2 keyboard")
+        .await
+        .unwrap();
+    let mut keyboard_decoder = keyboard::Decoder::default();
+    let keyboard_irq = subscribe(KEYBOARD_IRQ).await;
+
+    let (mouse, mouse_irq) = if controller.has_mouse() {
+        let device = Device::register(ffi::DeviceKind::Pointer, "PS/2 mouse")
+            .await
+            .unwrap();
+        (Some(device), subscribe(MOUSE_IRQ).await)
+    } else {
+        (None, None)
+    };
+    let mut mouse_decoder = mouse::Decoder::new(controller.mouse_has_wheel());
+
+    redshirt_log_interface::emit_log(
+        redshirt_log_interface::Level::Info,
+        &format!(
+            "Initialized PS/2 controller (mouse: {}, wheel: {})",
+            controller.has_mouse(),
+            controller.mouse_has_wheel()
+        ),
+    );
+
+    let mut keyboard_wait = wait(&keyboard_irq);
+    let mut mouse_wait = wait(&mouse_irq);
+
+    loop {
+        // Both devices share the data port, so we read everything that is available whichever
+        // interrupt has been triggered.
+        let mut keyboard_events = Vec::new();
+        let mut mouse_events = Vec::new();
+        for (port, byte) in unsafe { controller.read_available().await } {
+            match port {
+                controller::Port::Keyboard => keyboard_events.extend(keyboard_decoder.push(byte)),
+                controller::Port::Mouse => mouse_events.extend(mouse_decoder.push(byte)),
+            }
+        }
+
+        keyboard.report(keyboard_events);
+        if let Some(mouse) = &mouse {
+            mouse.report(mouse_events);
+        }
+
+        match future::select(keyboard_wait, mouse_wait).await {
+            future::Either::Left((_, m)) => {
+                keyboard_wait = wait(&keyboard_irq);
+                mouse_wait = m;
+            }
+            future::Either::Right((_, k)) => {
+                keyboard_wait = k;
+                mouse_wait = wait(&mouse_irq);
+            }
+        }
+    }
+}
+
+/// Subscribes to an ISA IRQ. Logs a warning and returns `None` if the subscription fails.
+async fn subscribe(irq: u8) -> Option<interrupts::Interrupt> {
+    let source = interrupts::InterruptSource::IsaIrq(irq);
+    match interrupts::subscribe(source).await {
+        Ok(interrupt) => Some(interrupt),
+        Err(err) => {
+            redshirt_log_interface::emit_log(
+                redshirt_log_interface::Level::Warn,
+                &format!("Failed to subscribe to IRQ {}: {:?}", irq, err),
+            );
+            None
+        }
+    }
+}
+
+/// Returns a future that waits for the given interrupt, or never finishes if `None` or if
+/// waiting fails.
+fn wait(interrupt: &Option<interrupts::Interrupt>) -> Pin<Box<dyn Future<Output = u32>>> {
+    match interrupt {
+        Some(interrupt) => Box::pin(interrupt.wait().then(|result| async move {
+            match result {
+                Ok(num) => num,
+                Err(()) => {
+                    redshirt_log_interface::emit_log(
+                        redshirt_log_interface::Level::Warn,
+                        "Failed to wait for PS/2 interrupt",
+                    );
+                    future::pending().await
+                }
+            }
+        })),
+        None => Box::pin(future::pending()),
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Decoding of the packets sent by a mouse.
+//!
+//! Each packet is 3 bytes long, or 4 bytes if the mouse reports its wheel:
+//!
+//! - The first byte contains the state of the buttons in its 3 lowest bits, a bit that is always
+//! set, and the sign and overflow bits of the movement.
+//! - The second and third bytes contain the horizontal and vertical movement. The vertical axis
+//! points upwards.
+//! - The fourth byte, if any, contains the movement of the wheel as a signed value. Positive
+//! values correspond to scrolling down.
+
+use redshirt_hid_interface::ffi::{ButtonState, DeviceEvent};
+
+/// Bit of the first byte of a packet that is always set. Used to detect when the decoder is out
+/// of sync with the mouse.
+const ALWAYS_ONE: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+
+/// Decodes the bytes sent by a mouse into events.
+pub struct Decoder {
+    /// Bytes of the packet being received.
+    packet: [u8; 4],
+    /// Number of bytes of `packet` received so far.
+    received: usize,
+    /// Length of a packet.
+    packet_len: usize,
+    /// State of the buttons, as found in the first byte of the previous packet.
+    buttons: u8,
+}
+
+impl Decoder {
+    /// Initializes a decoder. `has_wheel` must be true if the mouse sends 4-byte packets.
+    pub fn new(has_wheel: bool) -> Self {
+        Decoder {
+            packet: [0; 4],
+            received: 0,
+            packet_len: if has_wheel { 4 } else { 3 },
+            buttons: 0,
+        }
+    }
+
+    /// Processes a byte sent by the mouse. Returns the events of the packet if the byte
+    /// completes one.
+    pub fn push(&mut self, byte: u8) -> Vec<DeviceEvent> {
+        if self.received == 0 && byte & ALWAYS_ONE == 0 {
+            return Vec::new();
+        }
+
+        self.packet[self.received] = byte;
+        self.received += 1;
+        if self.received < self.packet_len {
+            return Vec::new();
+        }
+        self.received = 0;
+
+        let mut events = Vec::new();
+        let flags = self.packet[0];
+
+        for button in 0..3 {
+            let mask = 1 << button;
+            if (flags ^ self.buttons) & mask == 0 {
+                continue;
+            }
+            events.push(DeviceEvent::PointerButton {
+                // The first byte contains left, right, then middle, which matches the numbering
+                // of the interface.
+                button,
+                state: if flags & mask != 0 {
+                    ButtonState::Pressed
+                } else {
+                    ButtonState::Released
+                },
+            });
+        }
+        self.buttons = flags & 0x7;
+
+        // Movements are 9 bits signed values, whose sign bit is in the first byte. They are
+        // meaningless in case of overflow.
+        if flags & (X_OVERFLOW | Y_OVERFLOW) == 0 {
+            let dx = i32::from(self.packet[1]) - if flags & X_SIGN != 0 { 0x100 } else { 0 };
+            let dy = i32::from(self.packet[2]) - if flags & Y_SIGN != 0 { 0x100 } else { 0 };
+            if dx != 0 || dy != 0 {
+                events.push(DeviceEvent::PointerMotion { dx, dy: -dy });
+            }
+        }
+
+        if self.packet_len == 4 {
+            let dz = i32::from(self.packet[3] as i8);
+            if dz != 0 {
+                events.push(DeviceEvent::Wheel {
+                    dx: 0,
+                    dy: -dz * 100,
+                });
+            }
+        }
+
+        events
+    }
+}
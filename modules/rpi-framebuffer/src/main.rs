@@ -13,18 +13,124 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+//! Raspberry Pi framebuffer driver.
+//!
+//! Allocates a framebuffer through the property interface of the VideoCore mailbox, then
+//! implements the framebuffer interface by drawing the framebuffers of other processes on it.
+//! See the [`screen`] module for how they are drawn.
+//!
+//! While no framebuffer exists, the screen is given to the kernel to print its logs.
+
 // TODO: doc https://jsandler18.github.io/
 
-use parity_scale_codec::DecodeAll;
-use std::{convert::TryFrom as _, fmt};
+use redshirt_framebuffer_interface::ffi;
+use redshirt_kernel_log_interface::ffi::{
+    FramebufferFormat, FramebufferInfo, KernelLogMethod, UartInfo,
+};
+use redshirt_syscalls::Decode as _;
 
 mod mailbox;
 mod property;
+mod screen;
+
+/// Width of the screen to request to the GPU.
+const SCREEN_WIDTH: u32 = 640;
+/// Height of the screen to request to the GPU.
+const SCREEN_HEIGHT: u32 = 480;
+
+/// Location in physical memory of the UART on which the kernel prints its logs.
+const UART0_BASE: u64 = 0x3f201000; // 0x20201000 for raspi 1
 
 fn main() {
     redshirt_syscalls::block_on(async_main());
 }
 
 async fn async_main() {
-    property::init().await;
+    let allocation = match property::allocate_framebuffer(SCREEN_WIDTH, SCREEN_HEIGHT).await {
+        Some(allocation) => allocation,
+        None => {
+            redshirt_log_interface::emit_log(
+                redshirt_log_interface::Level::Error,
+                "Failed to allocate a framebuffer",
+            );
+            return;
+        }
+    };
+
+    let mut screen = screen::Screen::new(allocation);
+    let mut logs_on_screen = true;
+    redshirt_kernel_log_interface::configure_kernel(log_method(&screen, logs_on_screen)).await;
+
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    loop {
+        match redshirt_syscalls::next_interface_message().await {
+            redshirt_syscalls::DecodedInterfaceOrDestroyed::Interface(message) => {
+                assert_eq!(message.interface, ffi::INTERFACE);
+                let answers = match ffi::FramebufferMessage::decode(message.actual_data) {
+                    Ok(msg) => screen.handle_message(message.emitter_pid, message.message_id, msg),
+                    Err(_) => message
+                        .message_id
+                        .map(|id| (id, Err(())))
+                        .into_iter()
+                        .collect(),
+                };
+                for (message_id, answer) in answers {
+                    match answer {
+                        Ok(answer) => redshirt_syscalls::emit_answer(message_id, answer),
+                        Err(()) => redshirt_syscalls::emit_message_error(message_id),
+                    }
+                }
+            }
+            redshirt_syscalls::DecodedInterfaceOrDestroyed::ProcessDestroyed(p) => {
+                screen.process_destroyed(p.pid);
+            }
+        }
+
+        // The kernel logs are only printed on the screen while no framebuffer is shown.
+        if logs_on_screen == screen.is_in_use() {
+            logs_on_screen = !logs_on_screen;
+            redshirt_kernel_log_interface::configure_kernel(log_method(&screen, logs_on_screen))
+                .await;
+            // Erase the logs that the kernel might have printed in the meanwhile.
+            if !logs_on_screen {
+                screen.redraw_all();
+            }
+        }
+    }
+}
+
+/// Returns how the kernel should print its logs, depending on whether it can use the screen.
+fn log_method(screen: &screen::Screen, on_screen: bool) -> KernelLogMethod {
+    let framebuffer = if on_screen {
+        Some(FramebufferInfo {
+            address: screen.address(),
+            width: screen.width(),
+            height: screen.height(),
+            pitch: u64::from(screen.pitch()),
+            bytes_per_character: 3,
+            format: FramebufferFormat::Rgb {
+                red_size: 8,
+                red_position: 0,
+                green_size: 8,
+                green_position: 8,
+                blue_size: 8,
+                blue_position: 16,
+            },
+        })
+    } else {
+        None
+    };
+
+    KernelLogMethod {
+        enabled: true,
+        framebuffer,
+        uart: Some(UartInfo {
+            wait_low_address: UART0_BASE + 0x18,
+            wait_low_mask: 1 << 5,
+            write_address: UART0_BASE + 0x0,
+        }),
+    }
 }
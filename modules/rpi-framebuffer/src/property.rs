@@ -16,6 +16,13 @@
 use crate::mailbox;
 use std::{borrow::Cow, convert::TryFrom as _, iter};
 
+/// Offset to add to a physical address in order to obtain the address through which the GPU
+/// accesses the same memory while bypassing its caches.
+///
+/// Without it, the GPU might read a stale version of the requests that we write in memory, and
+/// we might read a stale version of its responses.
+const BUS_UNCACHED_ALIAS: u32 = 0xc0000000; // 0x40000000 for raspi 1
+
 /// Builder for a request on the property interface.
 pub struct PropertyMessageBuilder {
     /// Buffer containing the request.
@@ -58,20 +65,6 @@ impl PropertyMessageBuilder {
 
     pub async fn send(self) {
         unimplemented!()
-        /*let buffer1 = redshirt_hardware_interface::malloc::PhysicalBuffer::new(Packet1 {
-            data: [
-                80,                             // The whole buffer is 80 bytes
-                0,                              // This is a request, so the request/response code is 0
-                0x00048003, 8, 0, 640, 480,     // This tag sets the screen size to 640x480
-                0x00048004, 8, 0, 640, 480,     // This tag sets the virtual screen size to 640x480
-                0x00048005, 4, 0, 24,           // This tag sets the depth to 24 bits
-                0,                              // This is the end tag
-                0, 0, 0                         // This pads the message to by 16 byte aligned
-            ]
-        }).await;
-
-        assert_eq!(buffer1.pointer() % 16, 0);
-        mailbox::write_mailbox(mailbox::Message::new(8, u32::try_from(buffer1.pointer() >> 4).unwrap())).await;      // TODO: ` | 0x40000000` ?*/
     }
 }
 
@@ -124,79 +117,74 @@ pub struct PropertyMessageParserTag<'a> {
     data: &'a [u32],
 }
 
-#[repr(align(16))]
-struct Packet1 {
-    data: [u32; 20],
+/// Framebuffer allocated by the GPU.
+#[derive(Debug)]
+pub struct FramebufferAllocation {
+    /// Location in physical memory of the top-left pixel.
+    pub address: u64,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Number of bytes between the start of a line of pixels and the start of the next one.
+    pub pitch: u32,
 }
 
 #[repr(align(16))]
-struct Packet2 {
-    data: [u32; 8],
+struct AllocatePacket {
+    data: [u32; 32],
 }
 
 // TODO: make more generic and explicit, with tags and all, to be more robust to code changes
-pub async fn init() {
-    let buffer1 = redshirt_hardware_interface::malloc::PhysicalBuffer::new(Packet1 {
+/// Asks the GPU to allocate a framebuffer of the given dimensions, with three bytes per pixel
+/// in the red, green, blue order.
+///
+/// Returns `None` if the GPU has refused any of the requested settings.
+pub async fn allocate_framebuffer(width: u32, height: u32) -> Option<FramebufferAllocation> {
+    let buffer = redshirt_hardware_interface::malloc::PhysicalBuffer::new(AllocatePacket {
         data: [
-            80, // The whole buffer is 80 bytes
-            0,  // This is a request, so the request/response code is 0
-            0x00048003, 8, 0, 640, 480, // This tag sets the screen size to 640x480
-            0x00048004, 8, 0, 640, 480, // This tag sets the virtual screen size to 640x480
+            128, // The whole buffer is 128 bytes
+            0,   // This is a request, so the request/response code is 0
+            0x00048003, 8, 0, width, height, // This tag sets the screen size
+            0x00048004, 8, 0, width, height, // This tag sets the virtual screen size
             0x00048005, 4, 0, 24, // This tag sets the depth to 24 bits
-            0,  // This is the end tag
-            0, 0, 0, // This pads the message to by 16 byte aligned
-        ],
-    })
-    .await;
-
-    assert_eq!(buffer1.pointer() % 16, 0);
-    mailbox::write_mailbox(mailbox::Message::new(
-        8,
-        u32::try_from(buffer1.pointer() >> 4).unwrap(),
-    ))
-    .await; // TODO: ` | 0x40000000` ?
-
-    mailbox::read_mailbox().await;
-
-    let data1 = buffer1.take().await;
-    assert_eq!(data1.data[1], 0x80000000);
-
-    let actual_width = data1.data[5];
-    let actual_height = data1.data[6];
-
-    let buffer2 = redshirt_hardware_interface::malloc::PhysicalBuffer::new(Packet2 {
-        data: [
-            32, // The whole buffer is 32 bytes
-            0,  // This is a request, so the request/response code is 0
+            0x00048006, 4, 0, 1, // This tag sets the pixel order to RGB
             0x00040001, 8, 0, 16, 0, // This tag requests a 16 byte aligned framebuffer
+            0x00040008, 4, 0, 0, // This tag requests the pitch
             0, // This is the end tag
+            0, 0, // This pads the message to by 16 byte aligned
         ],
     })
     .await;
 
-    assert_eq!(buffer2.pointer() % 16, 0);
-    mailbox::write_mailbox(mailbox::Message::new(
-        8,
-        u32::try_from(buffer2.pointer() >> 4).unwrap(),
-    ))
-    .await;
+    assert_eq!(buffer.pointer() % 16, 0);
+    let bus_address = u32::try_from(buffer.pointer()).unwrap() | BUS_UNCACHED_ALIAS;
+    mailbox::write_mailbox(mailbox::Message::new(8, bus_address >> 4)).await;
 
     mailbox::read_mailbox().await;
 
-    let data2 = buffer2.take().await;
-    assert_eq!(data2.data[1], 0x80000000);
+    let data = buffer.take().await;
+    if data.data[1] != 0x80000000 {
+        return None;
+    }
 
-    let fb_addr = data2.data[5];
-    let fb_size = data2.data[6];
-    //panic!("{:x} size {}", fb_addr, fb_size);
+    // The highest bit of the code of each tag is set if the GPU has processed it.
+    if [4, 9, 14, 18, 22, 27]
+        .iter()
+        .any(|n| data.data[*n] & 0x80000000 == 0)
+    {
+        return None;
+    }
 
-    let mut op_builder = redshirt_hardware_interface::HardwareWriteOperationsBuilder::new();
-    unsafe {
-        op_builder.memset(
-            u64::from(fb_addr),
-            3 * u64::from(actual_height) * u64::from(actual_width),
-            0xff,
-        );
+    if data.data[15] != 24 || data.data[19] != 1 || data.data[23] == 0 {
+        return None;
     }
-    op_builder.send();
+
+    Some(FramebufferAllocation {
+        // The GPU returns a bus address, which we convert to a physical address.
+        address: u64::from(data.data[23] & !BUS_UNCACHED_ALIAS),
+        width: data.data[5],
+        height: data.data[6],
+        pitch: data.data[28],
+    })
 }
@@ -0,0 +1,134 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Framebuffers of processes drawn on the screen.
+//!
+//! The framebuffers are managed by the
+//! [`composite`](redshirt_framebuffer_interface::composite) module of the interface. All of them
+//! are drawn at the top-left corner of the screen, in the order in which they have been created.
+//! The most recently created framebuffer is therefore the one that is visible, and the ones below
+//! it only show where it doesn't cover them.
+
+use crate::property::FramebufferAllocation;
+
+use redshirt_framebuffer_interface::composite::Framebuffers;
+use redshirt_framebuffer_interface::ffi::{self, FramebufferMessage};
+use redshirt_syscalls::{EncodedMessage, MessageId, Pid};
+use std::convert::TryFrom as _;
+
+/// Screen allocated by the GPU and framebuffers drawn on it.
+pub struct Screen {
+    /// Location of the screen in physical memory.
+    allocation: FramebufferAllocation,
+    /// Framebuffers of the processes.
+    framebuffers: Framebuffers,
+}
+
+impl Screen {
+    /// Initializes a screen without any framebuffer.
+    ///
+    /// Nothing is drawn until a framebuffer is created.
+    pub fn new(allocation: FramebufferAllocation) -> Self {
+        // All the framebuffers are at the same position.
+        let framebuffers = Framebuffers::new(allocation.width, allocation.height, 0);
+        Screen {
+            allocation,
+            framebuffers,
+        }
+    }
+
+    /// Returns the width of the screen in pixels.
+    pub fn width(&self) -> u32 {
+        self.allocation.width
+    }
+
+    /// Returns the height of the screen in pixels.
+    pub fn height(&self) -> u32 {
+        self.allocation.height
+    }
+
+    /// Returns the number of bytes between the start of two lines of the screen.
+    pub fn pitch(&self) -> u32 {
+        self.allocation.pitch
+    }
+
+    /// Returns the location of the screen in physical memory.
+    pub fn address(&self) -> u64 {
+        self.allocation.address
+    }
+
+    /// Returns true if at least one framebuffer exists.
+    pub fn is_in_use(&self) -> bool {
+        !self.framebuffers.is_empty()
+    }
+
+    /// Processes a message, draws the screen accordingly, and returns the messages to answer.
+    pub fn handle_message(
+        &mut self,
+        emitter_pid: Pid,
+        message_id: Option<MessageId>,
+        message: FramebufferMessage,
+    ) -> Vec<(MessageId, Result<EncodedMessage, ()>)> {
+        let outcome =
+            self.framebuffers
+                .handle_message(emitter_pid, message_id, message, |payload| {
+                    payload.into_vec().ok()
+                });
+        if let Some(rect) = outcome.damage {
+            self.redraw(rect);
+        }
+        outcome.answers
+    }
+
+    /// Removes all the framebuffers of the given process from the screen.
+    pub fn process_destroyed(&mut self, pid: Pid) {
+        if let Some(rect) = self.framebuffers.process_destroyed(pid) {
+            self.redraw(rect);
+        }
+    }
+
+    /// Draws the entire screen.
+    pub fn redraw_all(&self) {
+        self.redraw(ffi::Rect {
+            x: 0,
+            y: 0,
+            width: self.width(),
+            height: self.height(),
+        });
+    }
+
+    /// Draws the given area of the screen by copying it line by line to the memory of the GPU.
+    ///
+    /// The area must be within the screen.
+    fn redraw(&self, rect: ffi::Rect) {
+        let mut ops = redshirt_hardware_interface::HardwareWriteOperationsBuilder::with_capacity(
+            usize::try_from(rect.height).unwrap(),
+        );
+
+        let mut row = vec![[0, 0, 0]; usize::try_from(rect.width).unwrap()];
+        for line in rect.y..rect.y + rect.height {
+            self.framebuffers.draw_row(rect.x, line, &mut row);
+
+            let data = row.iter().flatten().copied().collect::<Vec<u8>>();
+            let address =
+                self.address() + u64::from(line) * u64::from(self.pitch()) + u64::from(rect.x) * 3;
+            unsafe {
+                ops.write(address, data);
+            }
+        }
+
+        ops.send();
+    }
+}
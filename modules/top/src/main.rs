@@ -0,0 +1,147 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Periodically prints the list of running processes and interfaces.
+//!
+//! The information is obtained through the `kernel-debug` interface and printed through the
+//! `log` interface, similar to what the `top` program does on Unix-like systems. This program
+//! must be authorized to use the `kernel-debug` interface.
+//!
+//! The state of each thread of a process is shown as a single letter:
+//!
+//! - `R`: ready to run.
+//! - `X`: running.
+//! - `S`: sleeping, waiting for a notification.
+//! - `I`: waiting for an interface to be registered.
+//! - `W`: waiting for the kernel to answer a call.
+
+use redshirt_kernel_debug_interface::ffi;
+use std::{collections::HashMap, fmt::Write as _, time::Duration};
+
+/// Interval between two reports.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+fn main() {
+    redshirt_syscalls::block_on(async_main());
+}
+
+async fn async_main() {
+    // CPU time of each process and monotonic clock value at the time of the previous report.
+    // Used to calculate the CPU usage in-between two reports.
+    let mut previous_cpu_times = HashMap::new();
+    let mut previous_now = redshirt_time_interface::monotonic_clock().await;
+
+    loop {
+        redshirt_time_interface::monotonic_wait(REFRESH_INTERVAL).await;
+
+        let processes = redshirt_kernel_debug_interface::processes().await;
+        let interfaces = redshirt_kernel_debug_interface::interfaces().await;
+        let now = redshirt_time_interface::monotonic_clock().await;
+        let elapsed = now.saturating_sub(previous_now);
+
+        let mut report = String::new();
+        writeln!(report, "{} processes", processes.len()).unwrap();
+        writeln!(
+            report,
+            "{:>6} {:>8} {:>10} {:>6} {:>6}  {:<20} INTERFACES",
+            "PID", "THREADS", "MEMORY", "CPU%", "QUEUE", "NAME"
+        )
+        .unwrap();
+
+        let mut new_cpu_times = HashMap::with_capacity(processes.len());
+        for process in &processes {
+            let pid = u64::from(process.pid);
+
+            let cpu_usage = match (process.cpu_time, previous_cpu_times.get(&pid)) {
+                (Some(cpu_time), Some(previous)) if elapsed != 0 => {
+                    let used = u128::from(cpu_time.saturating_sub(*previous));
+                    format!("{:.1}", (used * 1000 / elapsed) as f64 / 10.0)
+                }
+                _ => "-".to_owned(),
+            };
+            if let Some(cpu_time) = process.cpu_time {
+                new_cpu_times.insert(pid, cpu_time);
+            }
+
+            let threads = process
+                .threads
+                .iter()
+                .map(|thread| thread_state_letter(&thread.state))
+                .collect::<String>();
+
+            // Processes without a name are identified by their module.
+            let name = match &process.name {
+                Some(name) => name.clone(),
+                None => short_hash(&process.module_hash),
+            };
+
+            let registered = process
+                .registered_interfaces
+                .iter()
+                .map(|interface| short_hash(&<[u8; 32]>::from(interface.clone())))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            writeln!(
+                report,
+                "{:>6} {:>8} {:>9}K {:>6} {:>6}  {:<20} {}",
+                pid,
+                threads,
+                process.memory_size / 1024,
+                cpu_usage,
+                process.notifications_queue_len,
+                name,
+                registered
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "{} interfaces", interfaces.len()).unwrap();
+        for interface in &interfaces {
+            let hash = short_hash(&<[u8; 32]>::from(interface.interface.clone()));
+            match interface.handler {
+                ffi::InterfaceHandler::Process(pid) => {
+                    writeln!(report, "{:<10} handled by {}", hash, u64::from(pid)).unwrap()
+                }
+                ffi::InterfaceHandler::Requested { waiting } => {
+                    writeln!(report, "{:<10} unregistered, {} waiting", hash, waiting).unwrap()
+                }
+            }
+        }
+
+        redshirt_log_interface::emit_log(redshirt_log_interface::Level::Info, report.trim_end());
+
+        previous_cpu_times = new_cpu_times;
+        previous_now = now;
+    }
+}
+
+/// Returns a single letter summarizing the state of a thread.
+fn thread_state_letter(state: &ffi::ThreadState) -> char {
+    match state {
+        ffi::ThreadState::Ready => 'R',
+        ffi::ThreadState::Running => 'X',
+        ffi::ThreadState::WaitNotification => 'S',
+        ffi::ThreadState::EmitMessage(_) => 'I',
+        ffi::ThreadState::WaitExtrinsic => 'W',
+    }
+}
+
+/// Returns the first characters of the base58 representation of the hash.
+fn short_hash(hash: &[u8; 32]) -> String {
+    let mut encoded = bs58::encode(hash).into_string();
+    encoded.truncate(8);
+    encoded
+}
@@ -0,0 +1,372 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::virtqueue::{VirtQueue, DESC_F_WRITE};
+
+use core::{convert::TryFrom as _, fmt};
+use futures::lock::Mutex;
+use std::collections::VecDeque;
+use redshirt_ethernet_interface::interface::{Checksums, Frame};
+
+/// State of a device.
+//
+// # Device overview
+//
+// We use the legacy interface of the Virtio specifications, where the registers of the device
+// are accessed through the I/O BAR 0.
+//
+// The device must have MSI-X enabled, which shifts the device-specific configuration by four
+// bytes. The completions of the receive queue and of the send queue are signalled through
+// MSI-X vectors [`RX_VECTOR`] and [`TX_VECTOR`]. Configuration changes aren't signalled, and
+// the link status is instead polled.
+//
+// The network device has two virtqueues: queue 0 is used to receive frames, and queue 1 is used
+// to send frames. Each frame, in both directions, is prefixed with a `virtio_net_hdr` header.
+//
+// # Implementation note
+//
+// Each descriptor of each queue permanently points to the same buffer in physical memory, that
+// we allocate at initialization. All the descriptors of the receive queue are always owned by
+// the device, except for the short period of time between when we read a frame and when we
+// give back the descriptor.
+//
+pub struct Device {
+    /// Base I/O port of the device. All registers are derived from this one.
+    base_port: u32,
+    /// MAC address of the device.
+    mac_address: [u8; 6],
+    /// Features that have been negotiated with the device.
+    features: u32,
+    /// Information necessary for receiving frames.
+    receiving: Mutex<Receiving>,
+    /// Information necessary for sending frames.
+    sending: Mutex<Sending>,
+}
+
+/// Receiving state of the device.
+struct Receiving {
+    /// Queue 0 of the device.
+    queue: VirtQueue,
+    /// For each descriptor, the physical address of its buffer.
+    buffers: Vec<u64>,
+}
+
+/// Sending state of the device.
+struct Sending {
+    /// Queue 1 of the device.
+    queue: VirtQueue,
+    /// For each descriptor, the physical address of its buffer.
+    buffers: Vec<u64>,
+    /// List of descriptors that aren't owned by the device.
+    free_descriptors: Vec<u16>,
+}
+
+/// Report of a call to [`Device::send_frames`].
+#[derive(Debug, Default)]
+pub struct SendReport {
+    /// Number of frames that have been queued for sending.
+    pub sent_frames: u64,
+    /// Total number of bytes of the frames that have been queued for sending.
+    pub sent_bytes: u64,
+    /// Number of frames that have been discarded because they are too large.
+    pub discarded: u64,
+}
+
+/// Error that can happen when initializing a device.
+#[derive(Debug)]
+pub enum InitError {
+    /// The device doesn't have the expected virtqueues, or refuses to signal their completions
+    /// through MSI-X.
+    MissingQueue,
+}
+
+/// MSI-X table entry that the device uses to signal the completion of received frames.
+pub const RX_VECTOR: u16 = 0;
+/// MSI-X table entry that the device uses to signal the completion of sent frames.
+pub const TX_VECTOR: u16 = 1;
+/// Value of an MSI-X vector register indicating that no interrupt must be triggered.
+const NO_VECTOR: u16 = 0xffff;
+/// Offset of the device-specific configuration from the base I/O port, when MSI-X is enabled.
+const DEVICE_CONFIG: u32 = 0x18;
+
+/// Size of the `virtio_net_hdr` header that precedes each frame.
+const NET_HDR_LEN: u32 = 10;
+/// Size of each buffer. Must be large enough to contain the header plus an Ethernet frame.
+const BUFFER_LEN: u32 = 2048;
+/// Maximum number of descriptors that we use per queue.
+const MAX_DESCRIPTORS: u16 = 64;
+
+/// The device verifies the checksums of received frames.
+const NET_F_GUEST_CSUM: u32 = 1 << 1;
+/// The device has a MAC address in its configuration.
+const NET_F_MAC: u32 = 1 << 5;
+/// The device reports its link status in its configuration.
+const NET_F_STATUS: u32 = 1 << 16;
+
+/// Flag of the header of received frames indicating that the checksum has been verified.
+const NET_HDR_F_DATA_VALID: u8 = 2;
+
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FAILED: u8 = 128;
+
+impl Device {
+    /// Assumes that a legacy Virtio network device has its I/O registers mapped starting at
+    /// `base_port` and reinitializes it.
+    ///
+    /// The MSI-X capability of the device must have been enabled beforehand, and the entries
+    /// [`RX_VECTOR`] and [`TX_VECTOR`] of its MSI-X table configured.
+    pub async unsafe fn reset(base_port: u32) -> Result<Self, InitError> {
+        // Writing 0 to the status register resets the device.
+        redshirt_hardware_interface::port_write_u8(base_port + 0x12, 0);
+        redshirt_hardware_interface::port_write_u8(base_port + 0x12, STATUS_ACKNOWLEDGE);
+        redshirt_hardware_interface::port_write_u8(
+            base_port + 0x12,
+            STATUS_ACKNOWLEDGE | STATUS_DRIVER,
+        );
+
+        // Features negotiation.
+        let features = {
+            let mut ops = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+            let mut device_features = 0;
+            ops.port_read_u32(base_port + 0x00, &mut device_features);
+            ops.send().await;
+            device_features & (NET_F_GUEST_CSUM | NET_F_MAC | NET_F_STATUS)
+        };
+        {
+            let mut ops = redshirt_hardware_interface::HardwareWriteOperationsBuilder::new();
+            ops.port_write_u32(base_port + 0x04, features);
+            // Configuration changes aren't signalled.
+            ops.port_write_u16(base_port + 0x14, NO_VECTOR);
+            ops.send();
+        }
+
+        // Read our MAC address. If the device doesn't have any, we generate a locally
+        // administered one.
+        let mac_address = if features & NET_F_MAC != 0 {
+            let mut mac_address = [0; 6];
+            let mut ops = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+            for (n, byte) in mac_address.iter_mut().enumerate() {
+                ops.port_read_u8(base_port + DEVICE_CONFIG + u32::try_from(n).unwrap(), byte);
+            }
+            ops.send().await;
+            mac_address
+        } else {
+            [0x02, 0x00, 0x00, 0x00, 0x00, 0x01]
+        };
+
+        let (rx_queue, tx_queue) = match (
+            VirtQueue::new(base_port, 0, RX_VECTOR).await,
+            VirtQueue::new(base_port, 1, TX_VECTOR).await,
+        ) {
+            (Some(rx), Some(tx)) => (rx, tx),
+            _ => {
+                redshirt_hardware_interface::port_write_u8(base_port + 0x12, STATUS_FAILED);
+                return Err(InitError::MissingQueue);
+            }
+        };
+
+        let mut receiving = Receiving {
+            buffers: allocate_buffers(rx_queue.size()).await,
+            queue: rx_queue,
+        };
+        let sending = Sending {
+            buffers: allocate_buffers(tx_queue.size()).await,
+            free_descriptors: (0..tx_queue.size().min(MAX_DESCRIPTORS)).collect(),
+            queue: tx_queue,
+        };
+
+        // Give all the receive buffers to the device, then start it.
+        let mut ops = redshirt_hardware_interface::HardwareWriteOperationsBuilder::new();
+        for (descriptor, buffer) in receiving.buffers.iter().enumerate() {
+            let descriptor = u16::try_from(descriptor).unwrap();
+            receiving
+                .queue
+                .write_descriptor(&mut ops, descriptor, *buffer, BUFFER_LEN, DESC_F_WRITE);
+        }
+        for descriptor in 0..u16::try_from(receiving.buffers.len()).unwrap() {
+            receiving.queue.push_available(&mut ops, descriptor);
+        }
+        ops.port_write_u8(
+            base_port + 0x12,
+            STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK,
+        );
+        receiving.queue.notify(&mut ops);
+        ops.send();
+
+        Ok(Device {
+            base_port,
+            mac_address,
+            features,
+            receiving: Mutex::new(receiving),
+            sending: Mutex::new(sending),
+        })
+    }
+
+    /// Returns the MAC address of the device.
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac_address
+    }
+
+    /// Returns the checksums that the device verifies on received frames.
+    pub fn rx_checksum_offload(&self) -> Checksums {
+        let verified = self.features & NET_F_GUEST_CSUM != 0;
+        Checksums {
+            ipv4: false,
+            tcp: verified,
+            udp: verified,
+        }
+    }
+
+    /// Returns the number of frames that can be waiting to be sent at the same time.
+    pub async fn send_capacity(&self) -> u32 {
+        u32::try_from(self.sending.lock().await.buffers.len()).unwrap()
+    }
+
+    /// Returns true if the link is up.
+    ///
+    /// Always returns `true` if the device doesn't report its link status.
+    pub async unsafe fn link_up(&self) -> bool {
+        if self.features & NET_F_STATUS == 0 {
+            return true;
+        }
+
+        let mut ops = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+        let mut status = 0;
+        ops.port_read_u16(self.base_port + DEVICE_CONFIG + 6, &mut status);
+        ops.send().await;
+        (status & 1) != 0
+    }
+
+    /// Reads all the frames that the device has received since the last call.
+    pub async unsafe fn read_incoming(&self) -> Vec<Frame> {
+        let mut receiving = self.receiving.lock().await;
+
+        let used = receiving.queue.pop_used().await;
+        if used.is_empty() {
+            return Vec::new();
+        }
+
+        // Read the buffers.
+        let mut raw = used
+            .iter()
+            .map(|(_, len)| vec![0; usize::try_from((*len).min(BUFFER_LEN)).unwrap()])
+            .collect::<Vec<Vec<u8>>>();
+        {
+            let mut ops = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+            for ((descriptor, _), out) in used.iter().zip(raw.iter_mut()) {
+                ops.read(receiving.buffers[usize::from(*descriptor)], out);
+            }
+            ops.send().await;
+        }
+
+        // Give the buffers back to the device.
+        {
+            let mut ops = redshirt_hardware_interface::HardwareWriteOperationsBuilder::new();
+            for (descriptor, _) in &used {
+                receiving.queue.push_available(&mut ops, *descriptor);
+            }
+            receiving.queue.notify(&mut ops);
+            ops.send();
+        }
+
+        raw.into_iter()
+            .filter(|buffer| buffer.len() > usize::try_from(NET_HDR_LEN).unwrap())
+            .map(|mut buffer| {
+                let verified = buffer[0] & NET_HDR_F_DATA_VALID != 0;
+                let data = buffer.split_off(usize::try_from(NET_HDR_LEN).unwrap());
+                Frame {
+                    data,
+                    checksums: Checksums {
+                        ipv4: false,
+                        tcp: verified,
+                        udp: verified,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Sends frames out.
+    ///
+    /// Frames are removed from the front of `frames` and queued for sending until the device's
+    /// queue is full. Frames that are too large are removed and discarded. The frames that are
+    /// left in `frames` must be passed again later, once the device has signalled through
+    /// [`TX_VECTOR`] that it has sent the previous ones.
+    pub async unsafe fn send_frames(&self, frames: &mut VecDeque<Frame>) -> SendReport {
+        let mut sending = self.sending.lock().await;
+
+        // Reclaim the descriptors that the device has finished sending.
+        let used = sending.queue.pop_used().await;
+        for (descriptor, _) in used {
+            sending.free_descriptors.push(descriptor);
+        }
+
+        let mut ops = redshirt_hardware_interface::HardwareWriteOperationsBuilder::new();
+        let mut report = SendReport::default();
+
+        while let Some(frame) = frames.front() {
+            let len = match u32::try_from(frame.data.len()) {
+                Ok(len) if len + NET_HDR_LEN <= BUFFER_LEN => len + NET_HDR_LEN,
+                _ => {
+                    frames.pop_front();
+                    report.discarded += 1;
+                    continue;
+                }
+            };
+
+            let descriptor = match sending.free_descriptors.pop() {
+                Some(d) => d,
+                None => break,
+            };
+            let frame = frames.pop_front().unwrap();
+
+            let buffer = sending.buffers[usize::from(descriptor)];
+            // We don't request any offloading, so the header is entirely zeroes.
+            let mut data = vec![0; usize::try_from(NET_HDR_LEN).unwrap()];
+            data.extend_from_slice(&frame.data);
+            ops.write(buffer, data);
+            sending
+                .queue
+                .write_descriptor(&mut ops, descriptor, buffer, len, 0);
+            sending.queue.push_available(&mut ops, descriptor);
+            report.sent_frames += 1;
+            report.sent_bytes += u64::try_from(frame.data.len()).unwrap();
+        }
+
+        if report.sent_frames != 0 {
+            sending.queue.notify(&mut ops);
+        }
+        ops.send();
+        report
+    }
+}
+
+impl fmt::Debug for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Device").finish()
+    }
+}
+
+/// Allocates the buffers of a queue with the given number of descriptors.
+async fn allocate_buffers(queue_size: u16) -> Vec<u64> {
+    let num = queue_size.min(MAX_DESCRIPTORS);
+    let mut buffers = Vec::with_capacity(usize::from(num));
+    for _ in 0..num {
+        buffers.push(redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 8).await);
+    }
+    buffers
+}
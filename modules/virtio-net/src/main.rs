@@ -0,0 +1,260 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for Virtio network devices.
+//!
+//! This program scans the PCI space for Virtio network devices. For each device it finds, it
+//! registers a new network interface towards the network manager, and handles the communication
+//! between the network manager and the hardware.
+//!
+//! Only the legacy interface of Virtio, where registers are accessed through I/O ports, is
+//! supported. This is the interface that QEMU exposes by default. Devices must support MSI-X,
+//! which QEMU also enables by default.
+//!
+//! Bibliography:
+//!
+//! - https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html
+//! - https://wiki.osdev.org/Virtio
+//!
+
+use futures::prelude::*;
+use redshirt_ethernet_interface::interface;
+use redshirt_hardware_interface::interrupts;
+use std::{cell::RefCell, collections::VecDeque, convert::TryFrom as _, time::Duration};
+
+mod device;
+mod virtqueue;
+
+/// Interval between two checks of the link status and two statistics reports.
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+fn main() {
+    redshirt_syscalls::block_on(async_main());
+}
+
+async fn async_main() {
+    let mut virtio_devices = Vec::new();
+
+    let pci_devices = redshirt_pci_interface::get_pci_devices().await;
+    for device in pci_devices {
+        // Transitional network device.
+        if device.vendor_id != 0x1af4 || device.device_id != 0x1000 {
+            continue;
+        }
+
+        let location = device.location;
+        let port_number = match device.base_address_registers.get(0) {
+            Some(Some(redshirt_pci_interface::PciBaseAddressRegister::Io { base_address, .. }))
+                if *base_address != 0 =>
+            {
+                *base_address
+            }
+            _ => continue,
+        };
+
+        // The device accesses the virtqueues through DMA.
+        if redshirt_pci_interface::set_bus_master(location, true)
+            .await
+            .is_err()
+        {
+            continue;
+        }
+
+        // The MSI-X vectors must be configured before the device is reset, as enabling MSI-X
+        // changes the layout of the device's registers.
+        let (rx_interrupt, tx_interrupt) = match (
+            enable_msi_x(location, device::RX_VECTOR).await,
+            enable_msi_x(location, device::TX_VECTOR).await,
+        ) {
+            (Ok(rx), Ok(tx)) => (rx, tx),
+            (Err(err), _) | (_, Err(err)) => {
+                redshirt_log_interface::emit_log(
+                    redshirt_log_interface::Level::Error,
+                    &format!(
+                        "Failed to set up the interrupts of virtio-net at 0x{:x}: {:?}",
+                        port_number, err
+                    ),
+                );
+                continue;
+            }
+        };
+
+        let device = match unsafe { device::Device::reset(port_number) }.await {
+            Ok(d) => d,
+            Err(err) => {
+                redshirt_log_interface::emit_log(
+                    redshirt_log_interface::Level::Error,
+                    &format!("Failed to initialize virtio-net at 0x{:x}: {:?}", port_number, err),
+                );
+                continue;
+            }
+        };
+
+        let registration = match interface::register_interface(interface::InterfaceConfig {
+            mac_address: device.mac_address(),
+            mtu: 1500,
+            num_queues: 1,
+            tx_batch_size: device.send_capacity().await,
+            rx_checksum_offload: device.rx_checksum_offload(),
+            tx_checksum_offload: Default::default(),
+        })
+        .await
+        {
+            Ok(r) => r,
+            Err(err) => {
+                redshirt_log_interface::emit_log(
+                    redshirt_log_interface::Level::Error,
+                    &format!("Failed to register virtio-net at 0x{:x}: {:?}", port_number, err),
+                );
+                continue;
+            }
+        };
+
+        redshirt_log_interface::emit_log(
+            redshirt_log_interface::Level::Info,
+            &format!("Initialized virtio-net at 0x{:x}", port_number),
+        );
+
+        virtio_devices.push((
+            registration,
+            device,
+            rx_interrupt,
+            tx_interrupt,
+            RefCell::new(interface::Statistics::default()),
+        ));
+    }
+
+    if virtio_devices.is_empty() {
+        return;
+    }
+
+    virtio_devices.shrink_to_fit();
+
+    let mut tasks = stream::FuturesUnordered::new();
+    for (registration, device, rx_interrupt, tx_interrupt, statistics) in &virtio_devices {
+        tasks.push(
+            async move {
+                loop {
+                    // We don't ask for the next frames until all of these have been queued, which
+                    // applies back-pressure on the network manager.
+                    let mut frames = VecDeque::from(registration.packets_to_send().await);
+                    loop {
+                        let report = unsafe { device.send_frames(&mut frames).await };
+                        {
+                            let mut statistics = statistics.borrow_mut();
+                            statistics.tx_frames += report.sent_frames;
+                            statistics.tx_bytes += report.sent_bytes;
+                            statistics.tx_errors += report.discarded;
+                        }
+
+                        if frames.is_empty() {
+                            break;
+                        }
+
+                        // The device's queue is full. Try again once the device signals that it
+                        // has sent some of the frames.
+                        if tx_interrupt.wait().await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+            .boxed_local(),
+        );
+
+        tasks.push(
+            async move {
+                loop {
+                    // Interrupts are buffered by the interface handler. A frame arriving after
+                    // we have read the incoming frames makes the next wait finish immediately.
+                    let frames = unsafe { device.read_incoming().await };
+                    if !frames.is_empty() {
+                        {
+                            let mut statistics = statistics.borrow_mut();
+                            statistics.rx_frames += u64::try_from(frames.len()).unwrap();
+                            statistics.rx_bytes += frames
+                                .iter()
+                                .map(|f| u64::try_from(f.data.len()).unwrap())
+                                .sum::<u64>();
+                        }
+
+                        registration.packet_from_network().await.send_frames(frames);
+                    }
+
+                    if rx_interrupt.wait().await.is_err() {
+                        return;
+                    }
+                }
+            }
+            .boxed_local(),
+        );
+
+        tasks.push(
+            async move {
+                let mut link_up = None;
+                loop {
+                    let now_up = unsafe { device.link_up().await };
+                    if link_up != Some(now_up) {
+                        link_up = Some(now_up);
+                        registration.set_link_status(if now_up {
+                            interface::LinkStatus::Up {
+                                speed_mbps: None,
+                                full_duplex: true,
+                            }
+                        } else {
+                            interface::LinkStatus::Down
+                        });
+                    }
+
+                    registration.report_statistics(&statistics.borrow());
+                    redshirt_time_interface::monotonic_wait(STATUS_INTERVAL).await;
+                }
+            }
+            .boxed_local(),
+        );
+    }
+
+    while let Some(_) = tasks.next().await {}
+}
+
+/// Error that can happen when setting up an interrupt of a device.
+#[derive(Debug)]
+enum InterruptSetupError {
+    /// Failed to subscribe to a message-signalled interrupt.
+    Subscribe(interrupts::InterruptError),
+    /// The interface handler didn't report the message that triggers the interrupt.
+    MsiMessage,
+    /// Failed to configure the device.
+    Pci(redshirt_pci_interface::PciError),
+}
+
+/// Subscribes to a message-signalled interrupt and configures the given entry of the MSI-X
+/// table of the device to trigger it.
+async fn enable_msi_x(
+    location: redshirt_pci_interface::PciDeviceLocation,
+    entry: u16,
+) -> Result<interrupts::Interrupt, InterruptSetupError> {
+    let interrupt = interrupts::subscribe(interrupts::InterruptSource::Msi)
+        .await
+        .map_err(InterruptSetupError::Subscribe)?;
+    let message = interrupt
+        .msi_message()
+        .await
+        .map_err(|()| InterruptSetupError::MsiMessage)?;
+    redshirt_pci_interface::enable_msi_x(location, entry, message.address, message.data)
+        .await
+        .map_err(InterruptSetupError::Pci)?;
+    Ok(interrupt)
+}
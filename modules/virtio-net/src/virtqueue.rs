@@ -0,0 +1,226 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Virtqueues, as described in the "legacy interface" sections of the Virtio specifications.
+//!
+//! A virtqueue consists of three parts, stored contiguously in physical memory:
+//!
+//! - The descriptors table. Each descriptor points to a buffer in physical memory.
+//! - The available ring, where the driver writes the indices of the descriptors that it passes
+//! to the device.
+//! - The used ring, aligned on a page boundary, where the device writes the indices of the
+//! descriptors that it hands back to the driver.
+
+use core::convert::TryFrom as _;
+
+/// Descriptor flag indicating that the buffer is write-only for the device.
+pub const DESC_F_WRITE: u16 = 2;
+
+/// Alignment of the virtqueue and of the used ring, as required by the legacy interface.
+const QUEUE_ALIGN: u64 = 4096;
+
+/// Virtqueue located in physical memory.
+pub struct VirtQueue {
+    /// Base I/O port of the device.
+    base_port: u32,
+    /// Index of the queue within the device.
+    index: u16,
+    /// Number of descriptors. Decided by the device.
+    size: u16,
+    /// Physical address of the descriptors table. Aligned to [`QUEUE_ALIGN`].
+    base: u64,
+    /// Physical address of the used ring. Aligned to [`QUEUE_ALIGN`].
+    used_ring: u64,
+    /// Value of the `idx` field of the available ring that we have last written.
+    avail_idx: u16,
+    /// Value of the `idx` field of the used ring up to which we have processed the entries.
+    last_used_idx: u16,
+}
+
+impl VirtQueue {
+    /// Allocates the virtqueue with the given index and passes it to the device. The device
+    /// signals the completion of buffers through the given MSI-X vector.
+    ///
+    /// Returns `None` if the device doesn't have a queue with this index, or if it refuses the
+    /// MSI-X vector.
+    ///
+    /// # Safety
+    ///
+    /// `base_port` must be the base I/O port of a legacy Virtio device that hasn't been marked
+    /// as `DRIVER_OK` yet, and whose MSI-X capability is enabled.
+    ///
+    pub async unsafe fn new(base_port: u32, index: u16, msix_vector: u16) -> Option<Self> {
+        let size = {
+            let mut ops = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+            ops.port_write_u16(base_port + 0x0e, index);
+            let mut size = 0;
+            ops.port_read_u16(base_port + 0x0c, &mut size);
+            ops.send().await;
+            size
+        };
+
+        if size == 0 {
+            return None;
+        }
+
+        let descriptors_and_avail_len = 16 * u64::from(size) + 6 + 2 * u64::from(size);
+        let used_offset = align_up(descriptors_and_avail_len);
+        let total_len = used_offset + align_up(6 + 8 * u64::from(size));
+
+        // The `hardware` interface doesn't support alignments as large as a page. We allocate
+        // more than necessary and align the pointer ourselves.
+        // Note that the allocation is never freed, as the device is never destroyed.
+        let allocation =
+            redshirt_hardware_interface::malloc::malloc(total_len + QUEUE_ALIGN, 8).await;
+        let base = align_up(allocation);
+
+        let mut ops = redshirt_hardware_interface::HardwareWriteOperationsBuilder::new();
+        ops.memset(base, total_len, 0);
+        ops.port_write_u16(base_port + 0x0e, index);
+        ops.port_write_u32(base_port + 0x08, u32::try_from(base / QUEUE_ALIGN).unwrap());
+        ops.send();
+
+        // The device reads back `0xffff` if it couldn't allocate the vector.
+        let assigned_vector = {
+            let mut ops = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+            ops.port_write_u16(base_port + 0x16, msix_vector);
+            let mut assigned_vector = 0;
+            ops.port_read_u16(base_port + 0x16, &mut assigned_vector);
+            ops.send().await;
+            assigned_vector
+        };
+        if assigned_vector != msix_vector {
+            return None;
+        }
+
+        Some(VirtQueue {
+            base_port,
+            index,
+            size,
+            base,
+            used_ring: base + used_offset,
+            avail_idx: 0,
+            last_used_idx: 0,
+        })
+    }
+
+    /// Returns the number of descriptors in the queue.
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Writes the descriptor with the given index.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor must not currently be owned by the device. `address` and `len` must point
+    /// to a valid buffer.
+    ///
+    pub unsafe fn write_descriptor(
+        &self,
+        ops: &mut redshirt_hardware_interface::HardwareWriteOperationsBuilder,
+        descriptor: u16,
+        address: u64,
+        len: u32,
+        flags: u16,
+    ) {
+        debug_assert!(descriptor < self.size);
+        let mut data = Vec::with_capacity(16);
+        data.extend_from_slice(&address.to_le_bytes());
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        ops.write(self.base + 16 * u64::from(descriptor), data);
+    }
+
+    /// Hands the given descriptor over to the device.
+    ///
+    /// The device isn't notified until [`VirtQueue::notify`] is called.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor must have been written with [`VirtQueue::write_descriptor`] and must not
+    /// already be owned by the device.
+    ///
+    pub unsafe fn push_available(
+        &mut self,
+        ops: &mut redshirt_hardware_interface::HardwareWriteOperationsBuilder,
+        descriptor: u16,
+    ) {
+        debug_assert!(descriptor < self.size);
+        let avail_ring = self.base + 16 * u64::from(self.size);
+        let slot = u64::from(self.avail_idx % self.size);
+        ops.write(avail_ring + 4 + 2 * slot, descriptor.to_le_bytes().to_vec());
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        // Operations are performed in order, so the device can't see the new `idx` before the
+        // ring entry has been written.
+        ops.write(avail_ring + 2, self.avail_idx.to_le_bytes().to_vec());
+    }
+
+    /// Notifies the device that new descriptors are available.
+    pub unsafe fn notify(
+        &self,
+        ops: &mut redshirt_hardware_interface::HardwareWriteOperationsBuilder,
+    ) {
+        ops.port_write_u16(self.base_port + 0x10, self.index);
+    }
+
+    /// Returns the list of descriptors that the device has handed back since the last call,
+    /// alongside with the number of bytes that the device has written in each of them.
+    pub async unsafe fn pop_used(&mut self) -> Vec<(u16, u32)> {
+        let used_idx = {
+            let mut ops = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+            let mut out = [0; 2];
+            ops.read(self.used_ring + 2, &mut out);
+            ops.send().await;
+            u16::from_le_bytes(out)
+        };
+
+        let num_new = used_idx.wrapping_sub(self.last_used_idx);
+        if num_new == 0 {
+            return Vec::new();
+        }
+
+        let mut elements = vec![[0u8; 8]; usize::from(num_new)];
+        {
+            let mut ops = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+            for (n, element) in elements.iter_mut().enumerate() {
+                let slot = u64::from(
+                    self.last_used_idx
+                        .wrapping_add(u16::try_from(n).unwrap())
+                        % self.size,
+                );
+                ops.read(self.used_ring + 4 + 8 * slot, element);
+            }
+            ops.send().await;
+        }
+
+        self.last_used_idx = used_idx;
+
+        elements
+            .into_iter()
+            .map(|element| {
+                let id = u32::from_le_bytes([element[0], element[1], element[2], element[3]]);
+                let len = u32::from_le_bytes([element[4], element[5], element[6], element[7]]);
+                (u16::try_from(id).unwrap(), len)
+            })
+            .collect()
+    }
+}
+
+/// Rounds up the given value to a multiple of [`QUEUE_ALIGN`].
+fn align_up(value: u64) -> u64 {
+    (value + QUEUE_ALIGN - 1) / QUEUE_ALIGN * QUEUE_ALIGN
+}
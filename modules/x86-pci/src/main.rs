@@ -15,16 +15,43 @@
 
 //! Implements the PCI interface.
 //!
+//! Devices are enumerated starting from bus 0, then recursively through the PCI-to-PCI bridges.
+//!
+//! The configuration space of the devices is accessed through the memory-mapped regions reported
+//! by the firmware (known as the Enhanced Configuration Access Mechanism, or ECAM), if any. For
+//! buses that aren't covered by these regions, we fall back to the legacy `0xcf8` and `0xcfc`
+//! I/O ports, which only give access to the first 256 bytes of the configuration space.
+//!
+//! Any process can read the configuration space of any device, but the first process that
+//! modifies the configuration of a device becomes its owner. Modifications requested by other
+//! processes are then denied until the owner terminates.
+//!
+//! Message-signalled interrupts can only be configured through the dedicated messages, which
+//! verify that the device writes to the interrupt range of the local APICs. Writes to the MSI and
+//! MSI-X capabilities through `WriteConfig` are denied.
+//!
 //! See https://en.wikipedia.org/wiki/PCI_configuration_space
 
-// TODO: support Enhanced Configuration Access Mechanism (ECAM)
-
 use fnv::FnvBuildHasher;
 use parity_scale_codec::DecodeAll;
+use redshirt_pci_interface::ffi::PciMessage;
+use redshirt_pci_interface::{
+    PciBaseAddressRegister, PciCapability, PciDeviceInfo, PciDeviceLocation, PciError,
+};
+use redshirt_syscalls::Pid;
 use std::{borrow::Cow, convert::TryFrom as _};
 
 include!(concat!(env!("OUT_DIR"), "/build-pci.rs"));
 
+/// Capability ID of MSI.
+const CAPABILITY_MSI: u8 = 0x05;
+/// Capability ID of MSI-X.
+const CAPABILITY_MSI_X: u8 = 0x11;
+/// Maximum size in bytes of the MSI capability structure.
+const CAPABILITY_MSI_LEN: u16 = 24;
+/// Size in bytes of the MSI-X capability structure.
+const CAPABILITY_MSI_X_LEN: u16 = 12;
+
 fn main() {
     redshirt_syscalls::block_on(async_main());
 }
@@ -34,22 +61,116 @@ async fn async_main() {
         .await
         .unwrap();
 
-    let devices = unsafe { read_pci_devices().await };
+    let config_space = ConfigSpace::new().await;
+    let devices = unsafe { read_pci_devices(&config_space).await };
+    let mut owners = hashbrown::HashMap::<_, _, FnvBuildHasher>::default();
 
     loop {
         let msg = match redshirt_syscalls::next_interface_message().await {
             redshirt_syscalls::DecodedInterfaceOrDestroyed::Interface(m) => m,
-            redshirt_syscalls::DecodedInterfaceOrDestroyed::ProcessDestroyed(_) => continue,
+            redshirt_syscalls::DecodedInterfaceOrDestroyed::ProcessDestroyed(p) => {
+                owners.retain(|_, owner| *owner != p.pid);
+                continue;
+            }
         };
         assert_eq!(msg.interface, redshirt_pci_interface::ffi::INTERFACE);
-        let redshirt_pci_interface::ffi::PciMessage::GetDevicesList =
-            DecodeAll::decode_all(&msg.actual_data.0).unwrap(); // TODO: don't unwrap; also, crappy decoding
-        redshirt_syscalls::emit_answer(
-            msg.message_id.unwrap(),
-            &redshirt_pci_interface::ffi::GetDevicesListResponse {
-                devices: devices.clone(),
-            },
-        );
+
+        let message = match DecodeAll::decode_all(&msg.actual_data.0) {
+            Ok(m) => m,
+            Err(_) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls::emit_message_error(message_id);
+                }
+                continue;
+            }
+        };
+
+        match message {
+            PciMessage::GetDevicesList => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls::emit_answer(
+                        message_id,
+                        &redshirt_pci_interface::ffi::GetDevicesListResponse {
+                            devices: devices.clone(),
+                        },
+                    );
+                }
+            }
+            PciMessage::ReadConfig { location, offset } => {
+                let response = match check_access(&config_space, &devices, location, offset) {
+                    Ok(()) => Ok(unsafe { config_space.read_u32(location, offset).await }),
+                    Err(err) => Err(err),
+                };
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls::emit_answer(message_id, &response);
+                }
+            }
+            PciMessage::WriteConfig {
+                location,
+                offset,
+                value,
+            } => {
+                let response = check_access(&config_space, &devices, location, offset)
+                    .and_then(|()| check_not_msi(&devices, location, offset))
+                    .and_then(|()| claim(&mut owners, &devices, location, msg.emitter_pid));
+                if response.is_ok() {
+                    unsafe { config_space.write_u32(location, offset, value) };
+                }
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls::emit_answer(message_id, &response);
+                }
+            }
+            PciMessage::SetBusMaster { location, enabled } => {
+                let response = match check_access(&config_space, &devices, location, 0x4)
+                    .and_then(|()| claim(&mut owners, &devices, location, msg.emitter_pid))
+                {
+                    Ok(()) => {
+                        unsafe { set_command_bit(&config_space, location, 2, enabled).await };
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                };
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls::emit_answer(message_id, &response);
+                }
+            }
+            PciMessage::EnableMsi {
+                location,
+                address,
+                data,
+            } => {
+                let response = match check_msi_message(address, data)
+                    .and_then(|()| claim(&mut owners, &devices, location, msg.emitter_pid))
+                {
+                    Ok(()) => unsafe {
+                        enable_msi(&config_space, &devices, location, address, data).await
+                    },
+                    Err(err) => Err(err),
+                };
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls::emit_answer(message_id, &response);
+                }
+            }
+            PciMessage::EnableMsiX {
+                location,
+                entry,
+                address,
+                data,
+            } => {
+                let response = match check_msi_message(address, data)
+                    .and_then(|()| claim(&mut owners, &devices, location, msg.emitter_pid))
+                {
+                    Ok(()) => unsafe {
+                        enable_msi_x(&config_space, &devices, location, entry, address, data)
+                            .await
+                    },
+                    Err(err) => Err(err),
+                };
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls::emit_answer(message_id, &response);
+                }
+            }
+        }
     }
 }
 
@@ -57,101 +178,545 @@ lazy_static::lazy_static! {
     static ref PCI_DEVICES: hashbrown::HashMap<(u16, u16), (&'static str, &'static str), FnvBuildHasher> = build_pci_info();
 }
 
-async unsafe fn read_pci_devices() -> Vec<redshirt_pci_interface::PciDeviceInfo> {
-    // https://wiki.osdev.org/PCI
-    let pci_devices = build_pci_info();
-    read_bus_pci_devices(0).await
+/// Access to the configuration space of the PCI devices.
+struct ConfigSpace {
+    /// For each bus, the physical memory address of its memory-mapped configuration space.
+    // TODO: only segment group 0 is supported
+    ecam: hashbrown::HashMap<u8, u64, FnvBuildHasher>,
+}
+
+impl ConfigSpace {
+    /// Queries the memory-mapped regions of the configuration space from the hardware.
+    async fn new() -> Self {
+        let ecam = redshirt_hardware_interface::pci_config_regions()
+            .await
+            .into_iter()
+            .filter(|region| region.segment_group == 0)
+            .map(|region| (region.bus, region.physical_address))
+            .collect();
+        ConfigSpace { ecam }
+    }
+
+    /// Returns the size of the configuration space of the devices of the given bus.
+    fn size(&self, bus: u8) -> u16 {
+        if self.ecam.contains_key(&bus) {
+            4096
+        } else {
+            256
+        }
+    }
+
+    /// Returns the physical memory address of the given register, if the bus is memory-mapped.
+    fn ecam_address(&self, location: PciDeviceLocation, offset: u16) -> Option<u64> {
+        let base = *self.ecam.get(&location.bus)?;
+        Some(
+            base + (u64::from(location.device) << 15)
+                + (u64::from(location.function) << 12)
+                + u64::from(offset),
+        )
+    }
+
+    /// Reads a register from the configuration space.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `offset` is misaligned or out of range. See [`ConfigSpace::size`].
+    ///
+    // TODO: ensure endianess? PCI is always little endian, but what if we're on a BE platform?
+    async unsafe fn read_u32(&self, location: PciDeviceLocation, offset: u16) -> u32 {
+        assert!(location.device < 32);
+        assert!(location.function < 8);
+        assert_eq!(offset & 3, 0);
+        assert!(offset < self.size(location.bus));
+
+        if let Some(address) = self.ecam_address(location, offset) {
+            return redshirt_hardware_interface::read_one_u32(address).await;
+        }
+
+        let mut operations_builder = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+        operations_builder.port_write_u32(0xcf8, legacy_address(location, offset));
+        let mut out = 0;
+        // TODO: is it correct to immediately read back afterwards without delay? seems weird to me
+        operations_builder.port_read_u32(0xcfc, &mut out);
+        operations_builder.send().await;
+        out
+    }
+
+    /// Writes a register of the configuration space.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `offset` is misaligned or out of range. See [`ConfigSpace::size`].
+    ///
+    unsafe fn write_u32(&self, location: PciDeviceLocation, offset: u16, value: u32) {
+        assert!(location.device < 32);
+        assert!(location.function < 8);
+        assert_eq!(offset & 3, 0);
+        assert!(offset < self.size(location.bus));
+
+        if let Some(address) = self.ecam_address(location, offset) {
+            redshirt_hardware_interface::write_one_u32(address, value);
+            return;
+        }
+
+        let mut operations_builder =
+            redshirt_hardware_interface::HardwareWriteOperationsBuilder::new();
+        operations_builder.port_write_u32(0xcf8, legacy_address(location, offset));
+        operations_builder.port_write_u32(0xcfc, value);
+        operations_builder.send();
+    }
+}
+
+/// Returns the value to write on port `0xcf8` in order to access the given register.
+fn legacy_address(location: PciDeviceLocation, offset: u16) -> u32 {
+    0x80000000
+        | (u32::from(location.bus) << 16)
+        | (u32::from(location.device) << 11)
+        | (u32::from(location.function) << 8)
+        | u32::from(offset)
+}
+
+/// Checks whether a request to access the configuration space of a device is valid.
+fn check_access(
+    config_space: &ConfigSpace,
+    devices: &[PciDeviceInfo],
+    location: PciDeviceLocation,
+    offset: u16,
+) -> Result<(), PciError> {
+    if !devices.iter().any(|d| d.location == location) {
+        return Err(PciError::UnknownDevice);
+    }
+
+    if offset % 4 != 0 || offset >= config_space.size(location.bus) {
+        return Err(PciError::InvalidOffset);
+    }
+
+    Ok(())
+}
+
+/// Checks that `offset` isn't within the MSI or MSI-X capability of the device at the given
+/// location.
+fn check_not_msi(
+    devices: &[PciDeviceInfo],
+    location: PciDeviceLocation,
+    offset: u16,
+) -> Result<(), PciError> {
+    let device = find_device(devices, location)?;
+    let overlaps = device.capabilities.iter().any(|cap| {
+        let len = match cap.id {
+            CAPABILITY_MSI => CAPABILITY_MSI_LEN,
+            CAPABILITY_MSI_X => CAPABILITY_MSI_X_LEN,
+            _ => return false,
+        };
+        let start = u16::from(cap.offset);
+        offset >= start && offset < start + len
+    });
+
+    if overlaps {
+        Err(PciError::Denied)
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that a device writing `data` at `address` triggers an interrupt on a processor.
+///
+/// The address must be within the range where the local APICs receive interrupt messages, and
+/// the data must designate a vector that isn't reserved for exceptions, with the fixed or lowest
+/// priority delivery mode. Other delivery modes, such as SMI, NMI or INIT, are refused.
+fn check_msi_message(address: u64, data: u32) -> Result<(), PciError> {
+    let in_lapic_range = address & !0xf_fffc == 0xfee0_0000;
+    let delivery_mode = (data >> 8) & 0b111;
+    let vector = data & 0xff;
+
+    if in_lapic_range && data <= 0xffff && delivery_mode <= 1 && vector >= 32 {
+        Ok(())
+    } else {
+        Err(PciError::InvalidMessage)
+    }
+}
+
+/// Checks whether `emitter` is allowed to modify the configuration of the device at the given
+/// location. If the device doesn't have an owner yet, `emitter` becomes its owner.
+fn claim(
+    owners: &mut hashbrown::HashMap<PciDeviceLocation, Pid, FnvBuildHasher>,
+    devices: &[PciDeviceInfo],
+    location: PciDeviceLocation,
+    emitter: Pid,
+) -> Result<(), PciError> {
+    find_device(devices, location)?;
+
+    if *owners.entry(location).or_insert(emitter) == emitter {
+        Ok(())
+    } else {
+        Err(PciError::Denied)
+    }
+}
+
+/// Sets or clears a bit of the command register of a device.
+async unsafe fn set_command_bit(
+    config_space: &ConfigSpace,
+    location: PciDeviceLocation,
+    bit: u8,
+    value: bool,
+) {
+    // The upper 16 bits contain the status register, whose bits are cleared by writing 1. We
+    // write 0 in order to not modify them.
+    let command = config_space.read_u32(location, 0x4).await & 0xffff;
+    let command = if value {
+        command | (1 << bit)
+    } else {
+        command & !(1 << bit)
+    };
+    config_space.write_u32(location, 0x4, command);
+}
+
+/// Handles a [`PciMessage::EnableMsi`].
+async unsafe fn enable_msi(
+    config_space: &ConfigSpace,
+    devices: &[PciDeviceInfo],
+    location: PciDeviceLocation,
+    address: u64,
+    data: u32,
+) -> Result<(), PciError> {
+    let device = find_device(devices, location)?;
+    let cap_offset = find_capability(device, CAPABILITY_MSI)?;
+
+    let header = config_space.read_u32(location, cap_offset).await;
+    let message_control = header >> 16;
+    let is_64bits = (message_control & (1 << 7)) != 0;
+
+    let address_lo = u32::try_from(address & 0xffffffff).unwrap();
+    let address_hi = u32::try_from(address >> 32).unwrap();
+    if !is_64bits && address_hi != 0 {
+        return Err(PciError::CapabilityNotSupported);
+    }
+
+    config_space.write_u32(location, cap_offset + 4, address_lo);
+    if is_64bits {
+        config_space.write_u32(location, cap_offset + 8, address_hi);
+        config_space.write_u32(location, cap_offset + 12, data & 0xffff);
+    } else {
+        config_space.write_u32(location, cap_offset + 8, data & 0xffff);
+    }
+
+    // Enable MSI with a single message (bits 4 to 6 set to 0).
+    let message_control = (message_control & !0b111_0000) | 1;
+    config_space.write_u32(location, cap_offset, (message_control << 16) | (header & 0xffff));
+
+    // Disable legacy interrupts.
+    set_command_bit(config_space, location, 10, true).await;
+    Ok(())
+}
+
+/// Handles a [`PciMessage::EnableMsiX`].
+async unsafe fn enable_msi_x(
+    config_space: &ConfigSpace,
+    devices: &[PciDeviceInfo],
+    location: PciDeviceLocation,
+    entry: u16,
+    address: u64,
+    data: u32,
+) -> Result<(), PciError> {
+    let device = find_device(devices, location)?;
+    let cap_offset = find_capability(device, CAPABILITY_MSI_X)?;
+
+    let header = config_space.read_u32(location, cap_offset).await;
+    let message_control = header >> 16;
+    let table_size = (message_control & 0x7ff) + 1;
+    if u32::from(entry) >= table_size {
+        return Err(PciError::InvalidEntry);
+    }
+
+    // The table is located in the memory of one of the BARs.
+    let table_location = config_space.read_u32(location, cap_offset + 4).await;
+    let bar_index = usize::try_from(table_location & 0b111).unwrap();
+    let table_base = match device.base_address_registers.get(bar_index) {
+        Some(Some(PciBaseAddressRegister::Memory { base_address, .. })) => {
+            base_address + u64::from(table_location & !0b111)
+        }
+        _ => return Err(PciError::CapabilityNotSupported),
+    };
+
+    let entry_address = table_base + 16 * u64::from(entry);
+    let mut ops = redshirt_hardware_interface::HardwareWriteOperationsBuilder::with_capacity(4);
+    ops.write_one_u32(entry_address, u32::try_from(address & 0xffffffff).unwrap());
+    ops.write_one_u32(entry_address + 4, u32::try_from(address >> 32).unwrap());
+    ops.write_one_u32(entry_address + 8, data);
+    // Vector control. Unmasks the entry.
+    ops.write_one_u32(entry_address + 12, 0);
+    ops.send();
+
+    // Enable MSI-X (bit 15) and clear the function mask (bit 14).
+    let message_control = (message_control & !(1 << 14)) | (1 << 15);
+    config_space.write_u32(location, cap_offset, (message_control << 16) | (header & 0xffff));
+
+    // Disable legacy interrupts.
+    set_command_bit(config_space, location, 10, true).await;
+    Ok(())
+}
+
+/// Finds the device at the given location in the list.
+fn find_device(
+    devices: &[PciDeviceInfo],
+    location: PciDeviceLocation,
+) -> Result<&PciDeviceInfo, PciError> {
+    devices
+        .iter()
+        .find(|d| d.location == location)
+        .ok_or(PciError::UnknownDevice)
+}
+
+/// Returns the offset of the given capability within the configuration space of the device.
+fn find_capability(device: &PciDeviceInfo, id: u8) -> Result<u16, PciError> {
+    device
+        .capabilities
+        .iter()
+        .find(|cap| cap.id == id)
+        .map(|cap| u16::from(cap.offset))
+        .ok_or(PciError::CapabilityNotSupported)
 }
 
-async unsafe fn read_bus_pci_devices(bus_idx: u8) -> Vec<redshirt_pci_interface::PciDeviceInfo> {
+/// Enumerates all the PCI devices, starting from bus 0 and going through bridges.
+async unsafe fn read_pci_devices(config_space: &ConfigSpace) -> Vec<PciDeviceInfo> {
+    // https://wiki.osdev.org/PCI
     let mut out = Vec::new();
 
-    for device_idx in 0..32 {
-        for func_idx in 0..8 {
-            // TODO: check function 0 only first
-            let (vendor_id, device_id) = {
-                let vendor_device = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0).await;
-                let vendor_id = u16::try_from(vendor_device & 0xffff).unwrap();
-                let device_id = u16::try_from(vendor_device >> 16).unwrap();
-                (vendor_id, device_id)
+    let mut visited_buses = [false; 256];
+    let mut buses_to_visit = vec![0u8];
+
+    while let Some(bus) = buses_to_visit.pop() {
+        if visited_buses[usize::from(bus)] {
+            continue;
+        }
+        visited_buses[usize::from(bus)] = true;
+
+        for device in 0..32 {
+            let function0 = PciDeviceLocation {
+                bus,
+                device,
+                function: 0,
             };
 
-            if vendor_id == 0xffff {
-                continue;
+            // Bit 7 of the header type indicates whether the device has multiple functions.
+            let num_functions = match read_function(config_space, function0).await {
+                Some((info, header_ty, secondary_bus)) => {
+                    out.push(info);
+                    buses_to_visit.extend(secondary_bus);
+                    if (header_ty & 0x80) != 0 {
+                        8
+                    } else {
+                        1
+                    }
+                }
+                None => continue,
+            };
+
+            for function in 1..num_functions {
+                let location = PciDeviceLocation {
+                    bus,
+                    device,
+                    function,
+                };
+
+                let function = read_function(config_space, location).await;
+                if let Some((info, _, secondary_bus)) = function {
+                    out.push(info);
+                    buses_to_visit.extend(secondary_bus);
+                }
             }
+        }
+    }
 
-            let (_bist, header_ty, latency, cache_line) = {
-                let val = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0xc).await;
-                let bytes = val.to_be_bytes();
-                (bytes[0], bytes[1], bytes[2], bytes[3])
-            };
+    out
+}
+
+/// Reads the information about a single function of a device.
+///
+/// Returns `None` if there is no function at this location. Otherwise, returns the information,
+/// the header type, and the secondary bus if the function is a PCI-to-PCI bridge.
+async unsafe fn read_function(
+    config_space: &ConfigSpace,
+    location: PciDeviceLocation,
+) -> Option<(PciDeviceInfo, u8, Option<u8>)> {
+    let (vendor_id, device_id) = {
+        let vendor_device = config_space.read_u32(location, 0).await;
+        let vendor_id = u16::try_from(vendor_device & 0xffff).unwrap();
+        let device_id = u16::try_from(vendor_device >> 16).unwrap();
+        (vendor_id, device_id)
+    };
+
+    if vendor_id == 0xffff {
+        return None;
+    }
+
+    let [revision_id, prog_if, subclass, class_code] =
+        config_space.read_u32(location, 0x8).await.to_le_bytes();
+    let [_cache_line, _latency, header_ty, _bist] =
+        config_space.read_u32(location, 0xc).await.to_le_bytes();
+
+    let (vendor_name, device_name) = match PCI_DEVICES.get(&(vendor_id, device_id)) {
+        Some((v, d)) => (Cow::Borrowed(*v), Cow::Borrowed(*d)),
+        None => (
+            Cow::Owned(format!("Unknown <0x{:x}>", vendor_id)),
+            Cow::Owned(format!("Unknown <0x{:x}>", device_id)),
+        ),
+    };
+
+    redshirt_log_interface::emit_log(
+        redshirt_log_interface::Level::Info,
+        &format!(
+            "PCI device at {:02x}:{:02x}.{}: {} - {}",
+            location.bus, location.device, location.function, vendor_name, device_name
+        ),
+    );
+
+    // The layout of the rest of the configuration space depends on the header type.
+    let (num_bars, secondary_bus) = match header_ty & 0x7f {
+        0x0 => (6, None),
+        0x1 => {
+            let [_primary, secondary, _subordinate, _latency] =
+                config_space.read_u32(location, 0x18).await.to_le_bytes();
+            (2, Some(secondary))
+        }
+        _ => (0, None),
+    };
+
+    let base_address_registers = read_bars(config_space, location, num_bars).await;
+
+    let interrupt_line = if num_bars != 0 {
+        let [line, pin, _, _] = config_space.read_u32(location, 0x3c).await.to_le_bytes();
+        // A pin of 0 means that the device doesn't use interrupts, and a line of 0xff means
+        // that no IRQ has been assigned.
+        if pin != 0 && line != 0xff {
+            Some(line)
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    let capabilities = read_capabilities(config_space, location).await;
+
+    let info = PciDeviceInfo {
+        location,
+        vendor_id,
+        device_id,
+        class_code,
+        subclass,
+        prog_if,
+        revision_id,
+        base_address_registers,
+        interrupt_line,
+        capabilities,
+    };
 
-            let (vendor_name, device_name) = match PCI_DEVICES.get(&(vendor_id, device_id)) {
-                Some((v, d)) => (Cow::Borrowed(*v), Cow::Borrowed(*d)),
-                None => (
-                    Cow::Owned(format!("Unknown <0x{:x}>", vendor_id)),
-                    Cow::Owned(format!("Unknown <0x{:x}>", device_id)),
-                ),
+    Some((info, header_ty, secondary_bus))
+}
+
+/// Reads the base address registers of a function and determines their size.
+async unsafe fn read_bars(
+    config_space: &ConfigSpace,
+    location: PciDeviceLocation,
+    num_bars: u16,
+) -> Vec<Option<PciBaseAddressRegister>> {
+    // In order to determine the size of a BAR, we write all 1s to it and read back the value.
+    // Decoding must be disabled while we do so, otherwise the device could respond to accesses
+    // at the temporary address.
+    let command = config_space.read_u32(location, 0x4).await & 0xffff;
+    config_space.write_u32(location, 0x4, command & !0b11);
+
+    let mut list = Vec::with_capacity(usize::from(num_bars));
+    let mut bar_n = 0;
+    while bar_n < num_bars {
+        let offset = 0x10 + bar_n * 0x4;
+        let bar = config_space.read_u32(location, offset).await;
+        config_space.write_u32(location, offset, 0xffffffff);
+        let mask = config_space.read_u32(location, offset).await;
+        config_space.write_u32(location, offset, bar);
+        bar_n += 1;
+
+        if mask == 0 {
+            list.push(None);
+            continue;
+        }
+
+        if (bar & 0x1) != 0 {
+            let base_address = bar & !0b11;
+            // Some devices only implement the lower 16 bits.
+            let mask = mask & !0b11;
+            let mask = if (mask & 0xffff0000) == 0 {
+                mask | 0xffff0000
+            } else {
+                mask
             };
+            let size = (!mask).wrapping_add(1);
+            list.push(Some(PciBaseAddressRegister::Io { base_address, size }));
+            continue;
+        }
 
-            let class_code = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0x8).await;
-
-            out.push(redshirt_pci_interface::PciDeviceInfo {
-                vendor_id,
-                device_id,
-                base_address_registers: {
-                    let mut list = Vec::with_capacity(6);
-                    for bar_n in 0..6 {
-                        let bar =
-                            pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0x10 + bar_n * 0x4)
-                                .await;
-                        list.push(if (bar & 0x1) == 0 {
-                            let prefetchable = (bar & (1 << 3)) != 0;
-                            let base_address = bar & !0b1111;
-                            redshirt_pci_interface::PciBaseAddressRegister::Memory {
-                                base_address,
-                                prefetchable,
-                            }
-                        } else {
-                            let base_address = bar & !0b11;
-                            redshirt_pci_interface::PciBaseAddressRegister::Io { base_address }
-                        });
-                    }
-                    list
-                },
-            });
+        let prefetchable = (bar & (1 << 3)) != 0;
+        let is_64bits = ((bar >> 1) & 0b11) == 0b10;
 
-            redshirt_log_interface::emit_log(
-                redshirt_log_interface::Level::Info,
-                &format!("PCI device: {} - {}", vendor_name, device_name),
-            );
+        let has_upper_half = is_64bits && bar_n < num_bars;
+        let (base_address, mask) = if has_upper_half {
+            let offset_hi = 0x10 + bar_n * 0x4;
+            let bar_hi = config_space.read_u32(location, offset_hi).await;
+            config_space.write_u32(location, offset_hi, 0xffffffff);
+            let mask_hi = config_space.read_u32(location, offset_hi).await;
+            config_space.write_u32(location, offset_hi, bar_hi);
+            bar_n += 1;
+            (
+                (u64::from(bar_hi) << 32) | u64::from(bar & !0b1111),
+                (u64::from(mask_hi) << 32) | u64::from(mask & !0b1111),
+            )
+        } else {
+            (
+                u64::from(bar & !0b1111),
+                0xffffffff_00000000 | u64::from(mask & !0b1111),
+            )
+        };
 
-            // TODO: wrong; need to enumerate other PCI buses
+        let size = (!mask).wrapping_add(1);
+        list.push(Some(PciBaseAddressRegister::Memory {
+            base_address,
+            size,
+            prefetchable,
+        }));
+        if has_upper_half {
+            // The next register is the upper half of this one.
+            list.push(None);
         }
     }
 
-    out
+    config_space.write_u32(location, 0x4, command);
+    list
 }
 
-// TODO: ensure endianess? PCI is always little endian, but what if we're on a BE platform?
-async unsafe fn pci_cfg_read_u32(bus: u8, slot: u8, func: u8, offset: u8) -> u32 {
-    //assert!(bus < 256); // commented out because always true
-    assert!(slot < 32);
-    assert!(func < 8);
-    //assert!(offset < 256) // commented out because always true
-    assert_eq!(offset & 3, 0);
-
-    let addr: u32 = 0x80000000
-        | (u32::from(bus) << 16)
-        | (u32::from(slot) << 11)
-        | (u32::from(func) << 8)
-        | u32::from(offset);
-
-    let mut operations_builder = redshirt_hardware_interface::HardwareOperationsBuilder::new();
-    operations_builder.port_write_u32(0xcf8, addr);
-    let mut out = 0;
-    // TODO: is it correct to immediately read back afterwards without delay? seems weird to me
-    operations_builder.port_read_u32(0xcfc, &mut out);
-    operations_builder.send().await;
+/// Walks the list of capabilities of a function.
+async unsafe fn read_capabilities(
+    config_space: &ConfigSpace,
+    location: PciDeviceLocation,
+) -> Vec<PciCapability> {
+    let mut out = Vec::new();
+
+    // Bit 4 of the status register indicates whether the list of capabilities is present.
+    let status = config_space.read_u32(location, 0x4).await >> 16;
+    if (status & (1 << 4)) == 0 {
+        return out;
+    }
+
+    let mut next = config_space.read_u32(location, 0x34).await.to_le_bytes()[0] & 0xfc;
+    // The limit on the number of iterations protects against malformed lists that loop.
+    while next != 0 && out.len() < 48 {
+        let [id, next_ptr, _, _] = config_space
+            .read_u32(location, u16::from(next))
+            .await
+            .to_le_bytes();
+        out.push(PciCapability { id, offset: next });
+        next = next_ptr & 0xfc;
+    }
+
     out
 }